@@ -0,0 +1,268 @@
+//! Synchronous wrapper around [`crate::RconClient`] for code that does
+//! not run on tokio, such as build scripts, simple tools and tests.
+//!
+//! Each [`RconClient`] here owns a small runtime and blocks the calling
+//! thread on it for every operation:
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use hyrcon_client::Protocol;
+//! use hyrcon_client::blocking::RconClient;
+//!
+//! # fn example() -> anyhow::Result<()> {
+//! let mut client = RconClient::connect(
+//!   Protocol::Source,
+//!   "mc.example.com",
+//!   25_575,
+//!   Duration::from_secs(5),
+//! )?;
+//! client.authenticate("hunter2")?;
+//! client.send_command("list")?;
+//! client.quit()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The methods must not be called from inside an async runtime, where
+//! blocking on one would panic; use the async client there instead.
+
+use std::time::Duration;
+
+use tokio::runtime::{self, Runtime};
+use tokio::sync::broadcast;
+
+use crate::error::Error;
+use crate::models::Player;
+use crate::protocol::Protocol;
+use crate::transport::{
+  self, AuthOutcome, ClientEvent, CommandOptions, CommandOutcome,
+  ConnectOptions, Greeting, ProtocolBackend, RconConnector, ServerMessage,
+  SessionStats,
+};
+
+/// Blocking counterpart of [`crate::RconClient`], with the same methods
+/// minus the `.await`.
+#[derive(Debug)]
+pub struct RconClient {
+  inner: transport::RconClient,
+  runtime: Runtime,
+}
+
+impl RconClient {
+  /// Blocking [`crate::RconClient::connect`].
+  pub fn connect(
+    protocol: Protocol,
+    host: &str,
+    port: u16,
+    deadline: Duration,
+  ) -> Result<Self, Error> {
+    Self::connect_with_options(
+      protocol,
+      host,
+      port,
+      deadline,
+      &ConnectOptions::default(),
+    )
+  }
+
+  /// Blocking [`crate::RconClient::connect_with_options`].
+  pub fn connect_with_options(
+    protocol: Protocol,
+    host: &str,
+    port: u16,
+    deadline: Duration,
+    options: &ConnectOptions,
+  ) -> Result<Self, Error> {
+    Self::open(transport::RconClient::connect_with_options(
+      protocol, host, port, deadline, options,
+    ))
+  }
+
+  /// Blocking [`crate::RconClient::connect_with`], for custom backends.
+  pub fn connect_with(
+    backend: Box<dyn ProtocolBackend>,
+    host: &str,
+    port: u16,
+    deadline: Duration,
+  ) -> Result<Self, Error> {
+    Self::open(transport::RconClient::connect_with(
+      backend, host, port, deadline,
+    ))
+  }
+
+  /// Connect and authenticate a session with settings checked by
+  /// [`RconClientBuilder::build`](crate::RconClientBuilder::build).
+  pub fn from_connector(connector: &RconConnector) -> Result<Self, Error> {
+    Self::open(connector.connect())
+  }
+
+  fn open(
+    connect: impl Future<Output = Result<transport::RconClient, Error>>,
+  ) -> Result<Self, Error> {
+    // One worker keeps background tasks, such as SSH tunnel relays,
+    // running between calls.
+    let runtime = runtime::Builder::new_multi_thread()
+      .worker_threads(1)
+      .enable_all()
+      .build()
+      .map_err(|source| Error::Io {
+        context: "starting the blocking client's runtime".to_string(),
+        source,
+      })?;
+    let inner = runtime.block_on(connect)?;
+    Ok(Self { inner, runtime })
+  }
+
+  pub fn protocol(&self) -> Protocol {
+    self.inner.protocol()
+  }
+
+  pub fn host(&self) -> &str {
+    self.inner.host()
+  }
+
+  pub fn port(&self) -> u16 {
+    self.inner.port()
+  }
+
+  pub fn greeting(&self) -> &Greeting {
+    self.inner.greeting()
+  }
+
+  pub fn is_closed(&self) -> bool {
+    self.inner.is_closed()
+  }
+
+  pub fn timeout(&self) -> Duration {
+    self.inner.timeout()
+  }
+
+  pub fn set_timeout(&mut self, timeout: Duration) {
+    self.inner.set_timeout(timeout);
+  }
+
+  pub fn stats(&self) -> SessionStats {
+    self.inner.stats()
+  }
+
+  /// See [`crate::RconClient::events`]; the receiver's `blocking_recv`
+  /// and `try_recv` work without a runtime.
+  pub fn events(&self) -> broadcast::Receiver<ClientEvent> {
+    self.inner.events()
+  }
+
+  pub fn take_messages(&mut self) -> Vec<ServerMessage> {
+    self.inner.take_messages()
+  }
+
+  pub fn authenticate(
+    &mut self,
+    password: &str,
+  ) -> Result<AuthOutcome, Error> {
+    self.runtime.block_on(self.inner.authenticate(password))
+  }
+
+  pub fn send_command(
+    &mut self,
+    command: &str,
+  ) -> Result<CommandOutcome, Error> {
+    self.runtime.block_on(self.inner.send_command(command))
+  }
+
+  pub fn send_command_with_timeout(
+    &mut self,
+    command: &str,
+    timeout: Duration,
+  ) -> Result<CommandOutcome, Error> {
+    self
+      .runtime
+      .block_on(self.inner.send_command_with_timeout(command, timeout))
+  }
+
+  pub fn send_command_with(
+    &mut self,
+    command: &str,
+    options: &CommandOptions,
+  ) -> Result<CommandOutcome, Error> {
+    self
+      .runtime
+      .block_on(self.inner.send_command_with(command, options))
+  }
+
+  pub fn send_many(
+    &mut self,
+    commands: &[&str],
+  ) -> Result<Vec<CommandOutcome>, Error> {
+    self.runtime.block_on(self.inner.send_many(commands))
+  }
+
+  pub fn say(&mut self, message: &str) -> Result<(), Error> {
+    self.runtime.block_on(self.inner.say(message))
+  }
+
+  pub fn players(&mut self) -> Result<Vec<Player>, Error> {
+    self.runtime.block_on(self.inner.players())
+  }
+
+  pub fn kick(
+    &mut self,
+    player: &str,
+    reason: Option<&str>,
+  ) -> Result<(), Error> {
+    self.runtime.block_on(self.inner.kick(player, reason))
+  }
+
+  pub fn keepalive(&mut self) -> Result<(), Error> {
+    self.runtime.block_on(self.inner.keepalive())
+  }
+
+  pub fn ping(&mut self) -> Result<Duration, Error> {
+    self.runtime.block_on(self.inner.ping())
+  }
+
+  pub fn quit(&mut self) -> Result<(), Error> {
+    self.runtime.block_on(self.inner.quit())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::testing::{MockHyrconServer, Reply};
+
+  #[test]
+  fn runs_commands_without_an_async_caller() {
+    let server_runtime = Runtime::new().expect("runtime");
+    let server = server_runtime
+      .block_on(
+        MockHyrconServer::builder()
+          .password("secret")
+          .expect("list", Reply::ok(["Steve"]))
+          .start(),
+      )
+      .expect("start");
+    let connector = crate::RconClient::builder()
+      .protocol(Protocol::Hyrcon)
+      .host("127.0.0.1")
+      .port(server.port())
+      .password("secret")
+      .timeout(Duration::from_secs(2))
+      .build()
+      .expect("settings");
+
+    let mut client =
+      RconClient::from_connector(&connector).expect("connect");
+    match client.send_command("list").expect("list") {
+      CommandOutcome::Response(response) => {
+        assert_eq!(response.payload, ["Steve"]);
+      }
+      CommandOutcome::Bye => panic!("unexpected bye"),
+    }
+    client.quit().expect("quit");
+    assert!(client.is_closed());
+    server_runtime
+      .block_on(server.finish())
+      .expect("script followed");
+  }
+}
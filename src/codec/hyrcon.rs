@@ -0,0 +1,210 @@
+//! HYRCON lines and blocks: newline-terminated UTF-8 lines, grouped into
+//! blocks that end with a line holding a single `.`.
+
+use super::{DecodeError, EncodeError};
+use crate::transport::{
+  CommandOutcome, RconResponse, ResponseStatus, ServerMessage, join_lines,
+};
+
+/// The line that ends every block.
+pub const BLOCK_END: &str = ".";
+
+/// Split the first complete line off `input`, without its `\n` or
+/// `\r\n`, along with the bytes it took up. `None` until the line ending
+/// has arrived.
+pub fn decode_line(input: &[u8]) -> Option<(&[u8], usize)> {
+  let end = input.iter().position(|&b| b == b'\n')?;
+  let line = &input[..end];
+  let line = line.strip_suffix(b"\r").unwrap_or(line);
+  Some((line, end + 1))
+}
+
+/// Write `line` and its `\n` to the start of `out`, returning how many
+/// bytes it took.
+pub fn encode_line(
+  line: &str,
+  out: &mut [u8],
+) -> Result<usize, EncodeError> {
+  if line.contains(['\r', '\n']) {
+    return Err(EncodeError::Newline);
+  }
+  let needed = line.len() + 1;
+  let Some(out) = out.get_mut(..needed) else {
+    return Err(EncodeError::BufferTooSmall { needed });
+  };
+  out[..line.len()].copy_from_slice(line.as_bytes());
+  out[line.len()] = b'\n';
+  Ok(needed)
+}
+
+/// Assembles blocks from bytes as they arrive, however they were split.
+#[derive(Debug, Default)]
+pub struct BlockDecoder {
+  /// Bytes of a line that has not been terminated yet.
+  buffer: Vec<u8>,
+  /// Lines of a block whose `.` terminator has not arrived yet.
+  partial: Vec<String>,
+}
+
+impl BlockDecoder {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn feed(&mut self, bytes: &[u8]) {
+    self.buffer.extend_from_slice(bytes);
+  }
+
+  /// The next complete block, without its `.` line, or `None` until
+  /// more bytes are fed.
+  pub fn next_block(
+    &mut self,
+  ) -> Result<Option<Vec<String>>, DecodeError> {
+    while let Some((line, consumed)) = decode_line(&self.buffer) {
+      let line = std::str::from_utf8(line)
+        .map(str::to_string)
+        .map_err(|_| DecodeError::NotUtf8);
+      self.buffer.drain(..consumed);
+      let line = line?;
+      if line == BLOCK_END {
+        return Ok(Some(std::mem::take(&mut self.partial)));
+      }
+      self.partial.push(line);
+    }
+    Ok(None)
+  }
+}
+
+/// Whether `block` was pushed by the server (`EVENT ...`) rather than
+/// answering a request.
+pub fn is_event(block: &[String]) -> bool {
+  block.first().is_some_and(|header| {
+    header
+      .strip_prefix("EVENT")
+      .is_some_and(|rest| rest.is_empty() || rest.starts_with(' '))
+  })
+}
+
+/// The messages in an `EVENT <KIND> [text]` block: the header's text,
+/// if any, then one per following line.
+pub fn event_messages(mut block: Vec<String>) -> Vec<ServerMessage> {
+  if !is_event(&block) {
+    return Vec::new();
+  }
+  let header = block.remove(0);
+  let header = header["EVENT".len()..].trim();
+  let (kind, inline) = header
+    .split_once(char::is_whitespace)
+    .map_or((header, ""), |(kind, text)| (kind, text.trim()));
+  let kind = (!kind.is_empty()).then(|| kind.to_string());
+
+  (!inline.is_empty())
+    .then(|| inline.to_string())
+    .into_iter()
+    .chain(block)
+    .map(|text| ServerMessage {
+      kind: kind.clone(),
+      text,
+    })
+    .collect()
+}
+
+/// Read a command's reply block: `OK` or `ERR`, then the payload, with
+/// a final `ERROR <message>` line taken as the error; or `BYE`.
+pub fn parse_reply(
+  mut block: Vec<String>,
+) -> Result<CommandOutcome, DecodeError> {
+  if block.is_empty() {
+    return Err(DecodeError::EmptyBlock);
+  }
+
+  let status = match block.remove(0).as_str() {
+    "OK" => ResponseStatus::Ok,
+    "ERR" => ResponseStatus::Err,
+    "BYE" => return Ok(CommandOutcome::Bye),
+    other => return Err(DecodeError::UnexpectedStatus(other.to_string())),
+  };
+  let (payload, error) = extract_error(block);
+  Ok(CommandOutcome::Response(RconResponse {
+    status,
+    raw: join_lines(&payload),
+    payload,
+    error,
+  }))
+}
+
+/// Extract the capability list from a `CAP <NAME>...` line, if present.
+pub fn parse_capabilities(lines: &[String]) -> Option<Vec<String>> {
+  lines.iter().find_map(|line| {
+    let rest = line.strip_prefix("CAP")?;
+    if !(rest.is_empty() || rest.starts_with(char::is_whitespace)) {
+      return None;
+    }
+    Some(rest.split_whitespace().map(str::to_string).collect())
+  })
+}
+
+fn extract_error(mut lines: Vec<String>) -> (Vec<String>, Option<String>) {
+  if let Some(message) = lines
+    .last()
+    .and_then(|last| last.strip_prefix("ERROR ").map(String::from))
+  {
+    lines.pop();
+    return (lines, Some(message));
+  }
+  (lines, None)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn extract_error_splits_last_line() {
+    let (payload, error) = extract_error(vec![
+      "line 1".to_string(),
+      "ERROR Something went wrong".to_string(),
+    ]);
+
+    assert_eq!(payload, vec!["line 1"]);
+    assert_eq!(error, Some("Something went wrong".to_string()));
+  }
+
+  #[test]
+  fn parse_capabilities_reads_cap_line() {
+    let lines = vec![
+      "HYRCON 1.2".to_string(),
+      "CAP EVENTS FILES MULTI".to_string(),
+    ];
+    assert_eq!(
+      parse_capabilities(&lines),
+      Some(vec![
+        "EVENTS".to_string(),
+        "FILES".to_string(),
+        "MULTI".to_string(),
+      ])
+    );
+    assert_eq!(parse_capabilities(&["CAPTAIN".to_string()]), None);
+    assert_eq!(parse_capabilities(&[]), None);
+  }
+
+  #[test]
+  fn blocks_are_assembled_across_reads() {
+    let mut decoder = BlockDecoder::new();
+    decoder.feed(b"OK\r\nSte");
+    assert_eq!(decoder.next_block(), Ok(None));
+    decoder.feed(b"ve\n.\nEVENT CHAT hi\n.\n");
+
+    let reply = decoder.next_block().expect("utf-8").expect("block");
+    assert_eq!(reply, ["OK", "Steve"]);
+    let event = decoder.next_block().expect("utf-8").expect("event");
+    assert!(is_event(&event));
+    assert_eq!(event_messages(event)[0].text, "hi");
+    assert_eq!(decoder.next_block(), Ok(None));
+
+    let mut line = [0_u8; 8];
+    assert_eq!(encode_line("LIST", &mut line), Ok(5));
+    assert_eq!(decode_line(&line[..5]), Some((&b"LIST"[..], 5)));
+    assert_eq!(encode_line("a\nb", &mut line), Err(EncodeError::Newline));
+  }
+}
@@ -0,0 +1,68 @@
+//! Sans-io framing of the HYRCON and Source RCON wire formats: bytes in,
+//! frames out, with no sockets, timeouts or runtime involved.
+//!
+//! The transports are built on these functions, and servers, fuzzers
+//! and alternative transports can reuse them as they are:
+//!
+//! ```
+//! use hyrcon_client::codec::source::{self, SERVERDATA_EXECCOMMAND};
+//!
+//! let mut wire = [0_u8; 64];
+//! let len = source::encode(7, SERVERDATA_EXECCOMMAND, "status", &mut wire)?;
+//!
+//! let (frame, consumed) =
+//!   source::decode(&wire[..len], None)?.expect("a whole packet");
+//! assert_eq!((frame.id, frame.payload()?), (7, "status"));
+//! assert_eq!(consumed, len);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! Source packets and HYRCON lines are encoded into and decoded from
+//! caller-provided buffers without allocating; only assembling and
+//! parsing whole HYRCON blocks builds strings.
+
+pub mod hyrcon;
+pub mod source;
+
+/// Why bytes received could not be decoded.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum DecodeError {
+  /// A Source packet's size field is smaller than its fixed fields.
+  #[error("Source RCON packet reported invalid payload length: {0}")]
+  InvalidLength(i32),
+  /// A Source packet's size field exceeds the allowed maximum.
+  #[error(
+    "Source RCON packet of {size} bytes exceeds the max packet size of \
+     {limit}"
+  )]
+  TooLarge { size: u32, limit: u32 },
+  /// A Source packet does not end in two NUL bytes.
+  #[error("Source RCON packet missing trailing NUL terminators")]
+  MissingTerminators,
+  /// A packet body or line is not UTF-8.
+  #[error("received data that is not valid UTF-8")]
+  NotUtf8,
+  /// A HYRCON reply block has no status line.
+  #[error("received empty response block from server")]
+  EmptyBlock,
+  /// A HYRCON reply block starts with something other than `OK`, `ERR`
+  /// or `BYE`.
+  #[error("unexpected status line `{0}` in command response")]
+  UnexpectedStatus(String),
+}
+
+/// Why a frame could not be encoded.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum EncodeError {
+  /// Source packets end at the first NUL.
+  #[error("payloads must not contain NUL characters")]
+  Nul,
+  /// HYRCON frames are single lines.
+  #[error("lines must not contain newline characters")]
+  Newline,
+  /// The output buffer is shorter than the encoded frame.
+  #[error("output buffer too small; {needed} bytes needed")]
+  BufferTooSmall { needed: usize },
+}
@@ -0,0 +1,168 @@
+//! Source RCON packets: a little-endian `i32` size field, then the
+//! request id, the packet type, the body and two NUL terminators.
+
+use super::{DecodeError, EncodeError};
+
+/// Packet kind of command output, and of the empty keepalive probe.
+pub const SERVERDATA_RESPONSE_VALUE: i32 = 0;
+/// Packet kind of a command sent by the client.
+pub const SERVERDATA_EXECCOMMAND: i32 = 2;
+/// Packet kind of the server's answer to [`SERVERDATA_AUTH`], with id
+/// `-1` when the password was wrong. Shares its value with
+/// [`SERVERDATA_EXECCOMMAND`].
+pub const SERVERDATA_AUTH_RESPONSE: i32 = 2;
+/// Packet kind of the client's password.
+pub const SERVERDATA_AUTH: i32 = 3;
+
+/// Bytes of the size field that starts every packet.
+pub const SIZE_FIELD_LEN: usize = 4;
+
+/// Bytes counted by the size field besides the body: the id, the type
+/// and the two terminators.
+pub const PACKET_OVERHEAD: usize = 10;
+
+/// A decoded packet, borrowing its body from the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame<'a> {
+  /// Request id, echoed by the server in its reply.
+  pub id: i32,
+  /// Packet type, such as [`SERVERDATA_EXECCOMMAND`].
+  pub kind: i32,
+  /// Body without the terminators, including anything after a NUL.
+  pub body: &'a [u8],
+}
+
+impl<'a> Frame<'a> {
+  /// The body up to its first NUL, as servers mean it.
+  pub fn payload(&self) -> Result<&'a str, DecodeError> {
+    let text =
+      std::str::from_utf8(self.body).map_err(|_| DecodeError::NotUtf8)?;
+    Ok(text.split('\0').next().unwrap_or_default())
+  }
+}
+
+/// Bytes [`encode`] writes for `payload`.
+pub fn encoded_len(payload: &str) -> usize {
+  SIZE_FIELD_LEN + PACKET_OVERHEAD + payload.len()
+}
+
+/// Write the packet for `payload` to the start of `out`, returning how
+/// many bytes it took.
+pub fn encode(
+  id: i32,
+  kind: i32,
+  payload: &str,
+  out: &mut [u8],
+) -> Result<usize, EncodeError> {
+  if payload.contains('\0') {
+    return Err(EncodeError::Nul);
+  }
+  let needed = encoded_len(payload);
+  let Some(out) = out.get_mut(..needed) else {
+    return Err(EncodeError::BufferTooSmall { needed });
+  };
+
+  let size = (needed - SIZE_FIELD_LEN) as i32;
+  let (header, rest) = out.split_at_mut(12);
+  header[0..4].copy_from_slice(&size.to_le_bytes());
+  header[4..8].copy_from_slice(&id.to_le_bytes());
+  header[8..12].copy_from_slice(&kind.to_le_bytes());
+  let (body, terminators) = rest.split_at_mut(payload.len());
+  body.copy_from_slice(payload.as_bytes());
+  terminators.fill(0);
+  Ok(needed)
+}
+
+/// Decode the packet at the start of `input`, along with the bytes it
+/// took up, or `None` until all of it has arrived. A size field above
+/// `max_packet_size` is an error, since the packet cannot be skipped
+/// without reading it.
+pub fn decode(
+  input: &[u8],
+  max_packet_size: Option<u32>,
+) -> Result<Option<(Frame<'_>, usize)>, DecodeError> {
+  let Some(size_bytes) = input.first_chunk::<SIZE_FIELD_LEN>() else {
+    return Ok(None);
+  };
+  let size = i32::from_le_bytes(*size_bytes);
+  if size < PACKET_OVERHEAD as i32 {
+    return Err(DecodeError::InvalidLength(size));
+  }
+  if let Some(limit) = max_packet_size
+    && size as u32 > limit
+  {
+    return Err(DecodeError::TooLarge {
+      size: size as u32,
+      limit,
+    });
+  }
+  let end = SIZE_FIELD_LEN + size as usize;
+  let Some(packet) = input.get(SIZE_FIELD_LEN..end) else {
+    return Ok(None);
+  };
+
+  let (fields, body) = packet.split_at(8);
+  let Some((body, [0, 0])) = body.split_last_chunk::<2>() else {
+    return Err(DecodeError::MissingTerminators);
+  };
+  let frame = Frame {
+    id: i32::from_le_bytes(fields[0..4].try_into().expect("4 bytes")),
+    kind: i32::from_le_bytes(fields[4..8].try_into().expect("4 bytes")),
+    body,
+  };
+  Ok(Some((frame, end)))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn packets_round_trip_in_pieces() {
+    let mut wire = [0_u8; 64];
+    let len =
+      encode(5, SERVERDATA_AUTH, "hunter2", &mut wire).expect("encode");
+    assert_eq!(len, encoded_len("hunter2"));
+    assert_eq!(&wire[..4], &17_i32.to_le_bytes());
+
+    assert_eq!(decode(&wire[..len - 1], None), Ok(None));
+    let (frame, consumed) =
+      decode(&wire[..len], None).expect("decode").expect("whole");
+    assert_eq!((frame.id, frame.kind), (5, SERVERDATA_AUTH));
+    assert_eq!(frame.payload(), Ok("hunter2"));
+    assert_eq!(consumed, len);
+
+    assert_eq!(
+      encode(1, SERVERDATA_AUTH, "hunter2", &mut wire[..8]),
+      Err(EncodeError::BufferTooSmall { needed: len })
+    );
+    assert_eq!(
+      encode(1, SERVERDATA_EXECCOMMAND, "a\0b", &mut wire),
+      Err(EncodeError::Nul)
+    );
+  }
+
+  #[test]
+  fn malformed_packets_are_rejected() {
+    let mut wire = [0_u8; 32];
+    let len = encode(1, SERVERDATA_RESPONSE_VALUE, "hello", &mut wire)
+      .expect("encode");
+    assert_eq!(
+      decode(&wire[..len], Some(12)),
+      Err(DecodeError::TooLarge {
+        size: 15,
+        limit: 12
+      })
+    );
+
+    wire[len - 1] = b'!';
+    assert_eq!(
+      decode(&wire[..len], None),
+      Err(DecodeError::MissingTerminators)
+    );
+    assert_eq!(
+      decode(&3_i32.to_le_bytes(), None),
+      Err(DecodeError::InvalidLength(3))
+    );
+  }
+}
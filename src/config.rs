@@ -0,0 +1,642 @@
+//! Named server profiles from `~/.config/hyrcon/config.toml`.
+//!
+//! ```toml
+//! default_profile = "survival"
+//! confirm = ["stop", "ban *", "wipe*"]
+//!
+//! [profiles.survival]
+//! host = "mc.example.com"
+//! protocol = "source"
+//! password_env = "SURVIVAL_RCON_PASSWORD"
+//!
+//! [profiles.creative]
+//! host = "10.0.0.7"
+//! port = 25576
+//! password_file = "~/.secrets/creative-rcon"
+//! timeout_ms = 3000
+//! plain = true
+//!
+//! [profiles.creative.vars]
+//! world = "creative"
+//!
+//! [macros]
+//! restartwarn = ["say Restarting in 60s", "sleep 60", "stop"]
+//! ```
+//!
+//! Profiles sit below environment variables and flags: a setting from
+//! the profile only applies when neither gave one.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::Protocol;
+use crate::transport::{percent_decode, split_host_port};
+use crate::util::command;
+
+/// Environment variable naming a config file to use instead of the
+/// default location.
+pub const CONFIG_ENV: &str = "HYRCON_CONFIG";
+
+/// Stand-in for secrets in displayed profiles.
+const REDACTED: &str = "<redacted>";
+
+/// Parsed configuration file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+  /// Profile used when none is requested explicitly.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub default_profile: Option<String>,
+  /// Commands the shell asks about before sending, as patterns in which
+  /// `*` matches anything; `--yes` sends them without asking.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub confirm: Vec<String>,
+  #[serde(default)]
+  pub profiles: BTreeMap<String, Profile>,
+  /// Shell macros: the lines each one runs, in order.
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub macros: BTreeMap<String, Vec<String>>,
+}
+
+/// Connection settings for one server. Unset fields leave the client's
+/// own defaults in place.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+  /// Hostname or address; a comma-separated list names fallbacks.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub host: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub port: Option<u16>,
+  /// Wire protocol, spelled like `--protocol`.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub protocol: Option<String>,
+  /// Password stored in the file itself.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub password: Option<String>,
+  /// Environment variable holding the password.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub password_env: Option<String>,
+  /// File whose first line is the password; `~/` is expanded.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub password_file: Option<PathBuf>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub timeout_ms: Option<u64>,
+  /// Disable ANSI colors, like `--plain`.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub plain: Option<bool>,
+  /// Values for `{name}` placeholders in commands, like `--var`.
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub vars: BTreeMap<String, String>,
+}
+
+impl Config {
+  /// `$HYRCON_CONFIG`, or `hyrcon/config.toml` under `$XDG_CONFIG_HOME`
+  /// (default `~/.config`).
+  #[must_use]
+  pub fn default_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os(CONFIG_ENV) {
+      return Some(PathBuf::from(path));
+    }
+    let base = match env::var_os("XDG_CONFIG_HOME") {
+      Some(base) if !base.is_empty() => PathBuf::from(base),
+      _ => env::home_dir()?.join(".config"),
+    };
+    Some(base.join("hyrcon").join("config.toml"))
+  }
+
+  /// Read the config file at `path`; a missing file is an empty config.
+  pub fn load(path: &Path) -> Result<Self> {
+    match fs::read_to_string(path) {
+      Ok(contents) => Self::parse(&contents)
+        .with_context(|| format!("failed to parse {}", path.display())),
+      Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::default()),
+      Err(err) => Err(err)
+        .with_context(|| format!("failed to read {}", path.display())),
+    }
+  }
+
+  /// Read the config file at [`Config::default_path`].
+  pub fn load_default() -> Result<Self> {
+    match Self::default_path() {
+      Some(path) => Self::load(&path),
+      None => Ok(Self::default()),
+    }
+  }
+
+  pub fn parse(contents: &str) -> Result<Self> {
+    let config: Self = toml::from_str(contents)?;
+    if let Some(name) = &config.default_profile
+      && !config.profiles.contains_key(name)
+    {
+      bail!("default_profile `{name}` is not defined under [profiles]");
+    }
+    for name in config.macros.keys() {
+      if name.is_empty()
+        || name.starts_with(':')
+        || name.contains(char::is_whitespace)
+      {
+        bail!(
+          "macro name `{name}` must be one word without a leading `:`"
+        );
+      }
+    }
+    Ok(config)
+  }
+
+  /// Write the config to `path`, creating its directory. Comments and
+  /// formatting of an existing file are not preserved.
+  pub fn save(&self, path: &Path) -> Result<()> {
+    if let Some(dir) = path.parent() {
+      fs::create_dir_all(dir)
+        .with_context(|| format!("failed to create {}", dir.display()))?;
+    }
+    let contents =
+      toml::to_string_pretty(self).context("failed to encode config")?;
+    write_private(path, &contents)
+      .with_context(|| format!("failed to write {}", path.display()))
+  }
+
+  /// The profile to connect with, if any: `requested` (from `--profile`)
+  /// or else `default_profile`.
+  pub fn selected(
+    &self,
+    requested: Option<&str>,
+  ) -> Result<Option<(&str, &Profile)>> {
+    let Some(name) = requested.or(self.default_profile.as_deref()) else {
+      return Ok(None);
+    };
+    self.profile(name).map(Some)
+  }
+
+  /// The profile called `name`.
+  pub fn profile(&self, name: &str) -> Result<(&str, &Profile)> {
+    self
+      .profiles
+      .get_key_value(name)
+      .map(|(name, profile)| (name.as_str(), profile))
+      .with_context(|| format!("no profile named `{name}`"))
+  }
+
+  /// The lines macro `name` runs, with the macros it runs in turn (as
+  /// `:run other` or just `other`) spelled out. A bare line naming a
+  /// macro already running, as in `stop = ["save-all", "stop"]`, is a
+  /// server command.
+  pub fn macro_steps(&self, name: &str) -> Result<Vec<String>> {
+    let mut steps = Vec::new();
+    self.expand_macro(name, &mut Vec::new(), &mut steps)?;
+    Ok(steps)
+  }
+
+  fn expand_macro<'a>(
+    &'a self,
+    name: &'a str,
+    running: &mut Vec<&'a str>,
+    steps: &mut Vec<String>,
+  ) -> Result<()> {
+    let lines = self
+      .macros
+      .get(name)
+      .with_context(|| format!("no macro named `{name}`"))?;
+    if running.contains(&name) {
+      bail!("macro `{name}` runs itself");
+    }
+    running.push(name);
+    for line in lines {
+      let line = line.trim();
+      let nested =
+        line.strip_prefix(":run ").map(str::trim).or_else(|| {
+          let named = self.macros.contains_key(line);
+          (named && !running.contains(&line)).then_some(line)
+        });
+      match nested {
+        Some(nested) => self.expand_macro(nested, running, steps)?,
+        None => steps.push(line.to_string()),
+      }
+    }
+    running.pop();
+    Ok(())
+  }
+
+  /// The first `confirm` pattern `command` matches, if any.
+  #[must_use]
+  pub fn confirmation_pattern(&self, command: &str) -> Option<&str> {
+    self
+      .confirm
+      .iter()
+      .map(String::as_str)
+      .find(|pattern| command::matches_pattern(pattern, command))
+  }
+
+  /// Add or replace the profile called `name`.
+  pub fn insert(
+    &mut self,
+    name: &str,
+    profile: Profile,
+  ) -> Option<Profile> {
+    self.profiles.insert(name.to_string(), profile)
+  }
+
+  /// Delete the profile called `name`, and stop using it as the default.
+  pub fn remove(&mut self, name: &str) -> Result<Profile> {
+    let profile = self
+      .profiles
+      .remove(name)
+      .with_context(|| format!("no profile named `{name}`"))?;
+    if self.default_profile.as_deref() == Some(name) {
+      self.default_profile = None;
+    }
+    Ok(profile)
+  }
+}
+
+impl Profile {
+  /// Copy of the profile that is safe to display: an inline password is
+  /// masked, while variable names and file paths are kept.
+  #[must_use]
+  pub fn redacted(&self) -> Self {
+    Self {
+      password: self.password.as_ref().map(|_| REDACTED.to_string()),
+      ..self.clone()
+    }
+  }
+
+  /// Look up the password from whichever source the profile names.
+  pub fn resolve_password(&self) -> Result<Option<String>> {
+    let sources = [
+      self.password.is_some(),
+      self.password_env.is_some(),
+      self.password_file.is_some(),
+    ];
+    if sources.iter().filter(|set| **set).count() > 1 {
+      bail!(
+        "set only one of `password`, `password_env` and `password_file`"
+      );
+    }
+
+    if let Some(password) = &self.password {
+      return Ok(Some(password.clone()));
+    }
+    if let Some(var) = &self.password_env {
+      return env::var(var)
+        .map(Some)
+        .with_context(|| format!("password variable ${var} is not set"));
+    }
+    if let Some(path) = &self.password_file {
+      let path = expand_home(path);
+      let contents = fs::read_to_string(&path).with_context(|| {
+        format!("failed to read password file {}", path.display())
+      })?;
+      let password = contents.lines().next().unwrap_or_default();
+      return Ok(Some(password.to_string()));
+    }
+    Ok(None)
+  }
+}
+
+/// Connection settings written as one URL, such as
+/// `rcon://:password@host:25575?protocol=source&timeout=5000`.
+///
+/// Besides the password in the user info, the query can set `protocol`,
+/// `timeout` (milliseconds), `plain`, `password_env` and
+/// `password_file`. The settings apply like a profile that takes
+/// precedence over the config file's.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ConnectionUrl {
+  profile: Profile,
+}
+
+impl ConnectionUrl {
+  /// The settings the URL spells out.
+  pub fn profile(&self) -> &Profile {
+    &self.profile
+  }
+}
+
+impl fmt::Display for ConnectionUrl {
+  /// URL with the password redacted.
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let profile = &self.profile;
+    f.write_str("rcon://")?;
+    if profile.password.is_some() {
+      write!(f, ":{REDACTED}@")?;
+    }
+    match profile.host.as_deref().unwrap_or_default() {
+      host if host.contains(':') => write!(f, "[{host}]")?,
+      host => f.write_str(host)?,
+    }
+    if let Some(port) = profile.port {
+      write!(f, ":{port}")?;
+    }
+
+    let query = [
+      ("protocol", profile.protocol.clone()),
+      ("timeout", profile.timeout_ms.map(|ms| ms.to_string())),
+      ("plain", profile.plain.map(|plain| plain.to_string())),
+      ("password_env", profile.password_env.clone()),
+      (
+        "password_file",
+        profile
+          .password_file
+          .as_ref()
+          .map(|path| path.display().to_string()),
+      ),
+    ];
+    let mut separator = '?';
+    for (key, value) in query {
+      if let Some(value) = value {
+        write!(f, "{separator}{key}={value}")?;
+        separator = '&';
+      }
+    }
+    Ok(())
+  }
+}
+
+impl fmt::Debug for ConnectionUrl {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("ConnectionUrl")
+      .field("profile", &self.profile.redacted())
+      .finish()
+  }
+}
+
+/// Error returned when parsing a [`ConnectionUrl`] fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseConnectionUrlError {
+  reason: &'static str,
+}
+
+impl fmt::Display for ParseConnectionUrlError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "invalid connection URL: {}", self.reason)
+  }
+}
+
+impl std::error::Error for ParseConnectionUrlError {}
+
+impl FromStr for ConnectionUrl {
+  type Err = ParseConnectionUrlError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let error = |reason| ParseConnectionUrlError { reason };
+    let decode = |raw| percent_decode(raw).ok_or(error("bad escape"));
+
+    let (scheme, rest) = s
+      .trim()
+      .split_once("://")
+      .ok_or(error("expected `rcon://host:port`"))?;
+    if !scheme.eq_ignore_ascii_case("rcon") {
+      return Err(error("unsupported scheme (use rcon://)"));
+    }
+    let (authority, query) = rest.split_once('?').unwrap_or((rest, ""));
+    let authority = authority.trim_end_matches('/');
+    if authority.contains('/') {
+      return Err(error("connection URLs cannot have a path"));
+    }
+
+    let mut profile = Profile::default();
+    let address = match authority.rsplit_once('@') {
+      Some((userinfo, address)) => {
+        let Some(("", password)) = userinfo.split_once(':') else {
+          return Err(error(
+            "RCON has no usernames; write the password as `:password@`",
+          ));
+        };
+        profile.password = Some(decode(password)?);
+        address
+      }
+      None => authority,
+    };
+    let (host, port) = split_host_port(address)
+      .ok_or(error("expected `host` or `host:port`"))?;
+    if host.is_empty() {
+      return Err(error("missing host"));
+    }
+    profile.host = Some(host.to_string());
+    profile.port = port
+      .map(|port| port.parse().map_err(|_| error("invalid port")))
+      .transpose()?;
+
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+      let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+      let value = decode(value)?;
+      match key {
+        "protocol" => {
+          value
+            .parse::<Protocol>()
+            .map_err(|_| error("unknown protocol"))?;
+          profile.protocol = Some(value);
+        }
+        "timeout" => {
+          let timeout =
+            value.parse().map_err(|_| error("invalid timeout"))?;
+          profile.timeout_ms = Some(timeout);
+        }
+        "plain" => {
+          let plain = value
+            .parse()
+            .map_err(|_| error("`plain` must be `true` or `false`"))?;
+          profile.plain = Some(plain);
+        }
+        "password_env" => profile.password_env = Some(value),
+        "password_file" => profile.password_file = Some(value.into()),
+        _ => {
+          return Err(error(
+            "unknown query parameter; expected protocol, timeout, plain, \
+             password_env or password_file",
+          ));
+        }
+      }
+    }
+    let sources = [
+      profile.password.is_some(),
+      profile.password_env.is_some(),
+      profile.password_file.is_some(),
+    ];
+    if sources.iter().filter(|set| **set).count() > 1 {
+      return Err(error("give the password only one way"));
+    }
+
+    Ok(Self { profile })
+  }
+}
+
+/// Profiles can hold passwords, so keep the file private to its owner.
+#[cfg(unix)]
+fn write_private(path: &Path, contents: &str) -> std::io::Result<()> {
+  use std::io::Write;
+  use std::os::unix::fs::OpenOptionsExt;
+
+  let mut file = fs::OpenOptions::new()
+    .write(true)
+    .create(true)
+    .truncate(true)
+    .mode(0o600)
+    .open(path)?;
+  file.write_all(contents.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &Path, contents: &str) -> std::io::Result<()> {
+  fs::write(path, contents)
+}
+
+fn expand_home(path: &Path) -> PathBuf {
+  match (path.strip_prefix("~"), env::home_dir()) {
+    (Ok(rest), Some(home)) => home.join(rest),
+    _ => path.to_path_buf(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_profiles() {
+    let config = Config::parse(
+      r#"
+        default_profile = "survival"
+
+        [profiles.survival]
+        host = "mc.example.com"
+        protocol = "source"
+        password = "hunter2"
+
+        [profiles.creative]
+        port = 25576
+        plain = true
+      "#,
+    )
+    .expect("config");
+
+    let (name, profile) =
+      config.selected(None).expect("selected").expect("default");
+    assert_eq!(name, "survival");
+    assert_eq!(profile.host.as_deref(), Some("mc.example.com"));
+    assert_eq!(
+      profile.resolve_password().expect("password").as_deref(),
+      Some("hunter2")
+    );
+    assert_eq!(config.profiles["creative"].port, Some(25_576));
+    assert_eq!(config.profiles["creative"].plain, Some(true));
+    assert_eq!(
+      config
+        .selected(Some("creative"))
+        .expect("requested")
+        .map(|p| p.0),
+      Some("creative")
+    );
+    assert!(config.selected(Some("lobby")).is_err());
+  }
+
+  #[test]
+  fn removing_the_default_profile_clears_it() {
+    let mut config = Config::default();
+    config.insert(
+      "survival",
+      Profile {
+        password: Some("hunter2".to_string()),
+        ..Profile::default()
+      },
+    );
+    config.default_profile = Some("survival".to_string());
+
+    let shown = config.profiles["survival"].redacted();
+    assert_eq!(shown.password.as_deref(), Some("<redacted>"));
+
+    config.remove("survival").expect("removed");
+    assert_eq!(config, Config::default());
+    assert!(config.remove("survival").is_err());
+  }
+
+  #[test]
+  fn parses_connection_urls() {
+    let url: ConnectionUrl =
+      "rcon://:p%40ss@mc.example.com:25575?protocol=source&timeout=5000"
+        .parse()
+        .expect("url");
+    let profile = url.profile();
+    assert_eq!(profile.host.as_deref(), Some("mc.example.com"));
+    assert_eq!(profile.port, Some(25_575));
+    assert_eq!(profile.password.as_deref(), Some("p@ss"));
+    assert_eq!(profile.protocol.as_deref(), Some("source"));
+    assert_eq!(profile.timeout_ms, Some(5_000));
+    assert_eq!(
+      url.to_string(),
+      "rcon://:<redacted>@mc.example.com:25575?protocol=source&timeout=5000"
+    );
+
+    let url: ConnectionUrl =
+      "rcon://[::1]?password_env=RCON_PW".parse().expect("url");
+    assert_eq!(url.profile().host.as_deref(), Some("::1"));
+    assert_eq!(url.profile().port, None);
+    assert_eq!(url.to_string(), "rcon://[::1]?password_env=RCON_PW");
+
+    for bad in [
+      "mc.example.com:25575",
+      "http://mc.example.com",
+      "rcon://admin:pw@mc.example.com",
+      "rcon://mc.example.com/console",
+      "rcon://mc.example.com?protocol=gopher",
+      "rcon://mc.example.com?retries=3",
+      "rcon://:pw@mc.example.com?password_env=RCON_PW",
+      "rcon://:25575",
+    ] {
+      assert!(bad.parse::<ConnectionUrl>().is_err(), "{bad}");
+    }
+  }
+
+  #[test]
+  fn macros_spell_out_the_macros_they_run() {
+    let config = Config::parse(
+      r#"
+        [macros]
+        warn = ["say Restarting in 60s", "sleep 60"]
+        restart = ["warn", "save-all", ":run stop"]
+        stop = ["stop"]
+        loop = ["say again", ":run loop"]
+      "#,
+    )
+    .expect("config");
+    assert_eq!(
+      config.macro_steps("restart").expect("steps"),
+      ["say Restarting in 60s", "sleep 60", "save-all", "stop"]
+    );
+    assert!(config.macro_steps("loop").is_err());
+    assert!(config.macro_steps("missing").is_err());
+    assert!(Config::parse("[macros]\n\":run\" = []").is_err());
+  }
+
+  #[test]
+  fn confirm_patterns_pick_out_dangerous_commands() {
+    let config = Config::parse(r#"confirm = ["stop", "ban *", "wipe*"]"#)
+      .expect("config");
+    assert_eq!(config.confirmation_pattern("/stop"), Some("stop"));
+    assert_eq!(config.confirmation_pattern("ban Steve"), Some("ban *"));
+    assert_eq!(config.confirmation_pattern("wipeall"), Some("wipe*"));
+    assert_eq!(config.confirmation_pattern("say stop"), None);
+  }
+
+  #[test]
+  fn rejects_unknown_keys_and_profiles() {
+    assert!(Config::parse("[profiles.a]\nhostname = \"x\"").is_err());
+    assert!(Config::parse("default_profile = \"missing\"").is_err());
+
+    let profile = Profile {
+      password: Some("a".to_string()),
+      password_env: Some("B".to_string()),
+      ..Profile::default()
+    };
+    assert!(profile.resolve_password().is_err());
+  }
+}
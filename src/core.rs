@@ -1,64 +1,544 @@
-use std::io::{self, IsTerminal};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::{self, IsTerminal, Write};
+use std::mem;
+use std::net::IpAddr;
+use std::ops::ControlFlow;
+use std::path::Path;
+use std::process::Stdio;
 use std::time::Duration;
 
 use anyhow::{Context, Result, anyhow, bail};
-use tokio::io::{AsyncBufReadExt, BufReader};
+use clap::CommandFactory;
+use clap_complete::Shell;
+use clap_complete::env::Shells;
+use futures_util::future::select_all;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::sync::mpsc;
+use tokio::time::{Instant, sleep, sleep_until};
 
 use crate::{
-  cli::Cli,
+  cli::{
+    COMPLETE_ENV, Cli, Commands, ConfigCommand, ExpectStatus, InitArgs,
+    Mode, OnError, OutputFormat, ProfilesCommand,
+  },
+  config::{Config, Profile},
+  editor::{CommandList, LineEditor},
+  exit::{Failed, Failure},
+  format::translate_section_codes,
+  help_cache::HelpCache,
   logging,
+  meta::{self, MetaCommand},
+  output_log,
+  protocol::Protocol,
+  runtime,
+  shutdown::{Interrupts, ShutdownListener},
+  transcript,
   transport::{
     self, AuthOutcome, CommandOutcome, RconClient, ResponseStatus,
+    ServerMessage,
   },
-  ui,
-  util::command,
+  ui::{self, CommandRecord},
+  util::{backoff::Backoff, command},
 };
 
+/// Delay before the first reconnect attempt; later ones back off
+/// exponentially.
+const RECONNECT_INITIAL_DELAY: Duration = Duration::from_millis(500);
+
+/// Longest pause between two reconnect attempts.
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Longest wait between two `--retries` attempts.
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Pause between two `--ping` samples.
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+
 /// Orchestrate the full HYRCON client lifecycle for a single invocation.
-pub async fn run(cli: Cli) -> Result<i32> {
-  let use_color_stdout = !cli.plain && io::stdout().is_terminal();
-  let use_color_logs = !cli.plain && io::stderr().is_terminal();
+pub async fn run(mut cli: Cli) -> Result<i32> {
+  cli.read_password_fd()?;
+  ui::set_ascii(cli.ascii_only());
+  let use_color = cli.use_color(io::stdout().is_terminal());
+  match &cli.subcommand {
+    Some(Commands::Profiles(command)) => {
+      return run_profiles(command, use_color);
+    }
+    Some(Commands::Config(command)) => {
+      return run_config(&cli, command, use_color).await;
+    }
+    Some(Commands::Completions { shell }) => {
+      return run_completions(*shell);
+    }
+    Some(Commands::Manpage { out_dir }) => {
+      return run_manpage(out_dir.as_deref());
+    }
+    _ => {}
+  }
+  // Further shell sessions start from the flags alone.
+  let base = cli.clone();
+
+  let config = Config::load_default()?;
+  let profile = config.selected(cli.profile.as_deref())?;
+  if let Some((name, profile)) = profile {
+    cli
+      .apply_profile(profile)
+      .with_context(|| format!("invalid profile `{name}`"))?;
+  }
+  if let Some(url) = cli.url.clone() {
+    cli
+      .apply_profile(url.profile())
+      .with_context(|| format!("invalid connection URL `{url}`"))?;
+  }
+
+  let json = cli.output == OutputFormat::Json;
+  let use_color_stdout =
+    !json && cli.use_color(io::stdout().is_terminal());
+  let use_color_logs = cli.use_color(io::stderr().is_terminal());
 
   logging::init(cli.verbose, use_color_logs);
+  if let Some(path) = &cli.log_output {
+    output_log::init(path)?;
+  }
+  if let Some(path) = &cli.record {
+    transcript::start(path)?;
+  }
+  if let Some((name, _)) = profile {
+    tracing::debug!(profile = name, "loaded settings from config file");
+  }
+
+  let mode = cli.mode().expect("management subcommands returned above");
+  let batch_stdin = match mode {
+    Mode::Script(path) => path == Path::new("-"),
+    Mode::Shell => !io::stdin().is_terminal(),
+    Mode::Exec(_) | Mode::Commands(_) | Mode::Ping { .. } => false,
+  };
+  if cli.on_error == OnError::Prompt && batch_stdin {
+    bail!(
+      "`--on-error prompt` needs stdin for the answers; use --script FILE"
+    );
+  }
+  // Read the script up front so a typo in its path or placeholders fails
+  // before the handshake.
+  let batch = match mode {
+    Mode::Script(path) => Some(read_script(path, &cli.vars).await?),
+    Mode::Commands(commands) => Some(prepare_commands(&cli, commands)?),
+    _ => None,
+  };
+  let planned = match (mode, &batch) {
+    (Mode::Exec(words), _) => vec![one_shot_command(&cli, words)?],
+    (_, Some(batch)) => batch.iter().map(|(_, c)| c.clone()).collect(),
+    _ => Vec::new(),
+  };
+  let target = profile
+    .map_or_else(|| cli.hosts.join(","), |(name, _)| name.to_string());
+
+  // Listen before prompting and connecting, so a signal that arrives
+  // while either waits still ends the run with its exit code.
+  let mut shutdown = ShutdownListener::install()
+    .context("failed to install termination signal handlers")?;
+  let opening = async {
+    confirm_planned(&cli, &config, &planned, &target, use_color_stdout)
+      .await?;
+    establish(&cli).await
+  };
+  let mut client = tokio::select! {
+    client = opening => client?,
+    signal = shutdown.recv() => {
+      tracing::warn!(
+        %signal,
+        "termination signal received before the session opened"
+      );
+      return Ok(signal.exit_code());
+    }
+  };
+  if cli.decorated() {
+    ui::render_greeting(client.greeting(), use_color_stdout);
+  }
+
+  let session = async {
+    match mode {
+      Mode::Exec(words) if let Some(interval) = cli.watch => {
+        let interval = Duration::from_secs(interval);
+        run_watch(&cli, words, interval, &mut client, use_color_stdout)
+          .await
+      }
+      Mode::Exec(words) => {
+        run_one_shot(&cli, words, &mut client, use_color_stdout).await
+      }
+      Mode::Shell => {
+        let profile = profile.map(|(name, _)| name);
+        run_interactive(
+          &cli,
+          &base,
+          &config,
+          profile,
+          &mut client,
+          use_color_stdout,
+        )
+        .await
+      }
+      Mode::Ping { count } => {
+        run_ping(count, &mut client, use_color_stdout).await
+      }
+      Mode::Script(_) | Mode::Commands(_) => {
+        let batch = batch.as_deref().expect("batch read above");
+        run_batch(&cli, batch, &mut client, use_color_stdout).await
+      }
+    }
+  };
+
+  let exit_code = tokio::select! {
+    result = session => result.map_err(|err| {
+      Failed::tag(Failure::of_session(&err), err)
+    })?,
+    signal = shutdown.recv() => {
+      // Any in-flight command future was dropped above; close the session
+      // below and make sure nothing buffered for the terminal is lost.
+      tracing::warn!(%signal, "termination signal received; shutting down");
+      let _ = io::stdout().flush();
+      signal.exit_code()
+    }
+  };
+
+  if !client.is_closed()
+    && let Err(err) = client.quit().await
+  {
+    tracing::debug!(error = %err, "failed to send QUIT during shutdown");
+  }
+  if cli.verbose > 0 {
+    ui::render_session_stats(&client.stats(), use_color_logs);
+  }
+
+  Ok(exit_code)
+}
+
+/// Carry out a `profiles` subcommand against the config file.
+fn run_profiles(
+  command: &ProfilesCommand,
+  use_color: bool,
+) -> Result<i32> {
+  let path = Config::default_path()
+    .context("cannot locate the config file; set HYRCON_CONFIG")?;
+  let mut config = Config::load(&path)?;
+
+  match command {
+    ProfilesCommand::List => {
+      ui::render_profile_list(&config, use_color);
+    }
+    ProfilesCommand::Show { name } => {
+      let (name, profile) = config.profile(name)?;
+      ui::render_profile(name, &profile.redacted())?;
+    }
+    ProfilesCommand::Add(args) => {
+      let replaced = config.insert(&args.name, args.to_profile());
+      if args.default {
+        config.default_profile = Some(args.name.clone());
+      }
+      config.save(&path)?;
+      let verb = if replaced.is_some() {
+        "Updated"
+      } else {
+        "Added"
+      };
+      println!("{verb} profile `{}` in {}", args.name, path.display());
+    }
+    ProfilesCommand::Remove { name } => {
+      config.remove(name)?;
+      config.save(&path)?;
+      println!("Removed profile `{name}` from {}", path.display());
+    }
+  }
+
+  Ok(0)
+}
+
+/// Print the script that registers `shell` completions. The script calls
+/// back into the client while completing, so profile names are always
+/// current.
+fn run_completions(shell: Shell) -> Result<i32> {
+  let command = Cli::command();
+  let name = command.get_name();
+  let shells = Shells::builtins();
+  let completer = shells
+    .completer(&shell.to_string())
+    .with_context(|| format!("no completion support for {shell}"))?;
+  completer
+    .write_registration(COMPLETE_ENV, name, name, name, &mut io::stdout())
+    .context("failed to write the completion script")?;
+  Ok(0)
+}
+
+/// Print the manual page, or write one page per subcommand to `out_dir`.
+fn run_manpage(out_dir: Option<&Path>) -> Result<i32> {
+  let command = Cli::command();
+  match out_dir {
+    Some(dir) => clap_mangen::generate_to(command, dir)
+      .with_context(|| format!("failed to write to {}", dir.display()))?,
+    None => clap_mangen::Man::new(command)
+      .render(&mut io::stdout())
+      .context("failed to write the manual page")?,
+  }
+  Ok(0)
+}
 
-  let mut client = transport::RconClient::connect(
+/// Carry out a `config` subcommand.
+async fn run_config(
+  cli: &Cli,
+  command: &ConfigCommand,
+  use_color: bool,
+) -> Result<i32> {
+  let ConfigCommand::Init(args) = command;
+  let path = Config::default_path()
+    .context("cannot locate the config file; set HYRCON_CONFIG")?;
+  if path.exists() && !args.force {
+    bail!(
+      "{} already exists; pass --force to replace it",
+      path.display()
+    );
+  }
+
+  let (name, profile) = init_profile(args, use_color).await?;
+  if !args.no_verify {
+    let mut probe = cli.clone();
+    probe.apply_profile(&profile)?;
+    let mut client = establish(&probe).await.context(
+      "test connection failed; fix the settings or pass --no-verify",
+    )?;
+    println!("Connected to {}", client.greeting().banner());
+    if let Err(err) = client.quit().await {
+      tracing::debug!(error = %err, "failed to send QUIT after the test");
+    }
+  }
+
+  let mut config = Config::default();
+  config.insert(&name, profile);
+  config.default_profile = Some(name.clone());
+  config.save(&path)?;
+  println!("Wrote profile `{name}` to {}", path.display());
+  Ok(0)
+}
+
+/// The first profile for `config init`, from the flags and, on a
+/// terminal, answers to questions about the rest.
+async fn init_profile(
+  args: &InitArgs,
+  use_color: bool,
+) -> Result<(String, Profile)> {
+  let interactive = io::stdin().is_terminal();
+  let setting =
+    async |given: Option<String>, question, default| match given {
+      Some(value) => Ok(value),
+      None if interactive => ui::ask(question, default, use_color)
+        .await
+        .context("failed to read the answer"),
+      None => Ok(default.to_string()),
+    };
+
+  let name = setting(args.name.clone(), "Profile name", "default").await?;
+  let protocol: Protocol = setting(
+    args.protocol.map(|protocol| protocol.to_string()),
+    "Protocol",
+    "source",
+  )
+  .await?
+  .parse()?;
+  let host = setting(args.host.clone(), "Host", "127.0.0.1").await?;
+  let default_port = protocol.default_port().to_string();
+  let port: u16 = setting(
+    args.port.map(|port| port.to_string()),
+    "Port",
+    &default_port,
+  )
+  .await?
+  .parse()
+  .context("invalid port")?;
+
+  let mut password_env = args.password_env.clone();
+  let password_given = args.password.is_some()
+    || password_env.is_some()
+    || args.password_file.is_some();
+  if !password_given && interactive {
+    let var = setting(
+      None,
+      "Environment variable holding the password (empty for none)",
+      "",
+    )
+    .await?;
+    password_env = Some(var).filter(|var| !var.is_empty());
+  }
+
+  let profile = Profile {
+    host: Some(host),
+    port: Some(port).filter(|port| *port != protocol.default_port()),
+    protocol: Some(protocol.to_string()),
+    password: args.password.clone(),
+    password_env,
+    password_file: args.password_file.clone(),
+    ..Profile::default()
+  };
+  Ok((name, profile))
+}
+
+/// Connect and authenticate to the first `--host` that accepts a
+/// session, trying them in the order given.
+async fn establish(cli: &Cli) -> Result<RconClient> {
+  let endpoints = endpoints(cli).await;
+  let mut remaining = endpoints.iter().peekable();
+  let mut last_error = None;
+
+  while let Some((host, port)) = remaining.next() {
+    let attempt = connect(cli, host, *port).await;
+    match attempt.map_err(|err| Failed::tag(Failure::Connect, err)) {
+      Ok(client) => {
+        if endpoints.len() > 1 {
+          tracing::info!(%host, port, "using host");
+        }
+        transcript::session(&client);
+        return Ok(client);
+      }
+      Err(err) if remaining.peek().is_some() => {
+        tracing::warn!(%host, "{err:#}; trying the next host");
+        last_error = Some(err);
+      }
+      Err(err) => last_error = Some(err),
+    }
+  }
+
+  let err = last_error.context("no host to connect to")?;
+  if endpoints.len() > 1 {
+    return Err(err.context(format!(
+      "none of the {} hosts accepted a session",
+      endpoints.len()
+    )));
+  }
+  Err(err)
+}
+
+/// Host and port pairs to try: each `--host` with the effective port, or
+/// with `--srv` and no `--port`, the targets of its SRV record.
+async fn endpoints(cli: &Cli) -> Vec<(String, u16)> {
+  let mut endpoints = Vec::with_capacity(cli.hosts.len());
+  for host in &cli.hosts {
+    if cli.srv && cli.port.is_none() && host.parse::<IpAddr>().is_err() {
+      let deadline = Duration::from_millis(cli.timeout_ms);
+      match transport::lookup_srv(host, deadline).await {
+        Ok(targets) if !targets.is_empty() => {
+          for target in targets {
+            tracing::debug!(%host, target = %target.host, port = target.port, "SRV target");
+            endpoints.push((target.host, target.port));
+          }
+          continue;
+        }
+        Ok(_) => {
+          tracing::debug!(%host, "no SRV record; using A/AAAA records");
+        }
+        Err(err) => {
+          tracing::warn!(%host, "{err:#}; using A/AAAA records");
+        }
+      }
+    }
+    endpoints.push((host.clone(), cli.effective_port()));
+  }
+  endpoints
+}
+
+async fn connect(cli: &Cli, host: &str, port: u16) -> Result<RconClient> {
+  let mut client = transport::RconClient::connect_with_options(
     cli.protocol,
-    &cli.host,
-    cli.port,
+    host,
+    port,
     Duration::from_millis(cli.timeout_ms),
+    &cli.connect_options(),
   )
-  .await
-  .with_context(|| {
-    format!(
-      "failed to connect to {}:{} via {}",
-      cli.host, cli.port, cli.protocol
-    )
-  })?;
+  .await?;
 
-  let greeting = client.greeting().clone();
+  let greeting = client.greeting();
   tracing::info!(
     auth_required = greeting.requires_auth(),
     banner = greeting.banner(),
     protocol = %client.protocol(),
     "connected to RCON server"
   );
-  ui::render_greeting(&greeting, use_color_stdout);
 
-  authenticate_if_required(&cli, &mut client).await?;
+  authenticate_if_required(cli, &mut client).await?;
+  Ok(client)
+}
 
-  let exit_code = if cli.command.is_empty() {
-    run_interactive(&mut client, use_color_stdout).await?
+/// Replace a dropped session with a fresh, authenticated one when
+/// `--reconnect` is set; otherwise give up with `err`.
+async fn recover(
+  cli: &Cli,
+  client: &mut RconClient,
+  err: anyhow::Error,
+  use_color: bool,
+) -> Result<()> {
+  if !cli.reconnect {
+    return Err(err);
+  }
+
+  // Reconnect notices would break up JSON or payload-only output; logs
+  // cover them.
+  let notify = cli.decorated();
+  record_event(&format!("connection lost: {err:#}"));
+  if notify {
+    ui::render_connection_lost(&format!("{err:#}"), use_color);
   } else {
-    run_one_shot(&cli, &mut client, use_color_stdout).await?
-  };
+    tracing::warn!("connection lost: {err:#}");
+  }
+  let mut backoff =
+    Backoff::new(RECONNECT_INITIAL_DELAY, RECONNECT_MAX_DELAY);
+  let mut attempt = 1;
+  loop {
+    let delay = backoff.next_delay();
+    if notify {
+      ui::render_reconnecting(
+        attempt,
+        cli.max_reconnect_attempts,
+        delay,
+        use_color,
+      );
+    }
+    sleep(delay).await;
 
-  if !client.is_closed() {
-    if let Err(err) = client.quit().await {
-      tracing::debug!(error = %err, "failed to send QUIT during shutdown");
+    match establish(cli).await {
+      Ok(fresh) => {
+        *client = fresh;
+        break;
+      }
+      Err(err) if attempt >= cli.max_reconnect_attempts => {
+        return Err(err.context(format!(
+          "gave up reconnecting after {attempt} attempts"
+        )));
+      }
+      Err(err) => {
+        tracing::warn!(attempt, "reconnect failed: {err:#}");
+        attempt += 1;
+      }
     }
   }
 
-  Ok(exit_code)
+  tracing::info!(banner = client.greeting().banner(), "session restored");
+  record_event("session restored");
+  if notify {
+    ui::render_session_restored(use_color);
+  }
+  Ok(())
+}
+
+/// Note a connection event in the output log and the transcript.
+fn record_event(text: &str) {
+  output_log::event(text);
+  transcript::connection(text);
+}
+
+/// Whether `err` from `client` means the connection itself is gone, as
+/// opposed to a rejected command.
+fn is_connection_lost(client: &RconClient, err: &anyhow::Error) -> bool {
+  client.is_closed()
+    || err.chain().any(|cause| {
+      cause.is::<io::Error>()
+        || matches!(cause.downcast_ref(), Some(crate::Error::Closed))
+    })
 }
 
 async fn authenticate_if_required(
@@ -66,14 +546,23 @@ async fn authenticate_if_required(
   client: &mut RconClient,
 ) -> Result<()> {
   if client.greeting().requires_auth() {
-    let password = cli
-            .password
-            .as_deref()
-            .ok_or_else(|| anyhow!("server requires authentication; supply --password or set HYRCON_PASSWORD"))?;
+    let password = cli.password.as_deref().ok_or_else(|| {
+      Failed::tag(
+        Failure::Auth,
+        anyhow!(
+          "server requires authentication; supply --password or set HYRCON_PASSWORD"
+        ),
+      )
+    })?;
 
     match client.authenticate(password).await? {
       AuthOutcome::Success => tracing::info!("authentication accepted"),
-      AuthOutcome::Failure => bail!("authentication rejected by server"),
+      AuthOutcome::Failure => {
+        return Err(Failed::tag(
+          Failure::Auth,
+          anyhow!("authentication rejected by server"),
+        ));
+      }
     }
   } else if let Some(password) = cli.password.as_deref() {
     match client.authenticate(password).await? {
@@ -87,80 +576,1798 @@ async fn authenticate_if_required(
   Ok(())
 }
 
+/// Time `--ping-count` no-op round trips and summarise them.
+async fn run_ping(
+  count: u32,
+  client: &mut RconClient,
+  use_color: bool,
+) -> Result<i32> {
+  let mut samples = Vec::with_capacity(count as usize);
+  for sequence in 1..=count {
+    if sequence > 1 {
+      sleep(PING_INTERVAL).await;
+    }
+    let rtt = client.ping().await?;
+    ui::render_ping(sequence, rtt, use_color);
+    samples.push(rtt);
+  }
+
+  ui::render_ping_summary(&samples, use_color);
+  Ok(0)
+}
+
 async fn run_one_shot(
   cli: &Cli,
+  words: &[String],
+  client: &mut RconClient,
+  use_color: bool,
+) -> Result<i32> {
+  let command = one_shot_command(cli, words)?;
+  let mut backoff = retry_backoff(cli);
+  let mut attempt = 0;
+  let outcome = loop {
+    let result = execute(cli, client, &command, use_color).await;
+    if attempt >= cli.retries || !is_worth_retrying(cli, &result) {
+      break result?;
+    }
+    attempt += 1;
+    let delay = backoff.next_delay();
+    match &result {
+      Ok(_) => {
+        tracing::warn!(attempt, "`{command}` was rejected; retrying")
+      }
+      Err(err) => tracing::warn!(attempt, "{err:#}; retrying"),
+    }
+    sleep(delay).await;
+    if result.is_err() {
+      // A late reply would be taken for the answer to the retry.
+      *client = establish(cli).await?;
+    }
+  };
+
+  check_expectations(cli, &outcome)
+    .map_err(|err| Failed::tag(Failure::Expect, err))?;
+  match outcome {
+    CommandOutcome::Response(response)
+      if matches!(response.status, ResponseStatus::Err)
+        && cli.expect_status != Some(ExpectStatus::Err) =>
+    {
+      Ok(cli.exit_code_map.code(Failure::Command))
+    }
+    _ => Ok(0),
+  }
+}
+
+/// Fail unless the one-shot command's `outcome` meets `--expect-status`
+/// and `--expect`.
+fn check_expectations(cli: &Cli, outcome: &CommandOutcome) -> Result<()> {
+  let response = match outcome {
+    CommandOutcome::Response(response) => Some(response),
+    CommandOutcome::Bye => None,
+  };
+
+  if let Some(expected) = cli.expect_status {
+    let (status, name) = match expected {
+      ExpectStatus::Ok => (ResponseStatus::Ok, "ok"),
+      ExpectStatus::Err => (ResponseStatus::Err, "err"),
+    };
+    if response.is_none_or(|response| response.status != status) {
+      bail!("expected an `{name}` response");
+    }
+  }
+  if let Some(pattern) = &cli.expect {
+    let matched = response.is_some_and(|response| {
+      // Match the text as displayed, without formatting codes.
+      response.payload.iter().any(|line| {
+        pattern.is_match(&translate_section_codes(line, false))
+      })
+    });
+    if !matched {
+      bail!("no response line matched `{pattern}`");
+    }
+  }
+  Ok(())
+}
+
+/// Waits between `--retries` attempts: `--retry-delay-ms`, doubling up
+/// to [`RETRY_MAX_DELAY`].
+fn retry_backoff(cli: &Cli) -> Backoff {
+  Backoff::new(Duration::from_millis(cli.retry_delay_ms), RETRY_MAX_DELAY)
+}
+
+/// Whether a one-shot command that ended in `result` should be sent
+/// again: it timed out, or `--retry-on` matches its rejection.
+fn is_worth_retrying(cli: &Cli, result: &Result<CommandOutcome>) -> bool {
+  match result {
+    Ok(CommandOutcome::Response(response))
+      if matches!(response.status, ResponseStatus::Err) =>
+    {
+      cli.retry_on.as_ref().is_some_and(|pattern| {
+        response
+          .error
+          .iter()
+          .chain(&response.payload)
+          .any(|text| pattern.is_match(text))
+      })
+    }
+    Ok(_) => false,
+    Err(err) => Failure::of_session(err) == Failure::Timeout,
+  }
+}
+
+/// Send the one-shot command every `interval` until interrupted or the
+/// server ends the session, reconnecting if `--reconnect` allows.
+async fn run_watch(
+  cli: &Cli,
+  words: &[String],
+  interval: Duration,
   client: &mut RconClient,
   use_color: bool,
 ) -> Result<i32> {
-  let command_text = cli.command.join(" ");
+  let command = one_shot_command(cli, words)?;
+  let clear = cli.clear && cli.decorated() && io::stdout().is_terminal();
+  let mut next_cycle = Instant::now();
+  loop {
+    if clear {
+      ui::render_watch_header(interval, &command, use_color);
+    }
+    match execute(cli, client, &command, use_color).await {
+      Ok(CommandOutcome::Response(_)) => {}
+      Ok(CommandOutcome::Bye) => return Ok(0),
+      Err(err) if is_connection_lost(client, &err) => {
+        recover(cli, client, err, use_color).await?;
+      }
+      Err(err) => return Err(err),
+    }
+    // Keep a steady cadence however long the command took.
+    next_cycle += interval;
+    sleep_until(next_cycle.max(Instant::now())).await;
+  }
+}
+
+/// The one-shot command given as `words`, with its placeholders filled.
+fn one_shot_command(cli: &Cli, words: &[String]) -> Result<String> {
+  let command_text = words.join(" ");
   let command = command::sanitize(&command_text).ok_or_else(|| {
     anyhow!("command was empty after trimming whitespace")
   })?;
+  Ok(command::expand(&command, &cli.vars)?)
+}
 
-  match client.send_command(&command).await? {
-    CommandOutcome::Response(response) => {
-      ui::render_response(&command, &response, use_color);
-      if matches!(response.status, ResponseStatus::Err) {
-        Ok(2)
-      } else {
-        Ok(0)
+/// Read the script at `path`, or stdin for `-`, and fill in the
+/// placeholders of its commands from `vars`, each labelled with its line.
+async fn read_script(
+  path: &Path,
+  vars: &[(String, String)],
+) -> Result<Vec<(String, String)>> {
+  let contents = if path == Path::new("-") {
+    let mut contents = String::new();
+    tokio::io::stdin()
+      .read_to_string(&mut contents)
+      .await
+      .context("failed to read the script from stdin")?;
+    contents
+  } else {
+    tokio::fs::read_to_string(path)
+      .await
+      .with_context(|| format!("failed to read {}", path.display()))?
+  };
+
+  command::script_lines(&contents)
+    .map(|(line, command)| {
+      let label = format!("{}:{line}", path.display());
+      let command =
+        command::expand(&command, vars).with_context(|| label.clone())?;
+      Ok((label, command))
+    })
+    .collect()
+}
+
+/// Make sure each of `commands` that matches a `confirm` pattern of the
+/// config file is meant, asking on the terminal before connecting to
+/// `target`, or failing without one.
+async fn confirm_planned(
+  cli: &Cli,
+  config: &Config,
+  commands: &[String],
+  target: &str,
+  use_color: bool,
+) -> Result<()> {
+  if cli.yes {
+    return Ok(());
+  }
+  for command in commands {
+    let Some(pattern) = config.confirmation_pattern(command) else {
+      continue;
+    };
+    if !io::stdin().is_terminal() {
+      bail!(
+        "`{command}` matches the confirm pattern `{pattern}`; pass --yes \
+         to send it"
+      );
+    }
+    let question = format!("Send `{command}` to `{target}`?");
+    if !ui::confirm(&question, use_color)
+      .await
+      .context("failed to read the answer")?
+    {
+      bail!("`{command}` was not confirmed");
+    }
+  }
+  Ok(())
+}
+
+/// Check and fill in the placeholders of the `-c` commands, each
+/// labelled with its position.
+fn prepare_commands(
+  cli: &Cli,
+  commands: &[String],
+) -> Result<Vec<(String, String)>> {
+  commands
+    .iter()
+    .enumerate()
+    .map(|(index, command)| {
+      let label = format!("command {}", index + 1);
+      let command = command::sanitize(command)
+        .with_context(|| format!("{label} is empty"))?;
+      let command = command::expand(&command, &cli.vars)
+        .with_context(|| label.clone())?;
+      Ok((label, command))
+    })
+    .collect()
+}
+
+/// Run the `commands` of a batch in order, stopping early if the server
+/// ends the session or `--on-error` says so. Each command comes with a
+/// label saying where it was given, for error reports.
+async fn run_batch(
+  cli: &Cli,
+  commands: &[(String, String)],
+  client: &mut RconClient,
+  use_color: bool,
+) -> Result<i32> {
+  let mut batch = Batch::default();
+  for (label, command) in commands {
+    batch.pace(cli).await;
+    let outcome = match execute(cli, client, command, use_color).await {
+      Ok(outcome) => outcome,
+      Err(err) => {
+        let err = err.context(format!("{label}: `{command}`"));
+        if client.is_closed() || cli.on_error == OnError::Stop {
+          return Err(err);
+        }
+        batch.fail(cli, &err);
+        if keep_going(cli, command, use_color).await? {
+          continue;
+        }
+        break;
+      }
+    };
+    match outcome {
+      CommandOutcome::Response(response)
+        if matches!(response.status, ResponseStatus::Err) =>
+      {
+        batch.rejected = true;
+        if !keep_going(cli, command, use_color).await? {
+          break;
+        }
+      }
+      CommandOutcome::Response(_) => {}
+      CommandOutcome::Bye => break,
+    }
+  }
+
+  Ok(batch.exit_code(cli))
+}
+
+/// Failures seen while running a batch of commands.
+#[derive(Debug, Default)]
+struct Batch {
+  /// A command was answered with an error status.
+  rejected: bool,
+  /// The first command that got no answer, e.g. because it timed out.
+  failed: Option<Failure>,
+  /// A command has been sent, so the next one waits for `--delay-ms`.
+  started: bool,
+}
+
+impl Batch {
+  /// Give the server `--delay-ms` to breathe before every command but
+  /// the first.
+  async fn pace(&mut self, cli: &Cli) {
+    if self.started && cli.delay_ms > 0 {
+      sleep(Duration::from_millis(cli.delay_ms)).await;
+    }
+    self.started = true;
+  }
+
+  /// Note a command that failed without ending the session.
+  fn fail(&mut self, cli: &Cli, err: &anyhow::Error) {
+    self.failed.get_or_insert(Failure::of_session(err));
+    // JSON output already carries the error in the command's record.
+    if cli.output == OutputFormat::Text {
+      runtime::report_error(err);
+    }
+  }
+
+  /// The status of the first command that failed, otherwise the one
+  /// for rejected commands if any was.
+  fn exit_code(&self, cli: &Cli) -> i32 {
+    let codes = &cli.exit_code_map;
+    match (self.failed, self.rejected) {
+      (Some(failure), _) => codes.code(failure),
+      (None, true) => codes.code(Failure::Command),
+      (None, false) => 0,
+    }
+  }
+}
+
+/// Whether a batch runs the commands after the failed `command`.
+async fn keep_going(
+  cli: &Cli,
+  command: &str,
+  use_color: bool,
+) -> Result<bool> {
+  match cli.on_error {
+    OnError::Stop => Ok(false),
+    OnError::Continue => Ok(true),
+    OnError::Prompt => ui::confirm(
+      &format!("`{command}` failed; run the remaining commands?"),
+      use_color,
+    )
+    .await
+    .context("failed to read the answer"),
+  }
+}
+
+/// Send `command` and print its outcome along with anything the server
+/// pushed meanwhile. Failures are reported in JSON output before being
+/// returned.
+async fn execute(
+  cli: &Cli,
+  client: &mut RconClient,
+  command: &str,
+  use_color: bool,
+) -> Result<CommandOutcome> {
+  let started = Instant::now();
+  let outcome = match client.send_command(command).await {
+    Ok(outcome) => outcome,
+    Err(err) => {
+      let err = anyhow::Error::from(err);
+      output_log::failure(command, &err);
+      let record =
+        CommandRecord::failed(command, &err, started.elapsed(), client);
+      transcript::command(&record);
+      if cli.output == OutputFormat::Json {
+        ui::render_json_record(&record);
       }
+      return Err(err);
     }
-    CommandOutcome::Bye => {
-      ui::render_bye(use_color);
-      Ok(0)
+  };
+  for message in client.take_messages() {
+    render_message(cli, &message, use_color);
+  }
+  render_outcome(cli, command, &outcome, started, client, use_color);
+  Ok(outcome)
+}
+
+/// Print the outcome of `command`, sent at `started`, in the selected
+/// output format.
+fn render_outcome(
+  cli: &Cli,
+  command: &str,
+  outcome: &CommandOutcome,
+  started: Instant,
+  client: &RconClient,
+  use_color: bool,
+) {
+  output_log::exchange(command, outcome);
+  let record =
+    CommandRecord::new(command, outcome, started.elapsed(), client);
+  transcript::command(&record);
+  match (cli.output, outcome) {
+    (OutputFormat::Json, _) => ui::render_json_record(&record),
+    (OutputFormat::Text, CommandOutcome::Response(response))
+      if cli.raw =>
+    {
+      ui::render_raw(response);
+    }
+    (OutputFormat::Text, CommandOutcome::Response(response))
+      if cli.quiet =>
+    {
+      ui::render_payload(response, cli.timestamps(), use_color);
+    }
+    (OutputFormat::Text, CommandOutcome::Response(response)) => {
+      ui::render_response(
+        command,
+        response,
+        cli.timestamps(),
+        cli.tables,
+        use_color,
+      );
     }
+    (OutputFormat::Text, CommandOutcome::Bye) if cli.quiet => {}
+    (OutputFormat::Text, CommandOutcome::Bye) => ui::render_bye(use_color),
+  }
+}
+
+/// Print a message pushed by the server in the selected output format.
+fn render_message(cli: &Cli, message: &ServerMessage, use_color: bool) {
+  output_log::message(message);
+  transcript::message(message);
+  match cli.output {
+    OutputFormat::Json => ui::render_json_message(message),
+    // Pushed messages are not payload.
+    OutputFormat::Text if cli.quiet => {}
+    OutputFormat::Text => {
+      ui::render_server_message(message, cli.timestamps(), use_color);
+    }
+  }
+}
+
+/// Where the shell reads its commands from.
+enum Input {
+  /// The line editor, when typing at a terminal.
+  Editor(LineEditor),
+  /// Plain lines, when piped or without a prompt.
+  Lines(mpsc::Receiver<io::Result<String>>),
+}
+
+impl Input {
+  async fn recv(&mut self) -> Option<io::Result<String>> {
+    match self {
+      Self::Editor(editor) => editor.recv().await,
+      Self::Lines(lines) => lines.recv().await,
+    }
+  }
+}
+
+/// Variables from `:set`, shared by every session of the shell.
+#[derive(Debug, Default)]
+struct ShellVars(Vec<(String, String)>);
+
+impl ShellVars {
+  /// Give `name` a new value, replacing any earlier one.
+  fn set(&mut self, name: String, value: String) {
+    self.0.retain(|(set, _)| *set != name);
+    self.0.push((name, value));
+  }
+
+  /// The `--var` values followed by the `:set` ones, so that the latter
+  /// win in placeholders.
+  fn with_flags(&self, cli: &Cli) -> Vec<(String, String)> {
+    [cli.vars.as_slice(), &self.0].concat()
+  }
+}
+
+/// A shell session other than the active one.
+struct Session {
+  cli: Cli,
+  client: RconClient,
+}
+
+/// State of the interactive shell kept across its commands and sessions.
+/// The active session's settings and client are passed alongside, since
+/// `:switch` swaps them out.
+struct ShellState<'a> {
+  base: &'a Cli,
+  config: &'a Config,
+  /// Name of the active session.
+  active: String,
+  /// Open sessions other than the active one, by name.
+  parked: BTreeMap<String, Session>,
+  timeout: Duration,
+  use_color: bool,
+  /// Whether a prompt is shown; JSON and payload-only output have none.
+  prompt: bool,
+  lines: Input,
+  interrupts: Interrupts,
+  /// Commands of each server the shell has been on, for tab completion.
+  known_commands: HashMap<(String, u16), CommandList>,
+  /// `help COMMAND` output of each session, for `:describe` and hints.
+  help_caches: HashMap<String, HelpCache>,
+  shell_vars: ShellVars,
+  /// Lines of a running macro, and when the next one may run.
+  queued: VecDeque<String>,
+  resume_at: Instant,
+}
+
+impl ShellState<'_> {
+  /// Run a `:` command typed at the shell, with `cli` and `client` the
+  /// active session's. Breaks when the shell should end.
+  async fn run_meta(
+    &mut self,
+    meta: MetaCommand,
+    cli: &mut Cli,
+    client: &mut RconClient,
+  ) -> Result<ControlFlow<()>> {
+    let result = match meta {
+      MetaCommand::QuitLocal => return Ok(ControlFlow::Break(())),
+      MetaCommand::Source(path) => {
+        let vars = self.shell_vars.with_flags(cli);
+        let sourced = match read_script(&path, &vars).await {
+          Ok(commands) => {
+            source_script(
+              cli,
+              self.config,
+              &commands,
+              &self.active,
+              client,
+              self.use_color,
+              &mut self.interrupts,
+            )
+            .await
+          }
+          Err(err) => Err(err),
+        };
+        if client.is_closed() && !cli.reconnect {
+          return Ok(ControlFlow::Break(()));
+        }
+        sourced
+      }
+      MetaCommand::Shell(command) => {
+        run_local(&command, &mut self.interrupts).await
+      }
+      MetaCommand::Help => {
+        ui::render_meta_help(self.use_color);
+        Ok(())
+      }
+      MetaCommand::Status => {
+        ui::render_status(client, self.timeout, self.use_color);
+        Ok(())
+      }
+      MetaCommand::Timeout(None) => {
+        ui::render_notice(
+          &format!("Timeout is {:.1}s", self.timeout.as_secs_f64()),
+          self.use_color,
+        );
+        Ok(())
+      }
+      MetaCommand::Timeout(Some(duration)) => {
+        self.timeout = duration;
+        client.set_timeout(self.timeout);
+        ui::render_notice(
+          &format!("Timeout set to {:.1}s", self.timeout.as_secs_f64()),
+          self.use_color,
+        );
+        Ok(())
+      }
+      MetaCommand::Plain => {
+        self.use_color = !self.use_color;
+        if let Input::Editor(editor) = &self.lines {
+          editor.set_color(self.use_color);
+        }
+        let state = if self.use_color { "on" } else { "off" };
+        ui::render_notice(&format!("Colors {state}"), self.use_color);
+        Ok(())
+      }
+      MetaCommand::Connect(name) => {
+        if name == self.active || self.parked.contains_key(&name) {
+          Err(anyhow!(
+            "already connected to `{name}`; use `:switch {name}`"
+          ))
+        } else {
+          let opened = tokio::select! {
+            opened = open_session(self.base, &name) => opened,
+            _ = self.interrupts.recv() => {
+              Err(anyhow!("connecting to `{name}` was cancelled"))
+            }
+          };
+          opened.map(|session| {
+            record_event(&format!("connected to `{name}`"));
+            if self.prompt {
+              ui::render_greeting(
+                session.client.greeting(),
+                self.use_color,
+              );
+            }
+            activate(
+              name,
+              session,
+              &mut self.active,
+              cli,
+              client,
+              &mut self.parked,
+            );
+          })
+        }
+      }
+      MetaCommand::Switch(None) => {
+        let sessions = std::iter::once((self.active.as_str(), &*client))
+          .chain(
+            self
+              .parked
+              .iter()
+              .map(|(name, session)| (name.as_str(), &session.client)),
+          );
+        ui::render_sessions(&self.active, sessions, self.use_color);
+        Ok(())
+      }
+      MetaCommand::Switch(Some(name)) if name == self.active => Ok(()),
+      MetaCommand::Switch(Some(name)) => match self.parked.remove(&name) {
+        Some(session) => {
+          activate(
+            name,
+            session,
+            &mut self.active,
+            cli,
+            client,
+            &mut self.parked,
+          );
+          Ok(())
+        }
+        None => Err(anyhow!("no session named `{name}`")),
+      },
+      MetaCommand::Reconnect => {
+        reconnect(cli, client, &self.active, &mut self.interrupts)
+          .await
+          .map(|()| {
+            if self.prompt {
+              ui::render_greeting(client.greeting(), self.use_color);
+            }
+          })
+      }
+      MetaCommand::Set(name, value) => {
+        self.shell_vars.set(name, value);
+        Ok(())
+      }
+      MetaCommand::Vars => {
+        // Later values win, as in placeholders.
+        let vars = self.shell_vars.with_flags(cli);
+        let vars: BTreeMap<_, _> = vars
+          .iter()
+          .map(|(name, value)| (name.as_str(), value.as_str()))
+          .collect();
+        ui::render_vars(&vars, self.use_color);
+        Ok(())
+      }
+      MetaCommand::Record(None) => {
+        let state = match transcript::path() {
+          Some(path) => format!("Recording to {}", path.display()),
+          None => "Not recording".to_string(),
+        };
+        ui::render_notice(&state, self.use_color);
+        Ok(())
+      }
+      MetaCommand::Record(Some(path)) => {
+        transcript::start(&path).map(|()| {
+          transcript::session(client);
+          ui::render_notice(
+            &format!("Recording to {}", path.display()),
+            self.use_color,
+          );
+        })
+      }
+      MetaCommand::StopRecording => {
+        let state = match transcript::stop() {
+          Some(path) => {
+            format!("Stopped recording to {}", path.display())
+          }
+          None => "Not recording".to_string(),
+        };
+        ui::render_notice(&state, self.use_color);
+        Ok(())
+      }
+      MetaCommand::Run(name) => {
+        self.config.macro_steps(&name).map(|steps| {
+          self.queued.extend(steps);
+          self.resume_at = Instant::now();
+        })
+      }
+      MetaCommand::Describe(verb) => {
+        let verb = verb.trim_start_matches('/').to_string();
+        let server = (client.host().to_string(), client.port());
+        let cache = self
+          .help_caches
+          .entry(self.active.clone())
+          .or_insert_with(|| HelpCache::load(&self.active));
+        let help =
+          match (cache.get(&verb), self.known_commands.get(&server)) {
+            (Some(help), _) => Ok(help.to_vec()),
+            (None, Some(commands))
+              if !commands.is_empty() && !commands.contains(&verb) =>
+            {
+              Err(anyhow!("the server has no `{verb}` command"))
+            }
+            (None, _) => {
+              let help = fetch_help(client, &verb).await;
+              for message in client.take_messages() {
+                render_message(cli, &message, self.use_color);
+              }
+              help.inspect(|help| cache.insert(&verb, help.clone()))
+            }
+          };
+        help.map(|help| {
+          ui::render_description(&help, self.use_color);
+          if let Some(commands) = self.known_commands.get_mut(&server) {
+            commands.describe(&verb, &help);
+            if let Input::Editor(editor) = &self.lines {
+              editor.set_commands(commands.clone());
+            }
+          }
+        })
+      }
+      MetaCommand::RefreshCompletions => {
+        match &self.lines {
+          Input::Editor(editor) => {
+            // Plugins may have changed what the cached help says too.
+            self
+              .help_caches
+              .entry(self.active.clone())
+              .or_insert_with(|| HelpCache::load(&self.active))
+              .clear();
+            let commands = learn_commands(client).await;
+            ui::render_notice(
+              &format!("Learned {} server commands", commands.len()),
+              self.use_color,
+            );
+            editor.set_commands(commands.clone());
+            self.known_commands.insert(
+              (client.host().to_string(), client.port()),
+              commands,
+            );
+          }
+          Input::Lines(_) => ui::render_notice(
+            "Tab completion needs an interactive terminal",
+            self.use_color,
+          ),
+        }
+        Ok(())
+      }
+    };
+    result.map(|()| ControlFlow::Continue(()))
   }
 }
 
 async fn run_interactive(
+  cli: &Cli,
+  base: &Cli,
+  config: &Config,
+  profile: Option<&str>,
   client: &mut RconClient,
   use_color: bool,
 ) -> Result<i32> {
-  let mut stdin = BufReader::new(tokio::io::stdin());
+  let history = cli.history_path(profile);
+  // The active session's settings, swapped out by `:switch`.
+  let mut active_cli = cli.clone();
+  let cli = &mut active_cli;
+  let keepalive = cli.keepalive_interval.map(Duration::from_secs);
   let mut stdout = tokio::io::stdout();
-  let mut input = String::new();
-  let mut exit_code = 0;
+  let mut batch = Batch::default();
+  // Piped input is a batch of commands, so `--on-error` applies to it.
+  let piped = !io::stdin().is_terminal();
+  let prompt = cli.decorated();
+  let lines = if prompt && !piped && io::stdout().is_terminal() {
+    Input::Editor(LineEditor::start(history, use_color)?)
+  } else {
+    Input::Lines(spawn_stdin_reader())
+  };
+  // The editor keeps its prompt below pushed output by itself.
+  let redraw = prompt && matches!(lines, Input::Lines(_));
+  let mut shell = ShellState {
+    base,
+    config,
+    active: profile
+      .map_or_else(|| client.host().to_string(), str::to_string),
+    parked: BTreeMap::new(),
+    timeout: Duration::from_millis(cli.timeout_ms),
+    use_color,
+    prompt,
+    lines,
+    interrupts: Interrupts::install()
+      .context("failed to install Ctrl-C handler")?,
+    known_commands: HashMap::new(),
+    help_caches: HashMap::new(),
+    shell_vars: ShellVars::default(),
+    queued: VecDeque::new(),
+    resume_at: Instant::now(),
+  };
+  let mut completing = None;
+  // How long the last command sent to the server took.
+  let mut last_latency = None;
 
-  loop {
-    ui::render_prompt(&mut stdout, use_color)
-      .await
-      .context("failed to render prompt")?;
+  'session: loop {
+    // A reconnect starts over from `--timeout-ms`.
+    client.set_timeout(shell.timeout);
+    // The prompt names the active session once there are several.
+    let label =
+      (!shell.parked.is_empty()).then_some(shell.active.as_str());
+    let latency = last_latency.filter(|_| cli.show_latency);
+    match &mut shell.lines {
+      // Macro lines run without a prompt.
+      _ if !shell.queued.is_empty() => {}
+      Input::Editor(editor) => {
+        let server = (client.host().to_string(), client.port());
+        if completing.as_ref() != Some(&server) {
+          if !shell.known_commands.contains_key(&server) {
+            let mut commands = learn_commands(client).await;
+            let cache = shell
+              .help_caches
+              .entry(shell.active.clone())
+              .or_insert_with(|| HelpCache::load(&shell.active));
+            for (verb, help) in cache.entries() {
+              commands.describe(verb, help);
+            }
+            shell.known_commands.insert(server.clone(), commands);
+            for message in client.take_messages() {
+              render_message(cli, &message, shell.use_color);
+            }
+          }
+          editor.set_commands(shell.known_commands[&server].clone());
+          completing = Some(server);
+        }
+        editor.prompt(&ui::prompt(label, latency, shell.use_color));
+      }
+      Input::Lines(_) if shell.prompt => {
+        ui::render_prompt(&mut stdout, label, latency, shell.use_color)
+          .await
+          .context("failed to render prompt")?
+      }
+      Input::Lines(_) => {}
+    }
 
-    input.clear();
-    let bytes_read = stdin
-      .read_line(&mut input)
-      .await
-      .context("failed to read line from stdin")?;
+    // Wait for the next line while printing anything the server pushes in
+    // the meantime (console output, chat, keepalive-driven disconnects).
+    let mut keepalive_at =
+      keepalive.map(|interval| Instant::now() + interval);
+    let mut stepping = false;
+    let line = loop {
+      tokio::select! {
+        // Typed lines wait until a running macro is done.
+        line = shell.lines.recv(), if shell.queued.is_empty() => break line,
+        () = sleep_until(shell.resume_at), if !shell.queued.is_empty() => {
+          stepping = true;
+          break shell.queued.pop_front().map(Ok);
+        }
+        // The editor takes Ctrl-C itself while its prompt is open.
+        repeated = shell.interrupts.recv() => {
+          if redraw {
+            println!();
+          }
+          if repeated {
+            break 'session;
+          }
+          if !shell.queued.is_empty() {
+            shell.queued.clear();
+            ui::render_notice("Macro cancelled", shell.use_color);
+          } else if shell.prompt {
+            ui::render_notice("Press Ctrl-C again to leave", shell.use_color);
+          }
+          continue 'session;
+        }
+        () = sleep_until(keepalive_at.unwrap_or_else(Instant::now)),
+          if keepalive_at.is_some() && !client.is_closed() =>
+        {
+          if let Err(err) = client.keepalive().await {
+            if redraw {
+              ui::clear_prompt_line(shell.use_color);
+            }
+            let err =
+              anyhow::Error::from(err).context("connection lost while idle");
+            recover_or_interrupt(cli, client, err, shell.use_color, piped, &mut shell.interrupts)
+              .await?;
+            continue 'session;
+          }
+          keepalive_at = keepalive.map(|interval| Instant::now() + interval);
+        }
+        // A session lost without `--reconnect` waits for `:reconnect`.
+        message = client.recv_message(), if !client.is_closed() => {
+          let message = message.map_err(anyhow::Error::from);
+          match message {
+          Ok(Some(message)) if redraw && shell.queued.is_empty() => {
+            output_log::message(&message);
+            transcript::message(&message);
+            ui::clear_prompt_line(shell.use_color);
+            ui::render_server_message(
+              &message,
+              cli.timestamps(),
+              shell.use_color,
+            );
+            ui::render_prompt(&mut stdout, label, latency, shell.use_color)
+              .await
+              .context("failed to render prompt")?;
+          }
+          Ok(Some(message)) => render_message(cli, &message, shell.use_color),
+          Ok(None) => {
+            if redraw {
+              println!();
+            }
+            if !cli.reconnect {
+              record_event("session closed by server");
+              if shell.prompt {
+                ui::render_bye(shell.use_color);
+              }
+              if fall_back(&mut shell.active, cli, client, &mut shell.parked, shell.use_color) {
+                continue 'session;
+              }
+              if piped {
+                break 'session;
+              }
+              if shell.prompt {
+                ui::render_notice(
+                  "Type `:reconnect` to connect again",
+                  shell.use_color,
+                );
+              }
+              continue 'session;
+            }
+            let err = anyhow!("server closed the connection");
+            recover_or_interrupt(cli, client, err, shell.use_color, piped, &mut shell.interrupts)
+              .await?;
+            continue 'session;
+          }
+          Err(err) => {
+            if redraw {
+              ui::clear_prompt_line(shell.use_color);
+            }
+            recover_or_interrupt(cli, client, err, shell.use_color, piped, &mut shell.interrupts)
+              .await?;
+            continue 'session;
+          }
+          }
+        }
+        (name, message) = parked_message(&mut shell.parked) => match message {
+          Ok(Some(mut message)) => {
+            let kind = message.kind.as_deref().unwrap_or("Console");
+            message.kind = Some(format!("{name}/{kind}"));
+            let redraw = redraw && shell.queued.is_empty();
+            if redraw {
+              ui::clear_prompt_line(shell.use_color);
+            }
+            render_message(cli, &message, shell.use_color);
+            if redraw {
+              ui::render_prompt(&mut stdout, label, latency, shell.use_color)
+                .await
+                .context("failed to render prompt")?;
+            }
+          }
+          Ok(None) | Err(_) => {
+            shell.parked.remove(&name);
+            record_event(&format!("session `{name}` closed"));
+            if shell.prompt {
+              ui::render_notice(&format!("Session `{name}` closed"), shell.use_color);
+            }
+            continue 'session;
+          }
+        },
+      }
+    };
 
-    if bytes_read == 0 {
-      println!();
+    if let Some(Err(err)) = &line
+      && err.kind() == io::ErrorKind::Interrupted
+    {
+      if shell.interrupts.press() {
+        break;
+      }
+      continue;
+    }
+    let Some(input) =
+      line.transpose().context("failed to read line from stdin")?
+    else {
+      // The editor ends the line itself.
+      if redraw {
+        println!();
+      }
       tracing::info!("stdin closed; terminating session");
       break;
-    }
+    };
 
     let Some(command) = command::sanitize(&input) else {
       continue;
     };
+    if stepping && let Some(pause) = macro_pause(&command) {
+      if shell.prompt {
+        ui::render_notice(
+          &format!("Waiting {:.1}s", pause.as_secs_f64()),
+          shell.use_color,
+        );
+      }
+      shell.resume_at = Instant::now() + pause;
+      continue;
+    }
+    // Typing a macro's name runs it, like `:run`.
+    let command =
+      if !stepping && shell.config.macros.contains_key(&command) {
+        format!(":run {command}")
+      } else {
+        command
+      };
+
+    if let Some(meta) = MetaCommand::parse(&command) {
+      let result = match meta {
+        Ok(meta) => shell.run_meta(meta, cli, client).await,
+        Err(err) => Err(err.into()),
+      };
+      match result {
+        Ok(ControlFlow::Break(())) => break,
+        Ok(ControlFlow::Continue(())) => {}
+        Err(err) => {
+          runtime::report_error(&err);
+          abandon_macro(&mut shell.queued, shell.use_color);
+          if piped {
+            batch.failed.get_or_insert(Failure::Other);
+            if cli.on_error == OnError::Stop {
+              break;
+            }
+          }
+        }
+      }
+      continue;
+    }
 
     let exit_command = command::is_exit_command(&input);
+    let vars = shell.shell_vars.with_flags(cli);
+    let command = match command::expand(&command, &vars) {
+      Ok(command) => command,
+      Err(err) => {
+        runtime::report_error(&err.into());
+        abandon_macro(&mut shell.queued, shell.use_color);
+        if piped {
+          batch.failed.get_or_insert(Failure::Other);
+          if cli.on_error == OnError::Stop {
+            break;
+          }
+        }
+        continue;
+      }
+    };
+
+    if !cli.yes
+      && let Some(pattern) = shell.config.confirmation_pattern(&command)
+    {
+      if piped {
+        runtime::report_error(&anyhow!(
+          "`{command}` matches the confirm pattern `{pattern}`; pass \
+           --yes to send it"
+        ));
+        abandon_macro(&mut shell.queued, shell.use_color);
+        batch.failed.get_or_insert(Failure::Other);
+        if cli.on_error == OnError::Stop {
+          break;
+        }
+        continue;
+      }
+      let question = format!("Send `{command}` to `{}`?", shell.active);
+      if !ask_in_shell(&mut shell.lines, &question, shell.use_color)
+        .await?
+      {
+        ui::render_notice(
+          &format!("`{command}` was not sent"),
+          shell.use_color,
+        );
+        if !shell.queued.is_empty() {
+          shell.queued.clear();
+          ui::render_notice("Macro stopped", shell.use_color);
+        }
+        continue;
+      }
+    }
+
+    if piped {
+      batch.pace(cli).await;
+    }
+    let started = Instant::now();
+    let result = tokio::select! {
+      result = client.send_command(&command) => {
+        last_latency = Some(started.elapsed());
+        result.map_err(anyhow::Error::from)
+      }
+      repeated = shell.interrupts.recv() => {
+        // The reply is skipped when it turns up later.
+        record_event(&format!("cancelled `{command}`"));
+        abandon_macro(&mut shell.queued, shell.use_color);
+        if repeated {
+          break;
+        }
+        ui::render_notice(
+          &format!("Cancelled `{command}`; press Ctrl-C again to leave"),
+          shell.use_color,
+        );
+        continue;
+      }
+    };
+    if let Err(err) = &result {
+      output_log::failure(&command, err);
+      transcript::command(&CommandRecord::failed(
+        &command,
+        err,
+        started.elapsed(),
+        client,
+      ));
+    }
+    let outcome = match result {
+      Ok(outcome) => outcome,
+      Err(err) if is_connection_lost(client, &err) => {
+        // The command may or may not have run; never replay it blindly.
+        let err = err.context(format!(
+          "`{command}` was interrupted and may not have been executed"
+        ));
+        abandon_macro(&mut shell.queued, shell.use_color);
+        recover_or_interrupt(
+          cli,
+          client,
+          err,
+          shell.use_color,
+          piped,
+          &mut shell.interrupts,
+        )
+        .await?;
+        continue;
+      }
+      Err(err) if piped && cli.on_error == OnError::Continue => {
+        batch.fail(cli, &err);
+        abandon_macro(&mut shell.queued, shell.use_color);
+        continue;
+      }
+      Err(err) => return Err(err),
+    };
+    for message in client.take_messages() {
+      render_message(cli, &message, shell.use_color);
+    }
 
-    match client.send_command(&command).await? {
+    render_outcome(
+      cli,
+      &command,
+      &outcome,
+      started,
+      client,
+      shell.use_color,
+    );
+    match outcome {
       CommandOutcome::Response(response) => {
-        ui::render_response(&command, &response, use_color);
         if matches!(response.status, ResponseStatus::Err) {
-          exit_code = 2;
+          batch.rejected = true;
+          abandon_macro(&mut shell.queued, shell.use_color);
+          if piped && cli.on_error == OnError::Stop {
+            break;
+          }
         }
         if exit_command {
           break;
         }
       }
-      CommandOutcome::Bye => {
-        ui::render_bye(use_color);
+      CommandOutcome::Bye => break,
+    }
+  }
+
+  for session in shell.parked.values_mut() {
+    if let Err(err) = session.client.quit().await {
+      tracing::debug!(error = %err, "failed to send QUIT to a session");
+    }
+  }
+  Ok(batch.exit_code(cli))
+}
+
+/// Replace `client`, the session named `active`, with a fresh one for
+/// `:reconnect`, unless Ctrl-C is pressed first.
+async fn reconnect(
+  cli: &Cli,
+  client: &mut RconClient,
+  active: &str,
+  interrupts: &mut Interrupts,
+) -> Result<()> {
+  let fresh = tokio::select! {
+    fresh = establish(cli) => fresh?,
+    _ = interrupts.recv() => {
+      bail!("reconnecting to `{active}` was cancelled")
+    }
+  };
+  // Dropping the old client closes its socket, dead or not.
+  *client = fresh;
+  record_event("session restored");
+  Ok(())
+}
+
+/// [`recover`], unless Ctrl-C is pressed first. Without `--reconnect`,
+/// a shell at a terminal only reports the loss and stays open for
+/// `:reconnect`.
+async fn recover_or_interrupt(
+  cli: &Cli,
+  client: &mut RconClient,
+  err: anyhow::Error,
+  use_color: bool,
+  piped: bool,
+  interrupts: &mut Interrupts,
+) -> Result<()> {
+  if !cli.reconnect && !piped {
+    record_event(&format!("connection lost: {err:#}"));
+    if cli.decorated() {
+      ui::render_connection_lost(&format!("{err:#}"), use_color);
+      ui::render_notice("Type `:reconnect` to connect again", use_color);
+    } else {
+      tracing::warn!("connection lost: {err:#}");
+    }
+    return Ok(());
+  }
+  tokio::select! {
+    result = recover(cli, client, err, use_color) => result,
+    _ = interrupts.recv() => bail!("reconnecting was interrupted"),
+  }
+}
+
+/// The pause a macro's `sleep DURATION` line asks for.
+fn macro_pause(line: &str) -> Option<Duration> {
+  let (verb, duration) = line.split_once(char::is_whitespace)?;
+  (verb == "sleep").then(|| meta::parse_duration(duration.trim()))?
+}
+
+/// Run the `commands` of a script over the `active` session for
+/// `:source`, as `--script` would. Ctrl-C stops it.
+async fn source_script(
+  cli: &Cli,
+  config: &Config,
+  commands: &[(String, String)],
+  active: &str,
+  client: &mut RconClient,
+  use_color: bool,
+  interrupts: &mut Interrupts,
+) -> Result<()> {
+  let planned: Vec<_> = commands
+    .iter()
+    .map(|(_, command)| command.clone())
+    .collect();
+  confirm_planned(cli, config, &planned, active, use_color).await?;
+  let exit_code = tokio::select! {
+    result = run_batch(cli, commands, client, use_color) => result?,
+    _ = interrupts.recv() => bail!("cancelled the sourced script"),
+  };
+  if exit_code != 0 {
+    bail!("the sourced script did not run cleanly");
+  }
+  Ok(())
+}
+
+/// Run `command` with the local shell for `!COMMAND` and print what it
+/// wrote once it exits. Ctrl-C stops it.
+async fn run_local(
+  command: &str,
+  interrupts: &mut Interrupts,
+) -> Result<()> {
+  #[cfg(unix)]
+  let mut shell = tokio::process::Command::new("sh");
+  #[cfg(unix)]
+  shell.arg("-c");
+  #[cfg(windows)]
+  let mut shell = tokio::process::Command::new("cmd");
+  #[cfg(windows)]
+  shell.arg("/C");
+  let output = shell
+    .arg(command)
+    .stdin(Stdio::null())
+    .kill_on_drop(true)
+    .output();
+  let output = tokio::select! {
+    output = output => {
+      output.with_context(|| format!("failed to run `{command}`"))?
+    }
+    _ = interrupts.recv() => bail!("cancelled `!{command}`"),
+  };
+  io::stdout().write_all(&output.stdout)?;
+  io::stderr().write_all(&output.stderr)?;
+  if !output.status.success() {
+    bail!("`{command}` failed: {}", output.status);
+  }
+  Ok(())
+}
+
+/// Ask a yes/no `question` on the shell's own input. Ctrl-C and the end
+/// of input mean no.
+async fn ask_in_shell(
+  lines: &mut Input,
+  question: &str,
+  use_color: bool,
+) -> Result<bool> {
+  let question = ui::yes_no_question(question, use_color);
+  let answer = match lines {
+    Input::Editor(editor) => editor.ask(&question).await,
+    Input::Lines(lines) => {
+      print!("{question}");
+      io::stdout().flush().context("failed to ask")?;
+      lines.recv().await
+    }
+  };
+  match answer {
+    Some(Ok(answer)) => Ok(ui::is_yes(&answer)),
+    Some(Err(err)) if err.kind() == io::ErrorKind::Interrupted => {
+      Ok(false)
+    }
+    Some(Err(err)) => Err(err).context("failed to read the answer"),
+    None => Ok(false),
+  }
+}
+
+/// Drop the rest of a running macro after one of its lines failed.
+fn abandon_macro(queued: &mut VecDeque<String>, use_color: bool) {
+  if !queued.is_empty() {
+    queued.clear();
+    ui::render_notice("Macro stopped after a failed line", use_color);
+  }
+}
+
+/// Connect another shell session with the config profile `name`.
+async fn open_session(base: &Cli, name: &str) -> Result<Session> {
+  let config = Config::load_default()?;
+  let (name, profile) = config.profile(name)?;
+  let cli = base
+    .for_profile(profile)
+    .with_context(|| format!("invalid profile `{name}`"))?;
+  let client = establish(&cli).await?;
+  Ok(Session { cli, client })
+}
+
+/// Make `session` the active one under `name`, parking the session it
+/// replaces.
+fn activate(
+  name: String,
+  mut session: Session,
+  active: &mut String,
+  cli: &mut Cli,
+  client: &mut RconClient,
+  parked: &mut BTreeMap<String, Session>,
+) {
+  mem::swap(cli, &mut session.cli);
+  mem::swap(client, &mut session.client);
+  parked.insert(mem::replace(active, name), session);
+}
+
+/// Replace the active session, which the server closed, with a parked
+/// one. Returns `false` when none is left.
+fn fall_back(
+  active: &mut String,
+  cli: &mut Cli,
+  client: &mut RconClient,
+  parked: &mut BTreeMap<String, Session>,
+  use_color: bool,
+) -> bool {
+  let Some((name, session)) = parked.pop_first() else {
+    return false;
+  };
+  ui::render_notice(
+    &format!("Session `{active}` closed; switched to `{name}`"),
+    use_color,
+  );
+  *active = name;
+  *cli = session.cli;
+  *client = session.client;
+  true
+}
+
+/// Wait for a message pushed to any parked session, returning the
+/// session's name with it. Never resolves while none is parked.
+async fn parked_message(
+  parked: &mut BTreeMap<String, Session>,
+) -> (String, Result<Option<ServerMessage>>) {
+  if parked.is_empty() {
+    return std::future::pending().await;
+  }
+  let pending = parked.iter_mut().map(|(name, session)| {
+    Box::pin(async move {
+      let message = session.client.recv_message().await;
+      (name.clone(), message.map_err(anyhow::Error::from))
+    })
+  });
+  select_all(pending).await.0
+}
+
+/// Ask the server which commands it has, for tab completion. Servers
+/// without a usable help command complete nothing.
+async fn learn_commands(client: &mut RconClient) -> CommandList {
+  let Some(help) = client.protocol().help_command() else {
+    return CommandList::default();
+  };
+  match client.send_command(help).await {
+    Ok(CommandOutcome::Response(response))
+      if response.status == ResponseStatus::Ok =>
+    {
+      let commands = CommandList::parse_help(&response.payload);
+      tracing::debug!(
+        count = commands.len(),
+        "learned commands from `{help}`"
+      );
+      commands
+    }
+    Ok(_) => {
+      tracing::debug!("`{help}` was rejected; completing nothing");
+      CommandList::default()
+    }
+    Err(err) => {
+      tracing::debug!("failed to list server commands: {err:#}");
+      CommandList::default()
+    }
+  }
+}
+
+/// Ask the server for its help on `verb`.
+async fn fetch_help(
+  client: &mut RconClient,
+  verb: &str,
+) -> Result<Vec<String>> {
+  let protocol = client.protocol();
+  let Some(help) = protocol.describe_command(verb) else {
+    bail!("{protocol} servers have no help command to ask");
+  };
+  match client.send_command(&help).await? {
+    CommandOutcome::Response(response)
+      if response.status == ResponseStatus::Ok
+        && !response.payload.is_empty() =>
+    {
+      Ok(response.payload)
+    }
+    CommandOutcome::Response(_) => {
+      bail!("the server has no help for `{verb}`")
+    }
+    CommandOutcome::Bye => bail!("the server closed the session"),
+  }
+}
+
+/// Read stdin lines on a background task so the REPL can race user input
+/// against server-initiated messages without losing partially read lines.
+fn spawn_stdin_reader() -> mpsc::Receiver<io::Result<String>> {
+  let (sender, receiver) = mpsc::channel(1);
+
+  tokio::spawn(async move {
+    let mut stdin = BufReader::new(tokio::io::stdin());
+    loop {
+      let mut line = String::new();
+      let item = match stdin.read_line(&mut line).await {
+        Ok(0) => break,
+        Ok(_) => Ok(line),
+        Err(err) => Err(err),
+      };
+      let failed = item.is_err();
+      if sender.send(item).await.is_err() || failed {
         break;
       }
     }
+  });
+
+  receiver
+}
+
+#[cfg(test)]
+mod tests {
+  use clap::Parser;
+
+  use super::*;
+  use crate::protocol::Protocol;
+  use crate::transport::{RconResponse, TimedOut};
+
+  fn parse(args: &[&str]) -> Cli {
+    Cli::parse_from(std::iter::once("hyrcon-client").chain(args.to_vec()))
   }
 
-  Ok(exit_code)
+  /// Interrupts that never fire, leaving the process's Ctrl-C alone.
+  fn no_interrupts() -> Interrupts {
+    Interrupts::from_channel(mpsc::unbounded_channel().1)
+  }
+
+  fn rejected(error: &str, payload: &[&str]) -> Result<CommandOutcome> {
+    Ok(CommandOutcome::Response(RconResponse {
+      status: ResponseStatus::Err,
+      payload: payload.iter().map(ToString::to_string).collect(),
+      error: Some(error.to_string()),
+      raw: String::new(),
+    }))
+  }
+
+  #[test]
+  fn retries_back_off_up_to_the_cap() {
+    let mut backoff = retry_backoff(&parse(&[]));
+    let delays: Vec<_> = (0..3).map(|_| backoff.next_delay()).collect();
+    assert_eq!(delays, [1, 2, 4].map(Duration::from_secs));
+
+    let mut backoff = retry_backoff(&parse(&["--retry-delay-ms", "8000"]));
+    let delays: Vec<_> = (0..4).map(|_| backoff.next_delay()).collect();
+    assert_eq!(delays, [8, 16, 30, 30].map(Duration::from_secs));
+  }
+
+  #[test]
+  fn retries_follow_timeouts_and_matching_rejections() {
+    let cli =
+      parse(&["--retries", "3", "--retry-on", "(?i)busy|try again"]);
+    assert!(is_worth_retrying(&cli, &rejected("Server busy", &[])));
+    assert!(is_worth_retrying(
+      &cli,
+      &rejected("failed", &["saving...", "please try again"])
+    ));
+    assert!(!is_worth_retrying(&cli, &rejected("Unknown command", &[])));
+
+    let answered = Ok(CommandOutcome::Response(RconResponse {
+      status: ResponseStatus::Ok,
+      payload: vec!["busy".to_string()],
+      error: None,
+      raw: String::new(),
+    }));
+    assert!(!is_worth_retrying(&cli, &answered));
+    assert!(!is_worth_retrying(&cli, &Ok(CommandOutcome::Bye)));
+
+    let timed_out = anyhow::Error::new(TimedOut::new(
+      "reading block from server",
+      Duration::from_secs(5),
+    ));
+    assert!(is_worth_retrying(&cli, &Err(timed_out)));
+    assert!(!is_worth_retrying(&cli, &Err(anyhow!("connection reset"))));
+
+    let cli = parse(&["--retries", "3"]);
+    assert!(!is_worth_retrying(&cli, &rejected("Server busy", &[])));
+  }
+
+  #[test]
+  fn command_flags_are_labelled_and_expanded() {
+    let cli = parse(&[
+      "--var",
+      "player=Steve",
+      "-c",
+      "say hi {player}\n",
+      "--command",
+      "save-all",
+    ]);
+    let batch = prepare_commands(&cli, &cli.commands).expect("commands");
+    assert_eq!(
+      batch,
+      [
+        ("command 1".to_string(), "say hi Steve".to_string()),
+        ("command 2".to_string(), "save-all".to_string()),
+      ]
+    );
+
+    let cli = parse(&["-c", "list", "-c", "  "]);
+    let err = prepare_commands(&cli, &cli.commands).expect_err("blank");
+    assert_eq!(err.to_string(), "command 2 is empty");
+
+    for args in [
+      &["-c", "list", "status"][..],
+      &["-c", "list", "--script", "nightly.rcon"],
+      &["-c", "list", "--ping"],
+    ] {
+      let args = std::iter::once(&"hyrcon-client").chain(args);
+      assert!(Cli::try_parse_from(args).is_err());
+    }
+  }
+
+  #[tokio::test]
+  async fn watch_repeats_until_the_server_says_bye() {
+    use crate::testing::{MockHyrconServer, Reply};
+
+    let server = MockHyrconServer::builder()
+      .expect("LIST", Reply::ok(["Steve"]))
+      .expect("LIST", Reply::ok(["Steve", "Alex"]))
+      .expect("LIST", Reply::bye())
+      .start()
+      .await
+      .expect("start");
+    let mut client = RconClient::connect(
+      Protocol::Hyrcon,
+      "127.0.0.1",
+      server.port(),
+      Duration::from_secs(2),
+    )
+    .await
+    .expect("connect");
+
+    let cli = parse(&["--watch", "1", "LIST"]);
+    let interval = Duration::from_millis(20);
+    let started = Instant::now();
+    let code = run_watch(&cli, &cli.command, interval, &mut client, false)
+      .await
+      .expect("watch");
+    assert_eq!(code, 0);
+    assert!(started.elapsed() >= interval * 2, "cycles are paced");
+    server.finish().await.expect("script followed");
+
+    for args in [
+      &["--watch", "0", "LIST"][..],
+      &["--clear", "LIST"],
+      &["--watch", "5", "--script", "nightly.rcon"],
+    ] {
+      let args = std::iter::once(&"hyrcon-client").chain(args);
+      assert!(Cli::try_parse_from(args).is_err());
+    }
+  }
+
+  #[test]
+  fn expectations_check_status_and_payload() {
+    let response = |status, lines: &[&str]| {
+      CommandOutcome::Response(RconResponse {
+        status,
+        payload: lines.iter().map(ToString::to_string).collect(),
+        error: None,
+        raw: String::new(),
+      })
+    };
+    let online = response(ResponseStatus::Ok, &["\u{a7}aonline: 3"]);
+
+    let cli = parse(&["--expect", "^online: [1-9]", "list"]);
+    check_expectations(&cli, &online).expect("formatting is ignored");
+    let err =
+      check_expectations(&cli, &response(ResponseStatus::Ok, &["0"]))
+        .expect_err("no match");
+    assert_eq!(
+      err.to_string(),
+      "no response line matched `^online: [1-9]`"
+    );
+    assert!(check_expectations(&cli, &CommandOutcome::Bye).is_err());
+
+    let cli = parse(&["--expect-status", "err", "kick Notch"]);
+    check_expectations(&cli, &response(ResponseStatus::Err, &[]))
+      .expect("rejection expected");
+    let err = check_expectations(&cli, &online).expect_err("accepted");
+    assert_eq!(err.to_string(), "expected an `err` response");
+
+    let cli = parse(&["--expect-status", "ok", "--expect", "x", "list"]);
+    assert!(
+      check_expectations(&cli, &response(ResponseStatus::Err, &["x"]))
+        .is_err()
+    );
+    assert_eq!(parse(&[]).exit_code_map.code(Failure::Expect), 7);
+  }
+
+  #[tokio::test]
+  async fn lost_sessions_wait_for_reconnect() {
+    use crate::testing::{MockHyrconServer, Reply};
+
+    let lost = MockHyrconServer::builder()
+      .expect("LIST", Reply::close())
+      .start()
+      .await
+      .expect("start");
+    let fresh = MockHyrconServer::builder()
+      .expect("LIST", Reply::ok(["Steve"]))
+      .start()
+      .await
+      .expect("start");
+    let mut client = RconClient::connect(
+      Protocol::Hyrcon,
+      "127.0.0.1",
+      lost.port(),
+      Duration::from_secs(2),
+    )
+    .await
+    .expect("connect");
+    let mut interrupts = no_interrupts();
+
+    assert!(client.send_command("LIST").await.is_err());
+    assert!(client.is_closed());
+    lost.finish().await.expect("script followed");
+
+    let port = fresh.port().to_string();
+    let cli = parse(&[
+      "--protocol",
+      "hyrcon",
+      "--host",
+      "127.0.0.1",
+      "--port",
+      &port,
+      "--plain",
+    ]);
+    let err = anyhow!("server closed the connection");
+    recover_or_interrupt(
+      &cli,
+      &mut client,
+      err,
+      false,
+      false,
+      &mut interrupts,
+    )
+    .await
+    .expect("the shell stays open");
+    assert!(client.is_closed(), "no reconnect without --reconnect");
+
+    reconnect(&cli, &mut client, "default", &mut interrupts)
+      .await
+      .expect("reconnect");
+    assert!(!client.is_closed());
+    assert_eq!(client.port(), fresh.port());
+    client.send_command("LIST").await.expect("list");
+    client.quit().await.expect("quit");
+    fresh.finish().await.expect("script followed");
+  }
+
+  #[test]
+  fn set_variables_fill_later_commands() {
+    let cli = parse(&["--var", "player=Alex", "--var", "world=nether"]);
+    let mut shell_vars = ShellVars::default();
+    for line in [":set player=Steve", ":set reason = griefing at spawn"] {
+      let Some(Ok(MetaCommand::Set(name, value))) =
+        MetaCommand::parse(line)
+      else {
+        panic!("`{line}` is a :set");
+      };
+      shell_vars.set(name, value);
+    }
+    shell_vars.set("reason".to_string(), "spam".to_string());
+
+    let vars = shell_vars.with_flags(&cli);
+    assert_eq!(
+      command::expand("kick $player $reason in {world}", &vars).unwrap(),
+      "kick Steve spam in nether"
+    );
+    assert_eq!(
+      command::expand("pay $player $5 {{player}}", &vars).unwrap(),
+      "pay Steve $5 {player}"
+    );
+    assert_eq!(
+      vars.iter().filter(|(name, _)| name == "reason").count(),
+      1,
+      "setting again replaces the value"
+    );
+  }
+
+  #[cfg(unix)]
+  #[tokio::test]
+  async fn local_commands_report_their_exit_status() {
+    let mut interrupts = no_interrupts();
+    run_local("printf 'local ok\\n'", &mut interrupts)
+      .await
+      .expect("succeeds");
+
+    let Some(Ok(MetaCommand::Shell(command))) =
+      MetaCommand::parse("!echo oops >&2; exit 3")
+    else {
+      panic!("a shell escape");
+    };
+    let err = run_local(&command, &mut interrupts)
+      .await
+      .expect_err("exit status 3");
+    assert_eq!(
+      err.to_string(),
+      "`echo oops >&2; exit 3` failed: exit status: 3"
+    );
+  }
+
+  #[tokio::test]
+  async fn sourced_scripts_run_over_the_session() {
+    use crate::testing::{MockHyrconServer, Reply};
+
+    let path = std::env::temp_dir()
+      .join(format!("hyrcon-source-{}.rcon", std::process::id()));
+    std::fs::write(&path, "# warn first\nSAY {msg}\n\nKICK $player\n")
+      .expect("script");
+    let mut shell_vars = ShellVars::default();
+    shell_vars.set("player".to_string(), "Steve".to_string());
+    let cli = parse(&["--var", "msg=restarting"]);
+    let commands = read_script(&path, &shell_vars.with_flags(&cli)).await;
+    std::fs::remove_file(&path).expect("remove");
+    let commands = commands.expect("read");
+    let label = |line| format!("{}:{line}", path.display());
+    assert_eq!(
+      commands,
+      [
+        (label(2), "SAY restarting".to_string()),
+        (label(4), "KICK Steve".to_string()),
+      ]
+    );
+
+    let server = MockHyrconServer::builder()
+      .expect("SAY restarting", Reply::ok(Vec::<String>::new()))
+      .expect("KICK Steve", Reply::err("no such player"))
+      .start()
+      .await
+      .expect("start");
+    let mut client = RconClient::connect(
+      Protocol::Hyrcon,
+      "127.0.0.1",
+      server.port(),
+      Duration::from_secs(2),
+    )
+    .await
+    .expect("connect");
+    let mut interrupts = no_interrupts();
+
+    let err = source_script(
+      &cli,
+      &Config::default(),
+      &commands,
+      "default",
+      &mut client,
+      false,
+      &mut interrupts,
+    )
+    .await
+    .expect_err("a command was rejected");
+    assert_eq!(err.to_string(), "the sourced script did not run cleanly");
+    assert!(!client.is_closed());
+    client.quit().await.expect("quit");
+    server.finish().await.expect("script followed");
+  }
 }
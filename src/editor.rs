@@ -0,0 +1,750 @@
+//! Line editing for the interactive shell on a terminal: cursor keys,
+//! history recall and search, history saved across sessions, and tab
+//! completion and highlighting of the server's commands.
+//!
+//! Reading a line blocks, so the editor runs on its own thread and hands
+//! lines back over a channel, which lets the shell keep printing pushed
+//! server messages while a prompt is open.
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::io::{self, ErrorKind};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc as std_mpsc;
+use std::sync::{Arc, RwLock};
+use std::thread;
+
+use anyhow::{Context, Result};
+use owo_colors::OwoColorize;
+use rustyline::completion::Completer;
+use rustyline::config::{CompletionType, Config};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::{CmdKind, Highlighter};
+use rustyline::hint::{Hint, Hinter};
+use rustyline::history::{DefaultHistory, History};
+use rustyline::validate::Validator;
+use rustyline::{Editor, Helper};
+use tokio::sync::mpsc;
+
+use crate::format::translate_section_codes;
+use crate::ui;
+use crate::util::command;
+
+/// Longest hint shown after a typed command, in characters.
+const HINT_WIDTH: usize = 60;
+
+/// Most history entries kept in memory and on disk.
+const HISTORY_LIMIT: usize = 1_000;
+
+/// A prompt on the terminal, read on a background thread.
+pub struct LineEditor {
+  /// Prompts to open, and whether their line goes into history.
+  prompts: std_mpsc::Sender<(String, bool)>,
+  lines: mpsc::Receiver<io::Result<String>>,
+  commands: Arc<RwLock<CommandList>>,
+  color: Arc<AtomicBool>,
+  /// Whether a prompt is open and its line not yet received.
+  reading: bool,
+  /// Terminal settings from before the editor started, put back on drop
+  /// in case a prompt is still open in raw mode.
+  #[cfg(unix)]
+  terminal: Option<nix::sys::termios::Termios>,
+}
+
+impl LineEditor {
+  /// Start the editor, loading earlier commands from `history` and
+  /// appending new ones to it. Commands that look like they carry a
+  /// password are never recorded. Typed commands are colored when
+  /// `use_color` is set.
+  pub fn start(history: Option<PathBuf>, use_color: bool) -> Result<Self> {
+    #[cfg(unix)]
+    let terminal = nix::sys::termios::tcgetattr(io::stdin()).ok();
+
+    let config = Config::builder()
+      .max_history_size(HISTORY_LIMIT)
+      .context("invalid history size")?
+      .completion_type(CompletionType::List)
+      .build();
+    let mut history = history.map(|path| HistoryFile::load(path, &config));
+    let mut editor = Editor::with_config(config)
+      .context("failed to start the line editor")?;
+    let commands = Arc::default();
+    let color = Arc::new(AtomicBool::new(use_color));
+    editor.set_helper(Some(ShellHelper {
+      commands: Arc::clone(&commands),
+      color: Arc::clone(&color),
+    }));
+    if let Some(history) = &history {
+      let entries: Vec<_> = history.entries.iter().cloned().collect();
+      for entry in latest_distinct(&entries) {
+        let _ = editor.add_history_entry(entry);
+      }
+    }
+    match editor.create_external_printer() {
+      Ok(printer) => ui::set_printer(Some(Box::new(printer))),
+      Err(err) => tracing::debug!("no external printer: {err}"),
+    }
+
+    let (prompts, requests) = std_mpsc::channel::<(String, bool)>();
+    let (sender, lines) = mpsc::channel(1);
+    thread::spawn(move || {
+      for (prompt, record) in requests {
+        let line = match editor.readline(&prompt) {
+          Ok(line) => line,
+          // Ctrl-C drops the line being typed, like a shell.
+          Err(ReadlineError::Interrupted) => {
+            let interrupted = io::Error::from(ErrorKind::Interrupted);
+            if sender.blocking_send(Err(interrupted)).is_err() {
+              break;
+            }
+            continue;
+          }
+          Err(ReadlineError::Eof) => break,
+          Err(err) => {
+            let _ = sender.blocking_send(Err(io::Error::other(err)));
+            break;
+          }
+        };
+        if record {
+          remember(&mut editor, history.as_mut(), &line);
+        }
+        if sender.blocking_send(Ok(line)).is_err() {
+          break;
+        }
+      }
+    });
+
+    Ok(Self {
+      prompts,
+      lines,
+      commands,
+      color,
+      reading: false,
+      #[cfg(unix)]
+      terminal,
+    })
+  }
+
+  /// Open a prompt, unless one is already waiting for its line.
+  pub fn prompt(&mut self, prompt: &str) {
+    if !self.reading {
+      self.reading = self.prompts.send((prompt.to_string(), true)).is_ok();
+    }
+  }
+
+  /// Ask `question` at a prompt whose answer is kept out of history,
+  /// and wait for the answer, like [`LineEditor::recv`].
+  pub async fn ask(
+    &mut self,
+    question: &str,
+  ) -> Option<io::Result<String>> {
+    if !self.reading {
+      self.reading =
+        self.prompts.send((question.to_string(), false)).is_ok();
+    }
+    self.recv().await
+  }
+
+  /// The line typed at the open prompt, or `None` once the user ended
+  /// input with Ctrl-D. Ctrl-C gives an [`ErrorKind::Interrupted`]
+  /// error.
+  pub async fn recv(&mut self) -> Option<io::Result<String>> {
+    let line = self.lines.recv().await;
+    self.reading = false;
+    line
+  }
+
+  /// Complete the commands in `commands` from now on.
+  pub fn set_commands(&self, commands: CommandList) {
+    *self.commands.write().unwrap_or_else(|err| err.into_inner()) =
+      commands;
+  }
+
+  /// Color typed commands from the next keystroke on, or stop.
+  pub fn set_color(&self, use_color: bool) {
+    self.color.store(use_color, Ordering::Relaxed);
+  }
+}
+
+/// Commands a server offers, with the subcommands named in their usage,
+/// as learned from its help output.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandList {
+  verbs: BTreeMap<String, BTreeSet<String>>,
+  /// One-line usage or description of each command, for hints.
+  summaries: BTreeMap<String, String>,
+}
+
+impl CommandList {
+  /// Read the commands from help output such as Minecraft's
+  /// `/whitelist (add|list|off|on|reload|remove)` lines, 7 Days to Die's
+  /// `admin => Manage user permission levels`, or a bare list of names.
+  /// Lines that do not start with a command-like word are skipped.
+  #[must_use]
+  pub fn parse_help(lines: &[String]) -> Self {
+    let mut verbs = BTreeMap::<_, BTreeSet<_>>::new();
+    let mut summaries = BTreeMap::new();
+    for line in lines {
+      let line = translate_section_codes(line, false);
+      let mut words = line.split_whitespace();
+      let Some(first) = words.next() else {
+        continue;
+      };
+      let Some(verb) = verb_of(first) else {
+        continue;
+      };
+      let summary = summary_of(verb, &line);
+      if !summary.is_empty() {
+        summaries
+          .entry(verb.to_string())
+          .or_insert_with(|| summary.to_string());
+      }
+      let subcommands = verbs.entry(verb.to_string()).or_default();
+      // `kick: Kicks a player` describes the command, not its usage.
+      if !first.ends_with(':')
+        && let Some(usage) = words.next()
+      {
+        subcommands.extend(subcommands_of(usage).map(str::to_string));
+      }
+    }
+    Self { verbs, summaries }
+  }
+
+  /// Use the output of `help VERB` as the hint for `verb`: the usage
+  /// lines it lists, joined on one line.
+  pub fn describe(&mut self, verb: &str, help: &[String]) {
+    let verb = verb.trim_start_matches('/');
+    let summary = help
+      .iter()
+      .map(|line| translate_section_codes(line, false))
+      .filter_map(|line| {
+        let summary = summary_of(verb, &line);
+        (!summary.is_empty()).then(|| summary.to_string())
+      })
+      .collect::<Vec<_>>()
+      .join(" | ");
+    if !summary.is_empty() {
+      self.summaries.insert(verb.to_string(), summary);
+    }
+  }
+
+  /// Whether the server has `verb`, which may start with `/`.
+  #[must_use]
+  pub fn contains(&self, verb: &str) -> bool {
+    self.subcommands(verb).is_some()
+  }
+
+  /// Number of commands.
+  #[must_use]
+  pub fn len(&self) -> usize {
+    self.verbs.len()
+  }
+
+  #[must_use]
+  pub fn is_empty(&self) -> bool {
+    self.verbs.is_empty()
+  }
+
+  /// Subcommands of `verb`, which may start with `/` and is matched
+  /// regardless of case.
+  fn subcommands(&self, verb: &str) -> Option<&BTreeSet<String>> {
+    let verb = verb.trim_start_matches('/');
+    self
+      .verbs
+      .iter()
+      .find(|(known, _)| known.eq_ignore_ascii_case(verb))
+      .map(|(_, subcommands)| subcommands)
+  }
+
+  /// Usage or description of `verb`, matched like
+  /// [`CommandList::subcommands`].
+  fn summary(&self, verb: &str) -> Option<&str> {
+    let verb = verb.trim_start_matches('/');
+    self
+      .summaries
+      .iter()
+      .find(|(known, _)| known.eq_ignore_ascii_case(verb))
+      .map(|(_, summary)| summary.as_str())
+  }
+
+  /// Parts of a typed `line` worth coloring: the command and its
+  /// subcommand when the server has them, quoted strings, and numbers.
+  /// Lines for the shell itself are left alone.
+  fn tokens(&self, line: &str) -> Vec<(Range<usize>, Token)> {
+    let mut tokens = Vec::new();
+    if line.trim_start().starts_with(':') {
+      return tokens;
+    }
+    let mut verb = None;
+    let mut start = 0;
+    for position in 0.. {
+      let Some(offset) = line[start..].find(|c: char| !c.is_whitespace())
+      else {
+        break;
+      };
+      start += offset;
+      let rest = &line[start..];
+      let end = match rest.chars().next() {
+        // An unclosed quote runs to the end of the line.
+        Some(quote @ ('"' | '\'')) => rest[1..]
+          .find(quote)
+          .map_or(line.len(), |close| start + close + 2),
+        _ => rest
+          .find(char::is_whitespace)
+          .map_or(line.len(), |space| start + space),
+      };
+      let word = &line[start..end];
+      let token = if word.starts_with(['"', '\'']) {
+        Some(Token::Quoted)
+      } else if is_number(word) {
+        Some(Token::Number)
+      } else if position == 0 && self.subcommands(word).is_some() {
+        verb = Some(word);
+        Some(Token::Command)
+      } else if position == 1
+        && verb.and_then(|verb| self.subcommands(verb)).is_some_and(
+          |subcommands| {
+            subcommands.iter().any(|sub| sub.eq_ignore_ascii_case(word))
+          },
+        )
+      {
+        Some(Token::Command)
+      } else {
+        None
+      };
+      if let Some(token) = token {
+        tokens.push((start..end, token));
+      }
+      start = end;
+    }
+    tokens
+  }
+
+  /// Completions for `word` when it follows `preceding`: a command name
+  /// first, then one of its subcommands. A leading `/` is kept.
+  fn complete(&self, preceding: &[&str], word: &str) -> Vec<String> {
+    let (slash, word) = match word.strip_prefix('/') {
+      Some(word) if preceding.is_empty() => ("/", word),
+      _ => ("", word),
+    };
+    let candidates: Vec<_> = match preceding {
+      [] => self.verbs.keys().collect(),
+      [verb] => self.subcommands(verb).into_iter().flatten().collect(),
+      _ => Vec::new(),
+    };
+    candidates
+      .into_iter()
+      .filter(|candidate| starts_with_ignore_case(candidate, word))
+      .map(|candidate| format!("{slash}{candidate}"))
+      .collect()
+  }
+}
+
+/// The command named by the first word of a help line, if it looks like
+/// one: a letter followed by letters, digits, `_`, `-`, `.` or `:`.
+fn verb_of(word: &str) -> Option<&str> {
+  let word = word.trim_start_matches('/').trim_end_matches(':');
+  let mut chars = word.chars();
+  let named = chars.next().is_some_and(char::is_alphabetic)
+    && chars.all(|c| c.is_alphanumeric() || "_-.:".contains(c));
+  named.then_some(word)
+}
+
+/// What a help `line` says beyond the name of `verb`: `(add|remove)` for
+/// `/whitelist (add|remove)`, `Kicks a player` for `kick: Kicks a player`.
+/// Lines that do not start with `verb` are kept whole.
+fn summary_of<'a>(verb: &str, line: &'a str) -> &'a str {
+  let line = line.trim();
+  let (first, rest) =
+    line.split_once(char::is_whitespace).unwrap_or((line, ""));
+  if !verb_of(first).is_some_and(|named| named.eq_ignore_ascii_case(verb))
+  {
+    return line;
+  }
+  rest
+    .trim_start()
+    .trim_start_matches("=>")
+    .trim_start_matches('-')
+    .trim()
+}
+
+/// Subcommands in the second word of a usage line: a literal word, or
+/// the choices of `(add|remove)` and `<on|off>` groups.
+fn subcommands_of(usage: &str) -> impl Iterator<Item = &str> {
+  let choices = usage
+    .strip_prefix(['(', '<', '['])
+    .and_then(|inner| inner.strip_suffix([')', '>', ']']))
+    .filter(|inner| inner.contains('|'))
+    .unwrap_or(usage);
+  let words: Vec<_> = choices.split('|').collect();
+  let literal = words
+    .iter()
+    .all(|word| word.chars().next().is_some_and(char::is_alphabetic))
+    && words.iter().all(|word| {
+      word
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+    });
+  words.into_iter().filter(move |_| literal)
+}
+
+/// Kind of a colored part of a typed line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+  /// A command or subcommand the server knows.
+  Command,
+  Quoted,
+  Number,
+}
+
+/// Whether `word` is a plain number such as `64`, `-3` or `0.5`.
+fn is_number(word: &str) -> bool {
+  let digits = word.strip_prefix(['-', '+']).unwrap_or(word);
+  let mut parts = digits.splitn(2, '.');
+  parts.all(|part| {
+    !part.is_empty() && part.chars().all(|c| c.is_ascii_digit())
+  })
+}
+
+fn starts_with_ignore_case(text: &str, prefix: &str) -> bool {
+  text
+    .get(..prefix.len())
+    .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+}
+
+/// Usage of the command being typed, shown after the cursor. Unlike
+/// history hints, it is never inserted into the line.
+struct UsageHint(String);
+
+impl Hint for UsageHint {
+  fn display(&self) -> &str {
+    &self.0
+  }
+
+  fn completion(&self) -> Option<&str> {
+    None
+  }
+}
+
+/// Hooks the editor calls while a line is typed.
+struct ShellHelper {
+  commands: Arc<RwLock<CommandList>>,
+  color: Arc<AtomicBool>,
+}
+
+impl Completer for ShellHelper {
+  type Candidate = String;
+
+  fn complete(
+    &self,
+    line: &str,
+    pos: usize,
+    _ctx: &rustyline::Context<'_>,
+  ) -> rustyline::Result<(usize, Vec<String>)> {
+    let before = &line[..pos];
+    let start = before
+      .rfind(char::is_whitespace)
+      .map_or(0, |index| index + 1);
+    let preceding: Vec<_> = before[..start].split_whitespace().collect();
+    let commands =
+      self.commands.read().unwrap_or_else(|err| err.into_inner());
+    Ok((start, commands.complete(&preceding, &before[start..])))
+  }
+}
+
+impl Hinter for ShellHelper {
+  type Hint = UsageHint;
+
+  /// The usage of a command once its name and a space are typed, until
+  /// its arguments start.
+  fn hint(
+    &self,
+    line: &str,
+    pos: usize,
+    _ctx: &rustyline::Context<'_>,
+  ) -> Option<UsageHint> {
+    if pos < line.len() {
+      return None;
+    }
+    let (verb, rest) =
+      line.trim_start().split_once(char::is_whitespace)?;
+    if !rest.trim().is_empty() {
+      return None;
+    }
+    let commands =
+      self.commands.read().unwrap_or_else(|err| err.into_inner());
+    let summary = commands.summary(verb)?;
+    let hint = match summary.char_indices().nth(HINT_WIDTH) {
+      Some((end, _)) => {
+        format!("{}{}", &summary[..end], ui::symbols().ellipsis)
+      }
+      None => summary.to_string(),
+    };
+    Some(UsageHint(hint))
+  }
+}
+
+impl Highlighter for ShellHelper {
+  fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+    if !self.color.load(Ordering::Relaxed) {
+      return Cow::Borrowed(line);
+    }
+    let commands =
+      self.commands.read().unwrap_or_else(|err| err.into_inner());
+    let tokens = commands.tokens(line);
+    if tokens.is_empty() {
+      return Cow::Borrowed(line);
+    }
+    let mut highlighted = String::with_capacity(line.len() * 2);
+    let mut last = 0;
+    for (range, token) in tokens {
+      highlighted.push_str(&line[last..range.start]);
+      let text = &line[range.clone()];
+      let styled = match token {
+        Token::Command => text.bold().to_string(),
+        Token::Quoted => text.green().to_string(),
+        Token::Number => text.yellow().to_string(),
+      };
+      highlighted.push_str(&styled);
+      last = range.end;
+    }
+    highlighted.push_str(&line[last..]);
+    Cow::Owned(highlighted)
+  }
+
+  fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+    if self.color.load(Ordering::Relaxed) {
+      Cow::Owned(hint.dimmed().to_string())
+    } else {
+      Cow::Borrowed(hint)
+    }
+  }
+
+  fn highlight_char(
+    &self,
+    _line: &str,
+    _pos: usize,
+    _kind: CmdKind,
+  ) -> bool {
+    // Any keystroke can turn a word into a command or close a quote.
+    self.color.load(Ordering::Relaxed)
+  }
+}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
+
+impl Drop for LineEditor {
+  fn drop(&mut self) {
+    ui::set_printer(None);
+    #[cfg(unix)]
+    if let Some(terminal) = &self.terminal {
+      use nix::sys::termios::{SetArg, tcsetattr};
+      let _ = tcsetattr(io::stdin(), SetArg::TCSADRAIN, terminal);
+    }
+  }
+}
+
+/// The history file, kept apart from the editor's own history. The
+/// editor is given the latest run of each command, so the arrow keys
+/// and Ctrl-R step through distinct commands, while the file keeps
+/// every run and is only ever appended to.
+struct HistoryFile {
+  path: PathBuf,
+  entries: DefaultHistory,
+}
+
+impl HistoryFile {
+  /// Read the history at `path`, creating its directory. A missing
+  /// or unreadable file starts an empty history.
+  fn load(path: PathBuf, config: &Config) -> Self {
+    if let Some(parent) = path.parent()
+      && let Err(err) = std::fs::create_dir_all(parent)
+    {
+      tracing::warn!("failed to create {}: {err}", parent.display());
+    }
+    let mut entries = DefaultHistory::with_config(config);
+    match entries.load(&path) {
+      Ok(()) => {}
+      Err(ReadlineError::Io(err)) if err.kind() == ErrorKind::NotFound => {
+      }
+      Err(err) => {
+        tracing::warn!(
+          "failed to load history from {}: {err}",
+          path.display()
+        );
+      }
+    }
+    Self { path, entries }
+  }
+
+  /// Append `line` to the file right away, since the process may exit
+  /// without unwinding.
+  fn append(&mut self, line: &str) {
+    let appended = self
+      .entries
+      .add(line)
+      .and_then(|_| self.entries.append(&self.path));
+    if let Err(err) = appended {
+      tracing::warn!(
+        "failed to save history to {}: {err}",
+        self.path.display()
+      );
+    }
+  }
+}
+
+/// `entries` without the earlier copies of repeated ones, oldest first.
+fn latest_distinct(entries: &[String]) -> Vec<&str> {
+  let mut seen = HashSet::new();
+  let mut distinct: Vec<_> = entries
+    .iter()
+    .rev()
+    .filter(|entry| seen.insert(entry.as_str()))
+    .map(String::as_str)
+    .collect();
+  distinct.reverse();
+  distinct
+}
+
+/// Record `line` in the editor and the history file.
+fn remember(
+  editor: &mut Editor<ShellHelper, DefaultHistory>,
+  history: Option<&mut HistoryFile>,
+  line: &str,
+) {
+  if line.trim().is_empty() || command::contains_secret(line) {
+    return;
+  }
+  let added = editor.add_history_entry(line).unwrap_or(false);
+  if added && let Some(history) = history {
+    history.append(line);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn lines(text: &str) -> Vec<String> {
+    text.lines().map(str::to_string).collect()
+  }
+
+  #[test]
+  fn help_output_yields_commands_and_subcommands() {
+    let commands = CommandList::parse_help(&lines(
+      "/ban <targets> [<reason>]\n\
+       /whitelist (add|list|off|on|reload|remove)\n\
+       /gamemode <gamemode> [<target>]\n\
+       admin => Manage user permission levels\n\
+       §6kick: §fKicks a player\n\
+       --- Showing help page 1 of 9 ---\n\
+       42 commands",
+    ));
+    assert_eq!(commands.len(), 5);
+
+    assert_eq!(commands.complete(&[], "g"), ["gamemode"]);
+    assert_eq!(commands.complete(&[], "/WH"), ["/whitelist"]);
+    assert_eq!(
+      commands.complete(&["/whitelist"], "re"),
+      ["reload", "remove"]
+    );
+    assert!(commands.complete(&["gamemode"], "").is_empty());
+    assert!(commands.complete(&["kick"], "").is_empty());
+    assert!(commands.complete(&["whitelist", "add"], "").is_empty());
+    assert_eq!(commands.complete(&[], "").len(), 5);
+  }
+
+  #[test]
+  fn help_output_gives_one_line_summaries() {
+    let mut commands = CommandList::parse_help(&lines(
+      "/whitelist (add|list|remove)
+       admin => Manage user permission levels
+       §6kick: §fKicks a player
+       say",
+    ));
+    assert_eq!(commands.summary("/WHITELIST"), Some("(add|list|remove)"));
+    assert_eq!(
+      commands.summary("admin"),
+      Some("Manage user permission levels")
+    );
+    assert_eq!(commands.summary("kick"), Some("Kicks a player"));
+    assert_eq!(commands.summary("say"), None);
+
+    commands.describe(
+      "/whitelist",
+      &lines(
+        "/whitelist add <targets>
+/whitelist list
+         Manages the server whitelist",
+      ),
+    );
+    assert_eq!(
+      commands.summary("whitelist"),
+      Some("add <targets> | list | Manages the server whitelist")
+    );
+    assert!(commands.contains("/Kick"));
+    assert!(!commands.contains("teleport"));
+  }
+
+  #[test]
+  fn typed_lines_are_split_into_colored_parts() {
+    let commands = CommandList::parse_help(&lines(
+      "/whitelist (add|list|remove)\n/give <target> <item> [<count>]",
+    ));
+    let line = "/WHITELIST add Steve";
+    let parts: Vec<_> = commands
+      .tokens(line)
+      .into_iter()
+      .map(|(range, token)| (&line[range], token))
+      .collect();
+    assert_eq!(
+      parts,
+      [("/WHITELIST", Token::Command), ("add", Token::Command)]
+    );
+
+    let line = r#"give Alex minecraft:stone 64 "a b" 'open"#;
+    let parts: Vec<_> = commands
+      .tokens(line)
+      .into_iter()
+      .map(|(range, token)| (&line[range], token))
+      .collect();
+    assert_eq!(
+      parts,
+      [
+        ("give", Token::Command),
+        ("64", Token::Number),
+        ("\"a b\"", Token::Quoted),
+        ("'open", Token::Quoted),
+      ]
+    );
+
+    assert!(commands.tokens("say 1.5.2 -x 1e3").is_empty());
+    assert!(commands.tokens(":timeout 20").is_empty());
+  }
+
+  #[test]
+  fn history_keeps_the_latest_run_of_each_command() {
+    let entries = lines("ban Steve\nlist\nsay hi\nban Steve\nlist");
+    assert_eq!(latest_distinct(&entries), ["say hi", "ban Steve", "list"]);
+  }
+
+  #[test]
+  fn history_file_is_only_appended_to() {
+    let path = std::env::temp_dir()
+      .join(format!("hyrcon-history-{}", std::process::id()));
+    let earlier = "#V2\nban Steve\nlist\nban Steve\n";
+    std::fs::write(&path, earlier).expect("write history");
+
+    let mut history = HistoryFile::load(path.clone(), &Config::default());
+    assert_eq!(std::fs::read_to_string(&path).expect("read"), earlier);
+    history.append("say hi");
+
+    let contents = std::fs::read_to_string(&path).expect("read");
+    std::fs::remove_file(&path).expect("remove");
+    assert_eq!(contents, format!("{earlier}say hi\n"));
+  }
+}
@@ -0,0 +1,175 @@
+//! Error type of the library's connection APIs: [`RconClient`],
+//! [`RconConnector`], [`RconPool`] and [`ProtocolBackend`].
+//!
+//! Callers can tell the ways talking to a server fails apart by matching
+//! on [`Error`] instead of reading messages:
+//!
+//! ```no_run
+//! use hyrcon_client::{Error, RconClient};
+//!
+//! # async fn example(client: &mut RconClient) {
+//! match client.send_command("list").await {
+//!   Ok(outcome) => println!("{outcome:?}"),
+//!   Err(Error::Timeout(timed_out)) => {
+//!     eprintln!("no reply after {:?}", timed_out.after());
+//!   }
+//!   Err(Error::Closed) => eprintln!("the server hung up"),
+//!   Err(err) => eprintln!("{err}"),
+//! }
+//! # }
+//! ```
+//!
+//! [`RconClient`]: crate::RconClient
+//! [`RconConnector`]: crate::RconConnector
+//! [`RconPool`]: crate::RconPool
+//! [`ProtocolBackend`]: crate::ProtocolBackend
+
+use std::io;
+
+use crate::protocol::Protocol;
+use crate::transport::TimedOut;
+
+/// Why connecting to or talking with a server failed.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+  /// The settings ask for something the protocol or this build of the
+  /// crate cannot do, or that contradict each other.
+  #[error("{0}")]
+  Unsupported(String),
+  /// No session could be opened with `host:port`; `source` says why.
+  #[error("failed to connect to {host}:{port} via {protocol}")]
+  Connect {
+    host: String,
+    port: u16,
+    protocol: Protocol,
+    #[source]
+    source: Box<Error>,
+  },
+  /// The server needs a password and none was given.
+  #[error("server requires authentication; no password was given")]
+  AuthRequired,
+  /// The server rejected the password.
+  #[error("authentication rejected by server")]
+  AuthRejected,
+  /// The server did not answer in time.
+  #[error(transparent)]
+  Timeout(#[from] TimedOut),
+  /// The server closed the connection.
+  #[error("server closed the connection unexpectedly")]
+  Closed,
+  /// A cancellation token fired before the operation finished.
+  #[error("cancelled before the server answered")]
+  Cancelled,
+  /// The server answered a command with an error, e.g. an unknown
+  /// player for [`RconClient::kick`](crate::RconClient::kick).
+  #[error("server rejected the command: {0}")]
+  Rejected(String),
+  /// Reading from or writing to the connection failed.
+  #[error("{context}")]
+  Io {
+    /// What was being done, e.g. `reading from Source RCON server`.
+    context: String,
+    #[source]
+    source: io::Error,
+  },
+  /// The server broke the protocol, e.g. with a malformed reply.
+  #[error("{0}")]
+  Protocol(String),
+}
+
+impl From<io::Error> for Error {
+  fn from(source: io::Error) -> Self {
+    Self::Io {
+      context: "connection I/O failed".to_string(),
+      source,
+    }
+  }
+}
+
+impl Error {
+  /// Sort an error from inside the transports into its kind, keeping
+  /// the context it was given on the way up.
+  pub(crate) fn from_internal(err: anyhow::Error) -> Self {
+    let err = match err.downcast::<Self>() {
+      Ok(err) => return err,
+      Err(err) => err,
+    };
+    for cause in err.chain() {
+      if let Some(timed_out) = cause.downcast_ref::<TimedOut>() {
+        return Self::Timeout(timed_out.clone());
+      }
+      if let Some(Self::Closed) = cause.downcast_ref::<Self>() {
+        return Self::Closed;
+      }
+    }
+
+    let causes: Vec<_> = err.chain().collect();
+    let io_cause = causes.iter().position(|cause| cause.is::<io::Error>());
+    if let Some(index) = io_cause {
+      let context = causes[..index]
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(": ");
+      let source = match err.downcast::<io::Error>() {
+        Ok(source) => source,
+        Err(err) => {
+          let cause = err
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<io::Error>())
+            .expect("an I/O error is in the chain");
+          io::Error::new(cause.kind(), cause.to_string())
+        }
+      };
+      if context.is_empty() {
+        return source.into();
+      }
+      return Self::Io { context, source };
+    }
+    Self::Protocol(format!("{err:#}"))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use anyhow::{Context, anyhow};
+
+  use super::*;
+
+  #[test]
+  fn internal_errors_keep_their_kind() {
+    let timed_out = anyhow::Error::new(TimedOut::new(
+      "reading block from server",
+      Duration::from_secs(1),
+    ))
+    .context("sending `list`");
+    assert!(matches!(Error::from_internal(timed_out), Error::Timeout(_)));
+
+    let closed =
+      anyhow::Error::new(Error::Closed).context("reading reply");
+    assert!(matches!(Error::from_internal(closed), Error::Closed));
+
+    let reset =
+      Err::<(), _>(io::Error::from(io::ErrorKind::ConnectionReset))
+        .context("writing command")
+        .unwrap_err();
+    match Error::from_internal(reset) {
+      Error::Io { context, source } => {
+        assert_eq!(context, "writing command");
+        assert_eq!(source.kind(), io::ErrorKind::ConnectionReset);
+      }
+      other => panic!("expected an I/O error, got {other:?}"),
+    }
+
+    let malformed = anyhow!("malformed packet").context("reading reply");
+    match Error::from_internal(malformed) {
+      Error::Protocol(message) => {
+        assert_eq!(message, "reading reply: malformed packet");
+      }
+      other => panic!("expected a protocol error, got {other:?}"),
+    }
+  }
+}
@@ -0,0 +1,273 @@
+//! Process exit statuses for the ways a run can fail.
+//!
+//! | Failure    | Status | Cause                                          |
+//! |------------|--------|------------------------------------------------|
+//! | `other`    | 1      | Anything else, e.g. a bad flag or config file  |
+//! | `command`  | 2      | The server answered a command with an error    |
+//! | `connect`  | 3      | No session could be opened                     |
+//! | `auth`     | 4      | The password was missing or rejected           |
+//! | `timeout`  | 5      | The server stopped answering in time           |
+//! | `protocol` | 6      | The session broke down after connecting        |
+//! | `expect`   | 7      | The response did not meet `--expect` checks    |
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::transport::TimedOut;
+
+/// Why a run failed, which decides its exit status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+  /// Anything not covered below, such as a bad flag or config file.
+  Other,
+  /// The server answered a command with an error status.
+  Command,
+  /// No session could be opened with any host.
+  Connect,
+  /// The server needed a password that was not given, or rejected it.
+  Auth,
+  /// The server stopped answering within the timeout.
+  Timeout,
+  /// The session broke down after connecting, e.g. on a malformed reply
+  /// or a dropped connection.
+  Protocol,
+  /// The response did not meet `--expect` or `--expect-status`.
+  Expect,
+}
+
+impl Failure {
+  const ALL: [Self; 7] = [
+    Self::Other,
+    Self::Command,
+    Self::Connect,
+    Self::Auth,
+    Self::Timeout,
+    Self::Protocol,
+    Self::Expect,
+  ];
+
+  /// Name used by `--exit-code-map`.
+  #[must_use]
+  pub fn name(self) -> &'static str {
+    match self {
+      Self::Other => "other",
+      Self::Command => "command",
+      Self::Connect => "connect",
+      Self::Auth => "auth",
+      Self::Timeout => "timeout",
+      Self::Protocol => "protocol",
+      Self::Expect => "expect",
+    }
+  }
+
+  /// The failure a session error stands for: [`Failure::Timeout`] if the
+  /// server went quiet, otherwise [`Failure::Protocol`].
+  #[must_use]
+  pub fn of_session(err: &anyhow::Error) -> Self {
+    let timed_out = err.chain().any(|cause| {
+      cause.is::<TimedOut>()
+        || cause.is::<tokio::time::error::Elapsed>()
+        || matches!(cause.downcast_ref(), Some(crate::Error::Timeout(_)))
+    });
+    if timed_out {
+      Self::Timeout
+    } else {
+      Self::Protocol
+    }
+  }
+}
+
+impl fmt::Display for Failure {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.name())
+  }
+}
+
+/// Exit status for each [`Failure`], parsed from `--exit-code-map`
+/// entries such as `auth=10,timeout=11`. Failures left out keep their
+/// default status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExitCodes {
+  codes: [u8; Failure::ALL.len()],
+}
+
+impl ExitCodes {
+  /// Exit status for `failure`.
+  #[must_use]
+  pub fn code(&self, failure: Failure) -> i32 {
+    i32::from(self.codes[failure as usize])
+  }
+
+  /// Use `code` as the exit status for `failure`.
+  #[must_use]
+  pub fn with(mut self, failure: Failure, code: u8) -> Self {
+    self.codes[failure as usize] = code;
+    self
+  }
+}
+
+impl Default for ExitCodes {
+  fn default() -> Self {
+    Self {
+      codes: [1, 2, 3, 4, 5, 6, 7],
+    }
+  }
+}
+
+impl fmt::Display for ExitCodes {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for (index, failure) in Failure::ALL.into_iter().enumerate() {
+      if index > 0 {
+        f.write_str(",")?;
+      }
+      write!(f, "{failure}={}", self.code(failure))?;
+    }
+    Ok(())
+  }
+}
+
+/// Error returned when parsing [`ExitCodes`] fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseExitCodesError {
+  reason: &'static str,
+}
+
+impl fmt::Display for ParseExitCodesError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "invalid exit code map: {}", self.reason)
+  }
+}
+
+impl std::error::Error for ParseExitCodesError {}
+
+impl FromStr for ExitCodes {
+  type Err = ParseExitCodesError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let error = |reason| ParseExitCodesError { reason };
+
+    let mut codes = Self::default();
+    for entry in s.split(',').map(str::trim) {
+      let Some((name, code)) = entry.split_once('=') else {
+        return Err(error("expected `failure=code` entries"));
+      };
+      let failure = Failure::ALL
+        .into_iter()
+        .find(|failure| failure.name() == name.trim())
+        .ok_or(error(
+          "unknown failure; expected other, command, connect, auth, \
+           timeout, protocol or expect",
+        ))?;
+      let code = code
+        .trim()
+        .parse()
+        .map_err(|_| error("exit codes must be between 0 and 255"))?;
+      codes = codes.with(failure, code);
+    }
+    Ok(codes)
+  }
+}
+
+/// An error tagged with the [`Failure`] it stands for. It displays as
+/// the error it wraps, so reports read the same with or without it.
+#[derive(Debug)]
+pub struct Failed {
+  failure: Failure,
+  error: anyhow::Error,
+}
+
+impl Failed {
+  /// Tag `error` with `failure`, unless an inner step already tagged it.
+  pub fn tag(failure: Failure, error: anyhow::Error) -> anyhow::Error {
+    if error.chain().any(|cause| cause.is::<Self>()) {
+      return error;
+    }
+    Self { failure, error }.into()
+  }
+
+  /// The failure `error` was tagged with, or [`Failure::Other`].
+  #[must_use]
+  pub fn failure_of(error: &anyhow::Error) -> Failure {
+    error
+      .chain()
+      .find_map(|cause| cause.downcast_ref::<Self>())
+      .map_or(Failure::Other, |failed| failed.failure)
+  }
+}
+
+impl fmt::Display for Failed {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    // Only the wrapped error's own message: its causes follow through
+    // `source`.
+    write!(f, "{}", self.error)
+  }
+}
+
+impl std::error::Error for Failed {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    self.error.source()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use anyhow::anyhow;
+  use std::time::Duration;
+
+  #[test]
+  fn maps_override_defaults() {
+    let codes: ExitCodes = "auth=10, timeout = 0".parse().expect("map");
+    assert_eq!(codes.code(Failure::Auth), 10);
+    assert_eq!(codes.code(Failure::Timeout), 0);
+    assert_eq!(codes.code(Failure::Connect), 3);
+    assert_eq!(
+      ExitCodes::default().to_string(),
+      "other=1,command=2,connect=3,auth=4,timeout=5,protocol=6,expect=7"
+    );
+    assert_eq!(
+      ExitCodes::default().to_string().parse(),
+      Ok(ExitCodes::default())
+    );
+
+    assert!("auth".parse::<ExitCodes>().is_err());
+    assert!("login=10".parse::<ExitCodes>().is_err());
+    assert!("auth=256".parse::<ExitCodes>().is_err());
+  }
+
+  #[test]
+  fn tags_survive_context_and_keep_the_message() {
+    let err = Failed::tag(
+      Failure::Auth,
+      anyhow!("authentication rejected by server"),
+    );
+    let err =
+      Failed::tag(Failure::Connect, err).context("failed to reconnect");
+    assert_eq!(Failed::failure_of(&err), Failure::Auth);
+    assert_eq!(
+      format!("{err:#}"),
+      "failed to reconnect: authentication rejected by server"
+    );
+    assert_eq!(Failed::failure_of(&anyhow!("bad flag")), Failure::Other);
+
+    let err = Failed::tag(
+      Failure::Connect,
+      anyhow!("connection refused").context("failed to connect"),
+    );
+    assert_eq!(
+      format!("{err:#}"),
+      "failed to connect: connection refused"
+    );
+
+    let err = anyhow::Error::new(TimedOut::new(
+      "reading block from server",
+      Duration::from_secs(5),
+    ))
+    .context("`save-all` failed");
+    assert_eq!(Failure::of_session(&err), Failure::Timeout);
+    assert_eq!(
+      Failure::of_session(&anyhow!("malformed packet")),
+      Failure::Protocol
+    );
+  }
+}
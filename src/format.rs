@@ -0,0 +1,149 @@
+use std::borrow::Cow;
+use std::fmt::Write as _;
+
+/// Marker that introduces Minecraft-style formatting codes (`§a`, `§l`, ...).
+pub const SECTION_SIGN: char = '§';
+
+/// Translate Minecraft `§` formatting codes in a payload line.
+///
+/// With `use_color` the codes become the closest ANSI SGR sequences
+/// (including `§x§R§R§G§G§B§B` hex colours as 24-bit colour) and the line is
+/// terminated with a reset; otherwise the codes are stripped so `--plain`
+/// output contains only the text. Lines without a section sign are returned
+/// unchanged.
+///
+/// # Examples
+///
+/// ```
+/// use hyrcon_client::format::translate_section_codes;
+///
+/// assert_eq!(translate_section_codes("§aOnline§r: 3", false), "Online: 3");
+/// assert_eq!(
+///   translate_section_codes("§cAlert", true),
+///   "\u{1b}[0;91mAlert\u{1b}[0m"
+/// );
+/// ```
+#[must_use]
+pub fn translate_section_codes(
+  line: &str,
+  use_color: bool,
+) -> Cow<'_, str> {
+  if !line.contains(SECTION_SIGN) {
+    return Cow::Borrowed(line);
+  }
+
+  let mut output = String::with_capacity(line.len() + 16);
+  let mut styled = false;
+  let mut chars = line.chars().peekable();
+
+  while let Some(c) = chars.next() {
+    if c != SECTION_SIGN {
+      output.push(c);
+      continue;
+    }
+
+    let Some(code) = chars.next() else {
+      break;
+    };
+    let code = code.to_ascii_lowercase();
+
+    if code == 'x' {
+      let mut hex = String::with_capacity(6);
+      while hex.len() < 6 && chars.peek() == Some(&SECTION_SIGN) {
+        chars.next();
+        match chars.next() {
+          Some(digit) if digit.is_ascii_hexdigit() => hex.push(digit),
+          _ => break,
+        }
+      }
+      if use_color && hex.len() == 6 {
+        let channel =
+          |range| u8::from_str_radix(&hex[range], 16).unwrap_or(0);
+        let _ = write!(
+          output,
+          "\x1b[0;38;2;{};{};{}m",
+          channel(0..2),
+          channel(2..4),
+          channel(4..6)
+        );
+        styled = true;
+      }
+      continue;
+    }
+
+    if use_color && let Some(sgr) = sgr_for(code) {
+      let _ = write!(output, "\x1b[{sgr}m");
+      styled = true;
+    }
+  }
+
+  if styled {
+    output.push_str("\x1b[0m");
+  }
+
+  Cow::Owned(output)
+}
+
+/// ANSI SGR parameters for a single formatting code. Colour codes also reset
+/// active styles, matching how the game renders them.
+fn sgr_for(code: char) -> Option<&'static str> {
+  let sgr = match code {
+    '0' => "0;30",
+    '1' => "0;34",
+    '2' => "0;32",
+    '3' => "0;36",
+    '4' => "0;31",
+    '5' => "0;35",
+    '6' => "0;33",
+    '7' => "0;37",
+    '8' => "0;90",
+    '9' => "0;94",
+    'a' => "0;92",
+    'b' => "0;96",
+    'c' => "0;91",
+    'd' => "0;95",
+    'e' => "0;93",
+    'f' => "0;97",
+    'l' => "1",
+    'm' => "9",
+    'n' => "4",
+    'o' => "3",
+    'r' => "0",
+    _ => return None,
+  };
+  Some(sgr)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn plain_mode_strips_all_codes() {
+    assert_eq!(
+      translate_section_codes("§6§lGold§r and §x§f§f§0§0§0§0red", false),
+      "Gold and red"
+    );
+    assert_eq!(translate_section_codes("trailing §", false), "trailing ");
+  }
+
+  #[test]
+  fn color_mode_emits_ansi_and_resets() {
+    assert_eq!(
+      translate_section_codes("§l§nHi", true),
+      "\x1b[1m\x1b[4mHi\x1b[0m"
+    );
+    assert_eq!(
+      translate_section_codes("§x§F§F§8§0§0§0orange", true),
+      "\x1b[0;38;2;255;128;0morange\x1b[0m"
+    );
+  }
+
+  #[test]
+  fn lines_without_codes_are_borrowed() {
+    assert!(matches!(
+      translate_section_codes("no codes here", true),
+      Cow::Borrowed(_)
+    ));
+  }
+}
@@ -0,0 +1,571 @@
+//! Shareable handle to one [`RconClient`] session, for services where
+//! many tasks send commands over the same connection.
+//!
+//! [`RconHandle::spawn`] moves the client onto a task of its own, which
+//! runs the commands queued by every clone of the handle one at a time:
+//!
+//! ```no_run
+//! use hyrcon_client::{CommandOptions, Priority, RconClient, RconHandle};
+//!
+//! # async fn example(client: RconClient) -> anyhow::Result<()> {
+//! let handle = RconHandle::spawn(client);
+//! let monitor = handle.clone();
+//! tokio::spawn(async move {
+//!   let routine = CommandOptions::default().priority(Priority::Low);
+//!   monitor.send_command_with("list", &routine).await
+//! });
+//!
+//! let urgent = CommandOptions::default().priority(Priority::High);
+//! handle.send_command_with("kick griefer", &urgent).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Waiting commands are run highest [`Priority`] first, so an operator's
+//! commands jump ahead of routine monitoring queries. Once the queue is
+//! full, senders wait for room. The session is closed once every handle
+//! has been dropped, or by [`RconHandle::quit`].
+//!
+//! With the `tower` feature, the handle is also a
+//! `tower::Service<`[`RconCommand`]`>`, so the usual middlewares for
+//! timeouts, retries, rate limits and load shedding wrap RCON commands
+//! too. Its `poll_ready` waits for room in the queue and holds that
+//! place for the next `call`, or until the handle is dropped.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+#[cfg(feature = "tower")]
+use std::task::{Context, Poll, ready};
+
+use tokio::sync::{Notify, OwnedSemaphorePermit, Semaphore, oneshot};
+#[cfg(feature = "tower")]
+use tokio_util::sync::PollSemaphore;
+
+use crate::error::Error;
+use crate::transport::{
+  CommandOptions, CommandOutcome, Priority, RconClient,
+};
+
+/// Commands that may wait for the session before senders are held up.
+const DEFAULT_QUEUE: usize = 32;
+
+/// Cheaply clonable handle to a session owned by a background task.
+#[derive(Debug)]
+pub struct RconHandle {
+  senders: Arc<Senders>,
+  #[cfg(feature = "tower")]
+  readiness: Readiness,
+}
+
+/// A command for [`RconHandle`]'s `tower::Service` implementation.
+#[cfg(feature = "tower")]
+#[derive(Debug, Clone)]
+pub struct RconCommand {
+  pub command: String,
+  /// Priority and per-command overrides, as for
+  /// [`RconHandle::send_command_with`].
+  pub options: CommandOptions,
+}
+
+#[cfg(feature = "tower")]
+impl RconCommand {
+  pub fn new(command: impl Into<String>) -> Self {
+    Self {
+      command: command.into(),
+      options: CommandOptions::default(),
+    }
+  }
+
+  #[must_use]
+  pub fn options(mut self, options: CommandOptions) -> Self {
+    self.options = options;
+    self
+  }
+}
+
+#[cfg(feature = "tower")]
+impl From<&str> for RconCommand {
+  fn from(command: &str) -> Self {
+    Self::new(command)
+  }
+}
+
+#[cfg(feature = "tower")]
+impl From<String> for RconCommand {
+  fn from(command: String) -> Self {
+    Self::new(command)
+  }
+}
+
+/// A place in the queue claimed by `poll_ready` for the next `call`.
+/// Each clone of a handle claims its own.
+#[cfg(feature = "tower")]
+#[derive(Debug)]
+struct Readiness {
+  room: PollSemaphore,
+  claimed: Option<OwnedSemaphorePermit>,
+}
+
+/// Snapshot of the commands waiting in an [`RconHandle`]'s queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueStatus {
+  /// Upper bound on waiting commands before senders are held up.
+  pub max_queued: usize,
+  /// Waiting [`Priority::High`] commands.
+  pub high: usize,
+  /// Waiting [`Priority::Normal`] commands, including a pending quit.
+  pub normal: usize,
+  /// Waiting [`Priority::Low`] commands.
+  pub low: usize,
+}
+
+impl QueueStatus {
+  /// Commands waiting at any priority; the one running is not counted.
+  pub fn queued(&self) -> usize {
+    self.high + self.normal + self.low
+  }
+}
+
+/// Owned jointly by the clones of a handle, so the session's task learns
+/// when the last one is dropped.
+#[derive(Debug)]
+struct Senders {
+  queue: Arc<Queue>,
+}
+
+#[derive(Debug)]
+struct Queue {
+  state: Mutex<State>,
+  /// Wakes the session's task for a new request or the last handle
+  /// going away.
+  wake: Notify,
+  /// A permit per free place in the queue, closed once the task stops.
+  room: Arc<Semaphore>,
+  max_queued: usize,
+}
+
+#[derive(Debug, Default)]
+struct State {
+  /// Waiting requests, one queue per [`Priority`] from low to high.
+  pending: [VecDeque<Queued>; 3],
+  /// Every handle has been dropped.
+  abandoned: bool,
+  /// The session's task has stopped serving requests.
+  stopped: bool,
+}
+
+#[derive(Debug)]
+struct Queued {
+  request: Request,
+  /// Frees the request's place in the queue once it is taken out.
+  _room: OwnedSemaphorePermit,
+}
+
+#[derive(Debug)]
+enum Request {
+  Command {
+    command: String,
+    options: CommandOptions,
+    reply: oneshot::Sender<Result<CommandOutcome, Error>>,
+  },
+  Quit {
+    reply: oneshot::Sender<Result<(), Error>>,
+  },
+}
+
+impl RconHandle {
+  /// Move `client` onto a new task and return the first handle to it.
+  /// Must be called from within a tokio runtime.
+  pub fn spawn(client: RconClient) -> Self {
+    Self::with_queue(client, DEFAULT_QUEUE)
+  }
+
+  /// Like [`spawn`](Self::spawn), letting up to `queue` commands wait
+  /// for the session (at least one).
+  pub fn with_queue(client: RconClient, queue: usize) -> Self {
+    let max_queued = queue.max(1);
+    let queue = Arc::new(Queue {
+      state: Mutex::new(State::default()),
+      wake: Notify::new(),
+      room: Arc::new(Semaphore::new(max_queued)),
+      max_queued,
+    });
+    tokio::spawn(run(client, Arc::clone(&queue)));
+    Self::new(Arc::new(Senders { queue }))
+  }
+
+  fn new(senders: Arc<Senders>) -> Self {
+    Self {
+      #[cfg(feature = "tower")]
+      readiness: Readiness {
+        room: PollSemaphore::new(Arc::clone(&senders.queue.room)),
+        claimed: None,
+      },
+      senders,
+    }
+  }
+
+  /// Queue `command` at [`Priority::Normal`] and wait for its outcome.
+  ///
+  /// Dropping the future while the command is still queued means it is
+  /// never sent; once sent, its reply is read and discarded.
+  pub async fn send_command(
+    &self,
+    command: impl Into<String>,
+  ) -> Result<CommandOutcome, Error> {
+    self
+      .send_command_with(command, &CommandOptions::default())
+      .await
+  }
+
+  /// Queue `command` at the priority in `options`, with the other
+  /// overrides applied as [`RconClient::send_command_with`] does.
+  pub async fn send_command_with(
+    &self,
+    command: impl Into<String>,
+    options: &CommandOptions,
+  ) -> Result<CommandOutcome, Error> {
+    let (reply, outcome) = oneshot::channel();
+    let request = Request::Command {
+      command: command.into(),
+      options: options.clone(),
+      reply,
+    };
+    self.request(request, options.queue_priority()).await?;
+    outcome.await.map_err(|_| Error::Closed)?
+  }
+
+  /// Close the session gracefully once the commands queued before this
+  /// at [`Priority::Normal`] or higher have run. Waiting and later
+  /// commands from any handle fail with [`Error::Closed`].
+  pub async fn quit(&self) -> Result<(), Error> {
+    let (reply, outcome) = oneshot::channel();
+    self
+      .request(Request::Quit { reply }, Priority::Normal)
+      .await?;
+    outcome.await.map_err(|_| Error::Closed)?
+  }
+
+  /// Whether the session's task has stopped, after [`quit`](Self::quit)
+  /// or once the connection was lost.
+  pub fn is_closed(&self) -> bool {
+    self.senders.queue.state().stopped
+  }
+
+  /// How many commands are waiting for the session, by priority.
+  #[must_use]
+  pub fn status(&self) -> QueueStatus {
+    let queue = &self.senders.queue;
+    let state = queue.state();
+    let [low, normal, high] = &state.pending;
+    QueueStatus {
+      max_queued: queue.max_queued,
+      high: high.len(),
+      normal: normal.len(),
+      low: low.len(),
+    }
+  }
+
+  /// Wait for room in the queue, then add `request` behind the others
+  /// at `priority`.
+  async fn request(
+    &self,
+    request: Request,
+    priority: Priority,
+  ) -> Result<(), Error> {
+    let room = Arc::clone(&self.senders.queue.room)
+      .acquire_owned()
+      .await
+      .map_err(|_| Error::Closed)?;
+    self.senders.queue.push(request, priority, room)
+  }
+}
+
+impl Clone for RconHandle {
+  /// A new handle to the same session, without the place in the queue
+  /// this one may have claimed.
+  fn clone(&self) -> Self {
+    Self::new(Arc::clone(&self.senders))
+  }
+}
+
+#[cfg(feature = "tower")]
+impl tower_service::Service<RconCommand> for RconHandle {
+  type Response = CommandOutcome;
+  type Error = Error;
+  type Future = std::pin::Pin<
+    Box<dyn Future<Output = Result<CommandOutcome, Error>> + Send>,
+  >;
+
+  fn poll_ready(
+    &mut self,
+    cx: &mut Context<'_>,
+  ) -> Poll<Result<(), Error>> {
+    let readiness = &mut self.readiness;
+    if readiness.claimed.is_none() {
+      let Some(room) = ready!(readiness.room.poll_acquire(cx)) else {
+        return Poll::Ready(Err(Error::Closed));
+      };
+      readiness.claimed = Some(room);
+    }
+    Poll::Ready(Ok(()))
+  }
+
+  fn call(&mut self, command: RconCommand) -> Self::Future {
+    let room = self
+      .readiness
+      .claimed
+      .take()
+      .expect("RconHandle called before poll_ready");
+    let (reply, outcome) = oneshot::channel();
+    let priority = command.options.queue_priority();
+    let request = Request::Command {
+      command: command.command,
+      options: command.options,
+      reply,
+    };
+    let queued = self.senders.queue.push(request, priority, room);
+    Box::pin(async move {
+      queued?;
+      outcome.await.map_err(|_| Error::Closed)?
+    })
+  }
+}
+
+impl Drop for Senders {
+  fn drop(&mut self) {
+    self.queue.state().abandoned = true;
+    self.queue.wake.notify_one();
+  }
+}
+
+impl Queue {
+  fn state(&self) -> MutexGuard<'_, State> {
+    self.state.lock().expect("queue lock poisoned")
+  }
+
+  /// Add `request` behind the others at `priority`, in the place `room`
+  /// holds for it.
+  fn push(
+    &self,
+    request: Request,
+    priority: Priority,
+    room: OwnedSemaphorePermit,
+  ) -> Result<(), Error> {
+    let mut state = self.state();
+    if state.stopped {
+      return Err(Error::Closed);
+    }
+    state.pending[priority as usize].push_back(Queued {
+      request,
+      _room: room,
+    });
+    drop(state);
+    self.wake.notify_one();
+    Ok(())
+  }
+
+  /// The oldest request at the highest priority waiting, or `None` once
+  /// the queue is empty and every handle is gone.
+  async fn next(&self) -> Option<Request> {
+    loop {
+      {
+        let mut state = self.state();
+        let next =
+          state.pending.iter_mut().rev().find_map(VecDeque::pop_front);
+        if let Some(queued) = next {
+          return Some(queued.request);
+        }
+        if state.abandoned {
+          return None;
+        }
+      }
+      self.wake.notified().await;
+    }
+  }
+
+  /// Turn new requests away and drop the waiting ones, whose senders
+  /// then see [`Error::Closed`].
+  fn stop(&self) {
+    // Also wakes the senders still waiting for room.
+    self.room.close();
+    let pending = {
+      let mut state = self.state();
+      state.stopped = true;
+      std::mem::take(&mut state.pending)
+    };
+    drop(pending);
+  }
+}
+
+/// Serve the queued requests until every handle is gone, the session is
+/// quit, or the connection closes.
+async fn run(mut client: RconClient, queue: Arc<Queue>) {
+  while let Some(request) = queue.next().await {
+    match request {
+      Request::Command {
+        command,
+        options,
+        reply,
+      } => {
+        if reply.is_closed() {
+          continue;
+        }
+        let outcome = client.send_command_with(&command, &options).await;
+        let _ = reply.send(outcome);
+        if client.is_closed() {
+          queue.stop();
+          return;
+        }
+      }
+      Request::Quit { reply } => {
+        queue.stop();
+        let _ = reply.send(client.quit().await);
+        return;
+      }
+    }
+  }
+  queue.stop();
+  if let Err(err) = client.quit().await {
+    tracing::debug!("closing shared session failed: {err}");
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use tokio::time::sleep;
+
+  use super::*;
+  use crate::protocol::Protocol;
+  use crate::testing::{MockHyrconServer, MockServer, Reply};
+
+  async fn connect(server: &MockServer) -> RconClient {
+    RconClient::connect(
+      Protocol::Hyrcon,
+      "127.0.0.1",
+      server.port(),
+      Duration::from_secs(2),
+    )
+    .await
+    .expect("connect")
+  }
+
+  #[tokio::test]
+  async fn clones_share_one_session() {
+    let server = MockHyrconServer::builder()
+      .expect("list", Reply::ok(["Steve"]))
+      .expect("seed", Reply::ok(["Seed: 42"]))
+      .start()
+      .await
+      .expect("start");
+    let handle = RconHandle::spawn(connect(&server).await);
+    let clone = handle.clone();
+
+    let list =
+      tokio::spawn(async move { clone.send_command("list").await })
+        .await
+        .expect("task")
+        .expect("list");
+    let CommandOutcome::Response(response) = list else {
+      panic!("unexpected outcome: {list:?}");
+    };
+    assert_eq!(response.payload, ["Steve"]);
+    handle.send_command("seed").await.expect("seed");
+
+    handle.quit().await.expect("quit");
+    let err = handle.send_command("list").await.expect_err("closed");
+    assert!(matches!(err, Error::Closed));
+    assert!(handle.is_closed());
+    server.finish().await.expect("script followed");
+  }
+
+  #[tokio::test]
+  async fn urgent_commands_jump_the_queue() {
+    // The slow save keeps the session busy while the others queue up.
+    let server = MockHyrconServer::builder()
+      .expect(
+        "save-all",
+        Reply::ok(["Saved"]).delay(Duration::from_millis(200)),
+      )
+      .expect("kick griefer", Reply::ok(Vec::<String>::new()))
+      .expect("list", Reply::ok(["Steve"]))
+      .start()
+      .await
+      .expect("start");
+    let handle = RconHandle::with_queue(connect(&server).await, 2);
+
+    let send = |command: &'static str, priority| {
+      let handle = handle.clone();
+      tokio::spawn(async move {
+        let options = CommandOptions::default().priority(priority);
+        handle.send_command_with(command, &options).await
+      })
+    };
+    let save = send("save-all", Priority::Normal);
+    sleep(Duration::from_millis(50)).await;
+    let list = send("list", Priority::Low);
+    let kick = send("kick griefer", Priority::High);
+    sleep(Duration::from_millis(50)).await;
+    assert_eq!(
+      handle.status(),
+      QueueStatus {
+        max_queued: 2,
+        high: 1,
+        normal: 0,
+        low: 1,
+      }
+    );
+
+    for task in [save, kick, list] {
+      task.await.expect("task").expect("command");
+    }
+    assert_eq!(handle.status().queued(), 0);
+    handle.quit().await.expect("quit");
+    server.finish().await.expect("script followed");
+  }
+
+  #[cfg(feature = "tower")]
+  #[tokio::test]
+  async fn service_waits_for_room_in_the_queue() {
+    use std::future::poll_fn;
+
+    use tower_service::Service;
+
+    let server = MockHyrconServer::builder()
+      .expect(
+        "save-all",
+        Reply::ok(["Saved"]).delay(Duration::from_millis(200)),
+      )
+      .expect("list", Reply::ok(["Steve"]))
+      .start()
+      .await
+      .expect("start");
+    let handle = RconHandle::with_queue(connect(&server).await, 1);
+
+    let mut service = handle.clone();
+    poll_fn(|cx| service.poll_ready(cx)).await.expect("ready");
+    let save = service.call("save-all".into());
+    let save = tokio::spawn(save);
+    sleep(Duration::from_millis(50)).await;
+    poll_fn(|cx| service.poll_ready(cx)).await.expect("ready");
+    let list = service.call("list".into());
+
+    // The queue's only place is taken by `list`.
+    let mut other = handle.clone();
+    let full = poll_fn(|cx| Poll::Ready(other.poll_ready(cx))).await;
+    assert!(full.is_pending());
+    // Or it would take the place `list` leaves.
+    drop(other);
+
+    save.await.expect("task").expect("save-all");
+    let CommandOutcome::Response(response) = list.await.expect("list")
+    else {
+      panic!("unexpected bye");
+    };
+    assert_eq!(response.payload, ["Steve"]);
+    handle.quit().await.expect("quit");
+    server.finish().await.expect("script followed");
+  }
+}
@@ -0,0 +1,109 @@
+//! Output of `help COMMAND`, kept on disk for each shell session so
+//! commands can be described again without asking the server.
+//!
+//! Each session has a JSON file under `help/` in the data directory,
+//! named after the session's profile (or host), mapping commands to the
+//! lines the server answered with.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use crate::cli;
+
+/// The cached help of one session.
+#[derive(Debug, Clone, Default)]
+pub struct HelpCache {
+  /// File the cache is saved to, or `None` without a data directory.
+  path: Option<PathBuf>,
+  entries: BTreeMap<String, Vec<String>>,
+}
+
+impl HelpCache {
+  /// Load the cache of the session `name`. A missing file is an empty
+  /// cache; an unreadable one is reported and started over.
+  #[must_use]
+  pub fn load(name: &str) -> Self {
+    let file_name: String = name
+      .chars()
+      .map(|c| {
+        if c.is_ascii_alphanumeric() || "-_.".contains(c) {
+          c
+        } else {
+          '_'
+        }
+      })
+      .collect();
+    let path = cli::data_dir()
+      .map(|dir| dir.join("help").join(format!("{file_name}.json")));
+    let entries = path
+      .as_ref()
+      .and_then(|path| match fs::read_to_string(path) {
+        Ok(contents) => serde_json::from_str(&contents)
+          .inspect_err(|err| {
+            tracing::warn!("ignoring {}: {err}", path.display());
+          })
+          .ok(),
+        Err(err) if err.kind() == ErrorKind::NotFound => None,
+        Err(err) => {
+          tracing::warn!("failed to read {}: {err}", path.display());
+          None
+        }
+      })
+      .unwrap_or_default();
+    Self { path, entries }
+  }
+
+  /// The cached help of `verb`, matched regardless of case.
+  #[must_use]
+  pub fn get(&self, verb: &str) -> Option<&[String]> {
+    self.entries.get(&verb.to_lowercase()).map(Vec::as_slice)
+  }
+
+  /// Every cached command with its help.
+  pub fn entries(&self) -> impl Iterator<Item = (&str, &[String])> {
+    self
+      .entries
+      .iter()
+      .map(|(verb, help)| (verb.as_str(), help.as_slice()))
+  }
+
+  /// Remember the `help` of `verb` and save the cache.
+  pub fn insert(&mut self, verb: &str, help: Vec<String>) {
+    self.entries.insert(verb.to_lowercase(), help);
+    self.save();
+  }
+
+  /// Forget every command, e.g. after the server's plugins changed.
+  pub fn clear(&mut self) {
+    if !self.entries.is_empty() {
+      self.entries.clear();
+      self.save();
+    }
+  }
+
+  /// Write the cache out. Failing only costs another round trip later,
+  /// so errors are logged rather than returned.
+  fn save(&self) {
+    let Some(path) = &self.path else {
+      return;
+    };
+    if let Some(parent) = path.parent()
+      && let Err(err) = fs::create_dir_all(parent)
+    {
+      tracing::warn!("failed to create {}: {err}", parent.display());
+      return;
+    }
+    let contents = match serde_json::to_string_pretty(&self.entries) {
+      Ok(contents) => contents,
+      Err(err) => {
+        tracing::warn!("failed to encode the help cache: {err}");
+        return;
+      }
+    };
+    if let Err(err) = fs::write(path, contents) {
+      tracing::warn!("failed to write {}: {err}", path.display());
+    }
+  }
+}
@@ -1,18 +1,63 @@
+pub mod blocking;
+#[cfg(feature = "cli")]
 pub mod cli;
+pub mod codec;
+#[cfg(feature = "cli")]
+pub mod config;
+#[cfg(feature = "cli")]
 pub mod core;
+#[cfg(feature = "cli")]
+pub mod editor;
+pub mod error;
+#[cfg(feature = "cli")]
+pub mod exit;
+pub mod format;
+pub mod handle;
+#[cfg(feature = "cli")]
+pub mod help_cache;
+#[cfg(feature = "cli")]
 pub mod logging;
+#[cfg(feature = "cli")]
+pub mod meta;
+pub mod models;
+#[cfg(feature = "cli")]
+pub mod output_log;
+pub mod pool;
 pub mod protocol;
+#[cfg(feature = "cli")]
 pub mod runtime;
+#[cfg(feature = "cli")]
+pub mod shutdown;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+#[cfg(feature = "cli")]
+pub mod transcript;
 pub mod transport;
+#[cfg(feature = "cli")]
 pub mod ui;
 pub mod util;
 
+#[cfg(feature = "cli")]
 pub use cli::Cli;
+#[cfg(feature = "cli")]
 pub use core::run;
-pub use protocol::{ParseProtocolError, Protocol};
+pub use error::Error;
+#[cfg(feature = "tower")]
+pub use handle::RconCommand;
+pub use handle::{QueueStatus, RconHandle};
+pub use pool::{PoolStatus, PooledClient, RconPool, RconPoolBuilder};
+pub use protocol::{
+  Dialect, ParseDialectError, ParseProtocolError, Protocol,
+};
+#[cfg(feature = "cli")]
 pub use runtime::Runtime;
 pub use transport::{
-  AuthMode, AuthOutcome, CommandOutcome, Greeting, RconClient,
-  RconResponse, ResponseStatus,
+  AuthMode, AuthOutcome, ClientEvent, CommandOptions, CommandOutcome,
+  ConnectOptions, Connector, Greeting, IpFamily, ManagedClient,
+  ParseProxyError, ParseResolveOverrideError, ParseSshTunnelError,
+  Priority, ProtocolBackend, Proxy, ProxyScheme, RconClient,
+  RconClientBuilder, RconConnector, RconResponse, ResolveOverride,
+  ResponseStatus, ServerMessage, SessionStats, SrvTarget, SshTunnel,
+  TimedOut, TlsOptions,
 };
 pub use util::command;
@@ -7,7 +7,8 @@ use tracing_subscriber::EnvFilter;
 ///   * `1` → DEBUG
 ///   * `2+` → TRACE
 ///
-/// `use_color` controls whether ANSI colour codes are emitted.
+/// `use_color` controls whether ANSI colour codes are emitted. Logs go to
+/// stderr so they never mix with command output such as `--output json`.
 pub fn init(verbosity: u8, use_color: bool) {
   // Map CLI verbosity to a tracing level.
   let level = match verbosity {
@@ -26,6 +27,7 @@ pub fn init(verbosity: u8, use_color: bool) {
     .with_target(false)
     .with_level(true)
     .with_ansi(use_color)
+    .with_writer(std::io::stderr)
     .compact()
     .init();
 }
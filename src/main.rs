@@ -1,15 +1,21 @@
-use clap::Parser;
-use hyrcon_client::{Cli, Runtime};
+use anyhow::{Context, Result};
+use clap::CommandFactory;
+use clap_complete::CompleteEnv;
+use hyrcon_client::cli::COMPLETE_ENV;
+use hyrcon_client::{Cli, Runtime, runtime, util::dotenv};
 use std::{
   env,
   ffi::{OsStr, OsString},
+  fs,
+  path::{Path, PathBuf},
 };
 
 const PRIMARY_PREFIX: &str = "RCON_";
 const ALIAS_PREFIX: &str = "HYRCON_";
+const DEFAULT_DOTENV: &str = ".env";
 
 fn set_env_var(key: &str, value: &OsStr) {
-  // SAFETY: the key and value originate from the process environment and therefore satisfy the platform-specific requirements for environment variables.
+  // SAFETY: the key and value originate from the process environment or a parsed dotenv file and therefore satisfy the platform-specific requirements for environment variables.
   unsafe {
     env::set_var(key, value);
   }
@@ -37,11 +43,103 @@ fn mirror_env_aliases() {
   }
 }
 
+/// Locate the dotenv file requested on the command line, if any.
+///
+/// This runs before clap so that the loaded variables can feed `env = ...`
+/// defaults; scanning stops at `--` so trailing commands are never parsed.
+fn requested_env_file() -> Option<PathBuf> {
+  let mut args = env::args_os().skip(1);
+  let mut discover = false;
+
+  while let Some(arg) = args.next() {
+    if arg == "--" {
+      break;
+    }
+    if arg == "--env-file" {
+      return args.next().map(PathBuf::from);
+    }
+    if let Some(path) =
+      arg.to_str().and_then(|arg| arg.strip_prefix("--env-file="))
+    {
+      return Some(PathBuf::from(path));
+    }
+    if arg == "--dotenv" {
+      discover = true;
+    }
+  }
+
+  let fallback = Path::new(DEFAULT_DOTENV);
+  (discover && fallback.is_file()).then(|| fallback.to_path_buf())
+}
+
+/// Export connection variables from a dotenv file.
+///
+/// Only `RCON_*`/`HYRCON_*` keys are honoured, and variables that are
+/// already present in the environment are left untouched. Explicit flags
+/// always win because clap prefers them over environment defaults.
+fn load_env_file(path: &Path) -> Result<()> {
+  let contents = fs::read_to_string(path)
+    .with_context(|| format!("failed to read {}", path.display()))?;
+  let vars = dotenv::parse(&contents)
+    .with_context(|| format!("failed to parse {}", path.display()))?;
+
+  for (key, value) in vars {
+    let recognised =
+      key.starts_with(PRIMARY_PREFIX) || key.starts_with(ALIAS_PREFIX);
+    if recognised && env::var_os(&key).is_none() {
+      set_env_var(&key, OsStr::new(&value));
+    }
+  }
+
+  Ok(())
+}
+
 #[tokio::main]
 async fn main() {
+  // Answer a completion request from the `completions` scripts before
+  // anything else touches stdout.
+  CompleteEnv::with_factory(Cli::command)
+    .var(COMPLETE_ENV)
+    .complete();
+
+  if let Some(path) = requested_env_file()
+    && let Err(err) = load_env_file(&path)
+  {
+    runtime::report_error(&err);
+    std::process::exit(1);
+  }
+
   mirror_env_aliases();
 
-  let cli = Cli::parse();
+  let cli = Cli::parse_with_sources();
   let exit_code = Runtime::new(cli).execute().await;
   std::process::exit(exit_code);
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use hyrcon_client::protocol::Protocol;
+
+  #[test]
+  fn explicit_flags_beat_dotenv_values() {
+    let path = env::temp_dir()
+      .join(format!("hyrcon-dotenv-{}.env", std::process::id()));
+    fs::write(&path, "RCON_PROTOCOL=hyrcon\nRCON_PASSWORD=from-dotenv\n")
+      .expect("write dotenv");
+
+    load_env_file(&path).expect("load dotenv");
+    mirror_env_aliases();
+    fs::remove_file(&path).ok();
+
+    let cli = Cli::try_parse_with_sources([
+      "hyrcon-client",
+      "--password",
+      "from-flag",
+      "status",
+    ])
+    .expect("parse arguments");
+    assert_eq!(cli.password.as_deref(), Some("from-flag"));
+    assert_eq!(cli.protocol, Protocol::Hyrcon);
+  }
+}
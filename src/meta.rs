@@ -0,0 +1,313 @@
+//! Commands the interactive shell handles itself instead of sending them
+//! to the server, written with a leading `:`.
+//!
+//! A leading `/` works too for the commands no server is likely to have
+//! itself, such as `/timeout` or `/quit-local`, and a leading `!` runs
+//! the rest of the line with the local shell.
+
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::util::command::is_variable_name;
+
+/// A command for the shell itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetaCommand {
+  /// List the meta-commands.
+  Help,
+  /// Show the connection and the shell's settings.
+  Status,
+  /// Show the reply timeout, or change it.
+  Timeout(Option<Duration>),
+  /// Turn colored output off, or back on.
+  Plain,
+  /// Ask the server for its commands again, for tab completion.
+  RefreshCompletions,
+  /// Show the server's help for a command, cached after the first time.
+  Describe(String),
+  /// Drop the active session's connection and open it again.
+  Reconnect,
+  /// Open another session with a config profile and make it active.
+  Connect(String),
+  /// Make the named session active, or list the sessions.
+  Switch(Option<String>),
+  /// Run the lines of a macro from the config file.
+  Run(String),
+  /// Run the commands of a script file, as `--script` does.
+  Source(PathBuf),
+  /// Give a variable a value for `$NAME` and `{NAME}` in later commands.
+  Set(String, String),
+  /// List the variables and their values.
+  Vars,
+  /// Show where the transcript goes, or start recording one to a file.
+  Record(Option<PathBuf>),
+  /// Stop recording the transcript.
+  StopRecording,
+  /// Run a command with the local shell and print its output.
+  Shell(String),
+  /// Leave the shell without sending anything to the server.
+  QuitLocal,
+}
+
+impl MetaCommand {
+  /// Usage and summary of every meta-command, for `:help`.
+  pub const USAGE: [(&'static str, &'static str); 16] = [
+    (":help", "List these commands"),
+    (":status", "Show the connection and session settings"),
+    (
+      ":timeout [DURATION]",
+      "Show or change how long to wait for replies, e.g. `20s`",
+    ),
+    (":plain", "Turn colors off, or back on"),
+    (
+      ":refresh-completions",
+      "Ask the server for its commands again",
+    ),
+    (
+      ":describe COMMAND",
+      "Show the server's help for a command, cached per profile",
+    ),
+    (
+      ":reconnect",
+      "Connect and authenticate to the active server again",
+    ),
+    (
+      ":connect PROFILE",
+      "Open another session with a config profile",
+    ),
+    (
+      ":switch [NAME]",
+      "Send commands to another session, or list them",
+    ),
+    (
+      ":run MACRO",
+      "Run the lines of a macro from the config file",
+    ),
+    (
+      ":source FILE",
+      "Run the commands of a script file over this session",
+    ),
+    (
+      ":set NAME=VALUE",
+      "Set a variable for `$NAME` in later commands",
+    ),
+    (":vars", "List the variables and their values"),
+    (
+      ":record [FILE|off]",
+      "Record a transcript of the session to a file, or stop",
+    ),
+    ("!COMMAND", "Run a command with the local shell"),
+    (":quit-local", "Leave the shell without telling the server"),
+  ];
+
+  /// Read `line` as a meta-command. `None` means it is meant for the
+  /// server.
+  pub fn parse(line: &str) -> Option<Result<Self, ParseMetaCommandError>> {
+    let line = line.trim();
+    if let Some(command) = line.strip_prefix('!') {
+      return Some(match command.trim() {
+        "" => Err(ParseMetaCommandError {
+          reason: "expected a local command after `!`",
+        }),
+        command => Ok(Self::Shell(command.to_string())),
+      });
+    }
+    let (slash, rest) = match line.strip_prefix(':') {
+      Some(rest) => (false, rest),
+      None => (true, line.strip_prefix('/')?),
+    };
+    let mut words = rest.split_whitespace();
+    let name = words.next().unwrap_or_default();
+    let argument = words.next();
+    if slash && !Self::takes_slash(name) {
+      return None;
+    }
+
+    let error = |reason| Some(Err(ParseMetaCommandError { reason }));
+    // Values may hold spaces, so `:set` takes the rest of the line.
+    if name == "set" {
+      let assignment = rest.trim_start()["set".len()..].trim();
+      let Some((var, value)) = assignment.split_once('=') else {
+        return error("expected NAME=VALUE");
+      };
+      if !is_variable_name(var.trim()) {
+        return error("names use letters, digits, `_` and `-`");
+      }
+      return Some(Ok(Self::Set(
+        var.trim().to_string(),
+        value.trim().to_string(),
+      )));
+    }
+    if words.next().is_some() {
+      return error("too many arguments");
+    }
+    let command = match (name, argument) {
+      ("help", None) => Self::Help,
+      ("status", None) => Self::Status,
+      ("timeout", None) => Self::Timeout(None),
+      ("timeout", Some(duration)) => match parse_duration(duration) {
+        Some(duration) => Self::Timeout(Some(duration)),
+        None => return error("expected a duration such as `20s`"),
+      },
+      ("plain", None) => Self::Plain,
+      ("refresh-completions", None) => Self::RefreshCompletions,
+      ("reconnect", None) => Self::Reconnect,
+      ("describe", Some(verb)) => Self::Describe(verb.to_string()),
+      ("describe", None) => return error("expected a command name"),
+      ("connect", Some(profile)) => Self::Connect(profile.to_string()),
+      ("connect", None) => return error("expected a profile name"),
+      ("switch", name) => Self::Switch(name.map(str::to_string)),
+      ("run", Some(name)) => Self::Run(name.to_string()),
+      ("run", None) => return error("expected a macro name"),
+      ("source", Some("-")) => return error("expected a file, not stdin"),
+      ("source", Some(path)) => Self::Source(PathBuf::from(path)),
+      ("source", None) => return error("expected a script file"),
+      ("vars", None) => Self::Vars,
+      ("record", Some("off")) => Self::StopRecording,
+      ("record", path) => Self::Record(path.map(PathBuf::from)),
+      ("quit-local", None) => Self::QuitLocal,
+      (
+        "help"
+        | "status"
+        | "plain"
+        | "refresh-completions"
+        | "reconnect"
+        | "vars"
+        | "quit-local",
+        _,
+      ) => {
+        return error("takes no arguments");
+      }
+      _ => return error("unknown command; try `:help`"),
+    };
+    Some(Ok(command))
+  }
+
+  /// Whether `/name` means the meta-command rather than a server command.
+  fn takes_slash(name: &str) -> bool {
+    matches!(
+      name,
+      "timeout" | "plain" | "refresh-completions" | "quit-local"
+    )
+  }
+}
+
+/// A duration such as `20s`, `1500ms` or `2m`; a bare number is seconds.
+pub(crate) fn parse_duration(raw: &str) -> Option<Duration> {
+  let split = raw
+    .find(|c: char| !c.is_ascii_digit() && c != '.')
+    .unwrap_or(raw.len());
+  let (value, unit) = raw.split_at(split);
+  let value: f64 = value.parse().ok()?;
+  let seconds = match unit {
+    "ms" => value / 1_000.0,
+    "" | "s" => value,
+    "m" => value * 60.0,
+    _ => return None,
+  };
+  Duration::try_from_secs_f64(seconds)
+    .ok()
+    .filter(|duration| !duration.is_zero())
+}
+
+/// Error returned when a `:` line is not a valid [`MetaCommand`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseMetaCommandError {
+  reason: &'static str,
+}
+
+impl fmt::Display for ParseMetaCommandError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "invalid shell command: {}", self.reason)
+  }
+}
+
+impl std::error::Error for ParseMetaCommandError {}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn colon_lines_are_meta_commands() {
+    assert_eq!(MetaCommand::parse(":help"), Some(Ok(MetaCommand::Help)));
+    assert_eq!(
+      MetaCommand::parse(" :timeout 20s "),
+      Some(Ok(MetaCommand::Timeout(Some(Duration::from_secs(20)))))
+    );
+    assert_eq!(
+      MetaCommand::parse(":timeout 1500ms"),
+      Some(Ok(MetaCommand::Timeout(Some(Duration::from_millis(1500)))))
+    );
+    assert_eq!(
+      MetaCommand::parse(":timeout"),
+      Some(Ok(MetaCommand::Timeout(None)))
+    );
+    assert!(matches!(MetaCommand::parse(":timeout soon"), Some(Err(_))));
+    assert!(matches!(MetaCommand::parse(":timeout 0"), Some(Err(_))));
+    assert!(matches!(MetaCommand::parse(":plain on"), Some(Err(_))));
+    assert_eq!(
+      MetaCommand::parse(":describe whitelist"),
+      Some(Ok(MetaCommand::Describe("whitelist".to_string())))
+    );
+    assert_eq!(
+      MetaCommand::parse(":reconnect"),
+      Some(Ok(MetaCommand::Reconnect))
+    );
+    assert!(matches!(MetaCommand::parse(":reconnect now"), Some(Err(_))));
+    assert!(matches!(MetaCommand::parse(":teleport"), Some(Err(_))));
+    assert_eq!(
+      MetaCommand::parse(":connect lobby"),
+      Some(Ok(MetaCommand::Connect("lobby".to_string())))
+    );
+    assert!(matches!(MetaCommand::parse(":connect"), Some(Err(_))));
+    assert_eq!(
+      MetaCommand::parse(":switch"),
+      Some(Ok(MetaCommand::Switch(None)))
+    );
+    assert_eq!(
+      MetaCommand::parse(":run restartwarn"),
+      Some(Ok(MetaCommand::Run("restartwarn".to_string())))
+    );
+    assert_eq!(
+      MetaCommand::parse(":source playbooks/restart.rcon"),
+      Some(Ok(MetaCommand::Source("playbooks/restart.rcon".into())))
+    );
+    assert!(matches!(MetaCommand::parse(":source"), Some(Err(_))));
+    assert_eq!(
+      MetaCommand::parse(":record incident.jsonl"),
+      Some(Ok(MetaCommand::Record(Some("incident.jsonl".into()))))
+    );
+    assert_eq!(
+      MetaCommand::parse(":record off"),
+      Some(Ok(MetaCommand::StopRecording))
+    );
+    assert_eq!(
+      MetaCommand::parse(":set reason = griefing at spawn"),
+      Some(Ok(MetaCommand::Set(
+        "reason".to_string(),
+        "griefing at spawn".to_string()
+      )))
+    );
+    assert!(matches!(MetaCommand::parse(":set player"), Some(Err(_))));
+    assert!(matches!(MetaCommand::parse(":set 1st=x"), Some(Err(_))));
+    assert_eq!(
+      MetaCommand::parse("!dig play.example.com"),
+      Some(Ok(MetaCommand::Shell("dig play.example.com".to_string())))
+    );
+    assert!(matches!(MetaCommand::parse("! "), Some(Err(_))));
+    assert_eq!(MetaCommand::parse("say :)"), None);
+  }
+
+  #[test]
+  fn slashes_leave_server_commands_alone() {
+    assert_eq!(
+      MetaCommand::parse("/quit-local"),
+      Some(Ok(MetaCommand::QuitLocal))
+    );
+    assert_eq!(MetaCommand::parse("/help"), None);
+    assert_eq!(MetaCommand::parse("/status"), None);
+    assert_eq!(MetaCommand::parse("/say hi"), None);
+  }
+}
@@ -0,0 +1,372 @@
+//! Typed views of the replies to well-known commands, so callers do not
+//! each have to pick apart the text themselves.
+//!
+//! Parse an [`RconResponse`] with [`RconResponse::parse`]:
+//!
+//! ```no_run
+//! use hyrcon_client::models::PlayerList;
+//! use hyrcon_client::{CommandOutcome, RconClient};
+//!
+//! # async fn example(client: &mut RconClient) -> anyhow::Result<()> {
+//! if let CommandOutcome::Response(response) =
+//!   client.send_command("list").await?
+//! {
+//!   let list: PlayerList = response.parse()?;
+//!   println!("{} of {:?} online", list.online, list.max);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Games change their output between versions, so fields the reply did
+//! not have are `None` rather than errors.
+
+use std::fmt;
+use std::net::SocketAddr;
+
+use crate::format::translate_section_codes;
+use crate::transport::{RconResponse, ResponseStatus};
+
+/// A type that can be read from a command's reply.
+pub trait FromResponse: Sized {
+  fn from_response(
+    response: &RconResponse,
+  ) -> Result<Self, ParseResponseError>;
+}
+
+/// A player as listed by `status`, `list` or `PLAYERS`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Player {
+  pub name: String,
+  /// Server-side identity, such as a Steam id.
+  pub id: Option<String>,
+  /// Round trip in milliseconds.
+  pub ping: Option<u32>,
+  pub addr: Option<SocketAddr>,
+}
+
+/// The reply to Source `status`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServerStatus {
+  pub hostname: Option<String>,
+  pub version: Option<String>,
+  pub map: Option<String>,
+  /// Players connected, humans and bots.
+  pub player_count: Option<u32>,
+  pub max_players: Option<u32>,
+  pub players: Vec<Player>,
+}
+
+/// The reply to Minecraft `list` or HYRCON `PLAYERS`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PlayerList {
+  pub online: u32,
+  /// Player slots, when the server says.
+  pub max: Option<u32>,
+  pub players: Vec<Player>,
+}
+
+impl FromResponse for ServerStatus {
+  /// Reads the `key : value` header and the `#` player table of Source
+  /// engine `status` output.
+  fn from_response(
+    response: &RconResponse,
+  ) -> Result<Self, ParseResponseError> {
+    let lines = successful(response, "status")?;
+    let mut status = Self::default();
+    let mut recognized = false;
+    for line in lines {
+      let line = line.trim();
+      if let Some(row) = line.strip_prefix('#') {
+        if let Some(player) = status_player(row) {
+          status.players.push(player);
+        }
+        continue;
+      }
+      let Some((key, value)) = line.split_once(':') else {
+        continue;
+      };
+      let value = value.trim();
+      match key.trim() {
+        "hostname" => status.hostname = Some(value.to_string()),
+        "version" => status.version = Some(value.to_string()),
+        "map" => {
+          status.map = value.split_whitespace().next().map(str::to_string);
+        }
+        "players" => {
+          status.player_count = leading_number(value);
+          status.max_players = value
+            .split_once('(')
+            .and_then(|(_, slots)| leading_number(slots));
+        }
+        _ => continue,
+      }
+      recognized = true;
+    }
+    if !recognized && status.players.is_empty() {
+      return Err(ParseResponseError::new("status", "no status fields"));
+    }
+    Ok(status)
+  }
+}
+
+impl FromResponse for PlayerList {
+  /// Reads Minecraft's `There are N of a max of M players online: ...`
+  /// (or the older `N/M` form, names on the next line) and otherwise
+  /// HYRCON `PLAYERS`: one player per line, the name followed by any of
+  /// `id=`, `ping=` and `addr=`.
+  fn from_response(
+    response: &RconResponse,
+  ) -> Result<Self, ParseResponseError> {
+    let lines = successful(response, "player list")?;
+    let Some(first) = lines.first() else {
+      return Ok(Self::default());
+    };
+    let first = translate_section_codes(first, false);
+    if first.trim_start().starts_with("There are") {
+      let rest: Vec<_> = lines[1..]
+        .iter()
+        .map(|line| translate_section_codes(line, false).into_owned())
+        .collect();
+      return minecraft_list(&first, &rest);
+    }
+
+    let players = lines
+      .iter()
+      .filter(|line| !line.trim().is_empty())
+      .map(|line| hyrcon_player(line))
+      .collect::<Result<Vec<_>, _>>()?;
+    Ok(Self {
+      online: players.len() as u32,
+      max: None,
+      players,
+    })
+  }
+}
+
+/// The reply's lines, unless the server answered with an error.
+fn successful<'a>(
+  response: &'a RconResponse,
+  model: &'static str,
+) -> Result<&'a [String], ParseResponseError> {
+  match response.status {
+    ResponseStatus::Ok => Ok(&response.payload),
+    ResponseStatus::Err => Err(ParseResponseError::new(
+      model,
+      "the server answered an error",
+    )),
+  }
+}
+
+/// A row of the `status` player table, e.g.
+/// `  2 "alice" STEAM_1:0:123 05:12 48 0 active 1.2.3.4:27005`. The
+/// column header and `#end` have no quoted name and are skipped.
+fn status_player(row: &str) -> Option<Player> {
+  let (_, rest) = row.split_once('"')?;
+  let (name, rest) = rest.rsplit_once('"')?;
+  let columns: Vec<_> = rest.split_whitespace().collect();
+  // Bots have no connection time, ping or address.
+  let connected = columns.get(1).is_some_and(|time| time.contains(':'));
+  Some(Player {
+    name: name.to_string(),
+    id: columns.first().map(|id| id.to_string()),
+    ping: columns
+      .get(2)
+      .filter(|_| connected)
+      .and_then(|ping| ping.parse().ok()),
+    addr: columns.last().and_then(|addr| addr.parse().ok()),
+  })
+}
+
+fn minecraft_list(
+  first: &str,
+  rest: &[String],
+) -> Result<PlayerList, ParseResponseError> {
+  let invalid =
+    || ParseResponseError::new("player list", "no player count");
+  let counts = first
+    .trim_start()
+    .trim_start_matches("There are")
+    .trim_start();
+  let (counts, names) = counts.split_once(':').unwrap_or((counts, ""));
+  let online = leading_number(counts).ok_or_else(invalid)?;
+  let max = match counts.split_once('/') {
+    Some((_, max)) => leading_number(max),
+    None => counts
+      .split_once("a max of")
+      .and_then(|(_, max)| leading_number(max.trim_start())),
+  };
+
+  let names = match names.trim() {
+    "" => rest.first().map(String::as_str).unwrap_or_default(),
+    names => names,
+  };
+  let players = names
+    .split(',')
+    .map(str::trim)
+    .filter(|name| !name.is_empty())
+    .map(|name| Player {
+      name: name.to_string(),
+      id: None,
+      ping: None,
+      addr: None,
+    })
+    .collect();
+  Ok(PlayerList {
+    online,
+    max,
+    players,
+  })
+}
+
+fn hyrcon_player(line: &str) -> Result<Player, ParseResponseError> {
+  let mut fields = line.split_whitespace();
+  let name = fields.next().unwrap_or_default();
+  let mut player = Player {
+    name: name.to_string(),
+    id: None,
+    ping: None,
+    addr: None,
+  };
+  let invalid = || ParseResponseError::new("player list", "bad field");
+  for field in fields {
+    let (key, value) = field.split_once('=').ok_or_else(invalid)?;
+    match key {
+      "id" => player.id = Some(value.to_string()),
+      "ping" => player.ping = Some(value.parse().map_err(|_| invalid())?),
+      "addr" => player.addr = Some(value.parse().map_err(|_| invalid())?),
+      _ => {}
+    }
+  }
+  Ok(player)
+}
+
+/// The number at the start of `text`, e.g. `16` in `16/0 max)`.
+fn leading_number(text: &str) -> Option<u32> {
+  let end = text
+    .find(|c: char| !c.is_ascii_digit())
+    .unwrap_or(text.len());
+  text[..end].parse().ok()
+}
+
+/// Error returned when a reply does not have the expected shape.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseResponseError {
+  model: &'static str,
+  reason: &'static str,
+}
+
+impl ParseResponseError {
+  fn new(model: &'static str, reason: &'static str) -> Self {
+    Self { model, reason }
+  }
+}
+
+impl fmt::Display for ParseResponseError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "unexpected {} output: {}", self.model, self.reason)
+  }
+}
+
+impl std::error::Error for ParseResponseError {}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn reply(lines: &[&str]) -> RconResponse {
+    RconResponse {
+      status: ResponseStatus::Ok,
+      payload: lines.iter().map(|line| line.to_string()).collect(),
+      error: None,
+      raw: String::new(),
+    }
+  }
+
+  #[test]
+  fn source_status_is_parsed() {
+    let status: ServerStatus = reply(&[
+      "hostname: Dust 24/7",
+      "version : 1.38.7.9/13879 1575/8835 secure",
+      "udp/ip  : 10.0.0.7:27015  (public ip: 203.0.113.9)",
+      "map     : de_dust2 at: 0 x, 0 y, 0 z",
+      "players : 2 humans, 1 bots (16/0 max) (not hibernating)",
+      "",
+      "# userid name uniqueid connected ping loss state rate adr",
+      "#      2 \"alice\" STEAM_1:0:123 05:12 48 0 active 196608 198.51.100.4:27005",
+      "#      3 \"Bob \\\"the\\\" Builder\" STEAM_1:1:456 01:02 65 0 active 196608 198.51.100.5:27005",
+      "#      4 \"Bot\" BOT active",
+      "#end",
+    ])
+    .parse()
+    .expect("status");
+
+    assert_eq!(status.hostname.as_deref(), Some("Dust 24/7"));
+    assert_eq!(status.map.as_deref(), Some("de_dust2"));
+    assert_eq!(status.player_count, Some(2));
+    assert_eq!(status.max_players, Some(16));
+    assert_eq!(status.players.len(), 3);
+    assert_eq!(
+      status.players[0],
+      Player {
+        name: "alice".to_string(),
+        id: Some("STEAM_1:0:123".to_string()),
+        ping: Some(48),
+        addr: Some("198.51.100.4:27005".parse().expect("addr")),
+      }
+    );
+    assert_eq!(status.players[1].name, "Bob \\\"the\\\" Builder");
+    assert_eq!(status.players[2].ping, None);
+    assert!(reply(&["Unknown command"]).parse::<ServerStatus>().is_err());
+  }
+
+  #[test]
+  fn minecraft_lists_are_parsed() {
+    let list: PlayerList =
+      reply(&["There are 2 of a max of 20 players online: alice, §bbob"])
+        .parse()
+        .expect("list");
+    assert_eq!(list.online, 2);
+    assert_eq!(list.max, Some(20));
+    let names: Vec<_> = list
+      .players
+      .iter()
+      .map(|player| player.name.as_str())
+      .collect();
+    assert_eq!(names, ["alice", "bob"]);
+
+    let older: PlayerList =
+      reply(&["There are 1/10 players online:", "carol"])
+        .parse()
+        .expect("older list");
+    assert_eq!((older.online, older.max), (1, Some(10)));
+    assert_eq!(older.players[0].name, "carol");
+
+    let empty: PlayerList =
+      reply(&["There are 0 of a max of 20 players online:"])
+        .parse()
+        .expect("empty list");
+    assert!(empty.players.is_empty());
+  }
+
+  #[test]
+  fn hyrcon_players_are_parsed() {
+    let list: PlayerList =
+      reply(&["Steve id=069a79f4 ping=42 addr=10.0.0.5:51234", "Alex"])
+        .parse()
+        .expect("players");
+    assert_eq!(list.online, 2);
+    assert_eq!(list.players[0].id.as_deref(), Some("069a79f4"));
+    assert_eq!(list.players[0].ping, Some(42));
+    assert_eq!(list.players[1].addr, None);
+    assert!(reply(&["Steve ping=fast"]).parse::<PlayerList>().is_err());
+
+    let failed = RconResponse {
+      status: ResponseStatus::Err,
+      payload: Vec::new(),
+      error: Some("unknown command".to_string()),
+      raw: String::new(),
+    };
+    assert!(failed.parse::<PlayerList>().is_err());
+  }
+}
@@ -0,0 +1,105 @@
+//! Append-only record of a session's commands and responses, written by
+//! `--log-output` alongside the normal terminal output.
+//!
+//! Every line starts with a local RFC 3339 timestamp, followed by a
+//! marker: `>` for a command, two spaces for payload lines, `!` for
+//! errors and `*` for pushed messages and connection events. Formatting
+//! codes are stripped.
+
+use std::fmt::Write as _;
+use std::fs::{File, OpenOptions};
+use std::io::Write as _;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result};
+use chrono::{Local, SecondsFormat};
+
+use crate::format::translate_section_codes;
+use crate::transport::{CommandOutcome, ResponseStatus, ServerMessage};
+
+static LOG: OnceLock<File> = OnceLock::new();
+
+/// Start appending to the file at `path`, creating it if needed. Until
+/// this is called, the recording functions do nothing.
+pub fn init(path: &Path) -> Result<()> {
+  let file = OpenOptions::new()
+    .create(true)
+    .append(true)
+    .open(path)
+    .with_context(|| format!("failed to open {}", path.display()))?;
+  // Only the first call takes effect, like `logging::init`.
+  let _ = LOG.set(file);
+  Ok(())
+}
+
+/// Record `command` and the server's answer to it.
+pub fn exchange(command: &str, outcome: &CommandOutcome) {
+  record(|entry| {
+    entry.line('>', command);
+    match outcome {
+      CommandOutcome::Response(response) => {
+        for line in &response.payload {
+          entry.line(' ', &translate_section_codes(line, false));
+        }
+        if let Some(error) = &response.error {
+          entry.line('!', &format!("ERR {error}"));
+        } else if response.status == ResponseStatus::Err {
+          entry.line('!', "ERR");
+        }
+      }
+      CommandOutcome::Bye => entry.line('*', "session closed by server"),
+    }
+  });
+}
+
+/// Record a `command` that got no answer.
+pub fn failure(command: &str, err: &anyhow::Error) {
+  record(|entry| {
+    entry.line('>', command);
+    entry.line('!', &format!("{err:#}"));
+  });
+}
+
+/// Record a message pushed by the server.
+pub fn message(message: &ServerMessage) {
+  let label = message.kind.as_deref().unwrap_or("Console");
+  record(|entry| {
+    for line in message.text.lines() {
+      let text = translate_section_codes(line, false);
+      entry.line('*', &format!("[{label}] {text}"));
+    }
+  });
+}
+
+/// Record a connection event, such as a reconnect.
+pub fn event(text: &str) {
+  record(|entry| entry.line('*', text));
+}
+
+/// Lines of one record, stamped with the same time.
+struct Entry {
+  timestamp: String,
+  text: String,
+}
+
+impl Entry {
+  fn line(&mut self, marker: char, text: &str) {
+    let _ = writeln!(self.text, "{} {marker} {text}", self.timestamp);
+  }
+}
+
+fn record(build: impl FnOnce(&mut Entry)) {
+  let Some(mut file) = LOG.get() else {
+    return;
+  };
+  let mut entry = Entry {
+    timestamp: Local::now().to_rfc3339_opts(SecondsFormat::Millis, false),
+    text: String::new(),
+  };
+  build(&mut entry);
+  // One write per record keeps concurrent appends from interleaving.
+  if let Err(err) = file.write_all(entry.text.as_bytes()) {
+    tracing::warn!("failed to write to the output log: {err}");
+  }
+}
@@ -0,0 +1,409 @@
+//! Bounded pool of authenticated [`RconClient`] sessions for embedding
+//! the client in long-running services.
+//!
+//! Sessions are opened lazily (or up front with
+//! [`RconPoolBuilder::min_idle`]), checked out with [`RconPool::get`] and
+//! returned to the pool when the [`PooledClient`] is dropped. Closed
+//! sessions are discarded on checkout, sessions that sat idle for a while
+//! are probed first, and replacements are connected on demand.
+//!
+//! ```no_run
+//! use hyrcon_client::{Protocol, RconPool};
+//!
+//! # async fn example() -> anyhow::Result<()> {
+//! let pool = RconPool::builder(Protocol::Source, "10.0.0.7", 25_575)
+//!   .password("hunter2")
+//!   .max_size(4)
+//!   .build()
+//!   .await?;
+//!
+//! let mut client = pool.get().await?;
+//! client.send_command("list").await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::{Instant, timeout};
+
+use crate::error::Error;
+use crate::protocol::Protocol;
+use crate::transport::{
+  ConnectOptions, RconClient, RconConnector, TimedOut,
+};
+
+const DEFAULT_MAX_SIZE: usize = 4;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(8);
+const DEFAULT_HEALTH_CHECK_AFTER: Duration = Duration::from_secs(30);
+
+/// Shared handle to a pool of sessions with one server. Cloning is cheap
+/// and every clone draws from the same sessions.
+#[derive(Debug, Clone)]
+pub struct RconPool {
+  shared: Arc<Shared>,
+}
+
+/// Builder for [`RconPool`].
+#[derive(Debug)]
+pub struct RconPoolBuilder {
+  protocol: Protocol,
+  host: String,
+  port: u16,
+  options: ConnectOptions,
+  password: Option<String>,
+  timeout: Duration,
+  max_size: usize,
+  min_idle: usize,
+  health_check_after: Duration,
+}
+
+/// Snapshot of how a pool's sessions are being used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStatus {
+  /// Upper bound on open sessions.
+  pub max_size: usize,
+  /// Sessions checked out, or being connected for a checkout.
+  pub in_use: usize,
+  /// Open sessions waiting in the pool.
+  pub idle: usize,
+}
+
+#[derive(Debug)]
+struct Shared {
+  connector: RconConnector,
+  timeout: Duration,
+  max_size: usize,
+  health_check_after: Duration,
+  permits: Arc<Semaphore>,
+  // Most recently returned last, so warm sessions are reused first.
+  idle: Mutex<Vec<IdleClient>>,
+}
+
+#[derive(Debug)]
+struct IdleClient {
+  client: RconClient,
+  since: Instant,
+}
+
+impl RconPool {
+  pub fn builder(
+    protocol: Protocol,
+    host: impl Into<String>,
+    port: u16,
+  ) -> RconPoolBuilder {
+    RconPoolBuilder {
+      protocol,
+      host: host.into(),
+      port,
+      options: ConnectOptions::default(),
+      password: None,
+      timeout: DEFAULT_TIMEOUT,
+      max_size: DEFAULT_MAX_SIZE,
+      min_idle: 0,
+      health_check_after: DEFAULT_HEALTH_CHECK_AFTER,
+    }
+  }
+
+  /// Check out a session, waiting up to the pool timeout for one to be
+  /// returned when all of them are in use.
+  ///
+  /// Idle sessions that have closed or fail a keepalive probe are dropped
+  /// and replaced by a freshly authenticated one. Server messages pushed
+  /// while a session sat in the pool are discarded.
+  pub async fn get(&self) -> Result<PooledClient, Error> {
+    let shared = &self.shared;
+    let permit =
+      timeout(shared.timeout, Arc::clone(&shared.permits).acquire_owned())
+        .await
+        .map_err(|_| {
+          TimedOut::new(
+            "waiting for a pooled RCON session",
+            shared.timeout,
+          )
+        })?
+        .expect("pool semaphore is never closed");
+
+    let client = loop {
+      let Some(mut idle) = shared.pop_idle() else {
+        break shared.open().await?;
+      };
+      if idle.client.is_closed() {
+        tracing::debug!("discarding closed pooled session");
+        continue;
+      }
+      if idle.since.elapsed() >= shared.health_check_after
+        && let Err(err) = idle.client.keepalive().await
+      {
+        tracing::debug!("discarding unhealthy pooled session: {err:#}");
+        continue;
+      }
+      idle.client.take_messages();
+      break idle.client;
+    };
+
+    Ok(PooledClient {
+      client: Some(client),
+      shared: Arc::clone(shared),
+      _permit: permit,
+    })
+  }
+
+  #[must_use]
+  pub fn status(&self) -> PoolStatus {
+    let shared = &self.shared;
+    PoolStatus {
+      max_size: shared.max_size,
+      in_use: shared.max_size - shared.permits.available_permits(),
+      idle: shared.idle.lock().expect("pool lock poisoned").len(),
+    }
+  }
+}
+
+impl RconPoolBuilder {
+  /// Protocol-specific connection settings for every session.
+  #[must_use]
+  pub fn options(mut self, options: ConnectOptions) -> Self {
+    self.options = options;
+    self
+  }
+
+  /// Authenticate every new session with `password`.
+  #[must_use]
+  pub fn password(mut self, password: impl Into<String>) -> Self {
+    self.password = Some(password.into());
+    self
+  }
+
+  /// Bound on connecting, and on waiting for a free session in
+  /// [`RconPool::get`] (default 8 seconds).
+  #[must_use]
+  pub fn timeout(mut self, timeout: Duration) -> Self {
+    self.timeout = timeout;
+    self
+  }
+
+  /// Most sessions open at once (default 4).
+  #[must_use]
+  pub fn max_size(mut self, max_size: usize) -> Self {
+    self.max_size = max_size;
+    self
+  }
+
+  /// Sessions to connect when the pool is built, so the first checkouts
+  /// don't pay for the handshake (default 0).
+  #[must_use]
+  pub fn min_idle(mut self, min_idle: usize) -> Self {
+    self.min_idle = min_idle;
+    self
+  }
+
+  /// Probe sessions that have been idle this long before handing them out
+  /// (default 30 seconds).
+  #[must_use]
+  pub fn health_check_after(mut self, idle: Duration) -> Self {
+    self.health_check_after = idle;
+    self
+  }
+
+  /// Create the pool, connecting the [`min_idle`](Self::min_idle)
+  /// sessions.
+  pub async fn build(self) -> Result<RconPool, Error> {
+    if self.max_size == 0 {
+      return Err(Error::Unsupported(
+        "the pool needs room for at least one session".to_string(),
+      ));
+    }
+    if self.min_idle > self.max_size {
+      return Err(Error::Unsupported(format!(
+        "cannot keep {} idle sessions in a pool of {}",
+        self.min_idle, self.max_size
+      )));
+    }
+
+    let mut connector = RconClient::builder()
+      .protocol(self.protocol)
+      .host(self.host)
+      .port(self.port)
+      .options(self.options)
+      .connect_timeout(self.timeout);
+    if let Some(password) = self.password {
+      connector = connector.password(password);
+    }
+    let shared = Shared {
+      connector: connector.build()?,
+      timeout: self.timeout,
+      max_size: self.max_size,
+      health_check_after: self.health_check_after,
+      permits: Arc::new(Semaphore::new(self.max_size)),
+      idle: Mutex::new(Vec::with_capacity(self.max_size)),
+    };
+
+    for _ in 0..self.min_idle {
+      let client = shared.open().await?;
+      shared.push_idle(client);
+    }
+
+    Ok(RconPool {
+      shared: Arc::new(shared),
+    })
+  }
+}
+
+impl Shared {
+  fn pop_idle(&self) -> Option<IdleClient> {
+    self.idle.lock().expect("pool lock poisoned").pop()
+  }
+
+  fn push_idle(&self, client: RconClient) {
+    self
+      .idle
+      .lock()
+      .expect("pool lock poisoned")
+      .push(IdleClient {
+        client,
+        since: Instant::now(),
+      });
+  }
+
+  /// Connect and authenticate a new session.
+  async fn open(&self) -> Result<RconClient, Error> {
+    tracing::debug!(
+      "--> pool connect {}:{}",
+      self.connector.host(),
+      self.connector.port()
+    );
+    self.connector.connect().await
+  }
+}
+
+/// Session checked out of an [`RconPool`], returned to it on drop unless
+/// it has closed.
+#[derive(Debug)]
+pub struct PooledClient {
+  client: Option<RconClient>,
+  shared: Arc<Shared>,
+  _permit: OwnedSemaphorePermit,
+}
+
+impl PooledClient {
+  /// Close the session instead of returning it, e.g. after a timeout left
+  /// a reply in flight. The pool connects a replacement when needed.
+  pub fn discard(mut self) {
+    self.client = None;
+  }
+}
+
+impl Deref for PooledClient {
+  type Target = RconClient;
+
+  fn deref(&self) -> &RconClient {
+    self
+      .client
+      .as_ref()
+      .expect("pooled client present until drop")
+  }
+}
+
+impl DerefMut for PooledClient {
+  fn deref_mut(&mut self) -> &mut RconClient {
+    self
+      .client
+      .as_mut()
+      .expect("pooled client present until drop")
+  }
+}
+
+impl Drop for PooledClient {
+  fn drop(&mut self) {
+    // The permit is released after this returns, so a waiting checkout
+    // finds the session already back in the pool.
+    if let Some(client) = self.client.take()
+      && !client.is_closed()
+    {
+      self.shared.push_idle(client);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::testing::{MockHyrconServer, Reply};
+  use crate::transport::CommandOutcome;
+
+  async fn send(client: &mut RconClient, command: &str) -> Vec<String> {
+    match client.send_command(command).await.expect(command) {
+      CommandOutcome::Response(response) => response.payload,
+      CommandOutcome::Bye => panic!("unexpected bye"),
+    }
+  }
+
+  #[tokio::test]
+  async fn checked_in_sessions_are_reused() {
+    // The mock accepts a single session, so both checkouts must share it.
+    let server = MockHyrconServer::builder()
+      .password("secret")
+      .expect("list", Reply::ok(["Steve"]))
+      .expect("status", Reply::ok(["running"]))
+      .start()
+      .await
+      .expect("start");
+    let pool =
+      RconPool::builder(Protocol::Hyrcon, "127.0.0.1", server.port())
+        .password("secret")
+        .health_check_after(Duration::ZERO)
+        .min_idle(1)
+        .build()
+        .await
+        .expect("pool");
+    assert_eq!(pool.status().idle, 1);
+
+    let mut client = pool.get().await.expect("checkout");
+    assert_eq!(send(&mut client, "list").await, ["Steve"]);
+    assert_eq!(
+      pool.status(),
+      PoolStatus {
+        max_size: 4,
+        in_use: 1,
+        idle: 0
+      }
+    );
+    drop(client);
+
+    let mut client = pool.get().await.expect("checkout");
+    assert_eq!(send(&mut client, "status").await, ["running"]);
+    client.quit().await.expect("quit");
+    drop(client);
+    assert_eq!(pool.status().idle, 0);
+
+    server.finish().await.expect("script followed");
+  }
+
+  #[tokio::test]
+  async fn checkout_waits_for_a_free_session() {
+    let server = MockHyrconServer::builder().start().await.expect("start");
+    let pool =
+      RconPool::builder(Protocol::Hyrcon, "127.0.0.1", server.port())
+        .max_size(1)
+        .timeout(Duration::from_millis(200))
+        .build()
+        .await
+        .expect("pool");
+
+    let client = pool.get().await.expect("checkout");
+    let err = pool.get().await.expect_err("pool exhausted");
+    assert!(matches!(err, Error::Timeout(_)));
+    assert!(err.to_string().contains("timed out after"));
+
+    let waiter = tokio::spawn({
+      let pool = pool.clone();
+      async move { pool.get().await.map(|_| ()) }
+    });
+    drop(client);
+    waiter.await.expect("join").expect("checkout after checkin");
+  }
+}
@@ -5,13 +5,46 @@ use std::str::FromStr;
 ///
 /// `Protocol::Source` is the default and represents the Valve/Source RCON
 /// dialect. `Protocol::Hyrcon` corresponds to the legacy HYRCON bridge
-/// protocol used by older servers.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// protocol used by older servers. `Protocol::WebRcon` is the
+/// JSON-over-WebSocket dialect used by games such as Rust, and
+/// `Protocol::Quake` is the connectionless UDP rcon spoken by Quake III and
+/// GoldSrc engines. `Protocol::BattlEye` is the UDP protocol used by ARMA
+/// and DayZ servers, and `Protocol::Telnet` drives password-protected line
+/// consoles such as the one in 7 Days to Die. `Protocol::Rest` talks to
+/// HTTP admin APIs such as Palworld's, and `Protocol::Pterodactyl` drives a
+/// server console through the Pterodactyl panel. `Protocol::Ssh` runs a
+/// console helper on the server over SSH. `Protocol::Custom` marks
+/// sessions driven by a user-supplied
+/// [`ProtocolBackend`](crate::ProtocolBackend); it cannot be parsed from
+/// text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(
+  feature = "serde",
+  derive(serde::Serialize, serde::Deserialize),
+  serde(rename_all = "lowercase")
+)]
 pub enum Protocol {
   /// Valve/Source RCON protocol.
+  #[default]
   Source,
   /// Legacy HYRCON bridge protocol.
   Hyrcon,
+  /// JSON-over-WebSocket RCON protocol.
+  WebRcon,
+  /// Quake III / GoldSrc UDP rcon protocol.
+  Quake,
+  /// BattlEye RCON protocol (ARMA, DayZ).
+  BattlEye,
+  /// Telnet-style line console.
+  Telnet,
+  /// HTTP REST admin API (Palworld and compatible servers).
+  Rest,
+  /// Pterodactyl panel console (API key + websocket).
+  Pterodactyl,
+  /// Console helper executed over SSH.
+  Ssh,
+  /// Protocol implemented outside of this crate.
+  Custom,
 }
 
 impl Protocol {
@@ -20,21 +53,66 @@ impl Protocol {
     match self {
       Self::Source => "source",
       Self::Hyrcon => "hyrcon",
+      Self::WebRcon => "webrcon",
+      Self::Quake => "quake",
+      Self::BattlEye => "battleye",
+      Self::Telnet => "telnet",
+      Self::Rest => "rest",
+      Self::Pterodactyl => "pterodactyl",
+      Self::Ssh => "ssh",
+      Self::Custom => "custom",
     }
   }
 
-  /// Returns the default TCP port typically used by the protocol.
+  /// Returns the default port typically used by the protocol.
+  ///
+  /// Custom protocols have no well-known port and report `0`.
   pub const fn default_port(self) -> u16 {
     match self {
       Self::Source => 25_575,
       Self::Hyrcon => 5_522,
+      Self::WebRcon => 28_016,
+      Self::Quake => 27_960,
+      Self::BattlEye => 2_306,
+      Self::Telnet => 8_081,
+      Self::Rest => 8_212,
+      Self::Pterodactyl => 443,
+      Self::Ssh => 22,
+      Self::Custom => 0,
+    }
+  }
+
+  /// Command that lists the server's commands, for tab completion in the
+  /// shell, where the protocol has a well-known one.
+  pub const fn help_command(self) -> Option<&'static str> {
+    match self {
+      Self::Source | Self::Hyrcon | Self::Telnet => Some("help"),
+      Self::WebRcon => Some("find ."),
+      Self::Quake => Some("cmdlist"),
+      Self::BattlEye
+      | Self::Rest
+      | Self::Pterodactyl
+      | Self::Ssh
+      | Self::Custom => None,
     }
   }
-}
 
-impl Default for Protocol {
-  fn default() -> Self {
-    Self::Source
+  /// Command that asks for the help text of `verb`, where the protocol
+  /// has one.
+  #[must_use]
+  pub fn describe_command(self, verb: &str) -> Option<String> {
+    match self {
+      Self::Source | Self::Hyrcon | Self::Telnet => {
+        Some(format!("help {verb}"))
+      }
+      Self::WebRcon => Some(format!("find {verb}")),
+      Self::Quake
+      | Self::BattlEye
+      | Self::Rest
+      | Self::Pterodactyl
+      | Self::Ssh
+      | Self::Custom => None,
+    }
   }
 }
 
@@ -44,6 +122,71 @@ impl fmt::Display for Protocol {
   }
 }
 
+/// Server-specific quirks layered on top of a protocol.
+///
+/// `Dialect::Factorio` adjusts the Source backend for Factorio, which may
+/// never answer the empty sentinel command and returns very large
+/// responses such as `/help` in oversized or multiple packets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Dialect {
+  /// Behave exactly as the protocol specifies.
+  #[default]
+  Standard,
+  /// Factorio's Source RCON implementation.
+  Factorio,
+}
+
+impl Dialect {
+  /// Returns the canonical lowercase string representation of the dialect.
+  pub const fn as_str(self) -> &'static str {
+    match self {
+      Self::Standard => "standard",
+      Self::Factorio => "factorio",
+    }
+  }
+}
+
+impl fmt::Display for Dialect {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+impl FromStr for Dialect {
+  type Err = ParseDialectError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.trim().to_ascii_lowercase().as_str() {
+      "standard" | "default" | "none" => Ok(Self::Standard),
+      "factorio" => Ok(Self::Factorio),
+      _ => Err(ParseDialectError {
+        input: s.to_string(),
+      }),
+    }
+  }
+}
+
+/// Error returned when parsing a [`Dialect`] from text fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseDialectError {
+  input: String,
+}
+
+impl ParseDialectError {
+  /// Returns the original input that failed to parse.
+  pub fn input(&self) -> &str {
+    &self.input
+  }
+}
+
+impl fmt::Display for ParseDialectError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "unsupported dialect `{}`", self.input)
+  }
+}
+
+impl std::error::Error for ParseDialectError {}
+
 /// Error returned when parsing a [`Protocol`] from text fails.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ParseProtocolError {
@@ -80,6 +223,13 @@ impl FromStr for Protocol {
     match normalized.as_str() {
       "source" | "src" => Ok(Self::Source),
       "hyrcon" | "legacy" => Ok(Self::Hyrcon),
+      "webrcon" | "websocket" | "ws" => Ok(Self::WebRcon),
+      "quake" | "goldsrc" | "q3" => Ok(Self::Quake),
+      "battleye" | "be" => Ok(Self::BattlEye),
+      "telnet" | "console" | "7dtd" => Ok(Self::Telnet),
+      "rest" | "http" | "palworld" => Ok(Self::Rest),
+      "pterodactyl" | "ptero" | "panel" => Ok(Self::Pterodactyl),
+      "ssh" => Ok(Self::Ssh),
       _ => Err(ParseProtocolError::new(s)),
     }
   }
@@ -98,6 +248,13 @@ mod tests {
   fn default_ports_match_expectations() {
     assert_eq!(Protocol::Source.default_port(), 25_575);
     assert_eq!(Protocol::Hyrcon.default_port(), 5_522);
+    assert_eq!(Protocol::WebRcon.default_port(), 28_016);
+    assert_eq!(Protocol::Quake.default_port(), 27_960);
+    assert_eq!(Protocol::BattlEye.default_port(), 2_306);
+    assert_eq!(Protocol::Telnet.default_port(), 8_081);
+    assert_eq!(Protocol::Rest.default_port(), 8_212);
+    assert_eq!(Protocol::Pterodactyl.default_port(), 443);
+    assert_eq!(Protocol::Ssh.default_port(), 22);
   }
 
   #[test]
@@ -106,6 +263,20 @@ mod tests {
     assert_eq!("SRC".parse::<Protocol>(), Ok(Protocol::Source));
     assert_eq!("hyrcon".parse::<Protocol>(), Ok(Protocol::Hyrcon));
     assert_eq!("LEGACY".parse::<Protocol>(), Ok(Protocol::Hyrcon));
+    assert_eq!("webrcon".parse::<Protocol>(), Ok(Protocol::WebRcon));
+    assert_eq!("WS".parse::<Protocol>(), Ok(Protocol::WebRcon));
+    assert_eq!("goldsrc".parse::<Protocol>(), Ok(Protocol::Quake));
+    assert_eq!("BE".parse::<Protocol>(), Ok(Protocol::BattlEye));
+    assert_eq!("7dtd".parse::<Protocol>(), Ok(Protocol::Telnet));
+    assert_eq!("palworld".parse::<Protocol>(), Ok(Protocol::Rest));
+    assert_eq!("ptero".parse::<Protocol>(), Ok(Protocol::Pterodactyl));
+  }
+
+  #[test]
+  fn dialect_parses_and_defaults_to_standard() {
+    assert_eq!(Dialect::default(), Dialect::Standard);
+    assert_eq!("Factorio".parse::<Dialect>(), Ok(Dialect::Factorio));
+    assert!("minecraft".parse::<Dialect>().is_err());
   }
 
   #[test]
@@ -1,4 +1,4 @@
-use crate::{Cli, run};
+use crate::{Cli, exit::Failed, run, ui};
 use owo_colors::OwoColorize;
 
 /// High-level wrapper that executes the HYRCON client lifecycle and reports errors uniformly.
@@ -16,22 +16,25 @@ impl Runtime {
   /// Execute the client and return the desired process exit code.
   ///
   /// On success the inner `run` function provides the exit status. Any error condition is logged
-  /// in a colourful, human-friendly format and coerced to exit code `1`.
+  /// in a colourful, human-friendly format and mapped to the status `--exit-code-map` gives its
+  /// [`Failure`](crate::exit::Failure).
   pub async fn execute(self) -> i32 {
+    let codes = self.cli.exit_code_map;
     match run(self.cli).await {
       Ok(code) => code,
       Err(err) => {
-        log_error_chain(&err);
-        1
+        report_error(&err);
+        codes.code(Failed::failure_of(&err))
       }
     }
   }
 }
 
-fn log_error_chain(err: &anyhow::Error) {
+/// Print an error and its cause chain to stderr in the client's house style.
+pub fn report_error(err: &anyhow::Error) {
   eprintln!("{} {}", "error:".red().bold(), err.to_string().red().bold());
 
   for cause in err.chain().skip(1) {
-    eprintln!("  {} {}", "↳".red(), cause);
+    eprintln!("  {} {}", ui::symbols().cause.red(), cause);
   }
 }
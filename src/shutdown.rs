@@ -0,0 +1,224 @@
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+/// How soon a second Ctrl-C must follow the first to leave the shell.
+const REPEAT_WINDOW: Duration = Duration::from_secs(2);
+
+/// Termination signal that interrupted a running session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerminationSignal {
+  /// `SIGTERM` (or a console shutdown event on Windows).
+  Terminate,
+  /// `SIGHUP` (or the console window being closed on Windows).
+  Hangup,
+}
+
+impl TerminationSignal {
+  /// Returns the conventional signal name.
+  pub const fn as_str(self) -> &'static str {
+    match self {
+      Self::Terminate => "SIGTERM",
+      Self::Hangup => "SIGHUP",
+    }
+  }
+
+  /// Returns the process exit code reported after a graceful shutdown.
+  ///
+  /// Follows the shell convention of `128 + signal number` so callers can
+  /// distinguish a terminated session from a command failure.
+  pub const fn exit_code(self) -> i32 {
+    match self {
+      Self::Terminate => 143,
+      Self::Hangup => 129,
+    }
+  }
+}
+
+impl fmt::Display for TerminationSignal {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+/// Listener for termination signals delivered to the process.
+///
+/// Installing the listener replaces the default "kill immediately"
+/// disposition, so whoever creates it must also wait on it: the client
+/// races the confirmation prompt, the handshake and the session itself
+/// against [`ShutdownListener::recv`].
+#[derive(Debug)]
+pub struct ShutdownListener {
+  #[cfg(unix)]
+  terminate: tokio::signal::unix::Signal,
+  #[cfg(unix)]
+  hangup: tokio::signal::unix::Signal,
+  #[cfg(windows)]
+  close: tokio::signal::windows::CtrlClose,
+  #[cfg(windows)]
+  shutdown: tokio::signal::windows::CtrlShutdown,
+}
+
+impl ShutdownListener {
+  /// Register the platform signal handlers.
+  #[cfg(unix)]
+  pub fn install() -> io::Result<Self> {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    Ok(Self {
+      terminate: signal(SignalKind::terminate())?,
+      hangup: signal(SignalKind::hangup())?,
+    })
+  }
+
+  /// Register the platform signal handlers.
+  #[cfg(windows)]
+  pub fn install() -> io::Result<Self> {
+    use tokio::signal::windows::{ctrl_close, ctrl_shutdown};
+
+    Ok(Self {
+      close: ctrl_close()?,
+      shutdown: ctrl_shutdown()?,
+    })
+  }
+
+  /// Register the platform signal handlers.
+  #[cfg(not(any(unix, windows)))]
+  pub fn install() -> io::Result<Self> {
+    Ok(Self {})
+  }
+
+  /// Wait until a termination signal is received.
+  #[cfg(unix)]
+  pub async fn recv(&mut self) -> TerminationSignal {
+    tokio::select! {
+      _ = self.terminate.recv() => TerminationSignal::Terminate,
+      _ = self.hangup.recv() => TerminationSignal::Hangup,
+    }
+  }
+
+  /// Wait until a termination signal is received.
+  #[cfg(windows)]
+  pub async fn recv(&mut self) -> TerminationSignal {
+    tokio::select! {
+      _ = self.shutdown.recv() => TerminationSignal::Terminate,
+      _ = self.close.recv() => TerminationSignal::Hangup,
+    }
+  }
+
+  /// Wait until a termination signal is received.
+  #[cfg(not(any(unix, windows)))]
+  pub async fn recv(&mut self) -> TerminationSignal {
+    std::future::pending().await
+  }
+}
+
+/// Ctrl-C presses while the interactive shell runs. Installing it stops
+/// Ctrl-C from killing the process, so the shell can cancel the command
+/// in flight instead; pressing it twice in a row means leave.
+#[derive(Debug)]
+pub struct Interrupts {
+  source: InterruptSource,
+  last: Option<Instant>,
+}
+
+/// Where Ctrl-C presses come from.
+#[derive(Debug)]
+enum InterruptSource {
+  #[cfg(unix)]
+  Signal(tokio::signal::unix::Signal),
+  #[cfg(windows)]
+  CtrlC(tokio::signal::windows::CtrlC),
+  Channel(mpsc::UnboundedReceiver<()>),
+}
+
+impl Interrupts {
+  /// Register the platform Ctrl-C handler.
+  #[cfg(unix)]
+  pub fn install() -> io::Result<Self> {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let interrupt = signal(SignalKind::interrupt())?;
+    Ok(Self::from_source(InterruptSource::Signal(interrupt)))
+  }
+
+  /// Register the platform Ctrl-C handler.
+  #[cfg(windows)]
+  pub fn install() -> io::Result<Self> {
+    let ctrl_c = tokio::signal::windows::ctrl_c()?;
+    Ok(Self::from_source(InterruptSource::CtrlC(ctrl_c)))
+  }
+
+  /// Register the platform Ctrl-C handler.
+  #[cfg(not(any(unix, windows)))]
+  pub fn install() -> io::Result<Self> {
+    let (_, presses) = mpsc::unbounded_channel();
+    Ok(Self::from_channel(presses))
+  }
+
+  /// Take Ctrl-C presses from `presses` instead of the platform
+  /// handler, leaving the process's own Ctrl-C handling alone. Once
+  /// every sender is gone no more presses arrive.
+  pub fn from_channel(presses: mpsc::UnboundedReceiver<()>) -> Self {
+    Self::from_source(InterruptSource::Channel(presses))
+  }
+
+  fn from_source(source: InterruptSource) -> Self {
+    Self { source, last: None }
+  }
+
+  /// Wait for Ctrl-C. Returns whether it closely followed the previous
+  /// press, as with [`Interrupts::press`].
+  pub async fn recv(&mut self) -> bool {
+    let received = match &mut self.source {
+      #[cfg(unix)]
+      InterruptSource::Signal(interrupt) => interrupt.recv().await,
+      #[cfg(windows)]
+      InterruptSource::CtrlC(ctrl_c) => ctrl_c.recv().await,
+      InterruptSource::Channel(presses) => presses.recv().await,
+    };
+    if received.is_none() {
+      std::future::pending::<()>().await;
+    }
+    self.press()
+  }
+
+  /// Note a Ctrl-C seen some other way, such as by the line editor.
+  /// Returns whether it closely followed the previous press.
+  pub fn press(&mut self) -> bool {
+    let now = Instant::now();
+    let repeated = self
+      .last
+      .is_some_and(|last| now.duration_since(last) < REPEAT_WINDOW);
+    self.last = Some(now);
+    repeated
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn signals_exit_with_128_plus_their_number() {
+    assert_eq!(TerminationSignal::Terminate.exit_code(), 128 + 15);
+    assert_eq!(TerminationSignal::Hangup.exit_code(), 128 + 1);
+    assert_eq!(TerminationSignal::Terminate.to_string(), "SIGTERM");
+    assert_eq!(TerminationSignal::Hangup.to_string(), "SIGHUP");
+  }
+
+  #[tokio::test]
+  async fn presses_in_quick_succession_are_repeats() {
+    let (press, presses) = mpsc::unbounded_channel();
+    let mut interrupts = Interrupts::from_channel(presses);
+
+    press.send(()).expect("press");
+    assert!(!interrupts.recv().await);
+    press.send(()).expect("press");
+    assert!(interrupts.recv().await);
+    assert!(interrupts.press());
+  }
+}
@@ -0,0 +1,180 @@
+use anyhow::Result;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::net::TcpStream;
+
+use super::{
+  Exchange, Flow, MockServer, Reply, ReplyKind, Script,
+  ensure_single_line, send_reply,
+};
+
+impl Reply {
+  /// Block sent verbatim, status line included.
+  fn block<const N: usize>(lines: [&str; N]) -> Self {
+    Self::raw(block(lines.map(String::from)))
+  }
+}
+
+/// Mock of the plain-text HYRCON bridge.
+///
+/// `HELLO`, `PING` and `QUIT` are answered automatically unless the script
+/// expects them next.
+#[derive(Debug)]
+pub struct MockHyrconServer;
+
+/// Builder for [`MockHyrconServer`].
+#[derive(Debug, Default)]
+pub struct MockHyrconServerBuilder {
+  password: Option<String>,
+  auth_optional: bool,
+  capabilities: Vec<String>,
+  exchanges: Vec<Exchange>,
+}
+
+impl MockHyrconServer {
+  pub fn builder() -> MockHyrconServerBuilder {
+    MockHyrconServerBuilder::default()
+  }
+}
+
+impl MockHyrconServerBuilder {
+  /// Require `password` (advertising `AUTH REQUIRED`). Without a password
+  /// any `AUTH` is accepted.
+  #[must_use]
+  pub fn password(mut self, password: impl Into<String>) -> Self {
+    self.password = Some(password.into());
+    self
+  }
+
+  /// Advertise `AUTH OPTIONAL` even when a password is configured.
+  #[must_use]
+  pub fn auth_optional(mut self) -> Self {
+    self.auth_optional = true;
+    self
+  }
+
+  /// Capabilities returned in reply to `HELLO`. Without any, `HELLO` is
+  /// rejected like a bridge that predates it.
+  #[must_use]
+  pub fn capabilities<I, S>(mut self, capabilities: I) -> Self
+  where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+  {
+    self.capabilities = capabilities.into_iter().map(Into::into).collect();
+    self
+  }
+
+  /// Expect `command` next and answer it with `reply`.
+  #[must_use]
+  pub fn expect(
+    mut self,
+    command: impl Into<String>,
+    reply: Reply,
+  ) -> Self {
+    self.exchanges.push(Exchange {
+      command: command.into(),
+      reply,
+    });
+    self
+  }
+
+  /// Bind an ephemeral port and start serving the script.
+  pub async fn start(self) -> Result<MockServer> {
+    for exchange in &self.exchanges {
+      ensure_single_line(&exchange.command, "scripted commands")?;
+    }
+    MockServer::spawn(move |stream| self.serve(stream)).await
+  }
+
+  async fn serve(mut self, stream: TcpStream) -> Result<()> {
+    let (read_half, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(read_half).lines();
+    let mut script = Script::new(std::mem::take(&mut self.exchanges));
+
+    let auth = if self.password.is_none() || self.auth_optional {
+      "AUTH OPTIONAL"
+    } else {
+      "AUTH REQUIRED"
+    };
+    let greeting = Reply::block(["HYRCON READY", auth]);
+    send_reply(&mut writer, &greeting, encode(&greeting)).await?;
+
+    while let Some(line) = lines.next_line().await? {
+      let command = line.trim_end_matches('\r');
+      let reply = match script.take(command) {
+        Some(reply) => reply,
+        None => match self.automatic_reply(command) {
+          Some(reply) => reply,
+          None => {
+            script.unexpected(command);
+            Reply::err(format!("unexpected command `{command}`"))
+          }
+        },
+      };
+
+      if send_reply(&mut writer, &reply, encode(&reply)).await?
+        == Flow::Disconnect
+      {
+        break;
+      }
+    }
+
+    script.into_result()
+  }
+
+  fn automatic_reply(&self, command: &str) -> Option<Reply> {
+    if let Some(password) = command.strip_prefix("AUTH ") {
+      let accepted = self
+        .password
+        .as_deref()
+        .is_none_or(|expected| expected == password);
+      let status = if accepted { "AUTH OK" } else { "AUTH FAIL" };
+      return Some(Reply::block([status]));
+    }
+
+    match command {
+      "HELLO" if self.capabilities.is_empty() => {
+        Some(Reply::err("unknown command"))
+      }
+      "HELLO" => {
+        Some(Reply::ok([format!("CAP {}", self.capabilities.join(" "))]))
+      }
+      "PING" => Some(Reply::ok(["PONG"])),
+      "QUIT" => Some(Reply::bye()),
+      _ => None,
+    }
+  }
+}
+
+fn encode(reply: &Reply) -> Vec<u8> {
+  let mut bytes = Vec::new();
+  for (kind, text) in &reply.events {
+    bytes.extend(block([format!("EVENT {kind}"), text.clone()]));
+  }
+
+  match &reply.kind {
+    ReplyKind::Ok(lines) => {
+      bytes.extend(block(
+        std::iter::once("OK".to_string()).chain(lines.iter().cloned()),
+      ));
+    }
+    ReplyKind::Err(message) => {
+      bytes.extend(block(["ERR".to_string(), format!("ERROR {message}")]));
+    }
+    ReplyKind::Bye => bytes.extend(block(["BYE".to_string()])),
+    ReplyKind::Raw(raw) => bytes.extend_from_slice(raw),
+    ReplyKind::Close => {}
+  }
+  bytes
+}
+
+/// Encode `lines` as a `.`-terminated block.
+fn block(lines: impl IntoIterator<Item = String>) -> Vec<u8> {
+  let mut text = String::new();
+  for line in lines {
+    text.push_str(&line);
+    text.push('\n');
+  }
+  text.push_str(".\n");
+  text.into_bytes()
+}
@@ -0,0 +1,491 @@
+//! Scriptable mock RCON servers for end-to-end tests.
+//!
+//! Each mock binds an ephemeral port on `127.0.0.1`, accepts a single
+//! session and replays a script of expected commands and canned replies.
+//! Replies can be delayed, truncated, replaced by malformed bytes or by an
+//! abrupt disconnect to exercise error handling. Call
+//! [`MockServer::finish`] once the client is done to surface unexpected
+//! commands or unfinished scripts.
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use hyrcon_client::testing::{MockHyrconServer, Reply};
+//! use hyrcon_client::{CommandOutcome, Protocol, RconClient};
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> anyhow::Result<()> {
+//! let server = MockHyrconServer::builder()
+//!   .expect("list", Reply::ok(["Steve"]))
+//!   .start()
+//!   .await?;
+//!
+//! let mut client = RconClient::connect(
+//!   Protocol::Hyrcon,
+//!   "127.0.0.1",
+//!   server.port(),
+//!   Duration::from_secs(1),
+//! )
+//! .await?;
+//! let CommandOutcome::Response(response) =
+//!   client.send_command("list").await?
+//! else {
+//!   panic!("unexpected bye");
+//! };
+//! assert_eq!(response.payload, ["Steve"]);
+//!
+//! client.quit().await?;
+//! server.finish().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow, bail};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+
+mod hyrcon;
+mod source;
+
+pub use hyrcon::{MockHyrconServer, MockHyrconServerBuilder};
+pub use source::{MockSourceServer, MockSourceServerBuilder};
+
+/// Canned reply to a scripted command, optionally with a fault attached.
+#[derive(Debug, Clone)]
+pub struct Reply {
+  kind: ReplyKind,
+  events: Vec<(String, String)>,
+  delay: Option<Duration>,
+  truncate: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+enum ReplyKind {
+  Ok(Vec<String>),
+  Err(String),
+  Bye,
+  Close,
+  /// Bytes sent as they are, in place of an encoded reply.
+  Raw(Vec<u8>),
+}
+
+impl Reply {
+  /// Successful reply carrying `lines`.
+  pub fn ok<I, S>(lines: I) -> Self
+  where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+  {
+    Self::new(ReplyKind::Ok(lines.into_iter().map(Into::into).collect()))
+  }
+
+  /// Error reply. HYRCON sends it as an `ERR` block; Source has no error
+  /// status, so the message is sent as the reply text.
+  pub fn err(message: impl Into<String>) -> Self {
+    Self::new(ReplyKind::Err(message.into()))
+  }
+
+  /// HYRCON `BYE`, ending the session. Source servers just disconnect.
+  pub fn bye() -> Self {
+    Self::new(ReplyKind::Bye)
+  }
+
+  /// Drop the connection without replying.
+  pub fn close() -> Self {
+    Self::new(ReplyKind::Close)
+  }
+
+  /// Send `bytes` as they are instead of a well-formed reply, e.g. a
+  /// Source packet with an impossible length or a HYRCON line that is not
+  /// UTF-8.
+  pub fn raw(bytes: impl Into<Vec<u8>>) -> Self {
+    Self::new(ReplyKind::Raw(bytes.into()))
+  }
+
+  /// Push an unsolicited HYRCON `EVENT` block before the reply. Ignored by
+  /// the Source mock.
+  #[must_use]
+  pub fn with_event(
+    mut self,
+    kind: impl Into<String>,
+    text: impl Into<String>,
+  ) -> Self {
+    self.events.push((kind.into(), text.into()));
+    self
+  }
+
+  /// Wait before sending the reply.
+  #[must_use]
+  pub fn delay(mut self, delay: Duration) -> Self {
+    self.delay = Some(delay);
+    self
+  }
+
+  /// Send only the first `bytes` bytes of the encoded reply, then drop the
+  /// connection.
+  #[must_use]
+  pub fn truncate(mut self, bytes: usize) -> Self {
+    self.truncate = Some(bytes);
+    self
+  }
+
+  /// Placeholder for replies the mock generates itself.
+  fn automatic() -> Self {
+    Self::new(ReplyKind::Ok(Vec::new()))
+  }
+
+  fn new(kind: ReplyKind) -> Self {
+    Self {
+      kind,
+      events: Vec::new(),
+      delay: None,
+      truncate: None,
+    }
+  }
+}
+
+/// Command the mock expects next, paired with the reply to send.
+#[derive(Debug, Clone)]
+struct Exchange {
+  command: String,
+  reply: Reply,
+}
+
+/// Whether the session continues after a reply has been written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Flow {
+  Continue,
+  Disconnect,
+}
+
+/// Handle to a running mock server.
+///
+/// Dropping the handle stops the server.
+#[derive(Debug)]
+pub struct MockServer {
+  addr: SocketAddr,
+  task: Option<JoinHandle<Result<()>>>,
+}
+
+impl MockServer {
+  /// Address the mock is listening on.
+  pub fn addr(&self) -> SocketAddr {
+    self.addr
+  }
+
+  /// Port the mock is listening on.
+  pub fn port(&self) -> u16 {
+    self.addr.port()
+  }
+
+  /// Wait for the session to end and report any script violations:
+  /// unexpected commands, or exchanges that never happened.
+  pub async fn finish(mut self) -> Result<()> {
+    let task = self.task.take().expect("mock server already finished");
+    task.await.context("mock server task panicked")?
+  }
+
+  async fn spawn<F, Fut>(session: F) -> Result<Self>
+  where
+    F: FnOnce(tokio::net::TcpStream) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<()>> + Send + 'static,
+  {
+    let listener = TcpListener::bind(("127.0.0.1", 0))
+      .await
+      .context("failed to bind mock server")?;
+    let addr = listener.local_addr()?;
+
+    let task = tokio::spawn(async move {
+      let (stream, _) = listener
+        .accept()
+        .await
+        .context("mock server failed to accept a connection")?;
+      stream.set_nodelay(true)?;
+      session(stream).await
+    });
+
+    Ok(Self {
+      addr,
+      task: Some(task),
+    })
+  }
+}
+
+impl Drop for MockServer {
+  fn drop(&mut self) {
+    if let Some(task) = self.task.take() {
+      task.abort();
+    }
+  }
+}
+
+/// Progress through a script, collecting violations along the way.
+#[derive(Debug)]
+struct Script {
+  exchanges: VecDeque<Exchange>,
+  violations: Vec<String>,
+}
+
+impl Script {
+  fn new(exchanges: Vec<Exchange>) -> Self {
+    Self {
+      exchanges: exchanges.into(),
+      violations: Vec::new(),
+    }
+  }
+
+  /// Take the scripted reply when `command` is the next expected one.
+  fn take(&mut self, command: &str) -> Option<Reply> {
+    if self.exchanges.front()?.command != command {
+      return None;
+    }
+    self.exchanges.pop_front().map(|exchange| exchange.reply)
+  }
+
+  fn unexpected(&mut self, command: &str) {
+    let expected = self
+      .exchanges
+      .front()
+      .map_or("end of script", |exchange| exchange.command.as_str());
+    self.violations.push(format!(
+      "unexpected command `{command}` (expected `{expected}`)"
+    ));
+  }
+
+  fn into_result(self) -> Result<()> {
+    let mut violations = self.violations;
+    if !self.exchanges.is_empty() {
+      let pending: Vec<_> = self
+        .exchanges
+        .iter()
+        .map(|exchange| format!("`{}`", exchange.command))
+        .collect();
+      violations
+        .push(format!("script not finished: {}", pending.join(", ")));
+    }
+
+    if violations.is_empty() {
+      return Ok(());
+    }
+    Err(anyhow!(violations.join("; ")))
+  }
+}
+
+/// Write an encoded reply, applying the reply's delay and truncation.
+async fn send_reply<W>(
+  writer: &mut W,
+  reply: &Reply,
+  encoded: Vec<u8>,
+) -> Result<Flow>
+where
+  W: AsyncWrite + Unpin,
+{
+  if let Some(delay) = reply.delay {
+    tokio::time::sleep(delay).await;
+  }
+
+  if matches!(reply.kind, ReplyKind::Close) {
+    return Ok(Flow::Disconnect);
+  }
+
+  let (bytes, flow) = match reply.truncate {
+    Some(limit) if limit < encoded.len() => {
+      (&encoded[..limit], Flow::Disconnect)
+    }
+    _ if matches!(reply.kind, ReplyKind::Bye) => {
+      (&encoded[..], Flow::Disconnect)
+    }
+    _ => (&encoded[..], Flow::Continue),
+  };
+
+  writer.write_all(bytes).await?;
+  writer.flush().await?;
+  if flow == Flow::Disconnect {
+    writer.shutdown().await?;
+  }
+  Ok(flow)
+}
+
+fn ensure_single_line(value: &str, what: &str) -> Result<()> {
+  if value.contains(['\r', '\n']) {
+    bail!("{what} must not contain newline characters");
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::protocol::Protocol;
+  use crate::transport::{AuthOutcome, CommandOutcome, RconClient};
+
+  async fn send(client: &mut RconClient, command: &str) -> Vec<String> {
+    match client.send_command(command).await.expect(command) {
+      CommandOutcome::Response(response) => response.payload,
+      CommandOutcome::Bye => panic!("unexpected bye"),
+    }
+  }
+
+  #[tokio::test]
+  async fn hyrcon_mock_drives_client_end_to_end() {
+    let server = MockHyrconServer::builder()
+      .password("secret")
+      .capabilities(["EVENTS"])
+      .expect(
+        "list",
+        Reply::ok(["Steve", "Alex"]).with_event("JOIN", "Alex"),
+      )
+      .expect("kick nobody", Reply::err("no such player"))
+      .start()
+      .await
+      .expect("start");
+
+    let mut client = RconClient::connect(
+      Protocol::Hyrcon,
+      "127.0.0.1",
+      server.port(),
+      Duration::from_secs(1),
+    )
+    .await
+    .expect("connect");
+    assert!(client.greeting().requires_auth());
+    assert!(client.greeting().supports("EVENTS"));
+    assert!(matches!(
+      client.authenticate("secret").await.expect("auth"),
+      AuthOutcome::Success
+    ));
+
+    assert_eq!(send(&mut client, "list").await, ["Steve", "Alex"]);
+    assert_eq!(client.take_messages()[0].text, "Alex");
+    let CommandOutcome::Response(response) =
+      client.send_command("kick nobody").await.expect("kick")
+    else {
+      panic!("unexpected bye");
+    };
+    assert_eq!(response.error.as_deref(), Some("no such player"));
+
+    client.quit().await.expect("quit");
+    server.finish().await.expect("script followed");
+  }
+
+  #[tokio::test]
+  async fn source_mock_splits_replies_across_packets() {
+    let long = "x".repeat(6_000);
+    let server = MockSourceServer::builder()
+      .password("secret")
+      .expect("cvarlist", Reply::ok([long.as_str()]))
+      .start()
+      .await
+      .expect("start");
+
+    let mut client = RconClient::connect(
+      Protocol::Source,
+      "127.0.0.1",
+      server.port(),
+      Duration::from_secs(1),
+    )
+    .await
+    .expect("connect");
+    assert!(matches!(
+      client.authenticate("wrong").await.expect("auth"),
+      AuthOutcome::Failure
+    ));
+    assert!(matches!(
+      client.authenticate("secret").await.expect("auth"),
+      AuthOutcome::Success
+    ));
+
+    assert_eq!(send(&mut client, "cvarlist").await.concat(), long);
+
+    client.quit().await.expect("quit");
+    server.finish().await.expect("script followed");
+  }
+
+  #[tokio::test]
+  async fn faults_and_violations_are_reported() {
+    let server = MockSourceServer::builder()
+      .expect("status", Reply::ok(["hostname: test"]).truncate(6))
+      .start()
+      .await
+      .expect("start");
+
+    let mut client = RconClient::connect(
+      Protocol::Source,
+      "127.0.0.1",
+      server.port(),
+      Duration::from_secs(1),
+    )
+    .await
+    .expect("connect");
+    client.authenticate("anything").await.expect("auth");
+    assert!(client.send_command("status").await.is_err());
+    server.finish().await.expect("script followed");
+
+    let server = MockHyrconServer::builder()
+      .expect("list", Reply::ok(["Steve"]))
+      .start()
+      .await
+      .expect("start");
+    let mut client = RconClient::connect(
+      Protocol::Hyrcon,
+      "127.0.0.1",
+      server.port(),
+      Duration::from_secs(1),
+    )
+    .await
+    .expect("connect");
+    send(&mut client, "status").await;
+    client.quit().await.expect("quit");
+
+    let err = server.finish().await.expect_err("violations");
+    assert!(err.to_string().contains("unexpected command `status`"));
+    assert!(err.to_string().contains("script not finished: `list`"));
+  }
+
+  #[tokio::test]
+  async fn malformed_replies_fail_the_command() {
+    let mut bogus = Vec::new();
+    bogus.extend_from_slice(&4_i32.to_le_bytes());
+    bogus.extend_from_slice(&[0; 4]);
+    let server = MockSourceServer::builder()
+      .expect("status", Reply::raw(bogus))
+      .start()
+      .await
+      .expect("start");
+    let mut client = RconClient::connect(
+      Protocol::Source,
+      "127.0.0.1",
+      server.port(),
+      Duration::from_secs(1),
+    )
+    .await
+    .expect("connect");
+    client.authenticate("anything").await.expect("auth");
+    assert!(client.send_command("status").await.is_err());
+    drop(client);
+    server.finish().await.expect("script followed");
+
+    let server = MockHyrconServer::builder()
+      .expect("list", Reply::raw(b"OK\n\xff\xfe\n.\n".to_vec()))
+      .start()
+      .await
+      .expect("start");
+    let mut client = RconClient::connect(
+      Protocol::Hyrcon,
+      "127.0.0.1",
+      server.port(),
+      Duration::from_secs(1),
+    )
+    .await
+    .expect("connect");
+    let err = client.send_command("list").await.expect_err("not UTF-8");
+    assert!(matches!(err, crate::Error::Protocol(_)));
+    drop(client);
+    server.finish().await.expect("script followed");
+  }
+}
@@ -0,0 +1,201 @@
+use std::io::ErrorKind;
+
+use anyhow::{Result, bail};
+use tokio::io::{AsyncReadExt, BufReader};
+use tokio::net::TcpStream;
+
+use super::{
+  Exchange, Flow, MockServer, Reply, ReplyKind, Script,
+  ensure_single_line, send_reply,
+};
+use crate::codec::source::{
+  SERVERDATA_AUTH, SERVERDATA_AUTH_RESPONSE, SERVERDATA_EXECCOMMAND,
+  SERVERDATA_RESPONSE_VALUE,
+};
+
+/// Largest reply payload per packet sent by Source servers.
+const DEFAULT_PACKET_PAYLOAD: usize = 4096;
+
+/// Mock of a Source RCON server.
+///
+/// Authentication, the empty "end of response" sentinel command and empty
+/// `SERVERDATA_RESPONSE_VALUE` keepalive probes are answered automatically.
+#[derive(Debug)]
+pub struct MockSourceServer;
+
+/// Builder for [`MockSourceServer`].
+#[derive(Debug)]
+pub struct MockSourceServerBuilder {
+  password: Option<String>,
+  answer_sentinel: bool,
+  packet_payload: usize,
+  exchanges: Vec<Exchange>,
+}
+
+impl MockSourceServer {
+  pub fn builder() -> MockSourceServerBuilder {
+    MockSourceServerBuilder {
+      password: None,
+      answer_sentinel: true,
+      packet_payload: DEFAULT_PACKET_PAYLOAD,
+      exchanges: Vec::new(),
+    }
+  }
+}
+
+impl MockSourceServerBuilder {
+  /// Only accept `password`. Without one any password is accepted.
+  #[must_use]
+  pub fn password(mut self, password: impl Into<String>) -> Self {
+    self.password = Some(password.into());
+    self
+  }
+
+  /// Silently drop the empty sentinel command, like older Minecraft
+  /// builds and Factorio.
+  #[must_use]
+  pub fn ignore_sentinel(mut self) -> Self {
+    self.answer_sentinel = false;
+    self
+  }
+
+  /// Split replies into packets carrying at most `bytes` bytes of payload.
+  #[must_use]
+  pub fn packet_payload(mut self, bytes: usize) -> Self {
+    self.packet_payload = bytes.max(1);
+    self
+  }
+
+  /// Expect `command` next and answer it with `reply`.
+  #[must_use]
+  pub fn expect(
+    mut self,
+    command: impl Into<String>,
+    reply: Reply,
+  ) -> Self {
+    self.exchanges.push(Exchange {
+      command: command.into(),
+      reply,
+    });
+    self
+  }
+
+  /// Bind an ephemeral port and start serving the script.
+  pub async fn start(self) -> Result<MockServer> {
+    for exchange in &self.exchanges {
+      if exchange.command.is_empty() {
+        bail!("scripted commands must not be empty");
+      }
+      ensure_single_line(&exchange.command, "scripted commands")?;
+    }
+    MockServer::spawn(move |stream| self.serve(stream)).await
+  }
+
+  async fn serve(mut self, stream: TcpStream) -> Result<()> {
+    let (read_half, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(read_half);
+    let mut script = Script::new(std::mem::take(&mut self.exchanges));
+
+    while let Some((id, kind, body)) = read_packet(&mut reader).await? {
+      let (reply, encoded) = match kind {
+        SERVERDATA_AUTH => {
+          let accepted = self
+            .password
+            .as_deref()
+            .is_none_or(|expected| expected == body);
+          let mut bytes = packet(id, SERVERDATA_RESPONSE_VALUE, "");
+          let auth_id = if accepted { id } else { -1 };
+          bytes.extend(packet(auth_id, SERVERDATA_AUTH_RESPONSE, ""));
+          (Reply::automatic(), bytes)
+        }
+        SERVERDATA_EXECCOMMAND if body.is_empty() => {
+          if !self.answer_sentinel {
+            continue;
+          }
+          (
+            Reply::automatic(),
+            packet(id, SERVERDATA_RESPONSE_VALUE, ""),
+          )
+        }
+        SERVERDATA_EXECCOMMAND => {
+          let reply = script.take(&body).unwrap_or_else(|| {
+            script.unexpected(&body);
+            Reply::err(format!("Unknown command \"{body}\""))
+          });
+          let encoded = self.encode(id, &reply);
+          (reply, encoded)
+        }
+        SERVERDATA_RESPONSE_VALUE => (
+          Reply::automatic(),
+          packet(id, SERVERDATA_RESPONSE_VALUE, ""),
+        ),
+        other => bail!("mock Source server received packet type {other}"),
+      };
+
+      if send_reply(&mut writer, &reply, encoded).await?
+        == Flow::Disconnect
+      {
+        break;
+      }
+    }
+
+    script.into_result()
+  }
+
+  fn encode(&self, id: i32, reply: &Reply) -> Vec<u8> {
+    let text = match &reply.kind {
+      ReplyKind::Ok(lines) => lines.join("\n"),
+      ReplyKind::Err(message) => message.clone(),
+      ReplyKind::Raw(raw) => return raw.clone(),
+      ReplyKind::Bye | ReplyKind::Close => return Vec::new(),
+    };
+
+    let mut bytes = Vec::new();
+    let mut rest = text.as_str();
+    loop {
+      let mut end = rest.len().min(self.packet_payload);
+      while !rest.is_char_boundary(end) {
+        end -= 1;
+      }
+      let (chunk, tail) = rest.split_at(end);
+      bytes.extend(packet(id, SERVERDATA_RESPONSE_VALUE, chunk));
+      rest = tail;
+      if rest.is_empty() {
+        return bytes;
+      }
+    }
+  }
+}
+
+/// Read one request, returning `None` once the client disconnects.
+async fn read_packet(
+  reader: &mut BufReader<tokio::net::tcp::OwnedReadHalf>,
+) -> Result<Option<(i32, i32, String)>> {
+  let length = match reader.read_i32_le().await {
+    Ok(length) => length,
+    Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+    Err(err) => return Err(err.into()),
+  };
+  if !(10..=65_536).contains(&length) {
+    bail!("mock Source server received invalid packet length {length}");
+  }
+
+  let mut buffer = vec![0_u8; length as usize];
+  reader.read_exact(&mut buffer).await?;
+  let id = i32::from_le_bytes(buffer[0..4].try_into()?);
+  let kind = i32::from_le_bytes(buffer[4..8].try_into()?);
+  let body =
+    String::from_utf8_lossy(&buffer[8..buffer.len() - 2]).into_owned();
+  Ok(Some((id, kind, body)))
+}
+
+fn packet(id: i32, kind: i32, body: &str) -> Vec<u8> {
+  let length = (10 + body.len()) as i32;
+  let mut bytes = Vec::with_capacity(4 + length as usize);
+  bytes.extend_from_slice(&length.to_le_bytes());
+  bytes.extend_from_slice(&id.to_le_bytes());
+  bytes.extend_from_slice(&kind.to_le_bytes());
+  bytes.extend_from_slice(body.as_bytes());
+  bytes.extend_from_slice(&[0, 0]);
+  bytes
+}
@@ -0,0 +1,214 @@
+//! Structured transcript of what happened in a session, written by
+//! `--record` and the shell's `:record` for audit trails.
+//!
+//! The transcript is JSON Lines: one object per line, each with a local
+//! RFC 3339 `time` and a `type`:
+//!
+//! | `type`       | Other fields                                        |
+//! |--------------|-----------------------------------------------------|
+//! | `session`    | `host`, `port`, `protocol` and `banner` of a new    |
+//! |              | connection                                          |
+//! | `command`    | Those of `--output json`: `command`, `status`,      |
+//! |              | `payload`, `error`, `duration_ms`, `host`, `port`   |
+//! | `message`    | `kind` and `message` pushed by the server           |
+//! | `connection` | `text` describing a connection event                |
+//!
+//! The commands can be replayed in order with
+//! `jq -r 'select(.type == "command").command' FILE | hyrcon-client --script -`.
+
+use std::fs::{File, OpenOptions};
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use anyhow::{Context, Result};
+use chrono::{Local, SecondsFormat};
+use serde::Serialize;
+use serde_json::json;
+
+use crate::transport::{RconClient, ServerMessage};
+use crate::ui::CommandRecord;
+
+static TRANSCRIPT: Mutex<Option<Transcript>> = Mutex::new(None);
+
+struct Transcript {
+  path: PathBuf,
+  file: File,
+}
+
+/// Start recording to the file at `path`, appending if it exists. Any
+/// transcript already being recorded is closed.
+pub fn start(path: &Path) -> Result<()> {
+  let file = OpenOptions::new()
+    .create(true)
+    .append(true)
+    .open(path)
+    .with_context(|| format!("failed to open {}", path.display()))?;
+  *lock() = Some(Transcript {
+    path: path.to_path_buf(),
+    file,
+  });
+  Ok(())
+}
+
+/// Stop recording. Returns the file the transcript went to, if any.
+pub fn stop() -> Option<PathBuf> {
+  lock().take().map(|transcript| transcript.path)
+}
+
+/// The file the transcript goes to, while one is being recorded.
+pub fn path() -> Option<PathBuf> {
+  lock().as_ref().map(|transcript| transcript.path.clone())
+}
+
+/// Record that `client` connected and authenticated.
+pub fn session(client: &RconClient) {
+  write(
+    "session",
+    json!({
+      "host": client.host(),
+      "port": client.port(),
+      "protocol": client.protocol().to_string(),
+      "banner": client.greeting().banner(),
+    }),
+  );
+}
+
+/// Record a command and its outcome.
+pub fn command(record: &CommandRecord<'_>) {
+  write("command", record);
+}
+
+/// Record a message pushed by the server.
+pub fn message(message: &ServerMessage) {
+  write(
+    "message",
+    json!({ "kind": message.kind, "message": message.text }),
+  );
+}
+
+/// Record a connection event, such as a reconnect.
+pub fn connection(text: &str) {
+  write("connection", json!({ "text": text }));
+}
+
+/// One line of the transcript.
+#[derive(Serialize)]
+struct Entry<T> {
+  time: String,
+  #[serde(rename = "type")]
+  kind: &'static str,
+  #[serde(flatten)]
+  fields: T,
+}
+
+fn write(kind: &'static str, fields: impl Serialize) {
+  let mut transcript = lock();
+  let Some(transcript) = transcript.as_mut() else {
+    return;
+  };
+  let entry = Entry {
+    time: Local::now().to_rfc3339_opts(SecondsFormat::Millis, false),
+    kind,
+    fields,
+  };
+  let mut line = match serde_json::to_string(&entry) {
+    Ok(line) => line,
+    Err(err) => {
+      tracing::warn!("failed to encode a transcript entry: {err}");
+      return;
+    }
+  };
+  line.push('\n');
+  if let Err(err) = transcript.file.write_all(line.as_bytes()) {
+    tracing::warn!(
+      "failed to write to {}: {err}",
+      transcript.path.display()
+    );
+  }
+}
+
+fn lock() -> MutexGuard<'static, Option<Transcript>> {
+  TRANSCRIPT.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::DateTime;
+  use serde_json::Value;
+
+  use super::*;
+
+  #[test]
+  fn entries_read_back_as_json_lines() {
+    let path = std::env::temp_dir()
+      .join(format!("hyrcon-transcript-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    start(&path).expect("start");
+    assert_eq!(super::path().as_deref(), Some(path.as_path()));
+    connection("connected to 127.0.0.1:27015");
+    command(&CommandRecord {
+      command: "list",
+      status: "ok",
+      payload: vec!["Steve".to_string(), "Alex".to_string()],
+      error: None,
+      duration_ms: 12.5,
+      host: "127.0.0.1",
+      port: 27_015,
+    });
+    message(&ServerMessage {
+      kind: Some("Chat".to_string()),
+      text: "<Steve> hi".to_string(),
+    });
+    assert_eq!(stop(), Some(path.clone()));
+    connection("not recorded");
+
+    let contents = std::fs::read_to_string(&path).expect("transcript");
+    std::fs::remove_file(&path).expect("remove");
+    let entries: Vec<Value> = contents
+      .lines()
+      .map(|line| serde_json::from_str(line).expect("JSON line"))
+      .collect();
+    assert_eq!(entries.len(), 3);
+
+    let times: Vec<_> = entries
+      .iter()
+      .map(|entry| {
+        DateTime::parse_from_rfc3339(entry["time"].as_str().expect("time"))
+          .expect("RFC 3339 time")
+      })
+      .collect();
+    assert!(times.is_sorted());
+
+    let strip_time = |entry: &Value| {
+      let mut entry = entry.clone();
+      entry.as_object_mut().expect("object").remove("time");
+      entry
+    };
+    assert_eq!(
+      strip_time(&entries[0]),
+      json!({
+        "type": "connection",
+        "text": "connected to 127.0.0.1:27015",
+      })
+    );
+    assert_eq!(
+      strip_time(&entries[1]),
+      json!({
+        "type": "command",
+        "command": "list",
+        "status": "ok",
+        "payload": ["Steve", "Alex"],
+        "error": null,
+        "duration_ms": 12.5,
+        "host": "127.0.0.1",
+        "port": 27_015,
+      })
+    );
+    assert_eq!(
+      strip_time(&entries[2]),
+      json!({ "type": "message", "kind": "Chat", "message": "<Steve> hi" })
+    );
+  }
+}
@@ -0,0 +1,168 @@
+use std::fmt;
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use super::{AuthOutcome, CommandOutcome, Greeting, ServerMessage};
+use crate::error::Error;
+
+/// Extension point for wire protocols that are not built into the crate.
+///
+/// Implementations are handed to [`RconClient::connect_with`], which drives
+/// `connect` and then wraps the backend so that it can be used exactly like
+/// the built-in protocols (including by the REPL helpers in this crate).
+///
+/// Configuration beyond host, port and timeout belongs on the implementing
+/// type itself and should be supplied when it is constructed.
+///
+/// Like the built-in backends, `authenticate` and `send_command` should be
+/// cancel-safe: when their future is dropped mid-read, the late reply must
+/// be skipped rather than taken for the next command's.
+///
+/// [`RconClient::connect_with`]: super::RconClient::connect_with
+#[async_trait]
+pub trait ProtocolBackend: fmt::Debug + Send {
+  /// Establish the connection and return the (possibly synthesized) server
+  /// greeting. Build it with [`Greeting::new`] and
+  /// [`Protocol::Custom`](crate::Protocol::Custom).
+  async fn connect(
+    &mut self,
+    host: &str,
+    port: u16,
+    deadline: Duration,
+  ) -> Result<Greeting, Error>;
+
+  /// Perform the authentication handshake.
+  async fn authenticate(
+    &mut self,
+    password: &str,
+  ) -> Result<AuthOutcome, Error>;
+
+  /// Send a single command and wait for its complete response.
+  async fn send_command(
+    &mut self,
+    command: &str,
+  ) -> Result<CommandOutcome, Error>;
+
+  /// Close the session gracefully.
+  async fn quit(&mut self) -> Result<(), Error>;
+
+  /// Whether the session has been closed by either side.
+  fn is_closed(&self) -> bool;
+
+  /// Probe an idle session so it is kept open and a dead connection is
+  /// reported promptly. The default does nothing.
+  async fn keepalive(&mut self) -> Result<(), Error> {
+    Ok(())
+  }
+
+  /// Exchange a no-op with the server for
+  /// [`RconClient::ping`](super::RconClient::ping) to time. The default
+  /// reports that pinging is unsupported.
+  async fn ping(&mut self) -> Result<(), Error> {
+    Err(Error::Unsupported(
+      "ping is not supported by this backend".to_string(),
+    ))
+  }
+
+  /// Use `timeout` instead of the connect deadline for later reads and
+  /// writes. The default ignores it.
+  fn set_timeout(&mut self, _timeout: Duration) {}
+
+  /// Drain unsolicited messages queued while a command was in flight.
+  fn take_messages(&mut self) -> Vec<ServerMessage> {
+    Vec::new()
+  }
+
+  /// Wait for the next unsolicited message while idle; see
+  /// [`RconClient::recv_message`](super::RconClient::recv_message) for the
+  /// contract. The default never resolves.
+  async fn recv_message(
+    &mut self,
+  ) -> Result<Option<ServerMessage>, Error> {
+    std::future::pending().await
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::protocol::Protocol;
+  use crate::transport::{
+    AuthMode, RconClient, RconResponse, ResponseStatus,
+  };
+
+  #[derive(Debug, Default)]
+  struct EchoBackend {
+    closed: bool,
+  }
+
+  #[async_trait]
+  impl ProtocolBackend for EchoBackend {
+    async fn connect(
+      &mut self,
+      _host: &str,
+      _port: u16,
+      _deadline: Duration,
+    ) -> Result<Greeting, Error> {
+      Ok(Greeting::new(
+        Protocol::Custom,
+        "ECHO READY",
+        AuthMode::Optional,
+      ))
+    }
+
+    async fn authenticate(
+      &mut self,
+      _password: &str,
+    ) -> Result<AuthOutcome, Error> {
+      Ok(AuthOutcome::Success)
+    }
+
+    async fn send_command(
+      &mut self,
+      command: &str,
+    ) -> Result<CommandOutcome, Error> {
+      Ok(CommandOutcome::Response(RconResponse {
+        status: ResponseStatus::Ok,
+        payload: vec![command.to_uppercase()],
+        error: None,
+        raw: format!("{}\n", command.to_uppercase()),
+      }))
+    }
+
+    async fn quit(&mut self) -> Result<(), Error> {
+      self.closed = true;
+      Ok(())
+    }
+
+    fn is_closed(&self) -> bool {
+      self.closed
+    }
+  }
+
+  #[tokio::test]
+  async fn custom_backend_drives_rcon_client() {
+    let mut client = RconClient::connect_with(
+      Box::new(EchoBackend::default()),
+      "localhost",
+      0,
+      Duration::from_secs(1),
+    )
+    .await
+    .expect("connect");
+
+    assert_eq!(client.protocol(), Protocol::Custom);
+    assert_eq!(client.greeting().banner(), "ECHO READY");
+
+    let CommandOutcome::Response(response) =
+      client.send_command("ping").await.expect("send")
+    else {
+      panic!("expected a response");
+    };
+    assert_eq!(response.payload, vec!["PING"]);
+
+    client.quit().await.expect("quit");
+    assert!(client.is_closed());
+  }
+}
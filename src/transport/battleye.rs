@@ -0,0 +1,404 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow, bail};
+use tokio::net::UdpSocket;
+use tokio::time::{Instant, sleep_until, timeout_at};
+
+use super::stats::Traffic;
+use super::{
+  AuthOutcome, CommandOutcome, ConnectOptions, MAX_UDP_DATAGRAM,
+  RconResponse, ResponseStatus, ServerMessage, TimedOut, connect_udp,
+  split_lines, with_timeout,
+};
+
+const PACKET_LOGIN: u8 = 0x00;
+const PACKET_COMMAND: u8 = 0x01;
+const PACKET_MESSAGE: u8 = 0x02;
+
+/// Servers drop clients that stay silent for 45 seconds; keep a margin.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// BattlEye RCON client (ARMA 2/3, DayZ) over UDP.
+///
+/// Besides command replies the server pushes log/chat messages that must be
+/// acknowledged; they are queued and handed out via `take_messages` and
+/// `recv_message`. An empty command packet is sent whenever the session has
+/// been quiet for [`KEEPALIVE_INTERVAL`].
+#[derive(Debug)]
+pub(super) struct BattlEyeClient {
+  socket: UdpSocket,
+  timeout: Duration,
+  authed: bool,
+  next_sequence: u8,
+  last_sent: Instant,
+  console: VecDeque<ServerMessage>,
+  closed: bool,
+  /// Bytes and datagrams exchanged, keepalives included.
+  traffic: Traffic,
+}
+
+/// Decoded BattlEye packet body (after header and checksum validation).
+#[derive(Debug, PartialEq, Eq)]
+enum Packet {
+  Login {
+    success: bool,
+  },
+  Command {
+    sequence: u8,
+    part: Option<Part>,
+    payload: Vec<u8>,
+  },
+  Message {
+    sequence: u8,
+    payload: Vec<u8>,
+  },
+}
+
+/// Multi-packet header attached to large command replies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Part {
+  total: u8,
+  index: u8,
+}
+
+impl BattlEyeClient {
+  pub(super) async fn connect(
+    host: &str,
+    port: u16,
+    deadline: Duration,
+    options: &ConnectOptions,
+  ) -> Result<Self> {
+    let socket = connect_udp(host, port, deadline, options).await?;
+
+    Ok(Self {
+      socket,
+      timeout: deadline,
+      authed: false,
+      next_sequence: 0,
+      last_sent: Instant::now(),
+      console: VecDeque::new(),
+      closed: false,
+      traffic: Traffic::default(),
+    })
+  }
+
+  pub(super) fn is_closed(&self) -> bool {
+    self.closed
+  }
+
+  pub(super) fn set_timeout(&mut self, timeout: Duration) {
+    self.timeout = timeout;
+  }
+
+  pub(super) fn traffic(&self) -> &Traffic {
+    &self.traffic
+  }
+
+  pub(super) fn take_messages(&mut self) -> Vec<ServerMessage> {
+    self.console.drain(..).collect()
+  }
+
+  fn next_sequence(&mut self) -> u8 {
+    let sequence = self.next_sequence;
+    self.next_sequence = self.next_sequence.wrapping_add(1);
+    sequence
+  }
+
+  pub(super) async fn authenticate(
+    &mut self,
+    password: &str,
+  ) -> Result<AuthOutcome> {
+    tracing::debug!("--> LOGIN <redacted>");
+    self.send(PACKET_LOGIN, password.as_bytes()).await?;
+
+    let deadline = Instant::now() + self.timeout;
+    loop {
+      match self.recv(deadline).await? {
+        Packet::Login { success: true } => {
+          self.authed = true;
+          return Ok(AuthOutcome::Success);
+        }
+        Packet::Login { success: false } => {
+          return Ok(AuthOutcome::Failure);
+        }
+        other => self.handle_unsolicited(other).await?,
+      }
+    }
+  }
+
+  pub(super) async fn send_command(
+    &mut self,
+    command: &str,
+  ) -> Result<CommandOutcome> {
+    if self.closed {
+      bail!("connection already closed");
+    }
+
+    if !self.authed {
+      bail!("server requires authentication before sending commands");
+    }
+
+    if command.trim().is_empty() {
+      bail!("command must not be empty");
+    }
+
+    let sequence = self.next_sequence();
+    tracing::debug!(request_id = sequence, "--> {}", command);
+
+    let mut body = vec![sequence];
+    body.extend_from_slice(command.as_bytes());
+    self.send(PACKET_COMMAND, &body).await?;
+
+    let deadline = Instant::now() + self.timeout;
+    let mut parts: Vec<Option<Vec<u8>>> = Vec::new();
+
+    loop {
+      let packet = self.recv(deadline).await?;
+      let Packet::Command {
+        sequence: reply_sequence,
+        part,
+        payload,
+      } = packet
+      else {
+        self.handle_unsolicited(packet).await?;
+        continue;
+      };
+
+      if reply_sequence != sequence {
+        tracing::debug!(
+          response_id = reply_sequence,
+          expected_id = sequence,
+          "ignoring stale BattlEye command reply"
+        );
+        continue;
+      }
+
+      let Some(part) = part else {
+        return Ok(command_response(&payload));
+      };
+
+      if parts.is_empty() {
+        parts.resize(usize::from(part.total), None);
+      }
+      let Some(slot) = parts.get_mut(usize::from(part.index)) else {
+        bail!(
+          "BattlEye reply part {} out of range for {} parts",
+          part.index,
+          part.total
+        );
+      };
+      *slot = Some(payload);
+
+      if parts.iter().all(Option::is_some) {
+        let payload: Vec<u8> =
+          parts.into_iter().flatten().flatten().collect();
+        return Ok(command_response(&payload));
+      }
+    }
+  }
+
+  /// Wait for the next server-pushed message, sending keepalives while the
+  /// session is otherwise idle.
+  pub(super) async fn recv_message(
+    &mut self,
+  ) -> Result<Option<ServerMessage>> {
+    loop {
+      if let Some(message) = self.console.pop_front() {
+        return Ok(Some(message));
+      }
+
+      let keepalive_at = self.last_sent + KEEPALIVE_INTERVAL;
+      tokio::select! {
+        packet = self.recv_datagram() => {
+          let packet = packet?;
+          self.handle_unsolicited(packet).await?;
+        }
+        () = sleep_until(keepalive_at) => {
+          let sequence = self.next_sequence();
+          tracing::trace!(request_id = sequence, "--> <keepalive>");
+          self.send(PACKET_COMMAND, &[sequence]).await?;
+        }
+      }
+    }
+  }
+
+  pub(super) async fn quit(&mut self) -> Result<()> {
+    // There is no logout packet; the server expires the session once
+    // keepalives stop.
+    self.closed = true;
+    Ok(())
+  }
+
+  /// Acknowledge and queue server messages; drop anything else.
+  async fn handle_unsolicited(&mut self, packet: Packet) -> Result<()> {
+    match packet {
+      Packet::Message { sequence, payload } => {
+        // Queue before acknowledging so a cancelled caller loses nothing.
+        let text = String::from_utf8_lossy(&payload).into_owned();
+        self.console.push_back(ServerMessage { kind: None, text });
+        self.send(PACKET_MESSAGE, &[sequence]).await?;
+      }
+      other => {
+        tracing::trace!(packet = ?other, "ignoring unexpected packet");
+      }
+    }
+    Ok(())
+  }
+
+  async fn send(&mut self, kind: u8, body: &[u8]) -> Result<()> {
+    let datagram = encode_packet(kind, body);
+    with_timeout(
+      self.timeout,
+      self.socket.send(&datagram),
+      "writing BattlEye packet",
+    )
+    .await?;
+    self.traffic.sent(datagram.len());
+    self.last_sent = Instant::now();
+    Ok(())
+  }
+
+  async fn recv(&mut self, deadline: Instant) -> Result<Packet> {
+    timeout_at(deadline, self.recv_datagram())
+      .await
+      .map_err(|_| {
+        TimedOut::new("reading BattlEye packet", self.timeout)
+      })?
+  }
+
+  /// Receive the next well-formed packet, skipping corrupted datagrams.
+  async fn recv_datagram(&self) -> Result<Packet> {
+    let mut buffer = vec![0_u8; MAX_UDP_DATAGRAM];
+    loop {
+      let len = self
+        .socket
+        .recv(&mut buffer)
+        .await
+        .context("reading BattlEye packet")?;
+      self.traffic.received(len);
+      tracing::trace!(payload_len = len, "received BattlEye packet");
+
+      match decode_packet(&buffer[..len]) {
+        Ok(packet) => return Ok(packet),
+        Err(err) => {
+          tracing::debug!(error = %err, "discarding malformed datagram");
+        }
+      }
+    }
+  }
+}
+
+fn command_response(payload: &[u8]) -> CommandOutcome {
+  let raw = String::from_utf8_lossy(payload).into_owned();
+  CommandOutcome::Response(RconResponse {
+    status: ResponseStatus::Ok,
+    payload: split_lines(&raw),
+    error: None,
+    raw,
+  })
+}
+
+/// Frame `kind` + `body` as `'B' 'E' <crc32> 0xFF <kind> <body>`.
+fn encode_packet(kind: u8, body: &[u8]) -> Vec<u8> {
+  let mut checked = Vec::with_capacity(2 + body.len());
+  checked.push(0xFF);
+  checked.push(kind);
+  checked.extend_from_slice(body);
+
+  let mut packet = Vec::with_capacity(6 + checked.len());
+  packet.extend_from_slice(b"BE");
+  packet.extend_from_slice(&crc32fast::hash(&checked).to_le_bytes());
+  packet.extend_from_slice(&checked);
+  packet
+}
+
+fn decode_packet(datagram: &[u8]) -> Result<Packet> {
+  let rest = datagram.strip_prefix(b"BE").ok_or_else(|| {
+    anyhow!("protocol violation: BattlEye packet missing `BE` header")
+  })?;
+  if rest.len() < 6 {
+    bail!("BattlEye packet too short: {} bytes", datagram.len());
+  }
+
+  let (crc_bytes, checked) = rest.split_at(4);
+  let expected = u32::from_le_bytes(
+    crc_bytes.try_into().expect("split_at yields four bytes"),
+  );
+  if crc32fast::hash(checked) != expected {
+    bail!("BattlEye packet failed checksum validation");
+  }
+  if checked[0] != 0xFF {
+    bail!("protocol violation: BattlEye packet missing 0xFF marker");
+  }
+
+  let kind = checked[1];
+  let body = &checked[2..];
+  match (kind, body) {
+    (PACKET_LOGIN, [result, ..]) => Ok(Packet::Login {
+      success: *result == 0x01,
+    }),
+    (PACKET_COMMAND, [sequence, 0x00, total, index, payload @ ..]) => {
+      Ok(Packet::Command {
+        sequence: *sequence,
+        part: Some(Part {
+          total: *total,
+          index: *index,
+        }),
+        payload: payload.to_vec(),
+      })
+    }
+    (PACKET_COMMAND, [sequence, payload @ ..]) => Ok(Packet::Command {
+      sequence: *sequence,
+      part: None,
+      payload: payload.to_vec(),
+    }),
+    (PACKET_MESSAGE, [sequence, payload @ ..]) => Ok(Packet::Message {
+      sequence: *sequence,
+      payload: payload.to_vec(),
+    }),
+    _ => bail!("unexpected BattlEye packet type {kind:#04x}"),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn packets_round_trip_through_checksum() {
+    let encoded = encode_packet(PACKET_COMMAND, b"\x07players");
+    assert_eq!(&encoded[..2], b"BE");
+    assert_eq!(
+      decode_packet(&encoded).expect("decode"),
+      Packet::Command {
+        sequence: 7,
+        part: None,
+        payload: b"players".to_vec(),
+      }
+    );
+
+    let mut corrupted = encoded;
+    *corrupted.last_mut().expect("payload") ^= 0x20;
+    assert!(decode_packet(&corrupted).is_err());
+  }
+
+  #[test]
+  fn decodes_multipart_and_login_replies() {
+    let part = encode_packet(PACKET_COMMAND, b"\x03\x00\x02\x01tail");
+    assert_eq!(
+      decode_packet(&part).expect("decode part"),
+      Packet::Command {
+        sequence: 3,
+        part: Some(Part { total: 2, index: 1 }),
+        payload: b"tail".to_vec(),
+      }
+    );
+
+    let login = encode_packet(PACKET_LOGIN, b"\x00");
+    assert_eq!(
+      decode_packet(&login).expect("decode login"),
+      Packet::Login { success: false }
+    );
+  }
+}
@@ -0,0 +1,412 @@
+//! Fluent construction of [`RconClient`] sessions.
+//!
+//! [`RconClient::builder`] collects the settings one at a time and
+//! [`RconClientBuilder::build`] checks them, giving an [`RconConnector`]
+//! that connects and authenticates as many sessions as needed:
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use hyrcon_client::{Protocol, RconClient};
+//!
+//! # async fn example() -> anyhow::Result<()> {
+//! let mut client = RconClient::builder()
+//!   .protocol(Protocol::Source)
+//!   .host("mc.example.com")
+//!   .password("hunter2")
+//!   .timeout(Duration::from_secs(3))
+//!   .build()?
+//!   .connect()
+//!   .await?;
+//!
+//! client.send_command("list").await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+
+use super::events::Events;
+use super::{
+  AuthOutcome, ClientEvent, ConnectOptions, Connector, Proxy, RconClient,
+  TlsOptions,
+};
+use crate::error::Error;
+use crate::protocol::Protocol;
+
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(8);
+
+/// Builder for [`RconClient`] sessions, from [`RconClient::builder`].
+#[derive(Debug, Clone)]
+pub struct RconClientBuilder {
+  protocol: Protocol,
+  host: Option<String>,
+  port: Option<u16>,
+  connect_timeout: Duration,
+  timeout: Option<Duration>,
+  options: ConnectOptions,
+  password: Option<String>,
+  cancellation: Option<CancellationToken>,
+}
+
+/// Checked connection settings from [`RconClientBuilder::build`]. Each
+/// [`connect`](Self::connect) opens a new session, e.g. to replace one
+/// that dropped.
+#[derive(Debug, Clone)]
+pub struct RconConnector {
+  protocol: Protocol,
+  host: String,
+  port: u16,
+  connect_timeout: Duration,
+  timeout: Duration,
+  options: ConnectOptions,
+  password: Option<String>,
+  cancellation: Option<CancellationToken>,
+  /// Shared by every session connected, and by clones.
+  events: Events,
+}
+
+impl Default for RconClientBuilder {
+  fn default() -> Self {
+    Self {
+      protocol: Protocol::default(),
+      host: None,
+      port: None,
+      connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+      timeout: None,
+      options: ConnectOptions::default(),
+      password: None,
+      cancellation: None,
+    }
+  }
+}
+
+impl RconClientBuilder {
+  /// Wire protocol to speak (default `source`). [`Protocol::Custom`]
+  /// backends are connected with [`RconClient::connect_with`] instead.
+  #[must_use]
+  pub fn protocol(mut self, protocol: Protocol) -> Self {
+    self.protocol = protocol;
+    self
+  }
+
+  /// Hostname or address of the server. Required.
+  #[must_use]
+  pub fn host(mut self, host: impl Into<String>) -> Self {
+    self.host = Some(host.into());
+    self
+  }
+
+  /// Port of the server (default: the protocol's usual port).
+  #[must_use]
+  pub fn port(mut self, port: u16) -> Self {
+    self.port = Some(port);
+    self
+  }
+
+  /// Bound on connecting and the handshake (default 8 seconds).
+  #[must_use]
+  pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+    self.connect_timeout = timeout;
+    self
+  }
+
+  /// How long to wait for each reply once connected (default: the
+  /// connect timeout).
+  #[must_use]
+  pub fn timeout(mut self, timeout: Duration) -> Self {
+    self.timeout = Some(timeout);
+    self
+  }
+
+  /// Encrypt the session with TLS, for the backends that support it.
+  #[must_use]
+  pub fn tls(mut self, tls: TlsOptions) -> Self {
+    self.options.tls = Some(tls);
+    self
+  }
+
+  /// Tunnel the connection through `proxy`.
+  #[must_use]
+  pub fn proxy(mut self, proxy: Proxy) -> Self {
+    self.options.proxy = Some(proxy);
+    self
+  }
+
+  /// Authenticate each session with `password`.
+  #[must_use]
+  pub fn password(mut self, password: impl Into<String>) -> Self {
+    self.password = Some(password.into());
+    self
+  }
+
+  /// Send TCP keepalive probes after the connection has been idle this
+  /// long.
+  #[must_use]
+  pub fn keepalive(mut self, idle: Duration) -> Self {
+    self.options.tcp_keepalive = Some(idle);
+    self
+  }
+
+  /// Give up connecting with [`Error::Cancelled`] once `token` is
+  /// cancelled, e.g. on graceful shutdown.
+  #[must_use]
+  pub fn cancel_on(mut self, token: CancellationToken) -> Self {
+    self.cancellation = Some(token);
+    self
+  }
+
+  /// Open connections with `connector` instead of dialing TCP.
+  #[must_use]
+  pub fn connector(mut self, connector: impl Connector + 'static) -> Self {
+    self.options.connector = Some(Arc::new(connector));
+    self
+  }
+
+  /// Replace every protocol-specific setting at once, including those
+  /// given to [`tls`](Self::tls), [`proxy`](Self::proxy),
+  /// [`keepalive`](Self::keepalive) and [`connector`](Self::connector)
+  /// so far.
+  #[must_use]
+  pub fn options(mut self, options: ConnectOptions) -> Self {
+    self.options = options;
+    self
+  }
+
+  /// Check the settings without connecting yet.
+  pub fn build(self) -> Result<RconConnector, Error> {
+    if self.protocol == Protocol::Custom {
+      return Err(Error::Unsupported(
+        "custom protocols must be connected via RconClient::connect_with"
+          .to_string(),
+      ));
+    }
+    let host = match self.host {
+      Some(host) if !host.trim().is_empty() => host,
+      _ => {
+        return Err(Error::Unsupported(
+          "no host to connect to".to_string(),
+        ));
+      }
+    };
+    Ok(RconConnector {
+      protocol: self.protocol,
+      host,
+      port: self.port.unwrap_or_else(|| self.protocol.default_port()),
+      connect_timeout: self.connect_timeout,
+      timeout: self.timeout.unwrap_or(self.connect_timeout),
+      options: self.options,
+      password: self.password,
+      cancellation: self.cancellation,
+      events: Events::default(),
+    })
+  }
+}
+
+impl RconConnector {
+  pub fn protocol(&self) -> Protocol {
+    self.protocol
+  }
+
+  pub fn host(&self) -> &str {
+    &self.host
+  }
+
+  pub fn port(&self) -> u16 {
+    self.port
+  }
+
+  /// Receive the [`ClientEvent`]s of every session this connector opens
+  /// from now on, starting with each one's
+  /// [`Connected`](ClientEvent::Connected).
+  pub fn events(&self) -> broadcast::Receiver<ClientEvent> {
+    self.events.subscribe()
+  }
+
+  /// Report `event` to the receivers of [`events`](Self::events).
+  pub(super) fn emit(&self, event: ClientEvent) {
+    self.events.emit(event);
+  }
+
+  /// Connect a new session and authenticate it with the password, if
+  /// one was given. Servers that require a password fail without one.
+  pub async fn connect(&self) -> Result<RconClient, Error> {
+    match &self.cancellation {
+      Some(token) => token
+        .run_until_cancelled(self.open())
+        .await
+        .unwrap_or(Err(Error::Cancelled)),
+      None => self.open().await,
+    }
+  }
+
+  async fn open(&self) -> Result<RconClient, Error> {
+    let mut client = RconClient::connect_with_options(
+      self.protocol,
+      &self.host,
+      self.port,
+      self.connect_timeout,
+      &self.options,
+    )
+    .await?;
+    client.attach_events(&self.events);
+
+    let required = client.greeting().requires_auth();
+    match self.password.as_deref() {
+      Some(password) => match client.authenticate(password).await? {
+        AuthOutcome::Success => {}
+        AuthOutcome::Failure if required => {
+          return Err(Error::AuthRejected);
+        }
+        AuthOutcome::Failure => tracing::warn!(
+          "session continues without credentials after a failed optional authentication"
+        ),
+      },
+      None if required => return Err(Error::AuthRequired),
+      None => {}
+    }
+
+    client.set_timeout(self.timeout);
+    Ok(client)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::testing::{MockHyrconServer, Reply};
+  use crate::transport::CommandOutcome;
+
+  #[tokio::test]
+  async fn connects_and_authenticates() {
+    let server = MockHyrconServer::builder()
+      .password("secret")
+      .expect("list", Reply::ok(["Steve"]))
+      .start()
+      .await
+      .expect("start");
+    let connector = RconClient::builder()
+      .protocol(Protocol::Hyrcon)
+      .host("127.0.0.1")
+      .port(server.port())
+      .password("secret")
+      .timeout(Duration::from_secs(2))
+      .build()
+      .expect("settings");
+
+    let mut client = connector.connect().await.expect("connect");
+    match client.send_command("list").await.expect("list") {
+      CommandOutcome::Response(response) => {
+        assert_eq!(response.payload, ["Steve"]);
+      }
+      CommandOutcome::Bye => panic!("unexpected bye"),
+    }
+    client.quit().await.expect("quit");
+    server.finish().await.expect("script followed");
+  }
+
+  #[tokio::test]
+  async fn sessions_report_their_lifecycle() {
+    let server = MockHyrconServer::builder()
+      .password("secret")
+      .expect("list", Reply::ok(["Steve"]))
+      .start()
+      .await
+      .expect("start");
+    let connector = RconClient::builder()
+      .protocol(Protocol::Hyrcon)
+      .host("127.0.0.1")
+      .port(server.port())
+      .password("secret")
+      .build()
+      .expect("settings");
+    let mut events = connector.events();
+
+    let mut client = connector.connect().await.expect("connect");
+    client.send_command("list").await.expect("list");
+    client.quit().await.expect("quit");
+    server.finish().await.expect("script followed");
+
+    let mut seen = Vec::new();
+    while let Ok(event) = events.try_recv() {
+      seen.push(match event {
+        ClientEvent::ResponseReceived { command, .. } => {
+          format!("response {command}")
+        }
+        ClientEvent::CommandSent { command } => format!("sent {command}"),
+        other => format!("{other:?}"),
+      });
+    }
+    assert_eq!(
+      seen,
+      [
+        "Connected",
+        "Authenticated",
+        "Idle",
+        "sent list",
+        "response list",
+        "Idle",
+        "Disconnected(Quit)",
+      ]
+    );
+  }
+
+  #[tokio::test]
+  async fn required_password_is_checked() {
+    let server = MockHyrconServer::builder()
+      .password("secret")
+      .start()
+      .await
+      .expect("start");
+    let connector = RconClient::builder()
+      .protocol(Protocol::Hyrcon)
+      .host("127.0.0.1")
+      .port(server.port())
+      .build()
+      .expect("settings");
+
+    let err = connector.connect().await.expect_err("no password");
+    assert!(matches!(err, Error::AuthRequired));
+  }
+
+  #[tokio::test]
+  async fn connecting_stops_when_cancelled() {
+    let server = MockHyrconServer::builder().start().await.expect("start");
+    let token = CancellationToken::new();
+    token.cancel();
+    let connector = RconClient::builder()
+      .protocol(Protocol::Hyrcon)
+      .host("127.0.0.1")
+      .port(server.port())
+      .cancel_on(token)
+      .build()
+      .expect("settings");
+
+    let err = connector.connect().await.expect_err("cancelled");
+    assert!(matches!(err, Error::Cancelled));
+  }
+
+  #[test]
+  fn build_checks_the_settings() {
+    assert!(RconClient::builder().build().is_err());
+    assert!(
+      RconClient::builder()
+        .protocol(Protocol::Custom)
+        .host("localhost")
+        .build()
+        .is_err()
+    );
+
+    let connector = RconClient::builder()
+      .protocol(Protocol::Source)
+      .host("mc.example.com")
+      .build()
+      .expect("settings");
+    assert_eq!(connector.port(), Protocol::Source.default_port());
+    assert_eq!(connector.host(), "mc.example.com");
+  }
+}
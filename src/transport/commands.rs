@@ -0,0 +1,248 @@
+//! Everyday admin commands on [`RconClient`], worded for each protocol so
+//! callers do not have to know every game's syntax.
+
+use serde::Deserialize;
+
+use super::{CommandOutcome, RconClient, RconResponse, ResponseStatus};
+use crate::error::Error;
+use crate::models::{Player, PlayerList, ServerStatus};
+use crate::protocol::Protocol;
+
+/// A player in a Palworld-style `GET /players` reply.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RestPlayer {
+  name: String,
+  user_id: Option<String>,
+  ping: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RestPlayers {
+  players: Vec<RestPlayer>,
+}
+
+impl RconClient {
+  /// Broadcast `message` to everyone on the server.
+  pub async fn say(&mut self, message: &str) -> Result<(), Error> {
+    let command = match self.protocol {
+      Protocol::Hyrcon => format!("SAY {message}"),
+      // `-1` addresses every player.
+      Protocol::BattlEye => format!("say -1 {message}"),
+      _ => format!("say {message}"),
+    };
+    self.run(&command).await.map(drop)
+  }
+
+  /// Ask the server who is online: HYRCON `PLAYERS`, Minecraft `list`
+  /// (falling back to Source engine `status` on the Source protocol) or
+  /// the REST API's player list.
+  pub async fn players(&mut self) -> Result<Vec<Player>, Error> {
+    let list = match self.protocol {
+      Protocol::Hyrcon => self.run("PLAYERS").await?.parse::<PlayerList>(),
+      Protocol::Source => {
+        let reply = self.run("list").await?;
+        if is_minecraft_list(&reply) {
+          reply.parse::<PlayerList>()
+        } else {
+          let status = self.run("status").await?;
+          return status
+            .parse::<ServerStatus>()
+            .map(|status| status.players)
+            .map_err(|err| Error::Protocol(err.to_string()));
+        }
+      }
+      // Consoles reached this way are usually Minecraft servers.
+      Protocol::Telnet | Protocol::Pterodactyl | Protocol::Ssh => {
+        let reply = self.run("list").await?;
+        if !is_minecraft_list(&reply) {
+          return Err(Error::Protocol(
+            "unexpected reply to `list`".to_string(),
+          ));
+        }
+        reply.parse::<PlayerList>()
+      }
+      Protocol::Rest => {
+        let reply = self.run("players").await?;
+        return rest_players(&reply);
+      }
+      protocol => {
+        return Err(Error::Unsupported(format!(
+          "listing players is not supported over the {protocol} protocol"
+        )));
+      }
+    };
+    list
+      .map(|list| list.players)
+      .map_err(|err| Error::Protocol(err.to_string()))
+  }
+
+  /// Remove `player` from the server, telling them `reason` where the
+  /// game supports one. REST servers take the player's user id.
+  pub async fn kick(
+    &mut self,
+    player: &str,
+    reason: Option<&str>,
+  ) -> Result<(), Error> {
+    let player = match self.protocol {
+      Protocol::Source | Protocol::WebRcon | Protocol::Quake
+        if player.contains(char::is_whitespace) =>
+      {
+        format!("\"{player}\"")
+      }
+      Protocol::BattlEye => {
+        return Err(Error::Unsupported(
+          "BattlEye kicks players by number; send `kick <number>` instead"
+            .to_string(),
+        ));
+      }
+      _ => player.to_string(),
+    };
+    let verb = match self.protocol {
+      Protocol::Hyrcon => "KICK",
+      _ => "kick",
+    };
+    let command = match reason {
+      Some(reason) if !reason.trim().is_empty() => {
+        format!("{verb} {player} {reason}")
+      }
+      _ => format!("{verb} {player}"),
+    };
+    self.run(&command).await.map(drop)
+  }
+
+  /// Send `command`, treating an error reply as a failure.
+  async fn run(&mut self, command: &str) -> Result<RconResponse, Error> {
+    match self.send_command(command).await? {
+      CommandOutcome::Response(response)
+        if response.status == ResponseStatus::Err =>
+      {
+        Err(Error::Rejected(
+          response
+            .error
+            .unwrap_or_else(|| response.payload.join("\n")),
+        ))
+      }
+      CommandOutcome::Response(response) => Ok(response),
+      CommandOutcome::Bye => Err(Error::Closed),
+    }
+  }
+}
+
+fn is_minecraft_list(reply: &RconResponse) -> bool {
+  reply
+    .payload
+    .first()
+    .is_some_and(|line| line.contains("There are"))
+}
+
+fn rest_players(reply: &RconResponse) -> Result<Vec<Player>, Error> {
+  let body = reply.payload.join("\n");
+  let players: RestPlayers =
+    serde_json::from_str(&body).map_err(|err| {
+      Error::Protocol(format!("unexpected player list: {err}"))
+    })?;
+  Ok(
+    players
+      .players
+      .into_iter()
+      .map(|player| Player {
+        name: player.name,
+        id: player.user_id,
+        ping: player.ping.map(|ping| ping.round() as u32),
+        addr: None,
+      })
+      .collect(),
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use super::*;
+  use crate::testing::{MockHyrconServer, MockSourceServer, Reply};
+
+  #[tokio::test]
+  async fn helpers_speak_each_protocol() {
+    let server = MockHyrconServer::builder()
+      .expect("SAY restarting soon", Reply::ok(Vec::<String>::new()))
+      .expect("PLAYERS", Reply::ok(["Steve ping=42", "Alex"]))
+      .expect("KICK Steve griefing", Reply::err("no such player"))
+      .start()
+      .await
+      .expect("start");
+    let mut client = RconClient::connect(
+      Protocol::Hyrcon,
+      "127.0.0.1",
+      server.port(),
+      Duration::from_secs(2),
+    )
+    .await
+    .expect("connect");
+
+    client.say("restarting soon").await.expect("say");
+    let players = client.players().await.expect("players");
+    assert_eq!(players.len(), 2);
+    assert_eq!(players[0].ping, Some(42));
+    let err = client
+      .kick("Steve", Some("griefing"))
+      .await
+      .expect_err("rejected");
+    assert!(
+      matches!(err, Error::Rejected(message) if message == "no such player")
+    );
+    client.quit().await.expect("quit");
+    server.finish().await.expect("script followed");
+  }
+
+  #[tokio::test]
+  async fn source_players_fall_back_to_status() {
+    let server = MockSourceServer::builder()
+      .password("secret")
+      .expect("list", Reply::ok(["Unknown command \"list\""]))
+      .expect(
+        "status",
+        Reply::ok([
+          "hostname: Dust 24/7",
+          "#      2 \"alice\" STEAM_1:0:123 05:12 48 0 active 198.51.100.4:27005",
+          "#end",
+        ]),
+      )
+      .start()
+      .await
+      .expect("start");
+    let mut client = RconClient::connect(
+      Protocol::Source,
+      "127.0.0.1",
+      server.port(),
+      Duration::from_secs(2),
+    )
+    .await
+    .expect("connect");
+    client.authenticate("secret").await.expect("auth");
+
+    let players = client.players().await.expect("players");
+    assert_eq!(players.len(), 1);
+    assert_eq!(players[0].name, "alice");
+    client.quit().await.expect("quit");
+    server.finish().await.expect("script followed");
+  }
+
+  #[test]
+  fn rest_player_lists_are_read() {
+    let reply = RconResponse {
+      status: ResponseStatus::Ok,
+      payload: vec![
+        r#"{"players": [{"name": "alice", "userId": "steam_1", "ping": 47.6}]}"#
+          .to_string(),
+      ],
+      error: None,
+      raw: String::new(),
+    };
+    let players = rest_players(&reply).expect("players");
+    assert_eq!(players[0].name, "alice");
+    assert_eq!(players[0].id.as_deref(), Some("steam_1"));
+    assert_eq!(players[0].ping, Some(48));
+  }
+}
@@ -0,0 +1,86 @@
+//! Lifecycle events of [`RconClient`] sessions, for callers that follow
+//! the connection state instead of polling [`RconClient::is_closed`].
+
+use std::time::Duration;
+
+use tokio::sync::broadcast;
+
+#[cfg(doc)]
+use super::{ManagedClient, RconClient, RconConnector};
+
+/// Events kept for receivers that fall behind before the oldest are
+/// dropped.
+const CAPACITY: usize = 64;
+
+/// Something that happened to a session.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ClientEvent {
+  /// The session was opened. Only seen through
+  /// [`RconConnector::events`], since it happens before the client
+  /// exists.
+  Connected,
+  /// The server accepted the password.
+  Authenticated,
+  /// A command was written to the server.
+  CommandSent { command: String },
+  /// The reply to `command` arrived after `elapsed`.
+  ResponseReceived { command: String, elapsed: Duration },
+  /// No command is in flight; the session is ready for the next one.
+  Idle,
+  /// The session ended and will not be used again.
+  Disconnected(DisconnectReason),
+  /// A [`ManagedClient`] is about to try connecting again, for the
+  /// `attempt`th time since the session dropped, after waiting `delay`.
+  Reconnecting { attempt: u32, delay: Duration },
+  /// A [`ManagedClient`] has a new session after `attempt` tries.
+  Reconnected { attempt: u32 },
+}
+
+/// Why a session ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DisconnectReason {
+  /// [`RconClient::quit`] closed it.
+  Quit,
+  /// The server said goodbye or closed the connection.
+  ServerClosed,
+  /// Talking to the server failed; the error message says how.
+  Failed(String),
+}
+
+/// Sending side of a session's events.
+#[derive(Debug, Clone)]
+pub(super) struct Events {
+  sender: broadcast::Sender<ClientEvent>,
+  disconnected: bool,
+}
+
+impl Default for Events {
+  fn default() -> Self {
+    Self {
+      sender: broadcast::Sender::new(CAPACITY),
+      disconnected: false,
+    }
+  }
+}
+
+impl Events {
+  pub(super) fn subscribe(&self) -> broadcast::Receiver<ClientEvent> {
+    self.sender.subscribe()
+  }
+
+  /// Send `event` to whoever is listening; without receivers it is
+  /// simply dropped.
+  pub(super) fn emit(&self, event: ClientEvent) {
+    let _ = self.sender.send(event);
+  }
+
+  /// Report the end of the session, once.
+  pub(super) fn disconnected(&mut self, reason: DisconnectReason) {
+    if !self.disconnected {
+      self.disconnected = true;
+      self.emit(ClientEvent::Disconnected(reason));
+    }
+  }
+}
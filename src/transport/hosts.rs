@@ -0,0 +1,261 @@
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use super::split_host_port;
+
+/// Fixed addresses for a `host:port` pair, used instead of DNS like
+/// curl's `--resolve`.
+///
+/// Parsed from `host:port:address[,address...]`; IPv6 addresses may be
+/// wrapped in brackets. TLS still verifies the certificate against `host`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolveOverride {
+  host: String,
+  port: u16,
+  addresses: Vec<IpAddr>,
+}
+
+impl ResolveOverride {
+  pub fn new(
+    host: impl Into<String>,
+    port: u16,
+    addresses: Vec<IpAddr>,
+  ) -> Self {
+    Self {
+      host: host.into(),
+      port,
+      addresses,
+    }
+  }
+
+  /// Hostname the override applies to.
+  pub fn host(&self) -> &str {
+    &self.host
+  }
+
+  pub fn port(&self) -> u16 {
+    self.port
+  }
+
+  /// Addresses connected to instead of the DNS answer, in order.
+  pub fn addresses(&self) -> &[IpAddr] {
+    &self.addresses
+  }
+
+  /// Whether connections to `host:port` use this override. Hostnames are
+  /// compared case-insensitively and without a trailing dot.
+  pub(super) fn matches(&self, host: &str, port: u16) -> bool {
+    self.port == port
+      && self
+        .host
+        .trim_end_matches('.')
+        .eq_ignore_ascii_case(host.trim_end_matches('.'))
+  }
+}
+
+impl fmt::Display for ResolveOverride {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}:{}:", self.host, self.port)?;
+    for (index, address) in self.addresses.iter().enumerate() {
+      if index > 0 {
+        f.write_str(",")?;
+      }
+      match address {
+        IpAddr::V4(address) => write!(f, "{address}")?,
+        IpAddr::V6(address) => write!(f, "[{address}]")?,
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Error returned when parsing a [`ResolveOverride`] fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseResolveOverrideError {
+  reason: &'static str,
+}
+
+impl fmt::Display for ParseResolveOverrideError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "invalid resolve override: {}", self.reason)
+  }
+}
+
+impl std::error::Error for ParseResolveOverrideError {}
+
+impl FromStr for ResolveOverride {
+  type Err = ParseResolveOverrideError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let error = |reason| ParseResolveOverrideError { reason };
+
+    let mut parts = s.trim().splitn(3, ':');
+    let (Some(host), Some(port), Some(addresses)) =
+      (parts.next(), parts.next(), parts.next())
+    else {
+      return Err(error("expected `host:port:address`"));
+    };
+    if host.is_empty() {
+      return Err(error("missing host"));
+    }
+    let port = port.parse().map_err(|_| error("invalid port"))?;
+    let addresses = addresses
+      .split(',')
+      .map(|address| {
+        address
+          .strip_prefix('[')
+          .and_then(|address| address.strip_suffix(']'))
+          .unwrap_or(address)
+          .parse()
+          .map_err(|_| error("invalid IP address"))
+      })
+      .collect::<Result<_, _>>()?;
+
+    Ok(Self::new(host, port, addresses))
+  }
+}
+
+/// Server to connect to, written `host[:port]` like most admin tools
+/// take endpoints. IPv6 addresses with a port go in brackets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+  host: String,
+  port: Option<u16>,
+}
+
+impl Target {
+  pub fn host(&self) -> &str {
+    &self.host
+  }
+
+  /// Port, when the target names one.
+  pub fn port(&self) -> Option<u16> {
+    self.port
+  }
+
+  /// Parse `word` as a target only if it cannot be mistaken for the first
+  /// word of a command: it must name a port or be an IP address.
+  pub fn from_positional(word: &str) -> Option<Self> {
+    let target: Self = word.parse().ok()?;
+    let unambiguous =
+      target.port.is_some() || target.host.parse::<IpAddr>().is_ok();
+    unambiguous.then_some(target)
+  }
+}
+
+impl fmt::Display for Target {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if self.host.contains(':') {
+      write!(f, "[{}]", self.host)?;
+    } else {
+      f.write_str(&self.host)?;
+    }
+    match self.port {
+      Some(port) => write!(f, ":{port}"),
+      None => Ok(()),
+    }
+  }
+}
+
+/// Error returned when parsing a [`Target`] fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseTargetError {
+  reason: &'static str,
+}
+
+impl fmt::Display for ParseTargetError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "invalid target: {}", self.reason)
+  }
+}
+
+impl std::error::Error for ParseTargetError {}
+
+impl FromStr for Target {
+  type Err = ParseTargetError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let error = |reason| ParseTargetError { reason };
+
+    let s = s.trim();
+    // A bare IPv6 address has colons but no port.
+    if let Ok(IpAddr::V6(address)) = s.parse::<IpAddr>() {
+      return Ok(Self {
+        host: address.to_string(),
+        port: None,
+      });
+    }
+    let (host, port) = split_host_port(s)
+      .ok_or(error("wrap IPv6 addresses in brackets to add a port"))?;
+    if host.is_empty() || host.contains(char::is_whitespace) {
+      return Err(error("expected `host[:port]`"));
+    }
+    let port = port
+      .map(|port| port.parse().map_err(|_| error("invalid port")))
+      .transpose()?;
+    Ok(Self {
+      host: host.to_string(),
+      port,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn targets_need_a_port_or_address_when_positional() {
+    let target: Target = "play.example.com:25575".parse().expect("target");
+    assert_eq!(target.host(), "play.example.com");
+    assert_eq!(target.port(), Some(25_575));
+    assert_eq!(target.to_string(), "play.example.com:25575");
+
+    let target: Target = "[2001:db8::8]:27015".parse().expect("v6");
+    assert_eq!(target.host(), "2001:db8::8");
+    assert_eq!(target.to_string(), "[2001:db8::8]:27015");
+    assert_eq!("::1".parse::<Target>().expect("bare v6").port(), None);
+    assert_eq!(
+      "mc.example.com".parse::<Target>().expect("bare").port(),
+      None
+    );
+
+    assert!(Target::from_positional("10.0.0.8").is_some());
+    assert!(Target::from_positional("mc.example.com:25575").is_some());
+    assert!(Target::from_positional("status").is_none());
+    assert!(Target::from_positional("minecraft:give").is_none());
+
+    assert!("host:rcon".parse::<Target>().is_err());
+    assert!(":25575".parse::<Target>().is_err());
+  }
+
+  #[test]
+  fn parses_curl_style_overrides() {
+    let pinned: ResolveOverride =
+      "rcon.example.com:25575:10.0.0.8,[2001:db8::8]"
+        .parse()
+        .expect("override");
+    assert_eq!(pinned.host(), "rcon.example.com");
+    assert_eq!(pinned.port(), 25_575);
+    assert_eq!(
+      pinned.addresses(),
+      [
+        "10.0.0.8".parse::<IpAddr>().expect("v4"),
+        "2001:db8::8".parse().expect("v6"),
+      ]
+    );
+    assert_eq!(
+      pinned.to_string(),
+      "rcon.example.com:25575:10.0.0.8,[2001:db8::8]"
+    );
+
+    assert!(pinned.matches("RCON.example.com.", 25_575));
+    assert!(!pinned.matches("rcon.example.com", 27_015));
+
+    assert!("rcon.example.com:25575".parse::<ResolveOverride>().is_err());
+    assert!(":25575:10.0.0.8".parse::<ResolveOverride>().is_err());
+    assert!("host:rcon:10.0.0.8".parse::<ResolveOverride>().is_err());
+    assert!("host:25575:standby".parse::<ResolveOverride>().is_err());
+  }
+}
@@ -0,0 +1,555 @@
+//! HYRCON backend.
+//!
+//! Sessions are driven through [`RconClient`](crate::RconClient); for
+//! bridges with nonstandard verbs or replies,
+//! [`RconClient::hyrcon_lines`](crate::RconClient::hyrcon_lines) sends
+//! raw lines and reads raw `.`-terminated blocks with the session's
+//! timeout and logging, skipping `send_command`'s `OK`/`ERR`/`BYE`
+//! parsing:
+//!
+//! ```no_run
+//! use hyrcon_client::RconClient;
+//!
+//! # async fn example(client: &mut RconClient) -> anyhow::Result<()> {
+//! if let Some(mut lines) = client.hyrcon_lines() {
+//!   lines.send_line("WATCH players").await?;
+//!   for line in lines.read_block().await? {
+//!     println!("{line}");
+//!   }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter};
+use tokio::time::timeout as await_timeout;
+
+use super::stats::Traffic;
+use super::stream::{self, StreamReader, StreamWriter};
+use super::{
+  AuthOutcome, CommandOutcome, ConnectOptions, Greeting, ResponseStatus,
+  ServerMessage, TimedOut, with_timeout,
+};
+use crate::codec::hyrcon::{
+  BlockDecoder, event_messages, is_event, parse_capabilities, parse_reply,
+};
+use crate::error::Error;
+
+#[derive(Debug)]
+pub(super) struct HyrconClient {
+  reader: BufReader<StreamReader>,
+  writer: BufWriter<StreamWriter>,
+  timeout: Duration,
+  /// Lines and blocks received so far, kept across cancelled reads.
+  decoder: BlockDecoder,
+  /// `EVENT` blocks pushed by the server, one message per line.
+  events: VecDeque<ServerMessage>,
+  closed: bool,
+  /// Set while a command response has not been fully read, e.g. when the
+  /// caller dropped the `send_command` future mid-flight.
+  awaiting_response: bool,
+  /// Bytes and lines exchanged.
+  traffic: Traffic,
+}
+
+impl HyrconClient {
+  pub(super) async fn connect(
+    host: &str,
+    port: u16,
+    deadline: Duration,
+    options: &ConnectOptions,
+  ) -> Result<(Self, Greeting)> {
+    let traffic = Traffic::default();
+    let (read_half, write_half) =
+      stream::connect(host, port, deadline, options, &traffic).await?;
+    Self::handshake(read_half, write_half, deadline, traffic).await
+  }
+
+  /// Read the greeting and ask for capabilities over a connected stream.
+  pub(super) async fn handshake(
+    read_half: StreamReader,
+    write_half: StreamWriter,
+    deadline: Duration,
+    traffic: Traffic,
+  ) -> Result<(Self, Greeting)> {
+    let mut client = Self {
+      reader: BufReader::new(read_half),
+      writer: BufWriter::new(write_half),
+      timeout: deadline,
+      decoder: BlockDecoder::new(),
+      events: VecDeque::new(),
+      closed: false,
+      awaiting_response: false,
+      traffic,
+    };
+
+    let greeting_lines = client
+      .read_reply()
+      .await
+      .context("failed to read greeting")?;
+    let mut greeting = Greeting::hyrcon_from_lines(greeting_lines)?;
+
+    if greeting.capabilities().is_empty()
+      && let Some(capabilities) = client.hello().await?
+    {
+      greeting = greeting.with_capabilities(capabilities);
+    }
+
+    Ok((client, greeting))
+  }
+
+  pub(super) fn is_closed(&self) -> bool {
+    self.closed
+  }
+
+  pub(super) fn set_timeout(&mut self, timeout: Duration) {
+    self.timeout = timeout;
+  }
+
+  pub(super) fn traffic(&self) -> &Traffic {
+    &self.traffic
+  }
+
+  pub(super) fn take_messages(&mut self) -> Vec<ServerMessage> {
+    self.events.drain(..).collect()
+  }
+
+  /// Wait for the next `EVENT` block pushed while no command is in
+  /// flight, returning `None` once the server closes the connection.
+  pub(super) async fn recv_message(
+    &mut self,
+  ) -> Result<Option<ServerMessage>> {
+    loop {
+      if let Some(message) = self.events.pop_front() {
+        return Ok(Some(message));
+      }
+
+      if self.closed {
+        return std::future::pending().await;
+      }
+
+      match self.next_block().await {
+        Ok(block) if is_event(&block) => self.queue_event(block),
+        Ok(block) if self.awaiting_response => {
+          tracing::debug!(?block, "discarding reply to abandoned command");
+          self.awaiting_response = false;
+        }
+        Ok(block) => {
+          tracing::debug!(?block, "ignoring unsolicited HYRCON block");
+        }
+        Err(_) if self.closed => return Ok(None),
+        Err(err) => return Err(err),
+      }
+    }
+  }
+
+  pub(super) async fn authenticate(
+    &mut self,
+    password: &str,
+  ) -> Result<AuthOutcome> {
+    if password.contains(['\r', '\n']) {
+      bail!("password must not contain newline characters");
+    }
+
+    self.skip_abandoned_reply().await?;
+    self
+      .write_line(&format!("AUTH {password}"), Some("AUTH <redacted>"))
+      .await?;
+    self.awaiting_response = true;
+
+    let block = self
+      .read_reply()
+      .await
+      .context("failed to read authentication response")?;
+    self.awaiting_response = false;
+
+    match block.first().map(String::as_str) {
+      Some("AUTH OK") => Ok(AuthOutcome::Success),
+      Some("AUTH FAIL") => Ok(AuthOutcome::Failure),
+      Some(other) => bail!("unexpected auth response: {other}"),
+      None => bail!("server returned an empty block for AUTH response"),
+    }
+  }
+
+  pub(super) async fn send_command(
+    &mut self,
+    command: &str,
+  ) -> Result<CommandOutcome> {
+    if self.closed {
+      bail!("connection already closed");
+    }
+    check_command(command)?;
+
+    self.skip_abandoned_reply().await?;
+    self.write_line(command, Some(command)).await?;
+    self.awaiting_response = true;
+
+    let block = self
+      .read_reply()
+      .await
+      .context("failed to read command response")?;
+    self.awaiting_response = false;
+
+    let outcome = parse_reply(block)?;
+    if matches!(outcome, CommandOutcome::Bye) {
+      self.closed = true;
+    }
+
+    Ok(outcome)
+  }
+
+  /// A cancelled command's reply would otherwise be taken for the next
+  /// one's, so read and drop it first.
+  async fn skip_abandoned_reply(&mut self) -> Result<()> {
+    if self.awaiting_response {
+      let block = self
+        .read_reply()
+        .await
+        .context("failed to skip the reply to a cancelled command")?;
+      tracing::debug!(?block, "discarding reply to abandoned command");
+      self.awaiting_response = false;
+    }
+    Ok(())
+  }
+
+  /// Send a `PING` so idle connections stay open and dead ones are
+  /// noticed. Any reply, even an error for an unknown command, will do.
+  pub(super) async fn keepalive(&mut self) -> Result<()> {
+    match self.send_command("PING").await? {
+      CommandOutcome::Response(_) => Ok(()),
+      CommandOutcome::Bye => bail!("server closed the connection"),
+    }
+  }
+
+  pub(super) async fn quit(&mut self) -> Result<()> {
+    if self.closed {
+      return Ok(());
+    }
+
+    if self.awaiting_response {
+      // The stream is out of sync with an abandoned command; a QUIT would
+      // only read the stale response, so close our side instead.
+      self.closed = true;
+      return with_timeout(
+        self.timeout,
+        self.writer.shutdown(),
+        "shutting down HYRCON writer".to_string(),
+      )
+      .await;
+    }
+
+    match self.send_command("QUIT").await {
+      Ok(CommandOutcome::Bye) => Ok(()),
+      Ok(CommandOutcome::Response(response)) => {
+        self.closed = true;
+        bail!("unexpected payload in QUIT response: {:?}", response)
+      }
+      Err(err) => Err(err),
+    }
+  }
+
+  /// Ask the server which optional features it supports. Bridges that
+  /// predate `HELLO` reject it as an unknown command, which simply means
+  /// no capabilities.
+  async fn hello(&mut self) -> Result<Option<Vec<String>>> {
+    match self.send_command("HELLO").await? {
+      CommandOutcome::Response(response)
+        if response.status == ResponseStatus::Ok =>
+      {
+        Ok(parse_capabilities(&response.payload))
+      }
+      CommandOutcome::Response(response) => {
+        tracing::debug!(
+          error = response.error.as_deref().unwrap_or_default(),
+          "server does not support HELLO"
+        );
+        Ok(None)
+      }
+      CommandOutcome::Bye => {
+        bail!("server closed the connection on HELLO")
+      }
+    }
+  }
+
+  /// Read the next block that is not an `EVENT`, queueing any events
+  /// that arrive first.
+  async fn read_reply(&mut self) -> Result<Vec<String>> {
+    loop {
+      let block = await_timeout(self.timeout, self.next_block())
+        .await
+        .map_err(|_| {
+        TimedOut::new("reading block from server", self.timeout)
+      })??;
+      if !is_event(&block) {
+        return Ok(block);
+      }
+      self.queue_event(block);
+    }
+  }
+
+  /// Read one `.`-terminated block. Cancel-safe: partial lines and blocks
+  /// are kept in the decoder and resumed by the next call.
+  async fn next_block(&mut self) -> Result<Vec<String>> {
+    loop {
+      let block = self
+        .decoder
+        .next_block()
+        .context("server sent a line that is not valid UTF-8")?;
+      if let Some(block) = block {
+        // The block's lines and its `.` terminator.
+        for _ in 0..=block.len() {
+          self.traffic.message_received();
+        }
+        return Ok(block);
+      }
+
+      let chunk = self.reader.fill_buf().await?;
+      if chunk.is_empty() {
+        self.closed = true;
+        return Err(Error::Closed.into());
+      }
+      let consumed = chunk.len();
+      self.decoder.feed(chunk);
+      self.reader.consume(consumed);
+    }
+  }
+
+  fn queue_event(&mut self, block: Vec<String>) {
+    for message in event_messages(block) {
+      tracing::debug!("<-- event {}", message.text);
+      self.events.push_back(message);
+    }
+  }
+
+  async fn write_line(
+    &mut self,
+    line: &str,
+    log_repr: Option<&str>,
+  ) -> Result<()> {
+    let label = log_repr.unwrap_or(line);
+    tracing::debug!("--> {}", label);
+
+    with_timeout(
+      self.timeout,
+      self.writer.write_all(line.as_bytes()),
+      format!("writing `{label}` to socket"),
+    )
+    .await?;
+
+    with_timeout(
+      self.timeout,
+      self.writer.write_all(b"\n"),
+      format!("writing newline after `{label}`"),
+    )
+    .await?;
+    self.traffic.message_sent();
+
+    with_timeout(
+      self.timeout,
+      self.writer.flush(),
+      "flushing command to socket".to_string(),
+    )
+    .await?;
+
+    Ok(())
+  }
+}
+
+/// Raw line access to a HYRCON session, from
+/// [`RconClient::hyrcon_lines`](crate::RconClient::hyrcon_lines).
+///
+/// `EVENT` blocks are still kept apart for
+/// [`RconClient::take_messages`](crate::RconClient::take_messages). The
+/// session's own commands expect each reply to have been read, so read
+/// the answers to whatever you send before sending commands again.
+#[derive(Debug)]
+pub struct Lines<'a> {
+  client: &'a mut HyrconClient,
+}
+
+impl<'a> Lines<'a> {
+  pub(super) fn new(client: &'a mut HyrconClient) -> Self {
+    Self { client }
+  }
+
+  /// Send `line` as is, followed by a newline.
+  pub async fn send_line(&mut self, line: &str) -> Result<(), Error> {
+    let client = &mut *self.client;
+    async {
+      if client.closed {
+        bail!("connection already closed");
+      }
+      if line.contains(['\r', '\n']) {
+        bail!("line must not contain newline characters");
+      }
+      client.skip_abandoned_reply().await?;
+      client.write_line(line, None).await
+    }
+    .await
+    .map_err(Error::from_internal)
+  }
+
+  /// Wait up to the session's timeout for the next block that is not an
+  /// `EVENT`, without its `.` terminator.
+  pub async fn read_block(&mut self) -> Result<Vec<String>, Error> {
+    self.client.read_reply().await.map_err(Error::from_internal)
+  }
+}
+
+/// Refuse commands a single line cannot carry.
+pub(super) fn check_command(command: &str) -> Result<()> {
+  if command.trim().is_empty() {
+    bail!("command must not be empty");
+  }
+
+  if command.contains(['\r', '\n']) {
+    bail!("command must not contain newline characters");
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn connect_negotiates_capabilities_with_hello() {
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+      .await
+      .expect("bind");
+    let port = listener.local_addr().expect("addr").port();
+
+    let server = tokio::spawn(async move {
+      let (stream, _) = listener.accept().await.expect("accept");
+      let (read_half, mut write_half) = stream.into_split();
+      let mut lines = BufReader::new(read_half).lines();
+      write_half
+        .write_all(b"HYRCON READY\nAUTH OPTIONAL\n.\n")
+        .await
+        .expect("greeting");
+      let line = lines.next_line().await.expect("read").expect("hello");
+      assert_eq!(line, "HELLO");
+      write_half
+        .write_all(b"OK\nCAP events multi\n.\n")
+        .await
+        .expect("cap");
+    });
+
+    let (_client, greeting) = HyrconClient::connect(
+      "127.0.0.1",
+      port,
+      Duration::from_secs(1),
+      &ConnectOptions::default(),
+    )
+    .await
+    .expect("connect");
+    server.await.expect("server");
+
+    assert_eq!(greeting.capabilities(), ["EVENTS", "MULTI"]);
+    assert!(greeting.supports("events"));
+    assert!(!greeting.supports("FILES"));
+  }
+
+  #[tokio::test]
+  async fn raw_lines_skip_reply_parsing() {
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+      .await
+      .expect("bind");
+    let port = listener.local_addr().expect("addr").port();
+
+    let server = tokio::spawn(async move {
+      let (stream, _) = listener.accept().await.expect("accept");
+      let (read_half, mut write_half) = stream.into_split();
+      let mut lines = BufReader::new(read_half).lines();
+      write_half
+        .write_all(b"HYRCON READY\nAUTH OPTIONAL\nCAP EVENTS\n.\n")
+        .await
+        .expect("greeting");
+      let line = lines.next_line().await.expect("read").expect("watch");
+      assert_eq!(line, "WATCH players");
+      write_half
+        .write_all(b"EVENT JOIN Alex\n.\nWATCHING\nplayers\n.\n")
+        .await
+        .expect("reply");
+    });
+
+    let (mut client, _) = HyrconClient::connect(
+      "127.0.0.1",
+      port,
+      Duration::from_secs(1),
+      &ConnectOptions::default(),
+    )
+    .await
+    .expect("connect");
+    let mut lines = Lines::new(&mut client);
+    lines.send_line("WATCH players").await.expect("send");
+    assert!(lines.send_line("two\nlines").await.is_err());
+    let block = lines.read_block().await.expect("block");
+    assert_eq!(block, ["WATCHING", "players"]);
+    assert_eq!(client.take_messages().len(), 1);
+    server.await.expect("server");
+  }
+
+  #[tokio::test]
+  async fn events_are_separated_from_command_responses() {
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+      .await
+      .expect("bind");
+    let port = listener.local_addr().expect("addr").port();
+
+    let server = tokio::spawn(async move {
+      let (stream, _) = listener.accept().await.expect("accept");
+      let (read_half, mut write_half) = stream.into_split();
+      let mut lines = BufReader::new(read_half).lines();
+      write_half
+        .write_all(b"HYRCON READY\nAUTH OPTIONAL\nCAP EVENTS\n.\n")
+        .await
+        .expect("greeting");
+      let line = lines.next_line().await.expect("read").expect("list");
+      assert_eq!(line, "list");
+      write_half
+        .write_all(
+          b"EVENT CHAT\nSteve: hi\n.\nOK\nSteve\n.\nEVENT JOIN Alex\n.\n",
+        )
+        .await
+        .expect("reply");
+    });
+
+    let (mut client, greeting) = HyrconClient::connect(
+      "127.0.0.1",
+      port,
+      Duration::from_secs(1),
+      &ConnectOptions::default(),
+    )
+    .await
+    .expect("connect");
+    assert!(greeting.supports("EVENTS"));
+
+    let CommandOutcome::Response(response) =
+      client.send_command("list").await.expect("list")
+    else {
+      panic!("unexpected bye");
+    };
+    assert_eq!(response.payload, vec!["Steve"]);
+
+    let events = client.take_messages();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].kind.as_deref(), Some("CHAT"));
+    assert_eq!(events[0].text, "Steve: hi");
+
+    let event = client
+      .recv_message()
+      .await
+      .expect("event")
+      .expect("open connection");
+    assert_eq!(event.kind.as_deref(), Some("JOIN"));
+    assert_eq!(event.text, "Alex");
+
+    server.await.expect("server");
+    assert!(client.recv_message().await.expect("eof").is_none());
+  }
+}
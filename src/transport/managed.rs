@@ -0,0 +1,291 @@
+//! Long-lived session that reconnects by itself, for bots and services
+//! that should ride out server restarts.
+//!
+//! ```no_run
+//! use hyrcon_client::{ManagedClient, Protocol, RconClient};
+//!
+//! # async fn example() -> anyhow::Result<()> {
+//! let connector = RconClient::builder()
+//!   .protocol(Protocol::Source)
+//!   .host("mc.example.com")
+//!   .password("hunter2")
+//!   .build()?;
+//! let mut client = ManagedClient::new(connector)
+//!   .init_commands(["gamerule sendCommandFeedback false"]);
+//!
+//! // Reconnects first if the last session dropped.
+//! client.send_command("list").await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::time::Duration;
+
+use tokio::sync::broadcast;
+use tokio::time::sleep;
+
+use super::{
+  ClientEvent, CommandOptions, CommandOutcome, RconClient, RconConnector,
+};
+use crate::error::Error;
+use crate::util::backoff::Backoff;
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 10;
+const DEFAULT_INITIAL_DELAY: Duration = Duration::from_millis(500);
+const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Session that is connected on first use and reconnected, with
+/// backoff, whenever the previous one has dropped.
+///
+/// A command whose connection fails mid-flight returns the error rather
+/// than being sent again, since it may already have run; the next
+/// command reconnects first.
+#[derive(Debug)]
+pub struct ManagedClient {
+  connector: RconConnector,
+  init_commands: Vec<String>,
+  max_attempts: u32,
+  initial_delay: Duration,
+  max_delay: Duration,
+  client: Option<RconClient>,
+  /// Whether a session was open before, so the next one is a reconnect.
+  connected_before: bool,
+}
+
+impl ManagedClient {
+  /// Manage sessions opened with `connector`, which also authenticates
+  /// them.
+  pub fn new(connector: RconConnector) -> Self {
+    Self {
+      connector,
+      init_commands: Vec::new(),
+      max_attempts: DEFAULT_MAX_ATTEMPTS,
+      initial_delay: DEFAULT_INITIAL_DELAY,
+      max_delay: DEFAULT_MAX_DELAY,
+      client: None,
+      connected_before: false,
+    }
+  }
+
+  /// Commands to send on every new session before any other, e.g. to
+  /// subscribe to logs again.
+  #[must_use]
+  pub fn init_commands<I, S>(mut self, commands: I) -> Self
+  where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+  {
+    self.init_commands = commands.into_iter().map(Into::into).collect();
+    self
+  }
+
+  /// Give up reconnecting after this many failed attempts in a row
+  /// (default 10).
+  #[must_use]
+  pub fn max_attempts(mut self, attempts: u32) -> Self {
+    self.max_attempts = attempts.max(1);
+    self
+  }
+
+  /// Wait `initial` before the first reconnect attempt, doubling up to
+  /// `max` between later ones (default 500 ms up to 30 seconds).
+  #[must_use]
+  pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+    self.initial_delay = initial;
+    self.max_delay = max;
+    self
+  }
+
+  /// Receive the [`ClientEvent`]s of every session, including
+  /// [`Reconnecting`](ClientEvent::Reconnecting) and
+  /// [`Reconnected`](ClientEvent::Reconnected).
+  pub fn events(&self) -> broadcast::Receiver<ClientEvent> {
+    self.connector.events()
+  }
+
+  /// Whether a session is open right now.
+  pub fn is_connected(&self) -> bool {
+    self
+      .client
+      .as_ref()
+      .is_some_and(|client| !client.is_closed())
+  }
+
+  /// The open session, connecting or reconnecting first when needed.
+  pub async fn client(&mut self) -> Result<&mut RconClient, Error> {
+    if !self.is_connected() {
+      self.client = None;
+      let client = if self.connected_before {
+        self.reconnect().await?
+      } else {
+        self.open().await?
+      };
+      self.connected_before = true;
+      self.client = Some(client);
+    }
+    Ok(self.client.as_mut().expect("session was just opened"))
+  }
+
+  /// Send `command` over the open session, reconnecting first if the
+  /// last one dropped.
+  pub async fn send_command(
+    &mut self,
+    command: &str,
+  ) -> Result<CommandOutcome, Error> {
+    self.client().await?.send_command(command).await
+  }
+
+  /// Like [`send_command`](Self::send_command), with the overrides in
+  /// `options`.
+  pub async fn send_command_with(
+    &mut self,
+    command: &str,
+    options: &CommandOptions,
+  ) -> Result<CommandOutcome, Error> {
+    self
+      .client()
+      .await?
+      .send_command_with(command, options)
+      .await
+  }
+
+  /// Close the open session, if any. The next command connects again.
+  pub async fn quit(&mut self) -> Result<(), Error> {
+    match self.client.take() {
+      Some(mut client) if !client.is_closed() => client.quit().await,
+      _ => Ok(()),
+    }
+  }
+
+  /// Connect, authenticate and run the init commands.
+  async fn open(&self) -> Result<RconClient, Error> {
+    let mut client = self.connector.connect().await?;
+    for command in &self.init_commands {
+      client.send_command(command).await?;
+    }
+    Ok(client)
+  }
+
+  async fn reconnect(&self) -> Result<RconClient, Error> {
+    let mut backoff = Backoff::new(self.initial_delay, self.max_delay);
+    let mut attempt = 1;
+    loop {
+      let delay = backoff.next_delay();
+      self
+        .connector
+        .emit(ClientEvent::Reconnecting { attempt, delay });
+      sleep(delay).await;
+
+      match self.open().await {
+        Ok(client) => {
+          self.connector.emit(ClientEvent::Reconnected { attempt });
+          return Ok(client);
+        }
+        // Retrying cannot fix the password or the settings.
+        Err(
+          err @ (Error::AuthRejected
+          | Error::AuthRequired
+          | Error::Unsupported(_)
+          | Error::Cancelled),
+        ) => return Err(err),
+        Err(err) if attempt >= self.max_attempts => return Err(err),
+        Err(err) => {
+          tracing::warn!(attempt, "reconnect failed: {err}");
+          attempt += 1;
+        }
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::VecDeque;
+  use std::io;
+  use std::sync::Mutex;
+
+  use super::*;
+  use crate::protocol::Protocol;
+  use crate::testing::{MockHyrconServer, Reply};
+  use crate::transport::{Connector, Stream};
+
+  /// Dials the next server in line, standing in for one that restarts.
+  #[derive(Debug)]
+  struct Restarts(Mutex<VecDeque<u16>>);
+
+  #[async_trait::async_trait]
+  impl Connector for Restarts {
+    async fn connect(
+      &self,
+      _host: &str,
+      _port: u16,
+    ) -> io::Result<Box<dyn Stream>> {
+      let port = self.0.lock().expect("ports").pop_front();
+      let port = port.ok_or(io::ErrorKind::ConnectionRefused)?;
+      let stream =
+        tokio::net::TcpStream::connect(("127.0.0.1", port)).await?;
+      Ok(Box::new(stream))
+    }
+  }
+
+  #[tokio::test]
+  async fn dropped_sessions_are_replaced() {
+    let mut servers = Vec::new();
+    for reply in [Reply::close(), Reply::ok(["Steve"])] {
+      let server = MockHyrconServer::builder()
+        .password("secret")
+        .expect("subscribe", Reply::ok(Vec::<String>::new()))
+        .expect("list", reply)
+        .start()
+        .await
+        .expect("start");
+      servers.push(server);
+    }
+    let ports = servers.iter().map(|server| server.port()).collect();
+    let connector = RconClient::builder()
+      .protocol(Protocol::Hyrcon)
+      .host("rcon.invalid")
+      .password("secret")
+      .timeout(Duration::from_secs(2))
+      .connector(Restarts(Mutex::new(ports)))
+      .build()
+      .expect("settings");
+    let mut client = ManagedClient::new(connector)
+      .init_commands(["subscribe"])
+      .backoff(Duration::from_millis(10), Duration::from_millis(10));
+    let mut events = client.events();
+
+    client.send_command("list").await.expect_err("dropped");
+    assert!(!client.is_connected());
+
+    let outcome = client.send_command("list").await.expect("list");
+    let CommandOutcome::Response(response) = outcome else {
+      panic!("unexpected outcome: {outcome:?}");
+    };
+    assert_eq!(response.payload, ["Steve"]);
+    client.quit().await.expect("quit");
+    for server in servers {
+      server.finish().await.expect("script followed");
+    }
+
+    let mut reconnects = Vec::new();
+    while let Ok(event) = events.try_recv() {
+      if matches!(
+        event,
+        ClientEvent::Reconnecting { .. } | ClientEvent::Reconnected { .. }
+      ) {
+        reconnects.push(event);
+      }
+    }
+    assert_eq!(
+      reconnects,
+      [
+        ClientEvent::Reconnecting {
+          attempt: 1,
+          delay: Duration::from_millis(10)
+        },
+        ClientEvent::Reconnected { attempt: 1 },
+      ]
+    );
+  }
+}
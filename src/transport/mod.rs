@@ -0,0 +1,2090 @@
+use std::collections::BTreeMap;
+use std::io::{self, ErrorKind};
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, anyhow, bail};
+use tokio::net::{UdpSocket, lookup_host};
+use tokio::sync::broadcast;
+use tokio::time::timeout as await_timeout;
+use tokio_util::sync::CancellationToken;
+
+use crate::error::Error;
+use crate::models::{FromResponse, ParseResponseError};
+use crate::protocol::{Dialect, Protocol};
+
+mod backend;
+mod battleye;
+mod builder;
+mod commands;
+mod events;
+mod hosts;
+pub mod hyrcon;
+mod managed;
+mod proxy;
+#[cfg(feature = "pterodactyl")]
+mod pterodactyl;
+mod quake;
+#[cfg(feature = "rest")]
+mod rest;
+pub mod source;
+mod srv;
+#[cfg(feature = "ssh")]
+mod ssh;
+mod stats;
+mod stream;
+#[cfg(feature = "std-transport")]
+pub mod sync;
+mod telnet;
+#[cfg(feature = "tls")]
+mod tls;
+mod tunnel;
+mod webrcon;
+
+pub use backend::ProtocolBackend;
+use battleye::BattlEyeClient;
+pub use builder::{RconClientBuilder, RconConnector};
+use events::Events;
+pub use events::{ClientEvent, DisconnectReason};
+pub use hosts::{
+  ParseResolveOverrideError, ParseTargetError, ResolveOverride, Target,
+};
+use hyrcon::HyrconClient;
+pub use managed::ManagedClient;
+#[cfg(feature = "cli")]
+pub(crate) use proxy::percent_decode;
+pub(crate) use proxy::split_host_port;
+pub use proxy::{ParseProxyError, Proxy, ProxyScheme};
+#[cfg(feature = "pterodactyl")]
+use pterodactyl::PterodactylClient;
+use quake::QuakeClient;
+#[cfg(feature = "rest")]
+use rest::RestClient;
+use source::SourceClient;
+pub use srv::{SrvTarget, lookup_srv};
+#[cfg(feature = "ssh")]
+use ssh::SshClient;
+pub use stats::SessionStats;
+use stats::Traffic;
+pub use stream::{Connector, Stream};
+use telnet::TelnetClient;
+pub use tunnel::{ParseSshTunnelError, SshTunnel};
+use webrcon::WebRconClient;
+
+/// Parsed greeting information returned (or synthesized) for the connected server.
+#[derive(Debug, Clone)]
+#[cfg_attr(
+  feature = "serde",
+  derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct Greeting {
+  banner: String,
+  auth_mode: AuthMode,
+  protocol: Protocol,
+  #[cfg_attr(feature = "serde", serde(default))]
+  capabilities: Vec<String>,
+  #[cfg_attr(feature = "serde", serde(default))]
+  extensions: BTreeMap<String, String>,
+}
+
+impl Greeting {
+  /// Construct a greeting, e.g. from a custom [`ProtocolBackend`].
+  pub fn new(
+    protocol: Protocol,
+    banner: impl Into<String>,
+    auth_mode: AuthMode,
+  ) -> Self {
+    Self {
+      banner: banner.into(),
+      auth_mode,
+      protocol,
+      capabilities: Vec::new(),
+      extensions: BTreeMap::new(),
+    }
+  }
+
+  /// Attach the capabilities advertised by the server.
+  #[must_use]
+  pub fn with_capabilities<I, S>(mut self, capabilities: I) -> Self
+  where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+  {
+    self.capabilities = capabilities
+      .into_iter()
+      .map(|capability| capability.into().to_ascii_uppercase())
+      .collect();
+    self
+  }
+
+  /// Attach an extra piece of server information, such as `VERSION`.
+  #[must_use]
+  pub fn with_extension(
+    mut self,
+    key: impl Into<String>,
+    value: impl Into<String>,
+  ) -> Self {
+    self
+      .extensions
+      .insert(key.into().to_ascii_uppercase(), value.into());
+    self
+  }
+
+  fn hyrcon_from_lines(lines: Vec<String>) -> Result<Self> {
+    if lines.len() < 2 {
+      bail!("protocol violation: greeting did not include auth mode");
+    }
+
+    let banner = lines.first().cloned().ok_or_else(|| {
+      anyhow!("protocol violation: greeting missing banner")
+    })?;
+
+    if banner != "HYRCON READY" {
+      bail!("unexpected greeting banner: {banner}");
+    }
+
+    let auth_mode = match lines[1].as_str() {
+      "AUTH REQUIRED" => AuthMode::Required,
+      "AUTH OPTIONAL" => AuthMode::Optional,
+      other => {
+        bail!("unknown authentication mode advertised by server: {other}")
+      }
+    };
+
+    let mut greeting = Self::new(Protocol::Hyrcon, banner, auth_mode);
+    if let Some(capabilities) =
+      crate::codec::hyrcon::parse_capabilities(&lines[2..])
+    {
+      greeting = greeting.with_capabilities(capabilities);
+    }
+    // Newer servers add `KEY value` lines, e.g. `VERSION 2.1.0` or
+    // `MOTD Welcome back`, after the auth mode.
+    for line in &lines[2..] {
+      let line = line.trim();
+      let (key, value) =
+        line.split_once(char::is_whitespace).unwrap_or((line, ""));
+      if !key.is_empty() && key != "CAP" {
+        greeting = greeting.with_extension(key, value.trim());
+      }
+    }
+    Ok(greeting)
+  }
+
+  pub fn from_lines(lines: Vec<String>) -> Result<Self, Error> {
+    Self::hyrcon_from_lines(lines).map_err(Error::from_internal)
+  }
+
+  pub fn source_default() -> Self {
+    Self::new(Protocol::Source, "SOURCE RCON READY", AuthMode::Required)
+  }
+
+  pub fn quake_default() -> Self {
+    Self::new(Protocol::Quake, "QUAKE RCON READY", AuthMode::Required)
+  }
+
+  pub fn battleye_default() -> Self {
+    Self::new(
+      Protocol::BattlEye,
+      "BATTLEYE RCON READY",
+      AuthMode::Required,
+    )
+  }
+
+  pub fn webrcon_default() -> Self {
+    Self::new(Protocol::WebRcon, "WEBRCON READY", AuthMode::Required)
+  }
+
+  pub fn rest_default() -> Self {
+    Self::new(Protocol::Rest, "REST ADMIN API READY", AuthMode::Required)
+  }
+
+  pub fn pterodactyl_default() -> Self {
+    Self::new(
+      Protocol::Pterodactyl,
+      "PTERODACTYL CONSOLE READY",
+      AuthMode::Required,
+    )
+  }
+
+  pub fn requires_auth(&self) -> bool {
+    matches!(self.auth_mode, AuthMode::Required)
+  }
+
+  pub fn banner(&self) -> &str {
+    &self.banner
+  }
+
+  pub fn auth_mode(&self) -> AuthMode {
+    self.auth_mode
+  }
+
+  pub fn protocol(&self) -> Protocol {
+    self.protocol
+  }
+
+  /// Capabilities advertised by the server (e.g. `EVENTS`, `FILES`,
+  /// `MULTI`), upper-cased. Empty when the server advertises none.
+  pub fn capabilities(&self) -> &[String] {
+    &self.capabilities
+  }
+
+  /// Extra server information from the greeting, keyed by upper-cased
+  /// name, e.g. `VERSION`, `MOTD` or `MAX-PLAYERS`. Empty for servers
+  /// that send none.
+  pub fn extensions(&self) -> &BTreeMap<String, String> {
+    &self.extensions
+  }
+
+  /// Whether the server advertised `capability` (case-insensitive).
+  pub fn supports(&self, capability: &str) -> bool {
+    self
+      .capabilities
+      .iter()
+      .any(|advertised| advertised.eq_ignore_ascii_case(capability))
+  }
+}
+
+/// Indicates whether authentication is mandatory or optional.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(
+  feature = "serde",
+  derive(serde::Serialize, serde::Deserialize),
+  serde(rename_all = "lowercase")
+)]
+pub enum AuthMode {
+  Required,
+  Optional,
+}
+
+/// Result of issuing an AUTH command.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(
+  feature = "serde",
+  derive(serde::Serialize, serde::Deserialize),
+  serde(rename_all = "lowercase")
+)]
+pub enum AuthOutcome {
+  Success,
+  Failure,
+}
+
+/// Aggregated payload returned by the RCON server.
+#[derive(Debug, Clone)]
+#[cfg_attr(
+  feature = "serde",
+  derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct RconResponse {
+  pub status: ResponseStatus,
+  pub payload: Vec<String>,
+  pub error: Option<String>,
+  /// The reply before it was split into `payload`: Source packets joined
+  /// back together, or the message or HTTP body as received. Line-based
+  /// protocols give their payload lines, each ending in `\n`.
+  #[cfg_attr(feature = "serde", serde(default))]
+  pub raw: String,
+}
+
+impl RconResponse {
+  /// Read the reply as a `T`, e.g. a [`ServerStatus`] or [`PlayerList`].
+  ///
+  /// [`ServerStatus`]: crate::models::ServerStatus
+  /// [`PlayerList`]: crate::models::PlayerList
+  pub fn parse<T: FromResponse>(&self) -> Result<T, ParseResponseError> {
+    T::from_response(self)
+  }
+}
+
+/// High-level status of a command response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+  feature = "serde",
+  derive(serde::Serialize, serde::Deserialize),
+  serde(rename_all = "lowercase")
+)]
+pub enum ResponseStatus {
+  Ok,
+  Err,
+}
+
+/// Unsolicited message pushed by the server outside of a command response.
+#[derive(Debug, Clone)]
+pub struct ServerMessage {
+  /// Server-provided category (e.g. `Chat`, `Log`), when available.
+  pub kind: Option<String>,
+  pub text: String,
+}
+
+/// Possible outcomes when sending a protocol command.
+#[derive(Debug)]
+pub enum CommandOutcome {
+  Response(RconResponse),
+  Bye,
+}
+
+/// Error raised when the server does not answer within the timeout.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimedOut {
+  operation: String,
+  after: Duration,
+}
+
+impl TimedOut {
+  pub(crate) fn new(
+    operation: impl Into<String>,
+    after: Duration,
+  ) -> Self {
+    Self {
+      operation: operation.into(),
+      after,
+    }
+  }
+
+  /// What was being waited for, e.g. `reading block from server`.
+  pub fn operation(&self) -> &str {
+    &self.operation
+  }
+
+  /// How long the client waited.
+  pub fn after(&self) -> Duration {
+    self.after
+  }
+}
+
+impl std::fmt::Display for TimedOut {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(
+      f,
+      "{} timed out after {} ms",
+      self.operation,
+      self.after.as_millis()
+    )
+  }
+}
+
+impl std::error::Error for TimedOut {}
+
+/// Protocol-specific settings applied when establishing a session.
+#[derive(Debug, Clone, Default)]
+pub struct ConnectOptions {
+  /// Server-specific quirks; only the Source backend has dialects.
+  pub dialect: Dialect,
+  /// Largest Source RCON packet (the size field, in bytes) to send or
+  /// accept; longer commands are split at `;` separators. Defaults to the
+  /// 4096 bytes allowed by the Source RCON specification for outbound
+  /// packets, with no inbound limit.
+  pub max_packet_size: Option<u32>,
+  /// Remote command started by the SSH backend; the remote login shell is
+  /// used when unset.
+  pub ssh_command: Option<String>,
+  /// Tunnel connections through this proxy. Supported by every TCP-based
+  /// backend.
+  pub proxy: Option<Proxy>,
+  /// Forward TCP connections through an SSH bastion. Supported by the
+  /// HYRCON, Source, WebRCON, telnet and SSH backends.
+  pub ssh_tunnel: Option<SshTunnel>,
+  /// Encrypt the session with TLS (`wss://` for WebRCON). Supported by the
+  /// HYRCON, Source, WebRCON and Pterodactyl backends.
+  pub tls: Option<TlsOptions>,
+  /// Address family to connect over. With [`IpFamily::Any`], dual-stack
+  /// hosts are connected to by racing their IPv6 and IPv4 addresses.
+  pub ip_family: IpFamily,
+  /// Local address to originate connections from, e.g. to satisfy
+  /// firewall rules keyed on the source IP. Also selects the address
+  /// family.
+  pub bind: Option<IpAddr>,
+  /// Addresses to connect to instead of resolving matching `host:port`
+  /// pairs, like curl's `--resolve`. Proxies and SSH tunnels are asked
+  /// for the pinned address instead of the hostname.
+  pub resolve: Vec<ResolveOverride>,
+  /// Send TCP keepalive probes after the connection has been idle this
+  /// long, so stateful firewalls keep long-lived sessions open. Not
+  /// available for UDP protocols; through an SSH tunnel the probes go to
+  /// the bastion.
+  pub tcp_keepalive: Option<Duration>,
+  /// Extra attempts after a transient connect failure (connection refused
+  /// or reset, timeouts) before giving up.
+  pub connect_retries: u32,
+  /// Pause between connect attempts.
+  pub connect_retry_delay: Duration,
+  /// Open connections with this instead of dialing TCP. Supported by the
+  /// HYRCON, Source, WebRCON, telnet and SSH backends; `ip_family`,
+  /// `bind` and `resolve` are left to the connector.
+  pub connector: Option<Arc<dyn Connector>>,
+}
+
+/// Per-command overrides of the session's settings, for
+/// [`RconClient::send_command_with`].
+#[derive(Debug, Clone, Default)]
+pub struct CommandOptions {
+  timeout: Option<Duration>,
+  cancellation: Option<CancellationToken>,
+  priority: Priority,
+}
+
+/// Where a command waits in an [`RconHandle`](crate::RconHandle)'s queue:
+/// higher priorities run first, equal ones in the order they were queued.
+#[derive(
+  Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default,
+)]
+pub enum Priority {
+  /// Background work, such as periodic monitoring queries.
+  Low,
+  #[default]
+  Normal,
+  /// Commands someone is waiting on, such as an operator's.
+  High,
+}
+
+impl CommandOptions {
+  /// Wait up to `timeout` for the reply instead of the session's timeout.
+  #[must_use]
+  pub fn timeout(mut self, timeout: Duration) -> Self {
+    self.timeout = Some(timeout);
+    self
+  }
+
+  /// Stop waiting with [`Error::Cancelled`] once `token` is cancelled,
+  /// e.g. on graceful shutdown. The reply is skipped when it arrives.
+  #[must_use]
+  pub fn cancel_on(mut self, token: CancellationToken) -> Self {
+    self.cancellation = Some(token);
+    self
+  }
+
+  /// Queue the command at `priority` when sent through an
+  /// [`RconHandle`](crate::RconHandle). A session used directly runs
+  /// commands in the order they are sent.
+  #[must_use]
+  pub fn priority(mut self, priority: Priority) -> Self {
+    self.priority = priority;
+    self
+  }
+
+  pub(crate) fn queue_priority(&self) -> Priority {
+    self.priority
+  }
+}
+
+/// Client whose timeout is overridden until the guard is dropped.
+struct TimeoutOverride<'a>(&'a mut RconClient, Duration);
+
+impl<'a> TimeoutOverride<'a> {
+  fn new(client: &'a mut RconClient, timeout: Duration) -> Self {
+    let previous = client.timeout;
+    client.set_timeout(timeout);
+    Self(client, previous)
+  }
+}
+
+impl Drop for TimeoutOverride<'_> {
+  fn drop(&mut self) {
+    self.0.set_timeout(self.1);
+  }
+}
+
+/// Reject the Source-only settings in `options` for other protocols.
+fn check_source_settings(
+  protocol: Protocol,
+  options: &ConnectOptions,
+) -> Result<(), Error> {
+  if options.dialect != Dialect::Standard && protocol != Protocol::Source {
+    return Err(Error::Unsupported(format!(
+      "the {} dialect only applies to the source protocol",
+      options.dialect
+    )));
+  }
+  if options.max_packet_size.is_some() && protocol != Protocol::Source {
+    return Err(Error::Unsupported(
+      "the packet size limit only applies to the source protocol"
+        .to_string(),
+    ));
+  }
+  Ok(())
+}
+
+/// TLS settings for backends that support encrypted transports.
+#[derive(Debug, Clone, Default)]
+pub struct TlsOptions {
+  /// PEM bundle of CA certificates to trust instead of the bundled roots.
+  pub ca_file: Option<PathBuf>,
+  /// PEM certificate chain presented to servers that require client
+  /// certificates (mutual TLS). Needs `client_key`.
+  pub client_cert: Option<PathBuf>,
+  /// PEM private key (PKCS#8, PKCS#1 or SEC1) matching `client_cert`.
+  pub client_key: Option<PathBuf>,
+  /// Skip server certificate verification. Only for testing; the
+  /// connection is then open to interception.
+  pub insecure: bool,
+}
+
+/// IP address family used to reach the server.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IpFamily {
+  /// Whichever family connects first.
+  #[default]
+  Any,
+  /// IPv4 only (`-4`).
+  V4,
+  /// IPv6 only (`-6`).
+  V6,
+}
+
+impl ConnectOptions {
+  /// Address family to connect over, taking `bind` into account.
+  fn family(&self) -> IpFamily {
+    match self.bind {
+      Some(IpAddr::V4(_)) => IpFamily::V4,
+      Some(IpAddr::V6(_)) => IpFamily::V6,
+      None => self.ip_family,
+    }
+  }
+
+  /// Addresses `host:port` is pinned to by the first matching override.
+  fn pinned(&self, host: &str, port: u16) -> Option<&[IpAddr]> {
+    self
+      .resolve
+      .iter()
+      .find(|pinned| pinned.matches(host, port))
+      .map(ResolveOverride::addresses)
+  }
+
+  /// Host a proxy or SSH bastion is asked to connect to: the first pinned
+  /// address of `host:port`, or `host` itself.
+  fn forwarded_host(&self, host: &str, port: u16) -> String {
+    match self.pinned(host, port).and_then(|pinned| pinned.first()) {
+      Some(address) => address.to_string(),
+      None => host.to_string(),
+    }
+  }
+
+  /// Apply the overrides for `host:port` to an HTTP client.
+  #[cfg(any(feature = "rest", feature = "pterodactyl"))]
+  fn pin_http_host(
+    &self,
+    http: reqwest::ClientBuilder,
+    host: &str,
+    port: u16,
+  ) -> reqwest::ClientBuilder {
+    match self.pinned(host, port) {
+      Some(addresses) => {
+        let addresses: Vec<_> = addresses
+          .iter()
+          .map(|address| SocketAddr::new(*address, port))
+          .collect();
+        http.resolve_to_addrs(host, &addresses)
+      }
+      None => http,
+    }
+  }
+
+  /// Local address for HTTP clients: `bind`, or the wildcard address of
+  /// the selected family.
+  #[cfg(any(feature = "rest", feature = "pterodactyl"))]
+  fn local_address(&self) -> Option<IpAddr> {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    match (self.bind, self.ip_family) {
+      (Some(bind), _) => Some(bind),
+      (None, IpFamily::Any) => None,
+      (None, IpFamily::V4) => Some(Ipv4Addr::UNSPECIFIED.into()),
+      (None, IpFamily::V6) => Some(Ipv6Addr::UNSPECIFIED.into()),
+    }
+  }
+}
+
+impl IpFamily {
+  fn admits(self, address: &SocketAddr) -> bool {
+    match self {
+      Self::Any => true,
+      Self::V4 => address.is_ipv4(),
+      Self::V6 => address.is_ipv6(),
+    }
+  }
+}
+
+/// Client responsible for reading/writing the selected RCON wire protocol.
+#[derive(Debug)]
+pub struct RconClient {
+  backend: Backend,
+  greeting: Greeting,
+  protocol: Protocol,
+  host: String,
+  port: u16,
+  /// Commands passed to `send_command`, for [`RconClient::stats`].
+  commands: u64,
+  /// Lifecycle events, for [`RconClient::events`].
+  events: Events,
+  /// How long the backend waits for the server, for
+  /// [`RconClient::timeout`].
+  timeout: Duration,
+}
+
+#[derive(Debug)]
+enum Backend {
+  Hyrcon(HyrconClient),
+  Source(SourceClient),
+  WebRcon(WebRconClient),
+  Quake(QuakeClient),
+  BattlEye(BattlEyeClient),
+  Telnet(TelnetClient),
+  #[cfg(feature = "rest")]
+  Rest(RestClient),
+  #[cfg(feature = "pterodactyl")]
+  Pterodactyl(PterodactylClient),
+  #[cfg(feature = "ssh")]
+  Ssh(SshClient),
+  Custom(Box<dyn ProtocolBackend>),
+}
+
+impl RconClient {
+  /// Describe a session setting by setting, then connect it with
+  /// [`RconClientBuilder::build`] and [`RconConnector::connect`].
+  #[must_use]
+  pub fn builder() -> RconClientBuilder {
+    RconClientBuilder::default()
+  }
+
+  /// Establish a connection for the given protocol and construct the client.
+  ///
+  /// Use [`RconClient::connect_with`] for [`Protocol::Custom`] backends.
+  pub async fn connect(
+    protocol: Protocol,
+    host: &str,
+    port: u16,
+    deadline: Duration,
+  ) -> Result<Self, Error> {
+    Self::connect_with_options(
+      protocol,
+      host,
+      port,
+      deadline,
+      &ConnectOptions::default(),
+    )
+    .await
+  }
+
+  /// Like [`RconClient::connect`], applying protocol-specific `options`.
+  pub async fn connect_with_options(
+    protocol: Protocol,
+    host: &str,
+    port: u16,
+    deadline: Duration,
+    options: &ConnectOptions,
+  ) -> Result<Self, Error> {
+    if options.tls.is_some()
+      && !matches!(
+        protocol,
+        Protocol::Hyrcon
+          | Protocol::Source
+          | Protocol::WebRcon
+          | Protocol::Pterodactyl
+      )
+    {
+      return Err(Error::Unsupported(format!(
+        "TLS is not supported by the {protocol} protocol"
+      )));
+    }
+
+    check_source_settings(protocol, options)?;
+
+    if options.proxy.is_some()
+      && matches!(protocol, Protocol::Quake | Protocol::BattlEye)
+    {
+      return Err(Error::Unsupported(format!(
+        "proxies are not supported by the {protocol} protocol"
+      )));
+    }
+
+    if let Some(tunnel) = &options.ssh_tunnel {
+      if !matches!(
+        protocol,
+        Protocol::Hyrcon
+          | Protocol::Source
+          | Protocol::WebRcon
+          | Protocol::Telnet
+          | Protocol::Ssh
+      ) {
+        return Err(Error::Unsupported(format!(
+          "SSH tunnels are not supported by the {protocol} protocol"
+        )));
+      }
+      if options.proxy.is_some() {
+        return Err(Error::Unsupported(format!(
+          "an SSH tunnel cannot be combined with a proxy; reach {} \
+           directly instead",
+          tunnel.host()
+        )));
+      }
+    }
+
+    if options.connector.is_some() {
+      if !matches!(
+        protocol,
+        Protocol::Hyrcon
+          | Protocol::Source
+          | Protocol::WebRcon
+          | Protocol::Telnet
+          | Protocol::Ssh
+      ) {
+        return Err(Error::Unsupported(format!(
+          "custom connectors are not supported by the {protocol} protocol"
+        )));
+      }
+      if options.proxy.is_some() || options.ssh_tunnel.is_some() {
+        return Err(Error::Unsupported(
+          "a custom connector cannot be combined with a proxy or SSH tunnel"
+            .to_string(),
+        ));
+      }
+      if options.tcp_keepalive.is_some() {
+        return Err(Error::Unsupported(
+          "TCP keepalives cannot be applied to a custom connector's \
+           streams"
+            .to_string(),
+        ));
+      }
+    }
+
+    if options.tcp_keepalive.is_some()
+      && matches!(protocol, Protocol::Quake | Protocol::BattlEye)
+    {
+      return Err(Error::Unsupported(format!(
+        "TCP keepalives are not supported by the {protocol} protocol"
+      )));
+    }
+
+    match (options.bind, options.ip_family) {
+      (Some(IpAddr::V4(_)), IpFamily::V6)
+      | (Some(IpAddr::V6(_)), IpFamily::V4) => {
+        return Err(Error::Unsupported(
+          "the bind address does not match the requested IP version"
+            .to_string(),
+        ));
+      }
+      _ => {}
+    }
+
+    let mut attempt = 0;
+    loop {
+      match Self::open(protocol, host, port, deadline, options).await {
+        Ok(client) => return Ok(client),
+        Err(err)
+          if attempt < options.connect_retries
+            && is_transient_connect_error(&err) =>
+        {
+          attempt += 1;
+          tracing::warn!(
+            attempt,
+            retries = options.connect_retries,
+            "connect failed, retrying in {} ms: {err:#}",
+            options.connect_retry_delay.as_millis()
+          );
+          tokio::time::sleep(options.connect_retry_delay).await;
+        }
+        Err(err) => {
+          return Err(match Error::from_internal(err) {
+            err @ Error::Unsupported(_) => err,
+            err => Error::Connect {
+              host: host.to_string(),
+              port,
+              protocol,
+              source: Box::new(err),
+            },
+          });
+        }
+      }
+    }
+  }
+
+  async fn open(
+    protocol: Protocol,
+    host: &str,
+    port: u16,
+    deadline: Duration,
+    options: &ConnectOptions,
+  ) -> Result<Self> {
+    match protocol {
+      Protocol::Hyrcon => {
+        let (client, greeting) =
+          HyrconClient::connect(host, port, deadline, options).await?;
+        Ok(Self {
+          backend: Backend::Hyrcon(client),
+          greeting,
+          protocol,
+          host: host.to_string(),
+          port,
+          commands: 0,
+          events: Events::default(),
+          timeout: deadline,
+        })
+      }
+      Protocol::Source => {
+        let client =
+          SourceClient::connect(host, port, deadline, options).await?;
+        let greeting = Greeting::source_default();
+        Ok(Self {
+          backend: Backend::Source(client),
+          greeting,
+          protocol,
+          host: host.to_string(),
+          port,
+          commands: 0,
+          events: Events::default(),
+          timeout: deadline,
+        })
+      }
+      Protocol::WebRcon => {
+        let client =
+          WebRconClient::connect(host, port, deadline, options).await?;
+        let greeting = Greeting::webrcon_default();
+        Ok(Self {
+          backend: Backend::WebRcon(client),
+          greeting,
+          protocol,
+          host: host.to_string(),
+          port,
+          commands: 0,
+          events: Events::default(),
+          timeout: deadline,
+        })
+      }
+      Protocol::Quake => {
+        let client =
+          QuakeClient::connect(host, port, deadline, options).await?;
+        let greeting = Greeting::quake_default();
+        Ok(Self {
+          backend: Backend::Quake(client),
+          greeting,
+          protocol,
+          host: host.to_string(),
+          port,
+          commands: 0,
+          events: Events::default(),
+          timeout: deadline,
+        })
+      }
+      Protocol::BattlEye => {
+        let client =
+          BattlEyeClient::connect(host, port, deadline, options).await?;
+        let greeting = Greeting::battleye_default();
+        Ok(Self {
+          backend: Backend::BattlEye(client),
+          greeting,
+          protocol,
+          host: host.to_string(),
+          port,
+          commands: 0,
+          events: Events::default(),
+          timeout: deadline,
+        })
+      }
+      Protocol::Telnet => {
+        let (client, greeting) =
+          TelnetClient::connect(host, port, deadline, options).await?;
+        Ok(Self {
+          backend: Backend::Telnet(client),
+          greeting,
+          protocol,
+          host: host.to_string(),
+          port,
+          commands: 0,
+          events: Events::default(),
+          timeout: deadline,
+        })
+      }
+      #[cfg(feature = "rest")]
+      Protocol::Rest => {
+        let client = RestClient::connect(host, port, deadline, options)?;
+        let greeting = Greeting::rest_default();
+        Ok(Self {
+          backend: Backend::Rest(client),
+          greeting,
+          protocol,
+          host: host.to_string(),
+          port,
+          commands: 0,
+          events: Events::default(),
+          timeout: deadline,
+        })
+      }
+      #[cfg(not(feature = "rest"))]
+      Protocol::Rest => Err(unsupported(
+        "REST support requires building with the `rest` feature",
+      )),
+      #[cfg(feature = "pterodactyl")]
+      Protocol::Pterodactyl => {
+        let client =
+          PterodactylClient::connect(host, port, deadline, options)?;
+        let greeting = Greeting::pterodactyl_default();
+        Ok(Self {
+          backend: Backend::Pterodactyl(client),
+          greeting,
+          protocol,
+          host: host.to_string(),
+          port,
+          commands: 0,
+          events: Events::default(),
+          timeout: deadline,
+        })
+      }
+      #[cfg(not(feature = "pterodactyl"))]
+      Protocol::Pterodactyl => Err(unsupported(
+        "Pterodactyl support requires building with the `pterodactyl` \
+         feature",
+      )),
+      #[cfg(feature = "ssh")]
+      Protocol::Ssh => {
+        let (client, greeting) =
+          SshClient::connect(host, port, deadline, options).await?;
+        Ok(Self {
+          backend: Backend::Ssh(client),
+          greeting,
+          protocol,
+          host: host.to_string(),
+          port,
+          commands: 0,
+          events: Events::default(),
+          timeout: deadline,
+        })
+      }
+      #[cfg(not(feature = "ssh"))]
+      Protocol::Ssh => {
+        let _ = options;
+        Err(unsupported(
+          "SSH support requires building with the `ssh` feature",
+        ))
+      }
+      Protocol::Custom => Err(unsupported(
+        "custom protocols must be connected via RconClient::connect_with",
+      )),
+    }
+  }
+
+  /// Run the `protocol` handshake over a stream the caller already
+  /// connected, e.g. one dialed through their own proxy stack, instead of
+  /// resolving and dialing a host. Supported by the HYRCON, Source and
+  /// telnet backends.
+  ///
+  /// The session's [`host`](Self::host) is empty and its
+  /// [`port`](Self::port) 0.
+  pub async fn from_stream(
+    protocol: Protocol,
+    stream: impl Stream + 'static,
+    deadline: Duration,
+  ) -> Result<Self, Error> {
+    Self::from_stream_with_options(
+      protocol,
+      stream,
+      deadline,
+      &ConnectOptions::default(),
+    )
+    .await
+  }
+
+  /// Like [`RconClient::from_stream`], applying the protocol settings of
+  /// `options` such as the Source dialect. Settings for reaching the
+  /// server (TLS, proxies, tunnels, connectors) are rejected.
+  pub async fn from_stream_with_options(
+    protocol: Protocol,
+    stream: impl Stream + 'static,
+    deadline: Duration,
+    options: &ConnectOptions,
+  ) -> Result<Self, Error> {
+    if options.tls.is_some()
+      || options.proxy.is_some()
+      || options.ssh_tunnel.is_some()
+      || options.connector.is_some()
+      || options.tcp_keepalive.is_some()
+    {
+      return Err(Error::Unsupported(
+        "connection settings do not apply to an already connected stream"
+          .to_string(),
+      ));
+    }
+    check_source_settings(protocol, options)?;
+
+    let traffic = Traffic::default();
+    let (read_half, write_half) = stream::adopt(stream, &traffic);
+    let (backend, greeting) = match protocol {
+      Protocol::Hyrcon => {
+        let (client, greeting) = HyrconClient::handshake(
+          read_half, write_half, deadline, traffic,
+        )
+        .await
+        .map_err(Error::from_internal)?;
+        (Backend::Hyrcon(client), greeting)
+      }
+      Protocol::Source => {
+        let client = SourceClient::handshake(
+          read_half, write_half, deadline, options, traffic,
+        )
+        .map_err(Error::from_internal)?;
+        (Backend::Source(client), Greeting::source_default())
+      }
+      Protocol::Telnet => {
+        let (client, greeting) = TelnetClient::handshake(
+          read_half, write_half, deadline, traffic,
+        )
+        .await
+        .map_err(Error::from_internal)?;
+        (Backend::Telnet(client), greeting)
+      }
+      _ => {
+        return Err(Error::Unsupported(format!(
+          "the {protocol} protocol cannot run over a caller-supplied stream"
+        )));
+      }
+    };
+    Ok(Self {
+      backend,
+      greeting,
+      protocol,
+      host: String::new(),
+      port: 0,
+      commands: 0,
+      events: Events::default(),
+      timeout: deadline,
+    })
+  }
+
+  /// Connect using a caller-supplied [`ProtocolBackend`] implementation.
+  pub async fn connect_with(
+    mut backend: Box<dyn ProtocolBackend>,
+    host: &str,
+    port: u16,
+    deadline: Duration,
+  ) -> Result<Self, Error> {
+    let greeting =
+      backend.connect(host, port, deadline).await.map_err(|err| {
+        Error::Connect {
+          host: host.to_string(),
+          port,
+          protocol: Protocol::Custom,
+          source: Box::new(err),
+        }
+      })?;
+    Ok(Self {
+      protocol: greeting.protocol(),
+      backend: Backend::Custom(backend),
+      greeting,
+      host: host.to_string(),
+      port,
+      commands: 0,
+      events: Events::default(),
+      timeout: deadline,
+    })
+  }
+
+  pub fn protocol(&self) -> Protocol {
+    self.protocol
+  }
+
+  /// Host the client connected to, as passed to `connect`; empty for
+  /// sessions made with [`from_stream`](Self::from_stream).
+  pub fn host(&self) -> &str {
+    &self.host
+  }
+
+  pub fn port(&self) -> u16 {
+    self.port
+  }
+
+  /// Receive the session's [`ClientEvent`]s from now on: commands sent
+  /// and answered, authentication, and the end of the session. Receivers
+  /// that fall far behind lose the oldest events.
+  pub fn events(&self) -> broadcast::Receiver<ClientEvent> {
+    self.events.subscribe()
+  }
+
+  /// Report this session's events to `events` instead, starting with
+  /// [`ClientEvent::Connected`].
+  fn attach_events(&mut self, events: &Events) {
+    self.events = events.clone();
+    self.events.emit(ClientEvent::Connected);
+  }
+
+  pub fn greeting(&self) -> &Greeting {
+    &self.greeting
+  }
+
+  pub fn is_closed(&self) -> bool {
+    match &self.backend {
+      Backend::Hyrcon(client) => client.is_closed(),
+      Backend::Source(client) => client.is_closed(),
+      Backend::WebRcon(client) => client.is_closed(),
+      Backend::Quake(client) => client.is_closed(),
+      Backend::BattlEye(client) => client.is_closed(),
+      Backend::Telnet(client) => client.is_closed(),
+      #[cfg(feature = "rest")]
+      Backend::Rest(client) => client.is_closed(),
+      #[cfg(feature = "pterodactyl")]
+      Backend::Pterodactyl(client) => client.is_closed(),
+      #[cfg(feature = "ssh")]
+      Backend::Ssh(client) => client.is_closed(),
+      Backend::Custom(client) => client.is_closed(),
+    }
+  }
+
+  /// How long the client waits for the server.
+  pub fn timeout(&self) -> Duration {
+    self.timeout
+  }
+
+  /// Wait up to `timeout` for the server from now on, instead of the
+  /// deadline given to `connect`.
+  pub fn set_timeout(&mut self, timeout: Duration) {
+    self.timeout = timeout;
+    match &mut self.backend {
+      Backend::Hyrcon(client) => client.set_timeout(timeout),
+      Backend::Source(client) => client.set_timeout(timeout),
+      Backend::WebRcon(client) => client.set_timeout(timeout),
+      Backend::Quake(client) => client.set_timeout(timeout),
+      Backend::BattlEye(client) => client.set_timeout(timeout),
+      Backend::Telnet(client) => client.set_timeout(timeout),
+      #[cfg(feature = "rest")]
+      Backend::Rest(client) => client.set_timeout(timeout),
+      #[cfg(feature = "pterodactyl")]
+      Backend::Pterodactyl(client) => client.set_timeout(timeout),
+      #[cfg(feature = "ssh")]
+      Backend::Ssh(client) => client.set_timeout(timeout),
+      Backend::Custom(client) => client.set_timeout(timeout),
+    }
+  }
+
+  /// Traffic exchanged so far: bytes, protocol messages (packets, lines,
+  /// datagrams, frames or requests) and commands. Custom backends only
+  /// report the command count.
+  pub fn stats(&self) -> SessionStats {
+    let traffic = match &self.backend {
+      Backend::Hyrcon(client) => Some(client.traffic()),
+      Backend::Source(client) => Some(client.traffic()),
+      Backend::WebRcon(client) => Some(client.traffic()),
+      Backend::Quake(client) => Some(client.traffic()),
+      Backend::BattlEye(client) => Some(client.traffic()),
+      Backend::Telnet(client) => Some(client.traffic()),
+      #[cfg(feature = "rest")]
+      Backend::Rest(client) => Some(client.traffic()),
+      #[cfg(feature = "pterodactyl")]
+      Backend::Pterodactyl(client) => Some(client.traffic()),
+      #[cfg(feature = "ssh")]
+      Backend::Ssh(client) => Some(client.traffic()),
+      Backend::Custom(_) => None,
+    };
+    SessionStats {
+      commands: self.commands,
+      ..traffic.map(Traffic::snapshot).unwrap_or_default()
+    }
+  }
+
+  /// Drain unsolicited server messages received since the last call.
+  ///
+  /// Only backends with a push channel (HYRCON events, WebRCON, BattlEye,
+  /// telnet, Pterodactyl and SSH consoles) ever queue messages; the others
+  /// always return an empty list.
+  pub fn take_messages(&mut self) -> Vec<ServerMessage> {
+    match &mut self.backend {
+      Backend::Hyrcon(client) => client.take_messages(),
+      Backend::WebRcon(client) => client.take_messages(),
+      Backend::BattlEye(client) => client.take_messages(),
+      Backend::Telnet(client) => client.take_messages(),
+      #[cfg(feature = "pterodactyl")]
+      Backend::Pterodactyl(client) => client.take_messages(),
+      #[cfg(feature = "ssh")]
+      Backend::Ssh(client) => client.take_messages(),
+      Backend::Custom(client) => client.take_messages(),
+      _ => Vec::new(),
+    }
+  }
+
+  /// Wait for the next unsolicited server message while the session is
+  /// idle, performing any protocol housekeeping (such as keepalives) along
+  /// the way.
+  ///
+  /// Returns `None` once the server has closed the session. For backends
+  /// without a push channel the future never resolves, so it is meant to be
+  /// raced against user input. It is cancel-safe: dropping it never loses a
+  /// message or leaves a partially read frame behind.
+  pub async fn recv_message(
+    &mut self,
+  ) -> Result<Option<ServerMessage>, Error> {
+    let message = self.next_message().await;
+    match &message {
+      Ok(Some(_)) => {}
+      Ok(None) => self.events.disconnected(DisconnectReason::ServerClosed),
+      Err(err) => self.note_failure(err),
+    }
+    message
+  }
+
+  async fn next_message(
+    &mut self,
+  ) -> Result<Option<ServerMessage>, Error> {
+    let message = match &mut self.backend {
+      Backend::Hyrcon(client) => client.recv_message().await,
+      Backend::WebRcon(client) => client.recv_message().await,
+      Backend::BattlEye(client) => client.recv_message().await,
+      Backend::Telnet(client) => client.recv_message().await,
+      #[cfg(feature = "pterodactyl")]
+      Backend::Pterodactyl(client) => client.recv_message().await,
+      #[cfg(feature = "ssh")]
+      Backend::Ssh(client) => client.recv_message().await,
+      Backend::Custom(client) => return client.recv_message().await,
+      _ => std::future::pending().await,
+    };
+    message.map_err(Error::from_internal)
+  }
+
+  /// Probe an idle session with a protocol-appropriate no-op (an empty
+  /// `SERVERDATA_RESPONSE_VALUE` packet for Source, `PING` for HYRCON).
+  ///
+  /// An error means the connection is gone. Backends without a suitable
+  /// probe, or that keep themselves alive (BattlEye), do nothing.
+  pub async fn keepalive(&mut self) -> Result<(), Error> {
+    let probed = match &mut self.backend {
+      Backend::Hyrcon(client) => client.keepalive().await,
+      Backend::Source(client) => client.keepalive().await,
+      Backend::Custom(client) => {
+        client.keepalive().await.map_err(Into::into)
+      }
+      _ => Ok(()),
+    };
+    let probed = probed.map_err(Error::from_internal);
+    if let Err(err) = &probed {
+      // The probe failing means the connection is gone.
+      self
+        .events
+        .disconnected(DisconnectReason::Failed(err.to_string()));
+    }
+    probed
+  }
+
+  /// Measure the round trip of a protocol-appropriate no-op: an empty
+  /// `SERVERDATA_RESPONSE_VALUE` exchange for Source, `PING` for HYRCON.
+  ///
+  /// Other built-in protocols have no such no-op and return an error.
+  pub async fn ping(&mut self) -> Result<Duration, Error> {
+    let started = Instant::now();
+    match &mut self.backend {
+      Backend::Hyrcon(client) => {
+        client.keepalive().await.map_err(Error::from_internal)?;
+      }
+      Backend::Source(client) => {
+        client.ping().await.map_err(Error::from_internal)?;
+      }
+      Backend::Custom(client) => client.ping().await?,
+      _ => {
+        return Err(Error::Unsupported(format!(
+          "ping is not supported by the {} protocol",
+          self.protocol
+        )));
+      }
+    }
+    Ok(started.elapsed())
+  }
+
+  /// Perform the authentication handshake as required by the backend.
+  ///
+  /// Cancel-safe: if the future is dropped before the server answers,
+  /// the late answer is skipped by the next call.
+  pub async fn authenticate(
+    &mut self,
+    password: &str,
+  ) -> Result<AuthOutcome, Error> {
+    let outcome = self.login(password).await;
+    match &outcome {
+      Ok(AuthOutcome::Success) => {
+        self.events.emit(ClientEvent::Authenticated);
+        self.events.emit(ClientEvent::Idle);
+      }
+      Ok(AuthOutcome::Failure) => {}
+      Err(err) => self.note_failure(err),
+    }
+    outcome
+  }
+
+  /// [`authenticate`](Self::authenticate), giving up with
+  /// [`Error::Cancelled`] once `token` is cancelled. A late answer is
+  /// skipped by the next call.
+  pub async fn authenticate_with(
+    &mut self,
+    password: &str,
+    token: &CancellationToken,
+  ) -> Result<AuthOutcome, Error> {
+    tokio::select! {
+      biased;
+      () = token.cancelled() => {
+        tracing::debug!("cancelled authentication");
+        Err(Error::Cancelled)
+      }
+      outcome = self.authenticate(password) => outcome,
+    }
+  }
+
+  async fn login(&mut self, password: &str) -> Result<AuthOutcome, Error> {
+    let outcome = match &mut self.backend {
+      Backend::Hyrcon(client) => client.authenticate(password).await,
+      Backend::Source(client) => client.authenticate(password).await,
+      Backend::WebRcon(client) => client.authenticate(password).await,
+      Backend::Quake(client) => client.authenticate(password).await,
+      Backend::BattlEye(client) => client.authenticate(password).await,
+      Backend::Telnet(client) => client.authenticate(password).await,
+      #[cfg(feature = "rest")]
+      Backend::Rest(client) => client.authenticate(password).await,
+      #[cfg(feature = "pterodactyl")]
+      Backend::Pterodactyl(client) => client.authenticate(password).await,
+      #[cfg(feature = "ssh")]
+      Backend::Ssh(client) => client.authenticate(password).await,
+      Backend::Custom(client) => {
+        return client.authenticate(password).await;
+      }
+    };
+    outcome.map_err(Error::from_internal)
+  }
+
+  /// Send an arbitrary command line to the server.
+  ///
+  /// Cancel-safe: if the future is dropped, e.g. by a `select!` on a
+  /// shutdown signal, the command may still run on the server but its
+  /// reply is skipped rather than taken for the next command's. The
+  /// session stays usable.
+  pub async fn send_command(
+    &mut self,
+    command: &str,
+  ) -> Result<CommandOutcome, Error> {
+    self.commands += 1;
+    let started = Instant::now();
+    self.events.emit(ClientEvent::CommandSent {
+      command: command.to_string(),
+    });
+    let outcome = self.dispatch(command).await;
+    match &outcome {
+      Ok(CommandOutcome::Response(_)) => {
+        self.events.emit(ClientEvent::ResponseReceived {
+          command: command.to_string(),
+          elapsed: started.elapsed(),
+        });
+        self.events.emit(ClientEvent::Idle);
+      }
+      Ok(CommandOutcome::Bye) => {
+        self.events.disconnected(DisconnectReason::ServerClosed);
+      }
+      Err(err) => self.note_failure(err),
+    }
+    outcome
+  }
+
+  /// Send a batch of commands, returning their outcomes in order. The
+  /// Source backend pipelines them, writing every command before reading
+  /// the replies, which are matched up by request id; other backends send
+  /// them one at a time. Stops at the first error.
+  pub async fn send_many(
+    &mut self,
+    commands: &[&str],
+  ) -> Result<Vec<CommandOutcome>, Error> {
+    let Backend::Source(client) = &mut self.backend else {
+      let mut outcomes = Vec::with_capacity(commands.len());
+      for command in commands {
+        outcomes.push(self.send_command(command).await?);
+      }
+      return Ok(outcomes);
+    };
+
+    self.commands += commands.len() as u64;
+    let started = Instant::now();
+    for command in commands {
+      self.events.emit(ClientEvent::CommandSent {
+        command: command.to_string(),
+      });
+    }
+    let outcomes = client
+      .send_many(commands)
+      .await
+      .map_err(Error::from_internal);
+    match &outcomes {
+      Ok(_) => {
+        for command in commands {
+          self.events.emit(ClientEvent::ResponseReceived {
+            command: command.to_string(),
+            elapsed: started.elapsed(),
+          });
+        }
+        self.events.emit(ClientEvent::Idle);
+      }
+      Err(err) => self.note_failure(err),
+    }
+    outcomes
+  }
+
+  /// Send raw lines and read raw blocks over a HYRCON session, e.g. for
+  /// nonstandard verbs; `None` for the other protocols.
+  pub fn hyrcon_lines(&mut self) -> Option<hyrcon::Lines<'_>> {
+    match &mut self.backend {
+      Backend::Hyrcon(client) => Some(hyrcon::Lines::new(client)),
+      _ => None,
+    }
+  }
+
+  /// Read and write raw packets over a Source session, e.g. for custom
+  /// packet kinds; `None` for the other protocols.
+  pub fn source_packets(&mut self) -> Option<source::Packets<'_>> {
+    match &mut self.backend {
+      Backend::Source(client) => Some(source::Packets::new(client)),
+      _ => None,
+    }
+  }
+
+  /// Send `command`, waiting up to `timeout` for its reply instead of the
+  /// session's timeout, e.g. longer for `save-all` or shorter for `list`.
+  pub async fn send_command_with_timeout(
+    &mut self,
+    command: &str,
+    timeout: Duration,
+  ) -> Result<CommandOutcome, Error> {
+    self
+      .send_command_with(
+        command,
+        &CommandOptions::default().timeout(timeout),
+      )
+      .await
+  }
+
+  /// Send `command` with the overrides in `options`. The session's own
+  /// settings are restored afterwards, even if the future is dropped.
+  pub async fn send_command_with(
+    &mut self,
+    command: &str,
+    options: &CommandOptions,
+  ) -> Result<CommandOutcome, Error> {
+    let timeout = options.timeout.unwrap_or(self.timeout);
+    let client = TimeoutOverride::new(self, timeout);
+    let Some(token) = &options.cancellation else {
+      return client.0.send_command(command).await;
+    };
+    tokio::select! {
+      biased;
+      () = token.cancelled() => {
+        tracing::debug!("cancelled `{command}`");
+        Err(Error::Cancelled)
+      }
+      outcome = client.0.send_command(command) => outcome,
+    }
+  }
+
+  async fn dispatch(
+    &mut self,
+    command: &str,
+  ) -> Result<CommandOutcome, Error> {
+    let outcome = match &mut self.backend {
+      Backend::Hyrcon(client) => client.send_command(command).await,
+      Backend::Source(client) => client.send_command(command).await,
+      Backend::WebRcon(client) => client.send_command(command).await,
+      Backend::Quake(client) => client.send_command(command).await,
+      Backend::BattlEye(client) => client.send_command(command).await,
+      Backend::Telnet(client) => client.send_command(command).await,
+      #[cfg(feature = "rest")]
+      Backend::Rest(client) => client.send_command(command).await,
+      #[cfg(feature = "pterodactyl")]
+      Backend::Pterodactyl(client) => client.send_command(command).await,
+      #[cfg(feature = "ssh")]
+      Backend::Ssh(client) => client.send_command(command).await,
+      Backend::Custom(client) => {
+        return client.send_command(command).await;
+      }
+    };
+    outcome.map_err(Error::from_internal)
+  }
+
+  /// Attempt a graceful shutdown of the session.
+  pub async fn quit(&mut self) -> Result<(), Error> {
+    let quit = self.close().await;
+    self.events.disconnected(match &quit {
+      Ok(()) => DisconnectReason::Quit,
+      Err(err) => DisconnectReason::Failed(err.to_string()),
+    });
+    quit
+  }
+
+  async fn close(&mut self) -> Result<(), Error> {
+    let quit = match &mut self.backend {
+      Backend::Hyrcon(client) => client.quit().await,
+      Backend::Source(client) => client.quit().await,
+      Backend::WebRcon(client) => client.quit().await,
+      Backend::Quake(client) => client.quit().await,
+      Backend::BattlEye(client) => client.quit().await,
+      Backend::Telnet(client) => client.quit().await,
+      #[cfg(feature = "rest")]
+      Backend::Rest(client) => client.quit().await,
+      #[cfg(feature = "pterodactyl")]
+      Backend::Pterodactyl(client) => client.quit().await,
+      #[cfg(feature = "ssh")]
+      Backend::Ssh(client) => client.quit().await,
+      Backend::Custom(client) => return client.quit().await,
+    };
+    quit.map_err(Error::from_internal)
+  }
+
+  /// Report the end of the session when `err` means it is over.
+  fn note_failure(&mut self, err: &Error) {
+    match err {
+      Error::Closed => {
+        self.events.disconnected(DisconnectReason::ServerClosed);
+      }
+      Error::Io { .. } => {
+        self
+          .events
+          .disconnected(DisconnectReason::Failed(err.to_string()));
+      }
+      _ if self.is_closed() => {
+        self
+          .events
+          .disconnected(DisconnectReason::Failed(err.to_string()));
+      }
+      _ => {}
+    }
+  }
+}
+
+/// An [`Error::Unsupported`] for the internal `anyhow` paths.
+fn unsupported(reason: &str) -> anyhow::Error {
+  Error::Unsupported(reason.to_string()).into()
+}
+
+/// Largest payload that fits in a single UDP datagram.
+const MAX_UDP_DATAGRAM: usize = 65_507;
+
+/// Resolve `host` to the addresses of the configured family, in resolver
+/// order, or to its pinned addresses when an override matches.
+async fn resolve(
+  host: &str,
+  port: u16,
+  deadline: Duration,
+  options: &ConnectOptions,
+) -> Result<Vec<SocketAddr>> {
+  let family = options.family();
+  let addresses: Vec<_> = match options.pinned(host, port) {
+    Some(pinned) => {
+      tracing::debug!("--> {host}:{port} pinned to {pinned:?}");
+      pinned
+        .iter()
+        .map(|address| SocketAddr::new(*address, port))
+        .collect()
+    }
+    None => await_timeout(deadline, lookup_host((host, port)))
+      .await
+      .context("address lookup timed out")?
+      .context("address lookup failed")?
+      .collect(),
+  };
+  if addresses.is_empty() {
+    bail!("no addresses found for {host}");
+  }
+
+  let admitted: Vec<_> = addresses
+    .into_iter()
+    .filter(|address| family.admits(address))
+    .collect();
+  match family {
+    _ if !admitted.is_empty() => Ok(admitted),
+    IpFamily::V4 => bail!("{host} has no IPv4 address"),
+    IpFamily::V6 => bail!("{host} has no IPv6 address"),
+    IpFamily::Any => unreachable!("every address is admitted"),
+  }
+}
+
+/// Resolve `host` and return a UDP socket associated with the first
+/// address of the configured family, bound to `options.bind` or the
+/// wildcard address of the matching family.
+async fn connect_udp(
+  host: &str,
+  port: u16,
+  deadline: Duration,
+  options: &ConnectOptions,
+) -> Result<UdpSocket> {
+  let target = resolve(host, port, deadline, options).await?[0];
+
+  let local = match options.bind {
+    Some(bind) => SocketAddr::new(bind, 0),
+    None if target.is_ipv4() => {
+      (std::net::Ipv4Addr::UNSPECIFIED, 0).into()
+    }
+    None => (std::net::Ipv6Addr::UNSPECIFIED, 0).into(),
+  };
+  let socket = UdpSocket::bind(local).await.with_context(|| {
+    format!("failed to bind local UDP socket to {local}")
+  })?;
+  socket
+    .connect(target)
+    .await
+    .with_context(|| format!("failed to associate with {target}"))?;
+
+  Ok(socket)
+}
+
+async fn with_timeout<F, T>(
+  duration: Duration,
+  future: F,
+  context: impl Into<String>,
+) -> Result<T>
+where
+  F: std::future::Future<Output = io::Result<T>>,
+{
+  let context = context.into();
+  match await_timeout(duration, future).await {
+    Ok(result) => result.with_context(|| context.clone()),
+    Err(_) => Err(TimedOut::new(context, duration).into()),
+  }
+}
+
+fn split_lines(payload: &str) -> Vec<String> {
+  if payload.is_empty() {
+    return vec![];
+  }
+
+  payload
+    .lines()
+    .map(|line| line.trim_end_matches('\r').to_string())
+    .collect()
+}
+
+/// Lines of a line-based reply as one text, each ending in `\n`, for
+/// [`RconResponse::raw`].
+pub(crate) fn join_lines(lines: &[String]) -> String {
+  lines.iter().map(|line| format!("{line}\n")).collect()
+}
+
+/// Whether a failed connect is worth retrying, e.g. because the server
+/// is restarting, as opposed to being misconfigured.
+fn is_transient_connect_error(err: &anyhow::Error) -> bool {
+  err.chain().any(|cause| {
+    if cause.is::<tokio::time::error::Elapsed>() {
+      return true;
+    }
+    cause.downcast_ref::<io::Error>().is_some_and(|io_err| {
+      matches!(
+        io_err.kind(),
+        ErrorKind::ConnectionRefused
+          | ErrorKind::ConnectionReset
+          | ErrorKind::ConnectionAborted
+          | ErrorKind::NotConnected
+          | ErrorKind::BrokenPipe
+          | ErrorKind::TimedOut
+          | ErrorKind::UnexpectedEof
+          | ErrorKind::HostUnreachable
+          | ErrorKind::NetworkUnreachable
+      )
+    })
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn greeting_parses_required_auth() {
+    let greeting = Greeting::hyrcon_from_lines(vec![
+      "HYRCON READY".to_string(),
+      "AUTH REQUIRED".to_string(),
+    ])
+    .expect("parse greeting");
+
+    assert!(greeting.requires_auth());
+    assert_eq!(greeting.banner(), "HYRCON READY");
+  }
+
+  #[test]
+  fn greeting_keeps_extension_lines() {
+    let greeting = Greeting::hyrcon_from_lines(vec![
+      "HYRCON READY".to_string(),
+      "AUTH OPTIONAL".to_string(),
+      "CAP EVENTS".to_string(),
+      "VERSION 2.1.0".to_string(),
+      "motd Welcome back, builders".to_string(),
+      "MAX-PLAYERS 20".to_string(),
+    ])
+    .expect("parse greeting");
+
+    assert!(greeting.supports("EVENTS"));
+    let extensions: Vec<_> = greeting
+      .extensions()
+      .iter()
+      .map(|(key, value)| (key.as_str(), value.as_str()))
+      .collect();
+    assert_eq!(
+      extensions,
+      [
+        ("MAX-PLAYERS", "20"),
+        ("MOTD", "Welcome back, builders"),
+        ("VERSION", "2.1.0"),
+      ]
+    );
+  }
+
+  #[cfg(feature = "serde")]
+  #[test]
+  fn response_types_round_trip_through_json() {
+    let greeting = Greeting::new(
+      Protocol::WebRcon,
+      "WEBRCON READY",
+      AuthMode::Optional,
+    )
+    .with_capabilities(["events"])
+    .with_extension("version", "2.1.0");
+    let json = serde_json::to_value(&greeting).expect("serialize");
+    assert_eq!(
+      json,
+      serde_json::json!({
+        "banner": "WEBRCON READY",
+        "auth_mode": "optional",
+        "protocol": "webrcon",
+        "capabilities": ["EVENTS"],
+        "extensions": { "VERSION": "2.1.0" },
+      })
+    );
+    let parsed: Greeting = serde_json::from_value(json).expect("parse");
+    assert_eq!(parsed.protocol(), Protocol::WebRcon);
+    assert_eq!(parsed.extensions()["VERSION"], "2.1.0");
+
+    let response: RconResponse = serde_json::from_str(
+      r#"{"status": "err", "payload": [], "error": "unknown command"}"#,
+    )
+    .expect("parse response");
+    assert_eq!(response.status, ResponseStatus::Err);
+    assert_eq!(
+      serde_json::to_string(&AuthOutcome::Success).expect("serialize"),
+      r#""success""#
+    );
+  }
+
+  #[test]
+  fn split_lines_handles_crlf() {
+    let lines = split_lines("foo\r\nbar\nbaz\r\n");
+    assert_eq!(lines, vec!["foo", "bar", "baz"]);
+  }
+
+  #[test]
+  fn only_unreachable_servers_are_transient() {
+    let refused =
+      anyhow::Error::new(io::Error::from(ErrorKind::ConnectionRefused))
+        .context("connect failed");
+    assert!(is_transient_connect_error(&refused));
+
+    let missing = anyhow::Error::new(io::Error::from(ErrorKind::NotFound));
+    assert!(!is_transient_connect_error(&missing));
+    assert!(!is_transient_connect_error(&anyhow!("bad certificate")));
+  }
+
+  #[tokio::test]
+  async fn connect_retries_until_the_server_is_up() {
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+
+    // Reserve a port, then free it so the first attempts are refused.
+    let port = TcpListener::bind("127.0.0.1:0")
+      .await
+      .expect("bind")
+      .local_addr()
+      .expect("addr")
+      .port();
+    let server = tokio::spawn(async move {
+      tokio::time::sleep(Duration::from_millis(150)).await;
+      let listener = TcpListener::bind(("127.0.0.1", port))
+        .await
+        .expect("rebind");
+      let (mut stream, _) = listener.accept().await.expect("accept");
+      stream
+        .write_all(b"HYRCON READY\nAUTH OPTIONAL\nCAP PING\n.\n")
+        .await
+        .expect("greeting");
+      stream
+    });
+
+    let options = ConnectOptions {
+      connect_retries: 20,
+      connect_retry_delay: Duration::from_millis(50),
+      ..ConnectOptions::default()
+    };
+    let client = RconClient::connect_with_options(
+      Protocol::Hyrcon,
+      "127.0.0.1",
+      port,
+      Duration::from_secs(1),
+      &options,
+    )
+    .await
+    .expect("connect after retries");
+    assert!(client.greeting().supports("PING"));
+
+    drop(server.await.expect("server"));
+  }
+
+  #[tokio::test]
+  async fn stats_count_session_traffic() {
+    use crate::testing::{MockHyrconServer, Reply};
+
+    let server = MockHyrconServer::builder()
+      .expect("list", Reply::ok(["Steve"]))
+      .start()
+      .await
+      .expect("start");
+    let mut client = RconClient::connect(
+      Protocol::Hyrcon,
+      "127.0.0.1",
+      server.port(),
+      Duration::from_secs(1),
+    )
+    .await
+    .expect("connect");
+    // The handshake may include a HELLO, so compare against it.
+    let handshake = client.stats();
+    assert_eq!(handshake.commands, 0);
+    assert!(handshake.bytes_received > 0);
+
+    client.send_command("list").await.expect("list");
+    let stats = client.stats();
+    assert_eq!(stats.commands, 1);
+    assert_eq!(stats.messages_sent, handshake.messages_sent + 1);
+    assert_eq!(stats.bytes_sent, handshake.bytes_sent + 5);
+    assert!(stats.messages_received > handshake.messages_received);
+    assert!(stats.bytes_received > handshake.bytes_received);
+
+    client.quit().await.expect("quit");
+    server.finish().await.expect("script followed");
+  }
+
+  #[tokio::test]
+  async fn connectors_replace_tcp_dialing() {
+    use crate::testing::{MockHyrconServer, Reply};
+
+    /// Reaches every host through the mock server's port.
+    #[derive(Debug)]
+    struct Loopback(u16);
+
+    #[async_trait::async_trait]
+    impl Connector for Loopback {
+      async fn connect(
+        &self,
+        host: &str,
+        _port: u16,
+      ) -> io::Result<Box<dyn Stream>> {
+        assert_eq!(host, "rcon.invalid");
+        let stream =
+          tokio::net::TcpStream::connect(("127.0.0.1", self.0)).await?;
+        Ok(Box::new(stream))
+      }
+    }
+
+    let server = MockHyrconServer::builder()
+      .expect("list", Reply::ok(["Steve"]))
+      .start()
+      .await
+      .expect("start");
+    let mut client = RconClient::builder()
+      .protocol(Protocol::Hyrcon)
+      .host("rcon.invalid")
+      .connector(Loopback(server.port()))
+      .build()
+      .expect("settings")
+      .connect()
+      .await
+      .expect("connect");
+    client.send_command("list").await.expect("list");
+    client.quit().await.expect("quit");
+    server.finish().await.expect("script followed");
+
+    let options = ConnectOptions {
+      connector: Some(Arc::new(Loopback(1))),
+      ..ConnectOptions::default()
+    };
+    let err = RconClient::connect_with_options(
+      Protocol::Quake,
+      "rcon.invalid",
+      1,
+      Duration::from_secs(1),
+      &options,
+    )
+    .await
+    .expect_err("UDP protocols dial themselves");
+    assert!(matches!(err, Error::Unsupported(_)));
+  }
+
+  #[tokio::test]
+  async fn sessions_run_over_caller_connected_streams() {
+    use crate::testing::{MockHyrconServer, Reply};
+
+    let server = MockHyrconServer::builder()
+      .expect("list", Reply::ok(["Steve"]))
+      .start()
+      .await
+      .expect("start");
+    let stream =
+      tokio::net::TcpStream::connect(("127.0.0.1", server.port()))
+        .await
+        .expect("dial");
+    let mut client = RconClient::from_stream(
+      Protocol::Hyrcon,
+      stream,
+      Duration::from_secs(1),
+    )
+    .await
+    .expect("handshake");
+    assert_eq!(client.host(), "");
+    client.send_command("list").await.expect("list");
+    assert!(client.stats().bytes_sent > 0);
+    client.quit().await.expect("quit");
+    server.finish().await.expect("script followed");
+
+    let (stream, _server) = tokio::io::duplex(64);
+    let err = RconClient::from_stream(
+      Protocol::WebRcon,
+      stream,
+      Duration::from_secs(1),
+    )
+    .await
+    .expect_err("WebRCON needs the host for its handshake");
+    assert!(matches!(err, Error::Unsupported(_)));
+  }
+
+  #[tokio::test]
+  async fn commands_can_override_the_timeout() {
+    use crate::testing::{MockSourceServer, Reply};
+
+    let server = MockSourceServer::builder()
+      .expect(
+        "save-all",
+        Reply::ok(["Saved the game"]).delay(Duration::from_millis(300)),
+      )
+      .expect("list", Reply::ok(["Steve"]).delay(Duration::from_secs(5)))
+      .start()
+      .await
+      .expect("start");
+    let mut client = RconClient::connect(
+      Protocol::Source,
+      "127.0.0.1",
+      server.port(),
+      Duration::from_millis(100),
+    )
+    .await
+    .expect("connect");
+    client.authenticate("anything").await.expect("auth");
+
+    client
+      .send_command_with_timeout("save-all", Duration::from_secs(2))
+      .await
+      .expect("slow command");
+    assert_eq!(client.timeout(), Duration::from_millis(100));
+
+    let options =
+      CommandOptions::default().timeout(Duration::from_millis(50));
+    let err = client
+      .send_command_with("list", &options)
+      .await
+      .expect_err("fails fast");
+    assert!(
+      matches!(err, Error::Timeout(timed_out) if timed_out.after() == Duration::from_millis(50))
+    );
+  }
+
+  #[tokio::test]
+  async fn cancelled_commands_leave_the_session_usable() {
+    use crate::testing::{MockHyrconServer, Reply};
+
+    let server = MockHyrconServer::builder()
+      .expect(
+        "save-all",
+        Reply::ok(["Saved"]).delay(Duration::from_millis(200)),
+      )
+      .expect("list", Reply::ok(["Steve"]))
+      .start()
+      .await
+      .expect("start");
+    let mut client = RconClient::connect(
+      Protocol::Hyrcon,
+      "127.0.0.1",
+      server.port(),
+      Duration::from_secs(1),
+    )
+    .await
+    .expect("connect");
+
+    let token = CancellationToken::new();
+    tokio::spawn({
+      let token = token.clone();
+      async move {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        token.cancel();
+      }
+    });
+    let options = CommandOptions::default().cancel_on(token);
+    let err = client
+      .send_command_with("save-all", &options)
+      .await
+      .expect_err("cancelled");
+    assert!(matches!(err, Error::Cancelled));
+
+    match client.send_command("list").await.expect("list") {
+      CommandOutcome::Response(response) => {
+        assert_eq!(response.payload, ["Steve"]);
+      }
+      CommandOutcome::Bye => panic!("unexpected bye"),
+    }
+    client.quit().await.expect("quit");
+    server.finish().await.expect("script followed");
+  }
+
+  #[tokio::test]
+  async fn cancelled_authentication_can_be_retried() {
+    use crate::testing::{MockHyrconServer, Reply};
+
+    let server = MockHyrconServer::builder()
+      .password("secret")
+      .expect(
+        "AUTH secret",
+        Reply::raw(b"AUTH OK\n.\n".to_vec())
+          .delay(Duration::from_millis(200)),
+      )
+      .expect("list", Reply::ok(["Steve"]))
+      .start()
+      .await
+      .expect("start");
+    let mut client = RconClient::connect(
+      Protocol::Hyrcon,
+      "127.0.0.1",
+      server.port(),
+      Duration::from_secs(1),
+    )
+    .await
+    .expect("connect");
+
+    let token = CancellationToken::new();
+    tokio::spawn({
+      let token = token.clone();
+      async move {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        token.cancel();
+      }
+    });
+    let err = client
+      .authenticate_with("secret", &token)
+      .await
+      .expect_err("cancelled");
+    assert!(matches!(err, Error::Cancelled));
+
+    let outcome = client
+      .authenticate_with("secret", &CancellationToken::new())
+      .await
+      .expect("auth");
+    assert!(matches!(outcome, AuthOutcome::Success));
+    match client.send_command("list").await.expect("list") {
+      CommandOutcome::Response(response) => {
+        assert_eq!(response.payload, ["Steve"]);
+      }
+      CommandOutcome::Bye => panic!("unexpected bye"),
+    }
+    client.quit().await.expect("quit");
+    server.finish().await.expect("script followed");
+  }
+}
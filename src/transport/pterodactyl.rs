@@ -0,0 +1,712 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow, bail};
+use futures_util::{SinkExt, StreamExt};
+use reqwest::{Client, StatusCode};
+use rustls::ClientConfig;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::net::TcpStream;
+use tokio::time::{Instant, timeout as await_timeout, timeout_at};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::handshake::client::{
+  Request, Response,
+};
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::{Message, Utf8Bytes};
+use tokio_tungstenite::{
+  Connector, MaybeTlsStream, WebSocketStream, client_async_tls_with_config,
+};
+
+use super::stats::{Metered, Traffic};
+use super::{
+  AuthOutcome, CommandOutcome, ConnectOptions, RconResponse,
+  ResponseStatus, ServerMessage, TimedOut, join_lines, stream, tls,
+};
+
+/// How long to wait for the first line of console output after a command.
+/// Consoles have no reply framing, and many commands print nothing at all.
+const FIRST_OUTPUT_WINDOW: Duration = Duration::from_secs(2);
+
+/// Quiet period after which streamed command output is considered complete.
+const RESPONSE_IDLE_WINDOW: Duration = Duration::from_millis(300);
+
+type Socket = WebSocketStream<MaybeTlsStream<Metered<TcpStream>>>;
+
+/// Pterodactyl panel console client.
+///
+/// `authenticate` exchanges the client API key for a short-lived console
+/// token, opens the Wings websocket and keeps the token fresh when the
+/// daemon announces its expiry. Commands are written to the server console
+/// and whatever it prints shortly afterwards becomes the reply; everything
+/// else is surfaced as pushed console messages.
+#[derive(Debug)]
+pub(super) struct PterodactylClient {
+  http: Client,
+  tls_config: Arc<ClientConfig>,
+  // Proxy, family and local address for the websocket.
+  connect_options: Box<ConnectOptions>,
+  panel_url: String,
+  server_id: String,
+  timeout: Duration,
+  api_key: Option<String>,
+  socket: Option<Box<Socket>>,
+  console: VecDeque<ServerMessage>,
+  closed: bool,
+  /// Console socket bytes and frames exchanged.
+  traffic: Traffic,
+}
+
+/// Panel URL and server identifier parsed from the `--host` value.
+#[derive(Debug, PartialEq, Eq)]
+struct Target {
+  secure: bool,
+  host: String,
+  server_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CredentialsEnvelope {
+  data: Credentials,
+}
+
+#[derive(Debug, Deserialize)]
+struct Credentials {
+  token: String,
+  socket: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Event {
+  event: String,
+  #[serde(default)]
+  args: Vec<Value>,
+}
+
+impl Event {
+  fn new(event: &str, arg: &str) -> Self {
+    Self {
+      event: event.to_string(),
+      args: vec![Value::String(arg.to_string())],
+    }
+  }
+
+  fn text(&self) -> String {
+    self
+      .args
+      .iter()
+      .map(|arg| match arg {
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+      })
+      .collect::<Vec<_>>()
+      .join(" ")
+  }
+}
+
+impl PterodactylClient {
+  pub(super) fn connect(
+    host: &str,
+    port: u16,
+    deadline: Duration,
+    options: &ConnectOptions,
+  ) -> Result<Self> {
+    let target = parse_target(host)?;
+    let tls_config =
+      tls::client_config(&options.tls.clone().unwrap_or_default())?;
+
+    let mut http = Client::builder()
+      .tls_backend_preconfigured((*tls_config).clone())
+      .timeout(deadline)
+      .connect_timeout(deadline)
+      .local_address(options.local_address());
+    if let Some(proxy) = &options.proxy {
+      http = http.proxy(proxy.to_reqwest()?);
+    }
+    if let Some(idle) = options.tcp_keepalive {
+      http = http.tcp_keepalive(idle);
+    }
+    let http =
+      options.pin_http_host(http, &target.host, target.panel_port(port));
+    let http = http.build().context("failed to build HTTP client")?;
+
+    Ok(Self {
+      http,
+      tls_config,
+      connect_options: Box::new(options.clone()),
+      panel_url: target.panel_url(port),
+      server_id: target.server_id,
+      timeout: deadline,
+      api_key: None,
+      socket: None,
+      console: VecDeque::new(),
+      closed: false,
+      traffic: Traffic::default(),
+    })
+  }
+
+  pub(super) fn is_closed(&self) -> bool {
+    self.closed
+  }
+
+  pub(super) fn set_timeout(&mut self, timeout: Duration) {
+    self.timeout = timeout;
+  }
+
+  pub(super) fn traffic(&self) -> &Traffic {
+    &self.traffic
+  }
+
+  pub(super) fn take_messages(&mut self) -> Vec<ServerMessage> {
+    self.console.drain(..).collect()
+  }
+
+  pub(super) async fn authenticate(
+    &mut self,
+    api_key: &str,
+  ) -> Result<AuthOutcome> {
+    self.api_key = Some(api_key.to_string());
+
+    let Some(credentials) = self.fetch_credentials().await? else {
+      self.api_key = None;
+      return Ok(AuthOutcome::Failure);
+    };
+
+    let mut request = credentials
+      .socket
+      .as_str()
+      .into_client_request()
+      .context("panel returned an invalid console socket URL")?;
+    // Wings only accepts console connections originating from the panel.
+    request.headers_mut().insert(
+      "Origin",
+      HeaderValue::from_str(&self.panel_url)
+        .context("panel URL is not a valid Origin header")?,
+    );
+
+    tracing::debug!("--> WebSocket handshake {}", credentials.socket);
+    let (socket, _response) =
+      await_timeout(self.timeout, self.open_socket(request))
+        .await
+        .map_err(|_| {
+          TimedOut::new("console websocket handshake", self.timeout)
+        })?
+        .context("console websocket handshake failed")?;
+    self.socket = Some(Box::new(socket));
+
+    self
+      .send_event(&Event::new("auth", &credentials.token))
+      .await?;
+
+    let deadline = Instant::now() + self.timeout;
+    loop {
+      let Some(event) = self
+        .read_event(Some(deadline))
+        .await
+        .context("waiting for the console to accept the token")?
+      else {
+        bail!("server closed the console socket during authentication");
+      };
+      match event.event.as_str() {
+        "auth success" => return Ok(AuthOutcome::Success),
+        "jwt error" => {
+          bail!("console rejected the panel token: {}", event.text())
+        }
+        _ => self.handle_event(event).await?,
+      }
+    }
+  }
+
+  pub(super) async fn send_command(
+    &mut self,
+    command: &str,
+  ) -> Result<CommandOutcome> {
+    if self.closed {
+      bail!("connection already closed");
+    }
+
+    if command.trim().is_empty() {
+      bail!("command must not be empty");
+    }
+
+    tracing::debug!("--> {}", command);
+    self
+      .send_event(&Event::new("send command", command))
+      .await?;
+
+    let mut payload = Vec::new();
+    let mut deadline =
+      Instant::now() + self.timeout.min(FIRST_OUTPUT_WINDOW);
+
+    loop {
+      let event = match self.read_event(Some(deadline)).await {
+        Ok(Some(event)) => event,
+        Ok(None) => return Ok(CommandOutcome::Bye),
+        Err(err) if err.is::<tokio::time::error::Elapsed>() => break,
+        Err(err) => return Err(err),
+      };
+
+      if event.event != "console output" {
+        self.handle_event(event).await?;
+        continue;
+      }
+
+      let line = event.text();
+      if line.trim() == command.trim() {
+        continue;
+      }
+      payload.push(line);
+      deadline = Instant::now() + RESPONSE_IDLE_WINDOW;
+    }
+
+    Ok(CommandOutcome::Response(RconResponse {
+      status: ResponseStatus::Ok,
+      raw: join_lines(&payload),
+      payload,
+      error: None,
+    }))
+  }
+
+  /// Wait for the next console line or status change while no command is
+  /// in flight, refreshing the console token along the way.
+  pub(super) async fn recv_message(
+    &mut self,
+  ) -> Result<Option<ServerMessage>> {
+    loop {
+      if let Some(message) = self.console.pop_front() {
+        return Ok(Some(message));
+      }
+
+      let Some(event) = self.read_event(None).await? else {
+        return Ok(None);
+      };
+      self.handle_event(event).await?;
+    }
+  }
+
+  pub(super) async fn quit(&mut self) -> Result<()> {
+    self.closed = true;
+    let Some(mut socket) = self.socket.take() else {
+      return Ok(());
+    };
+
+    await_timeout(self.timeout, WebSocketStream::close(&mut socket, None))
+      .await
+      .map_err(|_| TimedOut::new("closing console socket", self.timeout))?
+      .context("closing console socket")?;
+
+    Ok(())
+  }
+
+  /// Route an event that is not part of a command reply.
+  async fn handle_event(&mut self, event: Event) -> Result<()> {
+    let kind = match event.event.as_str() {
+      "console output" => "Console",
+      "status" => "Status",
+      "daemon message" | "install output" => "Daemon",
+      "daemon error" => "Error",
+      "token expiring" | "token expired" => {
+        tracing::debug!(event = %event.event, "refreshing console token");
+        let credentials =
+          self.fetch_credentials().await?.ok_or_else(|| {
+            anyhow!(
+              "panel rejected the API key while refreshing the token"
+            )
+          })?;
+        return self
+          .send_event(&Event::new("auth", &credentials.token))
+          .await;
+      }
+      other => {
+        tracing::trace!(event = other, "ignoring console event");
+        return Ok(());
+      }
+    };
+
+    self.console.push_back(ServerMessage {
+      kind: Some(kind.to_string()),
+      text: event.text(),
+    });
+    Ok(())
+  }
+
+  /// Request console credentials, returning `None` if the API key is
+  /// rejected.
+  async fn fetch_credentials(&self) -> Result<Option<Credentials>> {
+    let api_key = self.api_key.as_deref().ok_or_else(|| {
+      anyhow!("server requires authentication before sending commands")
+    })?;
+
+    let url = format!(
+      "{}/api/client/servers/{}/websocket",
+      self.panel_url, self.server_id
+    );
+    tracing::debug!("--> GET {url} <redacted>");
+
+    let response = self
+      .http
+      .get(&url)
+      .bearer_auth(api_key)
+      .header("Accept", "application/json")
+      .send()
+      .await
+      .context("requesting console credentials from the panel")?;
+
+    match response.status() {
+      StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => return Ok(None),
+      StatusCode::NOT_FOUND => {
+        bail!("server `{}` was not found on the panel", self.server_id)
+      }
+      status if !status.is_success() => {
+        bail!("panel returned HTTP {status} for console credentials")
+      }
+      _ => {}
+    }
+
+    let envelope: CredentialsEnvelope = response
+      .json()
+      .await
+      .context("panel returned malformed console credentials")?;
+    Ok(Some(envelope.data))
+  }
+
+  /// Open the console websocket, through the configured proxy if any,
+  /// applying the address family, local address and TCP keepalive.
+  async fn open_socket(
+    &self,
+    request: Request,
+  ) -> Result<(Socket, Response)> {
+    let connector = Connector::Rustls(self.tls_config.clone());
+    let uri = request.uri();
+    let host = uri
+      .host()
+      .context("console socket URL has no host")?
+      .trim_start_matches('[')
+      .trim_end_matches(']')
+      .to_string();
+    let port = uri.port_u16().unwrap_or(match uri.scheme_str() {
+      Some("wss") => 443,
+      _ => 80,
+    });
+    let options = &self.connect_options;
+    let stream = match &options.proxy {
+      Some(proxy) => {
+        proxy.connect(&host, port, self.timeout, options).await?
+      }
+      None => {
+        stream::connect_tcp(&host, port, self.timeout, options).await?
+      }
+    };
+    let stream = Metered::new(stream, &self.traffic);
+    Ok(
+      client_async_tls_with_config(request, stream, None, Some(connector))
+        .await?,
+    )
+  }
+
+  async fn send_event(&mut self, event: &Event) -> Result<()> {
+    let frame = serde_json::to_string(event)
+      .context("failed to encode console event")?;
+    let timeout = self.timeout;
+    let socket = self.socket()?;
+
+    await_timeout(timeout, socket.send(Message::Text(frame.into())))
+      .await
+      .map_err(|_| {
+        TimedOut::new(format!("writing `{}` event", event.event), timeout)
+      })?
+      .with_context(|| format!("writing `{}` event", event.event))?;
+    self.traffic.message_sent();
+    Ok(())
+  }
+
+  /// Read the next console event, returning `None` once the socket closes.
+  /// Hitting `deadline` surfaces as a [`tokio::time::error::Elapsed`] error.
+  async fn read_event(
+    &mut self,
+    deadline: Option<Instant>,
+  ) -> Result<Option<Event>> {
+    loop {
+      let socket = self.socket()?;
+      let frame = match deadline {
+        Some(deadline) => timeout_at(deadline, socket.next()).await?,
+        None => socket.next().await,
+      };
+
+      if let Some(Ok(_)) = &frame {
+        self.traffic.message_received();
+      }
+      let text: Utf8Bytes = match frame {
+        Some(Ok(Message::Text(text))) => text,
+        Some(Ok(Message::Close(frame))) => {
+          tracing::debug!(?frame, "console socket closed by the daemon");
+          self.closed = true;
+          self.socket = None;
+          return Ok(None);
+        }
+        Some(Ok(other)) => {
+          tracing::trace!(?other, "ignoring non-text console frame");
+          continue;
+        }
+        Some(Err(err)) => {
+          self.closed = true;
+          self.socket = None;
+          return Err(err).context("reading console event");
+        }
+        None => {
+          self.closed = true;
+          self.socket = None;
+          return Ok(None);
+        }
+      };
+
+      let event: Event =
+        serde_json::from_str(&text).with_context(|| {
+          format!("received malformed console event: {text}")
+        })?;
+      tracing::trace!(event = %event.event, "received console event");
+      return Ok(Some(event));
+    }
+  }
+
+  fn socket(&mut self) -> Result<&mut Socket> {
+    if self.closed {
+      bail!("connection already closed");
+    }
+    self.socket.as_deref_mut().ok_or_else(|| {
+      anyhow!("server requires authentication before sending commands")
+    })
+  }
+}
+
+impl Target {
+  /// Base URL of the panel, omitting the port when it is the scheme's
+  /// default so it doubles as the websocket `Origin`.
+  /// Port the panel is reached on.
+  fn panel_port(&self, port: u16) -> u16 {
+    // The protocol default (443) stands in for "unset" on plain-HTTP panels.
+    if !self.secure && port == 443 {
+      80
+    } else {
+      port
+    }
+  }
+
+  fn panel_url(&self, port: u16) -> String {
+    let (scheme, default_port) = if self.secure {
+      ("https", 443)
+    } else {
+      ("http", 80)
+    };
+    let port = self.panel_port(port);
+
+    if port == default_port {
+      format!("{scheme}://{}", self.host)
+    } else {
+      format!("{scheme}://{}:{port}", self.host)
+    }
+  }
+}
+
+/// Parse `panel.example.com/<server>` or a server URL copied from the panel
+/// (`https://panel.example.com/server/<server>`).
+fn parse_target(raw: &str) -> Result<Target> {
+  let raw = raw.trim();
+  let (secure, rest) = if let Some(rest) = raw.strip_prefix("https://") {
+    (true, rest)
+  } else if let Some(rest) = raw.strip_prefix("http://") {
+    (false, rest)
+  } else {
+    (true, raw)
+  };
+
+  let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+  let server_id = path
+    .split('/')
+    .rfind(|segment| !segment.is_empty())
+    .ok_or_else(|| {
+      anyhow!(
+        "pterodactyl targets must include the server identifier, \
+         e.g. panel.example.com/1a2b3c4d"
+      )
+    })?;
+
+  if host.is_empty() {
+    bail!("pterodactyl target `{raw}` is missing the panel host");
+  }
+
+  Ok(Target {
+    secure,
+    host: host.to_string(),
+    server_id: server_id.to_string(),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_short_and_copied_targets() {
+    assert_eq!(
+      parse_target("panel.example.com/1a2b3c4d").expect("short"),
+      Target {
+        secure: true,
+        host: "panel.example.com".to_string(),
+        server_id: "1a2b3c4d".to_string(),
+      }
+    );
+
+    let copied = parse_target("http://10.0.0.5/server/1a2b3c4d/")
+      .expect("copied URL");
+    assert!(!copied.secure);
+    assert_eq!(copied.server_id, "1a2b3c4d");
+    assert_eq!(copied.panel_url(443), "http://10.0.0.5");
+    assert_eq!(copied.panel_url(8080), "http://10.0.0.5:8080");
+
+    assert!(parse_target("panel.example.com").is_err());
+    assert!(parse_target("/1a2b3c4d").is_err());
+  }
+
+  #[test]
+  fn events_join_arguments_as_text() {
+    let event: Event = serde_json::from_str(
+      r#"{"event":"console output","args":["[12:00:00] Done"]}"#,
+    )
+    .expect("decode event");
+    assert_eq!(event.text(), "[12:00:00] Done");
+
+    let event: Event =
+      serde_json::from_str(r#"{"event":"auth success"}"#).expect("decode");
+    assert!(event.args.is_empty());
+  }
+
+  #[tokio::test]
+  #[allow(clippy::result_large_err)]
+  async fn authenticates_and_collects_console_output() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::accept_hdr_async;
+    use tokio_tungstenite::tungstenite::handshake::server::{
+      Request, Response,
+    };
+
+    let panel = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let panel_port = panel.local_addr().expect("addr").port();
+    let wings = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let wings_port = wings.local_addr().expect("addr").port();
+
+    let panel_task = tokio::spawn(async move {
+      let (mut stream, _) = panel.accept().await.expect("accept");
+      let mut request = Vec::new();
+      while !request.ends_with(b"\r\n\r\n") {
+        let mut byte = [0_u8; 1];
+        stream.read_exact(&mut byte).await.expect("read request");
+        request.push(byte[0]);
+      }
+      let request = String::from_utf8_lossy(&request).to_ascii_lowercase();
+      assert!(request.starts_with(
+        "get /api/client/servers/1a2b3c4d/websocket http/1.1"
+      ));
+      assert!(request.contains("authorization: bearer ptlc_key"));
+
+      let body = format!(
+        r#"{{"data":{{"token":"jwt","socket":"ws://127.0.0.1:{wings_port}/ws"}}}}"#
+      );
+      let response = format!(
+        "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\n\
+         content-length: {}\r\n\r\n{body}",
+        body.len()
+      );
+      stream
+        .write_all(response.as_bytes())
+        .await
+        .expect("respond");
+    });
+
+    let wings_task = tokio::spawn(async move {
+      let (stream, _) = wings.accept().await.expect("accept");
+      let mut socket = accept_hdr_async(
+        stream,
+        |request: &Request, response: Response| {
+          assert_eq!(
+            request.headers()["origin"],
+            format!("http://127.0.0.1:{panel_port}").as_str()
+          );
+          Ok(response)
+        },
+      )
+      .await
+      .expect("handshake");
+
+      let send = |event: &str, arg: &str| {
+        Message::Text(
+          serde_json::to_string(&Event::new(event, arg))
+            .expect("encode")
+            .into(),
+        )
+      };
+
+      let auth = socket.next().await.expect("frame").expect("auth");
+      let auth: Event =
+        serde_json::from_str(auth.to_text().expect("text")).expect("json");
+      assert_eq!(
+        (auth.event.as_str(), auth.text()),
+        ("auth", "jwt".into())
+      );
+      socket.send(send("auth success", "")).await.expect("send");
+
+      let command = socket.next().await.expect("frame").expect("command");
+      let command: Event =
+        serde_json::from_str(command.to_text().expect("text"))
+          .expect("json");
+      assert_eq!(command.event, "send command");
+      assert_eq!(command.text(), "list");
+
+      socket.send(send("status", "running")).await.expect("send");
+      socket
+        .send(send("console output", "There are 2 players online"))
+        .await
+        .expect("send");
+      socket
+        .send(send("console output", "alice, bob"))
+        .await
+        .expect("send");
+      socket.next().await;
+    });
+
+    let mut client = PterodactylClient::connect(
+      "http://127.0.0.1/server/1a2b3c4d",
+      panel_port,
+      Duration::from_secs(2),
+      &ConnectOptions::default(),
+    )
+    .expect("connect");
+
+    assert!(matches!(
+      client.authenticate("ptlc_key").await.expect("auth"),
+      AuthOutcome::Success
+    ));
+
+    let CommandOutcome::Response(response) =
+      client.send_command("list").await.expect("command")
+    else {
+      panic!("unexpected bye");
+    };
+    assert_eq!(
+      response.payload,
+      vec!["There are 2 players online", "alice, bob"]
+    );
+
+    let messages = client.take_messages();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].kind.as_deref(), Some("Status"));
+    assert_eq!(messages[0].text, "running");
+
+    client.quit().await.expect("quit");
+    panel_task.await.expect("panel");
+    wings_task.await.expect("wings");
+  }
+}
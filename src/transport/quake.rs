@@ -0,0 +1,267 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow, bail};
+use tokio::net::UdpSocket;
+use tokio::time::timeout as await_timeout;
+
+use super::stats::Traffic;
+use super::{
+  AuthOutcome, CommandOutcome, ConnectOptions, MAX_UDP_DATAGRAM,
+  RconResponse, ResponseStatus, connect_udp, split_lines, with_timeout,
+};
+
+/// Connectionless packet header shared by Quake-derived engines.
+const OOB_HEADER: &[u8] = b"\xff\xff\xff\xff";
+
+/// How long to keep collecting fragments after the last packet arrived.
+///
+/// The protocol has no end-of-response marker, so long outputs (e.g.
+/// `status` on a busy server) are reassembled until the socket goes quiet.
+const RESPONSE_IDLE_WINDOW: Duration = Duration::from_millis(250);
+
+/// Upper bound on how long to wait for a GoldSrc challenge before assuming
+/// a Quake III style server that does not use challenges.
+const CHALLENGE_PROBE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Connectionless UDP rcon client for Quake III and GoldSrc servers.
+///
+/// There is no session on the wire: the password travels with every
+/// command, and GoldSrc servers additionally require a challenge number
+/// obtained during `authenticate`.
+#[derive(Debug)]
+pub(super) struct QuakeClient {
+  socket: UdpSocket,
+  timeout: Duration,
+  password: Option<String>,
+  challenge: Option<String>,
+  closed: bool,
+  /// Bytes and datagrams exchanged.
+  traffic: Traffic,
+}
+
+impl QuakeClient {
+  pub(super) async fn connect(
+    host: &str,
+    port: u16,
+    deadline: Duration,
+    options: &ConnectOptions,
+  ) -> Result<Self> {
+    let socket = connect_udp(host, port, deadline, options).await?;
+
+    Ok(Self {
+      socket,
+      timeout: deadline,
+      password: None,
+      challenge: None,
+      closed: false,
+      traffic: Traffic::default(),
+    })
+  }
+
+  pub(super) fn is_closed(&self) -> bool {
+    self.closed
+  }
+
+  pub(super) fn set_timeout(&mut self, timeout: Duration) {
+    self.timeout = timeout;
+  }
+
+  pub(super) fn traffic(&self) -> &Traffic {
+    &self.traffic
+  }
+
+  pub(super) async fn authenticate(
+    &mut self,
+    password: &str,
+  ) -> Result<AuthOutcome> {
+    if password.contains(['\r', '\n', '"']) {
+      bail!("password must not contain newlines or double quotes");
+    }
+
+    self.password = Some(password.to_string());
+    self.challenge = self.request_challenge().await?;
+
+    // The protocol has no login step; a wrong password is only reported
+    // in the reply to the first command.
+    Ok(AuthOutcome::Success)
+  }
+
+  pub(super) async fn send_command(
+    &mut self,
+    command: &str,
+  ) -> Result<CommandOutcome> {
+    if self.closed {
+      bail!("connection already closed");
+    }
+
+    if command.trim().is_empty() {
+      bail!("command must not be empty");
+    }
+
+    if command.contains(['\r', '\n']) {
+      bail!("command must not contain newline characters");
+    }
+
+    let mut text = self.exchange(command).await?;
+    if text.starts_with("Bad challenge") {
+      tracing::debug!("challenge expired; requesting a new one");
+      self.challenge = self.request_challenge().await?;
+      text = self.exchange(command).await?;
+    }
+
+    let trimmed = text.trim_end();
+    if trimmed.starts_with("Bad rcon_password")
+      || trimmed.starts_with("Bad rconpassword")
+      || trimmed.starts_with("No rconpassword set")
+    {
+      return Ok(CommandOutcome::Response(RconResponse {
+        status: ResponseStatus::Err,
+        payload: Vec::new(),
+        error: Some(trimmed.to_string()),
+        raw: text,
+      }));
+    }
+
+    Ok(CommandOutcome::Response(RconResponse {
+      status: ResponseStatus::Ok,
+      payload: split_lines(&text),
+      error: None,
+      raw: text,
+    }))
+  }
+
+  pub(super) async fn quit(&mut self) -> Result<()> {
+    self.closed = true;
+    Ok(())
+  }
+
+  async fn exchange(&mut self, command: &str) -> Result<String> {
+    let password = self.password.as_deref().ok_or_else(|| {
+      anyhow!("server requires authentication before sending commands")
+    })?;
+
+    // Replies to a cancelled command may still be waiting.
+    // The rest of a datagram too long for `stale` is dropped with it.
+    let mut stale = [0_u8; 1];
+    while self.socket.try_recv(&mut stale).is_ok() {
+      tracing::debug!("discarding stale rcon datagram");
+    }
+
+    tracing::debug!("--> {}", command);
+    let line = match &self.challenge {
+      Some(challenge) => {
+        format!("rcon {challenge} \"{password}\" {command}\n")
+      }
+      None => format!("rcon {password} {command}"),
+    };
+    self.send_oob(line.as_bytes()).await?;
+
+    let mut text =
+      self.recv_oob(self.timeout).await?.ok_or_else(|| {
+        anyhow!("no rcon response within {} ms", self.timeout.as_millis())
+      })?;
+
+    while let Some(fragment) = self.recv_oob(RESPONSE_IDLE_WINDOW).await? {
+      text.push_str(&fragment);
+    }
+
+    Ok(text)
+  }
+
+  /// Ask for a GoldSrc challenge number, returning `None` for servers that
+  /// do not implement challenges (Quake III and derivatives).
+  async fn request_challenge(&mut self) -> Result<Option<String>> {
+    self.send_oob(b"challenge rcon\n").await?;
+
+    let window = self.timeout.min(CHALLENGE_PROBE_WINDOW);
+    let Some(reply) = self.recv_oob(window).await? else {
+      tracing::debug!("no challenge reply; assuming Quake III dialect");
+      return Ok(None);
+    };
+
+    Ok(parse_challenge(&reply))
+  }
+
+  async fn send_oob(&self, body: &[u8]) -> Result<()> {
+    let mut datagram = Vec::with_capacity(OOB_HEADER.len() + body.len());
+    datagram.extend_from_slice(OOB_HEADER);
+    datagram.extend_from_slice(body);
+
+    with_timeout(
+      self.timeout,
+      self.socket.send(&datagram),
+      "writing rcon datagram",
+    )
+    .await?;
+    self.traffic.sent(datagram.len());
+
+    Ok(())
+  }
+
+  /// Receive one datagram and decode its text, or `None` if nothing
+  /// arrives within `window`.
+  async fn recv_oob(&self, window: Duration) -> Result<Option<String>> {
+    let mut buffer = vec![0_u8; MAX_UDP_DATAGRAM];
+
+    let Ok(received) =
+      await_timeout(window, self.socket.recv(&mut buffer)).await
+    else {
+      return Ok(None);
+    };
+    let len = received.context("reading rcon datagram")?;
+    self.traffic.received(len);
+
+    tracing::trace!(payload_len = len, "received rcon datagram");
+    decode_datagram(&buffer[..len]).map(Some)
+  }
+}
+
+/// Strip the connectionless header and the engine-specific print marker.
+fn decode_datagram(datagram: &[u8]) -> Result<String> {
+  let body = datagram.strip_prefix(OOB_HEADER).ok_or_else(|| {
+    anyhow!("protocol violation: datagram missing 0xFFFFFFFF header")
+  })?;
+
+  let body = body
+    .strip_prefix(b"print\n")
+    .or_else(|| body.strip_prefix(b"l"))
+    .unwrap_or(body);
+  let body = body.strip_suffix(b"\0").unwrap_or(body);
+
+  Ok(String::from_utf8_lossy(body).into_owned())
+}
+
+fn parse_challenge(reply: &str) -> Option<String> {
+  reply
+    .trim_end()
+    .strip_prefix("challenge rcon ")
+    .map(|challenge| challenge.trim().to_string())
+    .filter(|challenge| !challenge.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn decode_strips_header_and_print_markers() {
+    let quake3 = decode_datagram(b"\xff\xff\xff\xffprint\nmap: q3dm17\n")
+      .expect("decode quake3");
+    assert_eq!(quake3, "map: q3dm17\n");
+
+    let goldsrc = decode_datagram(b"\xff\xff\xff\xfflhostname: hlds\n\0")
+      .expect("decode goldsrc");
+    assert_eq!(goldsrc, "hostname: hlds\n");
+
+    assert!(decode_datagram(b"print\nno header").is_err());
+  }
+
+  #[test]
+  fn parse_challenge_extracts_number() {
+    assert_eq!(
+      parse_challenge("challenge rcon 123456789\n"),
+      Some("123456789".to_string())
+    );
+    assert_eq!(parse_challenge("print\nunknown command"), None);
+  }
+}
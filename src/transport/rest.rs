@@ -0,0 +1,355 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use reqwest::{Client, Method, StatusCode};
+use serde_json::{Value, json};
+
+use super::stats::Traffic;
+use super::{
+  AuthOutcome, CommandOutcome, ConnectOptions, RconResponse,
+  ResponseStatus, split_lines,
+};
+
+/// Username expected by Palworld-style admin APIs (HTTP basic auth).
+const ADMIN_USER: &str = "admin";
+
+/// Path prefix of the Palworld REST API.
+const API_PREFIX: &str = "/v1/api";
+
+/// HTTP REST admin API client (Palworld and compatible servers).
+///
+/// There is no persistent session: every command is translated into a
+/// single HTTP request authenticated with basic auth. Well-known console
+/// commands map onto the documented endpoints, and `GET`/`POST <path>`
+/// reach arbitrary endpoints on other REST servers.
+#[derive(Debug)]
+pub(super) struct RestClient {
+  http: Client,
+  base_url: String,
+  /// Limit for each request, which may change after connecting.
+  timeout: Duration,
+  password: Option<String>,
+  closed: bool,
+  /// Body bytes and HTTP requests exchanged.
+  traffic: Traffic,
+}
+
+/// HTTP request derived from a console command line.
+#[derive(Debug, PartialEq)]
+struct RestCall {
+  method: Method,
+  path: String,
+  body: Option<Value>,
+}
+
+impl RestClient {
+  pub(super) fn connect(
+    host: &str,
+    port: u16,
+    deadline: Duration,
+    options: &ConnectOptions,
+  ) -> Result<Self> {
+    let mut http = Client::builder()
+      .timeout(deadline)
+      .connect_timeout(deadline)
+      .local_address(options.local_address());
+    if let Some(proxy) = &options.proxy {
+      http = http.proxy(proxy.to_reqwest()?);
+    }
+    if let Some(idle) = options.tcp_keepalive {
+      http = http.tcp_keepalive(idle);
+    }
+    let http = options.pin_http_host(http, host, port);
+    let http = http.build().context("failed to build HTTP client")?;
+
+    let host = if host.contains(':') {
+      format!("[{host}]")
+    } else {
+      host.to_string()
+    };
+
+    Ok(Self {
+      http,
+      base_url: format!("http://{host}:{port}"),
+      timeout: deadline,
+      password: None,
+      closed: false,
+      traffic: Traffic::default(),
+    })
+  }
+
+  pub(super) fn is_closed(&self) -> bool {
+    self.closed
+  }
+
+  pub(super) fn set_timeout(&mut self, timeout: Duration) {
+    self.timeout = timeout;
+  }
+
+  pub(super) fn traffic(&self) -> &Traffic {
+    &self.traffic
+  }
+
+  pub(super) async fn authenticate(
+    &mut self,
+    password: &str,
+  ) -> Result<AuthOutcome> {
+    self.password = Some(password.to_string());
+
+    tracing::debug!("--> GET {API_PREFIX}/info <redacted>");
+    let call = RestCall {
+      method: Method::GET,
+      path: format!("{API_PREFIX}/info"),
+      body: None,
+    };
+    let (status, _) = self.execute(&call).await?;
+
+    if status == StatusCode::UNAUTHORIZED
+      || status == StatusCode::FORBIDDEN
+    {
+      self.password = None;
+      return Ok(AuthOutcome::Failure);
+    }
+
+    Ok(AuthOutcome::Success)
+  }
+
+  pub(super) async fn send_command(
+    &mut self,
+    command: &str,
+  ) -> Result<CommandOutcome> {
+    if self.closed {
+      bail!("connection already closed");
+    }
+
+    if command.trim().is_empty() {
+      bail!("command must not be empty");
+    }
+
+    let call = match parse_command(command) {
+      Ok(call) => call,
+      Err(message) => {
+        return Ok(CommandOutcome::Response(RconResponse {
+          status: ResponseStatus::Err,
+          payload: Vec::new(),
+          error: Some(message),
+          raw: String::new(),
+        }));
+      }
+    };
+
+    tracing::debug!("--> {} {}", call.method, call.path);
+    let (status, body) = self.execute(&call).await?;
+    let raw = render_body(&body);
+    let payload = split_lines(&raw);
+
+    if !status.is_success() {
+      return Ok(CommandOutcome::Response(RconResponse {
+        status: ResponseStatus::Err,
+        payload,
+        error: Some(format!("HTTP {status}")),
+        raw,
+      }));
+    }
+
+    Ok(CommandOutcome::Response(RconResponse {
+      status: ResponseStatus::Ok,
+      payload,
+      error: None,
+      raw,
+    }))
+  }
+
+  pub(super) async fn quit(&mut self) -> Result<()> {
+    self.closed = true;
+    Ok(())
+  }
+
+  async fn execute(
+    &self,
+    call: &RestCall,
+  ) -> Result<(StatusCode, String)> {
+    let url = format!("{}{}", self.base_url, call.path);
+    let mut request = self
+      .http
+      .request(call.method.clone(), &url)
+      .timeout(self.timeout);
+    if let Some(password) = &self.password {
+      request = request.basic_auth(ADMIN_USER, Some(password));
+    }
+    let mut sent = 0;
+    if let Some(body) = &call.body {
+      sent = body.to_string().len();
+      request = request.json(body);
+    }
+
+    let response = request
+      .send()
+      .await
+      .with_context(|| format!("{} {} failed", call.method, call.path))?;
+    self.traffic.sent(sent);
+    let status = response.status();
+    let body = response
+      .text()
+      .await
+      .with_context(|| format!("reading {} response body", call.path))?;
+    self.traffic.received(body.len());
+
+    tracing::trace!(
+      http_status = status.as_u16(),
+      payload_len = body.len(),
+      "received REST response"
+    );
+
+    Ok((status, body))
+  }
+}
+
+/// Translate a console command into a REST call, or explain why it cannot
+/// be mapped.
+fn parse_command(command: &str) -> Result<RestCall, String> {
+  let command = command.trim();
+  let (verb, rest) = command
+    .split_once(char::is_whitespace)
+    .map_or((command, ""), |(verb, rest)| (verb, rest.trim()));
+  let (first, remainder) = rest
+    .split_once(char::is_whitespace)
+    .map_or((rest, ""), |(first, remainder)| (first, remainder.trim()));
+
+  let api =
+    |method: Method, endpoint: &str, body: Option<Value>| RestCall {
+      method,
+      path: format!("{API_PREFIX}/{endpoint}"),
+      body,
+    };
+
+  let call = match verb.to_ascii_lowercase().as_str() {
+    "info" => api(Method::GET, "info", None),
+    "players" | "showplayers" => api(Method::GET, "players", None),
+    "settings" => api(Method::GET, "settings", None),
+    "metrics" => api(Method::GET, "metrics", None),
+    "save" => api(Method::POST, "save", Some(json!({}))),
+    "stop" | "doexit" => api(Method::POST, "stop", Some(json!({}))),
+    "announce" | "broadcast" | "say" => {
+      if rest.is_empty() {
+        return Err(format!("usage: {verb} <message>"));
+      }
+      api(Method::POST, "announce", Some(json!({ "message": rest })))
+    }
+    "kick" | "ban" => {
+      if first.is_empty() {
+        return Err(format!("usage: {verb} <userid> [message]"));
+      }
+      let mut body = json!({ "userid": first });
+      if !remainder.is_empty() {
+        body["message"] = json!(remainder);
+      }
+      api(Method::POST, &verb.to_ascii_lowercase(), Some(body))
+    }
+    "unban" => {
+      if first.is_empty() {
+        return Err("usage: unban <userid>".to_string());
+      }
+      api(Method::POST, "unban", Some(json!({ "userid": first })))
+    }
+    "shutdown" => {
+      let Ok(waittime) = first.parse::<u64>() else {
+        return Err("usage: shutdown <seconds> [message]".to_string());
+      };
+      let mut body = json!({ "waittime": waittime });
+      if !remainder.is_empty() {
+        body["message"] = json!(remainder);
+      }
+      api(Method::POST, "shutdown", Some(body))
+    }
+    "get" | "post" | "put" | "delete" => {
+      if !first.starts_with('/') {
+        return Err(format!("usage: {verb} /<path> [json body]"));
+      }
+      let body = if remainder.is_empty() {
+        None
+      } else {
+        Some(
+          serde_json::from_str(remainder)
+            .map_err(|err| format!("invalid JSON body: {err}"))?,
+        )
+      };
+      let method =
+        Method::from_bytes(verb.to_ascii_uppercase().as_bytes())
+          .map_err(|err| err.to_string())?;
+      RestCall {
+        method,
+        path: first.to_string(),
+        body,
+      }
+    }
+    _ => {
+      return Err(format!(
+        "unknown REST command `{verb}`; use info, players, settings, \
+         metrics, announce, kick, ban, unban, save, shutdown, stop or \
+         GET/POST <path>"
+      ));
+    }
+  };
+
+  Ok(call)
+}
+
+/// Pretty-print JSON bodies; anything else is passed through untouched.
+fn render_body(body: &str) -> String {
+  serde_json::from_str::<Value>(body)
+    .ok()
+    .and_then(|value| serde_json::to_string_pretty(&value).ok())
+    .unwrap_or_else(|| body.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn maps_console_commands_to_endpoints() {
+    assert_eq!(
+      parse_command("players").expect("players"),
+      RestCall {
+        method: Method::GET,
+        path: "/v1/api/players".to_string(),
+        body: None,
+      }
+    );
+    assert_eq!(
+      parse_command("kick steam_123 be nice").expect("kick"),
+      RestCall {
+        method: Method::POST,
+        path: "/v1/api/kick".to_string(),
+        body: Some(json!({ "userid": "steam_123", "message": "be nice" })),
+      }
+    );
+    assert_eq!(
+      parse_command("shutdown 30 restarting")
+        .expect("shutdown")
+        .body,
+      Some(json!({ "waittime": 30, "message": "restarting" }))
+    );
+  }
+
+  #[test]
+  fn raw_requests_and_usage_errors() {
+    let call =
+      parse_command(r#"POST /api/broadcast {"text":"hi"}"#).expect("post");
+    assert_eq!(call.method, Method::POST);
+    assert_eq!(call.path, "/api/broadcast");
+    assert_eq!(call.body, Some(json!({ "text": "hi" })));
+
+    assert!(parse_command("kick").is_err());
+    assert!(parse_command("shutdown soon").is_err());
+    assert!(parse_command("GET players").is_err());
+    assert!(parse_command("teleport").is_err());
+  }
+
+  #[test]
+  fn render_body_pretty_prints_json() {
+    assert_eq!(render_body(r#"{"a":1}"#), "{\n  \"a\": 1\n}");
+    assert_eq!(render_body("plain text"), "plain text");
+  }
+}
@@ -0,0 +1,1226 @@
+//! Source RCON backend.
+//!
+//! Sessions are driven through [`RconClient`](crate::RconClient); for
+//! server-specific extensions, such as custom packet kinds,
+//! [`RconClient::source_packets`](crate::RconClient::source_packets)
+//! reads and writes raw [`Packet`]s with the session's framing, timeout
+//! and logging:
+//!
+//! ```no_run
+//! use hyrcon_client::RconClient;
+//! use hyrcon_client::transport::source::{Packet, SERVERDATA_EXECCOMMAND};
+//!
+//! # async fn example(client: &mut RconClient) -> anyhow::Result<()> {
+//! if let Some(mut packets) = client.source_packets() {
+//!   let id = packets.next_id();
+//!   packets
+//!     .write_packet(&Packet::new(id, SERVERDATA_EXECCOMMAND, "status"))
+//!     .await?;
+//!   let reply = packets.read_packet().await?;
+//!   println!("{}", reply.payload);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow, bail};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter};
+use tokio::time::{Instant, timeout as await_timeout};
+
+use super::stats::Traffic;
+use super::stream::{self, StreamReader, StreamWriter};
+use super::{
+  AuthOutcome, CommandOutcome, ConnectOptions, RconResponse,
+  ResponseStatus, TimedOut, split_lines, with_timeout,
+};
+use crate::codec::DecodeError;
+use crate::codec::source::{self as codec, PACKET_OVERHEAD};
+pub use crate::codec::source::{
+  SERVERDATA_AUTH, SERVERDATA_AUTH_RESPONSE, SERVERDATA_EXECCOMMAND,
+  SERVERDATA_RESPONSE_VALUE,
+};
+use crate::error::Error;
+use crate::protocol::Dialect;
+
+#[derive(Debug)]
+pub(super) struct SourceClient {
+  reader: BufReader<StreamReader>,
+  writer: BufWriter<StreamWriter>,
+  timeout: Duration,
+  authed: bool,
+  next_request_id: i32,
+  sentinel: SentinelSupport,
+  idle_window: Duration,
+  max_packet_size: Option<u32>,
+  /// Set once the server has answered a keepalive, after which a missing
+  /// answer means the connection is gone.
+  keepalive_answered: bool,
+  closed: bool,
+  /// Bytes of a packet that has not fully arrived yet, kept so a read
+  /// that was cancelled resumes without losing its place in the stream.
+  read_buffer: Vec<u8>,
+  /// Bytes and packets exchanged.
+  traffic: Traffic,
+}
+
+/// Whether the server answers the empty sentinel command used to delimit
+/// multi-packet responses. Some servers (notably older Minecraft builds)
+/// silently drop it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum SentinelSupport {
+  Unknown,
+  Echoed,
+  Missing,
+}
+
+/// How long to wait for the sentinel reply after response data arrived
+/// before concluding the server never answers it.
+pub(super) const SENTINEL_GRACE_WINDOW: Duration =
+  Duration::from_millis(500);
+
+/// Quiet period that ends a response once sentinels are known to be
+/// unsupported.
+pub(super) const RESPONSE_IDLE_WINDOW: Duration =
+  Duration::from_millis(250);
+
+/// Factorio streams large outputs (e.g. `/help`) with noticeable gaps
+/// between packets, so it gets a longer quiet period.
+const FACTORIO_IDLE_WINDOW: Duration = Duration::from_millis(750);
+
+/// Servers split long responses into packets of roughly 4 KiB; a shorter
+/// packet is assumed to be the final fragment when no sentinel is in use.
+pub(super) const SPLIT_PAYLOAD_THRESHOLD: usize = 4000;
+
+/// Largest packet size field the Source RCON specification allows a
+/// client to send.
+const DEFAULT_MAX_PACKET_SIZE: u32 = 4096;
+
+impl SourceClient {
+  pub(super) async fn connect(
+    host: &str,
+    port: u16,
+    deadline: Duration,
+    options: &ConnectOptions,
+  ) -> Result<Self> {
+    let traffic = Traffic::default();
+    let (read_half, write_half) =
+      stream::connect(host, port, deadline, options, &traffic).await?;
+    Self::handshake(read_half, write_half, deadline, options, traffic)
+  }
+
+  /// Set up a session over a connected stream. Source RCON has no
+  /// greeting, so nothing is exchanged until authentication.
+  pub(super) fn handshake(
+    read_half: StreamReader,
+    write_half: StreamWriter,
+    deadline: Duration,
+    options: &ConnectOptions,
+    traffic: Traffic,
+  ) -> Result<Self> {
+    let max_packet_size = options.max_packet_size;
+    if let Some(size) = max_packet_size
+      && (size as usize) <= PACKET_OVERHEAD
+    {
+      bail!("max packet size must exceed {PACKET_OVERHEAD} bytes");
+    }
+
+    // Factorio may never answer the sentinel, so skip it from the start
+    // instead of paying the detection delay on the first command.
+    let (sentinel, idle_window) = match options.dialect {
+      Dialect::Standard => {
+        (SentinelSupport::Unknown, RESPONSE_IDLE_WINDOW)
+      }
+      Dialect::Factorio => {
+        (SentinelSupport::Missing, FACTORIO_IDLE_WINDOW)
+      }
+    };
+
+    Ok(Self {
+      reader: BufReader::new(read_half),
+      writer: BufWriter::new(write_half),
+      timeout: deadline,
+      authed: false,
+      next_request_id: 1,
+      sentinel,
+      idle_window,
+      max_packet_size,
+      keepalive_answered: false,
+      closed: false,
+      read_buffer: Vec::new(),
+      traffic,
+    })
+  }
+
+  pub(super) fn is_closed(&self) -> bool {
+    self.closed
+  }
+
+  pub(super) fn set_timeout(&mut self, timeout: Duration) {
+    self.timeout = timeout;
+  }
+
+  pub(super) fn traffic(&self) -> &Traffic {
+    &self.traffic
+  }
+
+  fn next_request_id(&mut self) -> i32 {
+    let id = self.next_request_id;
+    self.next_request_id = self.next_request_id.wrapping_add(1);
+    id
+  }
+
+  pub(super) async fn authenticate(
+    &mut self,
+    password: &str,
+  ) -> Result<AuthOutcome> {
+    if password.contains(['\r', '\n']) {
+      bail!("password must not contain newline characters");
+    }
+    if password.contains('\0') {
+      bail!("password must not contain NUL characters");
+    }
+
+    let auth_id = self.next_request_id();
+    self
+      .write_packet(
+        auth_id,
+        SERVERDATA_AUTH,
+        password,
+        Some("AUTH <redacted>"),
+      )
+      .await?;
+
+    let mut outcome = AuthOutcome::Failure;
+    loop {
+      let packet = self.read_packet().await?;
+      match packet.kind {
+        SERVERDATA_RESPONSE_VALUE => {
+          // Ignore intermediary response-value packet emitted by some servers.
+          continue;
+        }
+        SERVERDATA_AUTH_RESPONSE => {
+          if packet.id == auth_id {
+            self.authed = true;
+            outcome = AuthOutcome::Success;
+          } else if packet.id == -1 {
+            self.authed = false;
+            outcome = AuthOutcome::Failure;
+          } else {
+            tracing::debug!(
+              response_id = packet.id,
+              expected_id = auth_id,
+              "received unexpected AUTH response identifier"
+            );
+          }
+          break;
+        }
+        other => {
+          tracing::debug!(
+            packet_id = packet.id,
+            packet_kind = other,
+            "ignoring unexpected packet while authenticating"
+          );
+        }
+      }
+    }
+
+    Ok(outcome)
+  }
+
+  pub(super) async fn send_command(
+    &mut self,
+    command: &str,
+  ) -> Result<CommandOutcome> {
+    let chunks = self.chunks(command)?;
+    let mut body = String::new();
+    for chunk in chunks {
+      body.push_str(&self.exchange(chunk).await?);
+    }
+    Ok(response(body))
+  }
+
+  /// Send `commands` without waiting for each reply before the next, and
+  /// return their outcomes in order. Replies are told apart by request
+  /// id and the sentinel after each command, so servers that do not echo
+  /// the sentinel get the commands one at a time instead.
+  pub(super) async fn send_many(
+    &mut self,
+    commands: &[&str],
+  ) -> Result<Vec<CommandOutcome>> {
+    let mut outcomes = Vec::with_capacity(commands.len());
+    let mut rest = commands;
+    while self.sentinel != SentinelSupport::Echoed
+      && let Some((command, tail)) = rest.split_first()
+    {
+      outcomes.push(self.send_command(command).await?);
+      rest = tail;
+    }
+    if rest.is_empty() {
+      return Ok(outcomes);
+    }
+
+    /// What a request id in the reply stream belongs to.
+    enum Slot {
+      Reply(usize),
+      Sentinel,
+    }
+
+    let mut slots = HashMap::new();
+    for (index, command) in rest.iter().enumerate() {
+      for chunk in self.chunks(command)? {
+        let command_id = self.next_request_id();
+        tracing::debug!(request_id = command_id, "--> {}", chunk);
+        self
+          .write_packet(command_id, SERVERDATA_EXECCOMMAND, chunk, None)
+          .await?;
+        let sentinel_id = self.next_request_id();
+        self
+          .write_packet(
+            sentinel_id,
+            SERVERDATA_EXECCOMMAND,
+            "",
+            Some("<sentinel>"),
+          )
+          .await?;
+        slots.insert(command_id, Slot::Reply(index));
+        slots.insert(sentinel_id, Slot::Sentinel);
+      }
+    }
+
+    let mut bodies = vec![String::new(); rest.len()];
+    let mut pending = slots.len() / 2;
+    while pending > 0 {
+      let packet = self.read_packet().await?;
+      if packet.kind == SERVERDATA_AUTH_RESPONSE && packet.id == -1 {
+        self.authed = false;
+        bail!("server reported that authentication is no longer valid");
+      }
+      match slots.get(&packet.id) {
+        Some(Slot::Reply(index))
+          if packet.kind == SERVERDATA_RESPONSE_VALUE =>
+        {
+          bodies[*index].push_str(&packet.payload);
+        }
+        Some(Slot::Sentinel) => pending -= 1,
+        _ => tracing::debug!(
+          packet_id = packet.id,
+          packet_kind = packet.kind,
+          "ignoring non-matching packet while collecting responses"
+        ),
+      }
+    }
+
+    outcomes.extend(bodies.into_iter().map(response));
+    Ok(outcomes)
+  }
+
+  /// Check `command` and split it into packets within the size limit.
+  fn chunks<'a>(&self, command: &'a str) -> Result<Vec<&'a str>> {
+    if self.closed {
+      bail!("connection already closed");
+    }
+
+    if !self.authed {
+      bail!("server requires authentication before sending commands");
+    }
+
+    command_chunks(command, self.max_packet_size)
+  }
+
+  /// Send a single command packet and collect its response text, which
+  /// may span several packets.
+  async fn exchange(&mut self, command: &str) -> Result<String> {
+    let command_id = self.next_request_id();
+    tracing::debug!(request_id = command_id, "--> {}", command);
+    self
+      .write_packet(
+        command_id,
+        SERVERDATA_EXECCOMMAND,
+        command,
+        Some(command),
+      )
+      .await?;
+
+    // Sentinel packet to delimit the end of the response stream.
+    let sentinel_id = if self.sentinel == SentinelSupport::Missing {
+      None
+    } else {
+      let sentinel_id = self.next_request_id();
+      self
+        .write_packet(
+          sentinel_id,
+          SERVERDATA_EXECCOMMAND,
+          "",
+          Some("<sentinel>"),
+        )
+        .await?;
+      Some(sentinel_id)
+    };
+
+    let mut body = String::new();
+    let mut received_data = false;
+
+    loop {
+      if received_data {
+        let window = match self.sentinel {
+          SentinelSupport::Echoed => None,
+          SentinelSupport::Unknown => Some(SENTINEL_GRACE_WINDOW),
+          SentinelSupport::Missing => Some(self.idle_window),
+        };
+        if let Some(window) = window
+          && !self.wait_readable(window).await?
+        {
+          if self.sentinel == SentinelSupport::Unknown {
+            tracing::debug!(
+              "server did not answer the sentinel packet; \
+               falling back to idle-window reassembly"
+            );
+            self.sentinel = SentinelSupport::Missing;
+          }
+          break;
+        }
+      }
+
+      let packet = self.read_packet().await?;
+
+      if packet.kind == SERVERDATA_AUTH_RESPONSE && packet.id == -1 {
+        self.authed = false;
+        bail!("server reported that authentication is no longer valid");
+      }
+
+      if Some(packet.id) == sentinel_id {
+        if packet.kind != SERVERDATA_RESPONSE_VALUE {
+          bail!(
+            "server returned unexpected sentinel packet kind: {}",
+            packet.kind
+          );
+        }
+        if !packet.payload.is_empty() {
+          bail!("server returned data alongside sentinel response");
+        }
+        self.sentinel = SentinelSupport::Echoed;
+        break;
+      }
+
+      if packet.kind == SERVERDATA_RESPONSE_VALUE
+        && packet.id == command_id
+      {
+        body.push_str(&packet.payload);
+        received_data = true;
+        if self.sentinel == SentinelSupport::Missing
+          && packet.payload_len < SPLIT_PAYLOAD_THRESHOLD
+        {
+          break;
+        }
+        continue;
+      }
+
+      tracing::debug!(
+        packet_id = packet.id,
+        packet_kind = packet.kind,
+        "ignoring non-matching packet while collecting response"
+      );
+    }
+
+    Ok(body)
+  }
+
+  /// Probe an idle connection with an empty `SERVERDATA_RESPONSE_VALUE`
+  /// packet, which servers answer without running anything.
+  pub(super) async fn keepalive(&mut self) -> Result<()> {
+    if self.probe().await? {
+      self.keepalive_answered = true;
+    } else if self.keepalive_answered {
+      bail!("server stopped answering keepalives");
+    } else {
+      // Some servers ignore the probe entirely; that proves nothing.
+      tracing::debug!("server did not answer the keepalive packet");
+    }
+    Ok(())
+  }
+
+  /// Like [`keepalive`](Self::keepalive), but an unanswered probe is an
+  /// error since there is no round trip to measure.
+  pub(super) async fn ping(&mut self) -> Result<()> {
+    if !self.probe().await? {
+      bail!(
+        "server did not answer the empty RESPONSE_VALUE probe within {} ms",
+        self.timeout.as_millis()
+      );
+    }
+    self.keepalive_answered = true;
+    Ok(())
+  }
+
+  /// Send an empty `SERVERDATA_RESPONSE_VALUE` packet and report whether
+  /// the server echoed it before the timeout.
+  async fn probe(&mut self) -> Result<bool> {
+    if self.closed {
+      bail!("connection already closed");
+    }
+
+    let keepalive_id = self.next_request_id();
+    self
+      .write_packet(
+        keepalive_id,
+        SERVERDATA_RESPONSE_VALUE,
+        "",
+        Some("<keepalive>"),
+      )
+      .await?;
+
+    let deadline = Instant::now() + self.timeout;
+    loop {
+      let remaining = deadline.saturating_duration_since(Instant::now());
+      if !self.wait_readable(remaining).await? {
+        return Ok(false);
+      }
+
+      let packet = self.read_packet().await?;
+      if packet.id == keepalive_id {
+        return Ok(true);
+      }
+      tracing::debug!(
+        packet_id = packet.id,
+        packet_kind = packet.kind,
+        "ignoring non-matching packet while awaiting keepalive"
+      );
+    }
+  }
+
+  pub(super) async fn quit(&mut self) -> Result<()> {
+    if self.closed {
+      return Ok(());
+    }
+
+    self.closed = true;
+
+    with_timeout(
+      self.timeout,
+      self.writer.flush(),
+      "flushing buffered data before shutdown".to_string(),
+    )
+    .await?;
+
+    with_timeout(
+      self.timeout,
+      self.writer.shutdown(),
+      "shutting down Source RCON writer".to_string(),
+    )
+    .await?;
+
+    Ok(())
+  }
+
+  async fn write_packet(
+    &mut self,
+    id: i32,
+    kind: i32,
+    payload: &str,
+    log_repr: Option<&str>,
+  ) -> Result<()> {
+    let label = log_repr.unwrap_or(payload);
+    tracing::trace!(
+      request_id = id,
+      packet_kind = kind,
+      "writing packet {label}"
+    );
+
+    let packet = encode_packet(id, kind, payload)?;
+    with_timeout(
+      self.timeout,
+      self.writer.write_all(&packet),
+      format!("writing `{label}` packet to socket"),
+    )
+    .await?;
+
+    with_timeout(
+      self.timeout,
+      self.writer.flush(),
+      format!("flushing `{label}` packet to socket"),
+    )
+    .await?;
+    self.traffic.message_sent();
+
+    Ok(())
+  }
+
+  /// Wait up to `window` for more data to arrive without consuming it.
+  /// Returns `false` if the socket stayed quiet.
+  async fn wait_readable(&mut self, window: Duration) -> Result<bool> {
+    if !self.read_buffer.is_empty() {
+      return Ok(true);
+    }
+    match await_timeout(window, self.reader.fill_buf()).await {
+      Ok(filled) => {
+        filled.context("waiting for Source RCON response data")?;
+        Ok(true)
+      }
+      Err(_) => Ok(false),
+    }
+  }
+
+  async fn read_packet(&mut self) -> Result<Packet> {
+    let packet = await_timeout(self.timeout, self.next_packet())
+      .await
+      .map_err(|_| {
+      TimedOut::new("reading packet from Source RCON server", self.timeout)
+    })??;
+    self.traffic.message_received();
+    Ok(packet)
+  }
+
+  /// Cancel-safe: bytes received so far stay in `read_buffer` for the
+  /// next call.
+  async fn next_packet(&mut self) -> Result<Packet> {
+    loop {
+      match take_packet(&mut self.read_buffer, self.max_packet_size) {
+        Ok(Some(packet)) => return Ok(packet),
+        Ok(None) => {}
+        Err(err) => {
+          self.closed = is_out_of_sync(&err);
+          return Err(err);
+        }
+      }
+
+      let chunk = self
+        .reader
+        .fill_buf()
+        .await
+        .context("reading from Source RCON server")?;
+      if chunk.is_empty() {
+        self.closed = true;
+        return Err(Error::Closed.into());
+      }
+      let consumed = chunk.len();
+      self.read_buffer.extend_from_slice(chunk);
+      self.reader.consume(consumed);
+    }
+  }
+}
+
+/// Check `command` and split it into packets that fit in
+/// `max_packet_size` (default 4096 bytes).
+pub(super) fn command_chunks(
+  command: &str,
+  max_packet_size: Option<u32>,
+) -> Result<Vec<&str>> {
+  if command.trim().is_empty() {
+    bail!("command must not be empty");
+  }
+
+  if command.contains(['\r', '\n']) {
+    bail!("command must not contain newline characters");
+  }
+
+  if command.contains('\0') {
+    bail!("command must not contain NUL characters");
+  }
+
+  let limit = max_packet_size.unwrap_or(DEFAULT_MAX_PACKET_SIZE) as usize
+    - PACKET_OVERHEAD;
+  let chunks = split_command(command, limit)?;
+  if chunks.len() > 1 {
+    tracing::debug!(
+      chunks = chunks.len(),
+      "splitting command that exceeds the packet size limit"
+    );
+  }
+  Ok(chunks)
+}
+
+/// Frame a packet for the wire, size field and terminators included.
+pub(super) fn encode_packet(
+  id: i32,
+  kind: i32,
+  payload: &str,
+) -> Result<Vec<u8>> {
+  let mut packet = vec![0; codec::encoded_len(payload)];
+  codec::encode(id, kind, payload, &mut packet)?;
+  Ok(packet)
+}
+
+/// Take the next whole packet off the front of `buffer`, or `None`
+/// until all of it has arrived.
+pub(super) fn take_packet(
+  buffer: &mut Vec<u8>,
+  max_packet_size: Option<u32>,
+) -> Result<Option<Packet>> {
+  let decoded =
+    codec::decode(buffer, max_packet_size).map_err(|err| match err {
+      DecodeError::TooLarge { .. } => {
+        anyhow!("{err}; raise --max-packet-size")
+      }
+      err => err.into(),
+    })?;
+  let Some((frame, consumed)) = decoded else {
+    return Ok(None);
+  };
+  let packet = frame
+    .payload()
+    .map(|payload| Packet {
+      id: frame.id,
+      kind: frame.kind,
+      payload: payload.to_string(),
+      payload_len: frame.body.len(),
+    })
+    .context("received non-UTF8 data in Source RCON packet");
+  buffer.drain(..consumed);
+  let packet = packet?;
+
+  tracing::trace!(
+    packet_id = packet.id,
+    packet_kind = packet.kind,
+    payload_len = packet.payload_len,
+    "received Source RCON packet"
+  );
+  Ok(Some(packet))
+}
+
+/// Whether a [`take_packet`] error left the rest of a packet on the
+/// wire, so the stream can't be resynchronised.
+pub(super) fn is_out_of_sync(err: &anyhow::Error) -> bool {
+  !matches!(err.downcast_ref(), Some(DecodeError::NotUtf8))
+}
+
+/// A successful reply made of the packet payloads in `body`, split into
+/// lines only once joined, so a line cut across packets stays whole.
+pub(super) fn response(body: String) -> CommandOutcome {
+  CommandOutcome::Response(RconResponse {
+    status: ResponseStatus::Ok,
+    payload: split_lines(&body),
+    error: None,
+    raw: body,
+  })
+}
+
+/// Split `command` at `;` separators (outside double quotes) into chunks
+/// whose payload fits in `limit` bytes.
+fn split_command(command: &str, limit: usize) -> Result<Vec<&str>> {
+  if command.len() <= limit {
+    return Ok(vec![command]);
+  }
+
+  let mut segments = Vec::new();
+  let mut start = 0;
+  let mut quoted = false;
+  for (index, ch) in command.char_indices() {
+    match ch {
+      '"' => quoted = !quoted,
+      ';' if !quoted => {
+        segments.push((start, index));
+        start = index + 1;
+      }
+      _ => {}
+    }
+  }
+  segments.push((start, command.len()));
+
+  let mut chunks = Vec::new();
+  let mut chunk: Option<(usize, usize)> = None;
+  for (start, end) in segments {
+    if command[start..end].trim().is_empty() {
+      continue;
+    }
+    if end - start > limit {
+      bail!(
+        "command segment of {} bytes exceeds the max packet payload of \
+         {limit} bytes",
+        end - start
+      );
+    }
+    chunk = match chunk {
+      Some((chunk_start, _)) if end - chunk_start <= limit => {
+        Some((chunk_start, end))
+      }
+      Some((chunk_start, chunk_end)) => {
+        chunks.push(command[chunk_start..chunk_end].trim());
+        Some((start, end))
+      }
+      None => Some((start, end)),
+    };
+  }
+  if let Some((chunk_start, chunk_end)) = chunk {
+    chunks.push(command[chunk_start..chunk_end].trim());
+  }
+
+  Ok(chunks)
+}
+
+/// A Source RCON packet, without its size field and terminators.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Packet {
+  /// Request id, echoed by the server in its reply.
+  pub id: i32,
+  /// Packet type, such as [`SERVERDATA_EXECCOMMAND`].
+  pub kind: i32,
+  /// Body up to the first NUL.
+  pub payload: String,
+  /// Bytes of body received, including anything after a NUL.
+  pub(super) payload_len: usize,
+}
+
+impl Packet {
+  pub fn new(id: i32, kind: i32, payload: impl Into<String>) -> Self {
+    let payload = payload.into();
+    Self {
+      id,
+      kind,
+      payload_len: payload.len(),
+      payload,
+    }
+  }
+}
+
+/// Raw packet access to a Source session, from
+/// [`RconClient::source_packets`](crate::RconClient::source_packets).
+///
+/// The session's own commands rely on every reply being read, so read
+/// the answers to whatever you write before sending commands again.
+#[derive(Debug)]
+pub struct Packets<'a> {
+  client: &'a mut SourceClient,
+}
+
+impl<'a> Packets<'a> {
+  pub(super) fn new(client: &'a mut SourceClient) -> Self {
+    Self { client }
+  }
+
+  /// A request id the session has not used yet.
+  pub fn next_id(&mut self) -> i32 {
+    self.client.next_request_id()
+  }
+
+  /// Frame and send `packet`, within the session's timeout.
+  pub async fn write_packet(
+    &mut self,
+    packet: &Packet,
+  ) -> Result<(), Error> {
+    self
+      .client
+      .write_packet(packet.id, packet.kind, &packet.payload, None)
+      .await
+      .map_err(Error::from_internal)
+  }
+
+  /// Wait up to the session's timeout for the next packet.
+  pub async fn read_packet(&mut self) -> Result<Packet, Error> {
+    self
+      .client
+      .read_packet()
+      .await
+      .map_err(Error::from_internal)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tokio::io::AsyncReadExt;
+  use tokio::net::{TcpListener, TcpStream};
+
+  async fn read_request(stream: &mut TcpStream) -> (i32, String) {
+    let mut length = [0_u8; 4];
+    stream.read_exact(&mut length).await.expect("length");
+    let mut body = vec![0_u8; i32::from_le_bytes(length) as usize];
+    stream.read_exact(&mut body).await.expect("body");
+    let id = i32::from_le_bytes(body[0..4].try_into().expect("id"));
+    let text = String::from_utf8_lossy(&body[8..body.len() - 2]);
+    (id, text.into_owned())
+  }
+
+  fn response_packet(id: i32, text: &str) -> Vec<u8> {
+    let length = (10 + text.len()) as i32;
+    let mut packet = length.to_le_bytes().to_vec();
+    packet.extend_from_slice(&id.to_le_bytes());
+    packet.extend_from_slice(&SERVERDATA_RESPONSE_VALUE.to_le_bytes());
+    packet.extend_from_slice(text.as_bytes());
+    packet.extend_from_slice(&[0, 0]);
+    packet
+  }
+
+  async fn write_response(stream: &mut TcpStream, id: i32, text: &str) {
+    let packet = response_packet(id, text);
+    stream.write_all(&packet).await.expect("write response");
+  }
+
+  #[test]
+  fn split_command_packs_segments_within_limit() {
+    assert_eq!(split_command("status", 6).expect("fits"), vec!["status"]);
+    assert_eq!(
+      split_command("say a; say b; say \"c;d\"", 12).expect("split"),
+      vec!["say a; say b", "say \"c;d\""]
+    );
+    assert!(split_command("say hello world", 8).is_err());
+  }
+
+  #[tokio::test]
+  async fn rejects_packets_over_the_configured_limit() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let port = listener.local_addr().expect("addr").port();
+
+    let server = tokio::spawn(async move {
+      let (mut stream, _) = listener.accept().await.expect("accept");
+      let (id, _) = read_request(&mut stream).await;
+      write_response(&mut stream, id, &"x".repeat(2_000)).await;
+    });
+
+    let mut client = SourceClient::connect(
+      "127.0.0.1",
+      port,
+      Duration::from_secs(2),
+      &ConnectOptions {
+        dialect: Dialect::Factorio,
+        max_packet_size: Some(1_024),
+        ..ConnectOptions::default()
+      },
+    )
+    .await
+    .expect("connect");
+    client.authed = true;
+
+    let err = client.send_command("status").await.expect_err("too big");
+    assert!(err.to_string().contains("max packet size"), "{err}");
+    assert!(client.is_closed());
+
+    server.await.expect("server");
+  }
+
+  #[tokio::test]
+  async fn cancelled_commands_leave_the_stream_in_sync() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let port = listener.local_addr().expect("addr").port();
+
+    let server = tokio::spawn(async move {
+      let (mut stream, _) = listener.accept().await.expect("accept");
+      let (id, _) = read_request(&mut stream).await;
+      // The client gives up while only half the reply has arrived.
+      let packet = response_packet(id, "slow reply");
+      stream.write_all(&packet[..6]).await.expect("first half");
+      tokio::time::sleep(Duration::from_millis(150)).await;
+      stream.write_all(&packet[6..]).await.expect("second half");
+      let (id, body) = read_request(&mut stream).await;
+      assert_eq!(body, "list");
+      write_response(&mut stream, id, "fast reply").await;
+    });
+
+    let mut client = SourceClient::connect(
+      "127.0.0.1",
+      port,
+      Duration::from_secs(2),
+      &ConnectOptions {
+        dialect: Dialect::Factorio,
+        ..ConnectOptions::default()
+      },
+    )
+    .await
+    .expect("connect");
+    client.authed = true;
+
+    let cancelled = await_timeout(
+      Duration::from_millis(100),
+      client.send_command("save-all"),
+    )
+    .await;
+    assert!(cancelled.is_err());
+
+    let outcome = client.send_command("list").await.expect("list");
+    let CommandOutcome::Response(response) = outcome else {
+      panic!("unexpected outcome: {outcome:?}");
+    };
+    assert_eq!(response.payload, ["fast reply"]);
+    server.await.expect("server");
+  }
+
+  #[tokio::test]
+  async fn replies_keep_their_raw_text_across_packets() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let port = listener.local_addr().expect("addr").port();
+
+    let server = tokio::spawn(async move {
+      let (mut stream, _) = listener.accept().await.expect("accept");
+      let (id, body) = read_request(&mut stream).await;
+      assert_eq!(body, "status");
+      let (sentinel, _) = read_request(&mut stream).await;
+      write_response(&mut stream, id, "name \tping  \n\nSteve\t").await;
+      write_response(&mut stream, id, " 12  \n\n").await;
+      write_response(&mut stream, sentinel, "").await;
+    });
+
+    let mut client = SourceClient::connect(
+      "127.0.0.1",
+      port,
+      Duration::from_secs(2),
+      &ConnectOptions::default(),
+    )
+    .await
+    .expect("connect");
+    client.authed = true;
+
+    let outcome = client.send_command("status").await.expect("status");
+    let CommandOutcome::Response(response) = outcome else {
+      panic!("unexpected outcome: {outcome:?}");
+    };
+    assert_eq!(response.raw, "name \tping  \n\nSteve\t 12  \n\n");
+    assert_eq!(
+      response.payload,
+      ["name \tping  ", "", "Steve\t 12  ", ""]
+    );
+    server.await.expect("server");
+  }
+
+  #[tokio::test]
+  async fn keepalive_detects_closed_connection() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let port = listener.local_addr().expect("addr").port();
+
+    let server = tokio::spawn(async move {
+      let (mut stream, _) = listener.accept().await.expect("accept");
+      let (id, body) = read_request(&mut stream).await;
+      assert_eq!(body, "");
+      write_response(&mut stream, id, "").await;
+    });
+
+    let mut client = SourceClient::connect(
+      "127.0.0.1",
+      port,
+      Duration::from_secs(2),
+      &ConnectOptions::default(),
+    )
+    .await
+    .expect("connect");
+
+    client.keepalive().await.expect("answered keepalive");
+    assert!(client.keepalive_answered);
+    server.await.expect("server");
+
+    assert!(client.keepalive().await.is_err());
+  }
+
+  #[tokio::test]
+  async fn ping_requires_an_answer() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let port = listener.local_addr().expect("addr").port();
+
+    let server = tokio::spawn(async move {
+      let (mut stream, _) = listener.accept().await.expect("accept");
+      let (id, _) = read_request(&mut stream).await;
+      write_response(&mut stream, id, "").await;
+      // Ignore the second probe but keep the connection open.
+      read_request(&mut stream).await;
+      stream
+    });
+
+    let mut client = SourceClient::connect(
+      "127.0.0.1",
+      port,
+      Duration::from_millis(200),
+      &ConnectOptions::default(),
+    )
+    .await
+    .expect("connect");
+
+    client.ping().await.expect("answered ping");
+    let err = client.ping().await.expect_err("unanswered ping");
+    assert!(err.to_string().contains("did not answer"));
+    drop(server.await.expect("server"));
+  }
+
+  #[tokio::test]
+  async fn raw_packets_reuse_the_framing() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let port = listener.local_addr().expect("addr").port();
+
+    let server = tokio::spawn(async move {
+      let (mut stream, _) = listener.accept().await.expect("accept");
+      let (id, body) = read_request(&mut stream).await;
+      assert_eq!(body, "subscribe chat");
+      let mut packet = response_packet(id, "ok");
+      // A server-specific packet kind.
+      packet[8..12].copy_from_slice(&7_i32.to_le_bytes());
+      stream.write_all(&packet).await.expect("write");
+    });
+
+    let mut client = SourceClient::connect(
+      "127.0.0.1",
+      port,
+      Duration::from_secs(2),
+      &ConnectOptions::default(),
+    )
+    .await
+    .expect("connect");
+    let mut packets = Packets::new(&mut client);
+    let id = packets.next_id();
+    packets
+      .write_packet(&Packet::new(
+        id,
+        SERVERDATA_EXECCOMMAND,
+        "subscribe chat",
+      ))
+      .await
+      .expect("write");
+    let reply = packets.read_packet().await.expect("read");
+    assert_eq!(reply, Packet::new(id, 7, "ok"));
+    assert_eq!(client.traffic().snapshot().messages_sent, 1);
+    server.await.expect("server");
+  }
+
+  #[tokio::test]
+  async fn send_many_pipelines_once_sentinels_are_echoed() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let port = listener.local_addr().expect("addr").port();
+
+    let server = tokio::spawn(async move {
+      let (mut stream, _) = listener.accept().await.expect("accept");
+      let (id, body) = read_request(&mut stream).await;
+      assert_eq!(body, "list");
+      let (sentinel, _) = read_request(&mut stream).await;
+      write_response(&mut stream, id, "3 players").await;
+      write_response(&mut stream, sentinel, "").await;
+
+      // Both remaining commands arrive before any reply is sent.
+      let mut requests = Vec::new();
+      for _ in 0..4 {
+        requests.push(read_request(&mut stream).await);
+      }
+      let bodies: Vec<_> =
+        requests.iter().map(|(_, body)| body.as_str()).collect();
+      assert_eq!(bodies, ["time query day", "", "seed", ""]);
+      for (id, text) in [
+        (requests[2].0, "Seed: 42"),
+        (requests[3].0, ""),
+        (requests[0].0, "The time is 1200"),
+        (requests[1].0, ""),
+      ] {
+        write_response(&mut stream, id, text).await;
+      }
+    });
+
+    let mut client = SourceClient::connect(
+      "127.0.0.1",
+      port,
+      Duration::from_secs(2),
+      &ConnectOptions::default(),
+    )
+    .await
+    .expect("connect");
+    client.authed = true;
+
+    let outcomes = client
+      .send_many(&["list", "time query day", "seed"])
+      .await
+      .expect("batch");
+    let payloads: Vec<_> = outcomes
+      .into_iter()
+      .map(|outcome| match outcome {
+        CommandOutcome::Response(response) => response.payload,
+        CommandOutcome::Bye => panic!("unexpected bye"),
+      })
+      .collect();
+    assert_eq!(
+      payloads,
+      [
+        vec!["3 players"],
+        vec!["The time is 1200"],
+        vec!["Seed: 42"]
+      ]
+    );
+    server.await.expect("server");
+  }
+
+  #[tokio::test]
+  async fn falls_back_when_sentinel_is_never_answered() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let port = listener.local_addr().expect("addr").port();
+
+    let server = tokio::spawn(async move {
+      let (mut stream, _) = listener.accept().await.expect("accept");
+      let (id, command) = read_request(&mut stream).await;
+      assert_eq!(command, "list");
+      let (_, sentinel) = read_request(&mut stream).await;
+      assert_eq!(sentinel, "");
+      write_response(&mut stream, id, "There are 0 players online").await;
+
+      // Once the fallback kicks in no further sentinels are sent.
+      let (id, command) = read_request(&mut stream).await;
+      assert_eq!(command, "seed");
+      write_response(&mut stream, id, "Seed: [42]").await;
+    });
+
+    let mut client = SourceClient::connect(
+      "127.0.0.1",
+      port,
+      Duration::from_secs(2),
+      &ConnectOptions::default(),
+    )
+    .await
+    .expect("connect");
+    client.authed = true;
+
+    for (command, expected) in [
+      ("list", "There are 0 players online"),
+      ("seed", "Seed: [42]"),
+    ] {
+      let CommandOutcome::Response(response) =
+        client.send_command(command).await.expect("command")
+      else {
+        panic!("unexpected bye");
+      };
+      assert_eq!(response.payload, vec![expected.to_string()]);
+    }
+    assert_eq!(client.sentinel, SentinelSupport::Missing);
+
+    server.await.expect("server");
+  }
+
+  #[tokio::test]
+  async fn factorio_dialect_reassembles_without_sentinel() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let port = listener.local_addr().expect("addr").port();
+    let help = "x".repeat(5_000);
+    let expected = format!("{help}tail");
+
+    let server = tokio::spawn(async move {
+      let (mut stream, _) = listener.accept().await.expect("accept");
+      let (id, command) = read_request(&mut stream).await;
+      assert_eq!(command, "/help");
+      write_response(&mut stream, id, &help).await;
+      tokio::time::sleep(Duration::from_millis(100)).await;
+      write_response(&mut stream, id, "tail").await;
+
+      // The next request is the next command, not a sentinel.
+      let (_, command) = read_request(&mut stream).await;
+      assert_eq!(command, "/players");
+    });
+
+    let mut client = SourceClient::connect(
+      "127.0.0.1",
+      port,
+      Duration::from_secs(2),
+      &ConnectOptions {
+        dialect: Dialect::Factorio,
+        ..ConnectOptions::default()
+      },
+    )
+    .await
+    .expect("connect");
+    client.authed = true;
+
+    let CommandOutcome::Response(response) =
+      client.send_command("/help").await.expect("command")
+    else {
+      panic!("unexpected bye");
+    };
+    assert_eq!(response.payload, vec![expected]);
+
+    client
+      .write_packet(3, SERVERDATA_EXECCOMMAND, "/players", None)
+      .await
+      .expect("write");
+    server.await.expect("server");
+  }
+}
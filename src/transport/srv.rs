@@ -0,0 +1,120 @@
+#[cfg(any(feature = "srv", test))]
+use std::cmp::Reverse;
+use std::time::Duration;
+
+#[cfg(feature = "srv")]
+use hickory_resolver::TokioResolver;
+#[cfg(feature = "srv")]
+use tokio::time::timeout as await_timeout;
+
+#[cfg(feature = "srv")]
+use super::TimedOut;
+use crate::error::Error;
+
+/// Service name looked up for RCON endpoints, as published by
+/// Minecraft-style deployments.
+const SERVICE: &str = "_rcon._tcp";
+
+/// Endpoint advertised by an RCON SRV record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SrvTarget {
+  /// Host to connect to, without the trailing dot.
+  pub host: String,
+  pub port: u16,
+  /// Lower values are tried first.
+  pub priority: u16,
+  /// Relative preference among targets of equal priority.
+  pub weight: u16,
+}
+
+/// Look up the `_rcon._tcp` SRV record of `host` using the system resolver
+/// configuration.
+///
+/// Targets are returned in the order they should be tried: lowest
+/// priority first, heavier weights first within a priority. An empty list
+/// means `host` publishes no usable record, so callers should fall back to
+/// its A/AAAA records.
+#[cfg(feature = "srv")]
+pub async fn lookup_srv(
+  host: &str,
+  deadline: Duration,
+) -> Result<Vec<SrvTarget>, Error> {
+  let resolver = TokioResolver::builder_tokio()
+    .map_err(|err| {
+      Error::Unsupported(format!(
+        "failed to read the system DNS configuration: {err}"
+      ))
+    })?
+    .build();
+  let name = format!("{SERVICE}.{}.", host.trim_end_matches('.'));
+
+  tracing::debug!("--> SRV {name}");
+  let lookup =
+    match await_timeout(deadline, resolver.srv_lookup(name.as_str()))
+      .await
+      .map_err(|_| {
+        TimedOut::new(format!("SRV lookup for {name}"), deadline)
+      })? {
+      Ok(lookup) => lookup,
+      Err(err) if err.is_no_records_found() => return Ok(Vec::new()),
+      Err(err) => {
+        return Err(Error::Protocol(format!(
+          "SRV lookup for {name} failed: {err}"
+        )));
+      }
+    };
+
+  let mut targets: Vec<_> = lookup
+    .iter()
+    // A target of "." means the service is decidedly not available.
+    .filter(|record| !record.target().is_root())
+    .map(|record| SrvTarget {
+      host: record.target().to_utf8().trim_end_matches('.').to_string(),
+      port: record.port(),
+      priority: record.priority(),
+      weight: record.weight(),
+    })
+    .collect();
+  sort_by_preference(&mut targets);
+  Ok(targets)
+}
+
+#[cfg(not(feature = "srv"))]
+pub async fn lookup_srv(
+  _host: &str,
+  _deadline: Duration,
+) -> Result<Vec<SrvTarget>, Error> {
+  Err(Error::Unsupported(format!(
+    "SRV lookups for {SERVICE} require building with the `srv` feature"
+  )))
+}
+
+#[cfg(any(feature = "srv", test))]
+fn sort_by_preference(targets: &mut [SrvTarget]) {
+  targets.sort_by_key(|target| (target.priority, Reverse(target.weight)));
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn targets_are_ordered_by_priority_then_weight() {
+    let target = |host: &str, priority, weight| SrvTarget {
+      host: host.to_string(),
+      port: 25_575,
+      priority,
+      weight,
+    };
+    let mut targets = vec![
+      target("backup", 20, 100),
+      target("light", 10, 5),
+      target("heavy", 10, 60),
+    ];
+    sort_by_preference(&mut targets);
+
+    let hosts: Vec<_> =
+      targets.iter().map(|target| target.host.as_str()).collect();
+    assert_eq!(hosts, ["heavy", "light", "backup"]);
+  }
+}
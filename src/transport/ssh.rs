@@ -0,0 +1,841 @@
+use std::collections::VecDeque;
+use std::env;
+use std::fmt;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow, bail};
+use russh::client::{self, Handle, KeyboardInteractiveAuthResponse};
+use russh::keys::{self, PrivateKey, PrivateKeyWithHashAlg, PublicKey};
+use russh::{ChannelMsg, ChannelReadHalf, ChannelWriteHalf, Disconnect};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tokio::time::{Instant, timeout as await_timeout, timeout_at};
+
+use super::stats::Traffic;
+use super::stream::{self, BoxedStream};
+use super::{
+  AuthMode, AuthOutcome, CommandOutcome, ConnectOptions, Greeting,
+  RconResponse, ResponseStatus, ServerMessage, TimedOut, join_lines,
+  with_timeout,
+};
+use crate::protocol::Protocol;
+
+/// Line printed by the remote shell right before it hands over to the
+/// console command, proving that the command is about to start.
+const READY_MARKER: &str = "__HYRCON_SSH_READY__";
+
+/// How long to wait for the first line of output after a command.
+const FIRST_OUTPUT_WINDOW: Duration = Duration::from_secs(2);
+
+/// Quiet period after which a command's output is considered complete.
+const RESPONSE_IDLE_WINDOW: Duration = Duration::from_millis(300);
+
+/// Private keys in `~/.ssh` tried after the agent's, as OpenSSH does.
+const DEFAULT_IDENTITIES: [&str; 3] = ["id_ed25519", "id_ecdsa", "id_rsa"];
+
+/// Keyboard-interactive rounds answered before giving up.
+const MAX_PROMPT_ROUNDS: usize = 3;
+
+/// SSH extended data type of a channel's stderr.
+const STDERR: u32 = 1;
+
+/// Console helper driven over an SSH exec channel.
+///
+/// The session is authenticated with the agent's keys or the default
+/// identities in `~/.ssh` while connecting; when none is accepted the
+/// greeting asks for a password, which is tried as a password and then
+/// as the answer to keyboard-interactive prompts. Commands are written to
+/// the remote program's stdin, and what it prints until it goes quiet
+/// becomes the reply.
+pub(super) struct SshClient {
+  /// The SSH connection, absent when driving a console without one.
+  session: Option<Session>,
+  /// The running console command, once authenticated.
+  console: Option<Console>,
+  timeout: Duration,
+  messages: VecDeque<ServerMessage>,
+  closed: bool,
+  /// Bytes on the wire and lines written to and read from the console.
+  traffic: Traffic,
+}
+
+struct Session {
+  handle: Handle<KnownHosts>,
+  user: String,
+  /// Shell snippet run on the exec channel, from [`remote_command`].
+  remote: String,
+}
+
+/// Stdin and line-framed output of the console command.
+struct Console {
+  stdin: Pin<Box<dyn AsyncWrite + Send>>,
+  output: mpsc::Receiver<Output>,
+  /// Used to signal EOF on quit.
+  channel: Option<ChannelWriteHalf<client::Msg>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Output {
+  Stdout(String),
+  Stderr(String),
+}
+
+impl SshClient {
+  pub(super) async fn connect(
+    host: &str,
+    port: u16,
+    deadline: Duration,
+    options: &ConnectOptions,
+  ) -> Result<(Self, Greeting)> {
+    let (user, host) = split_user(host)?;
+    let traffic = Traffic::default();
+    let give_up = Instant::now() + deadline;
+
+    tracing::debug!("--> ssh {user}@{host} -p {port}");
+    let stream =
+      stream::open(host, port, deadline, options, &traffic).await?;
+    let mut handle =
+      handshake(stream, host, port, give_up, deadline).await?;
+
+    let authenticated =
+      timeout_at(give_up, authenticate_with_keys(&mut handle, &user))
+        .await
+        .map_err(|_| {
+          TimedOut::new("SSH key authentication", deadline)
+        })??;
+
+    let mut client = Self {
+      session: Some(Session {
+        handle,
+        user,
+        remote: remote_command(options.ssh_command.as_deref()),
+      }),
+      console: None,
+      timeout: deadline,
+      messages: VecDeque::new(),
+      closed: false,
+      traffic,
+    };
+    let auth_mode = if authenticated {
+      client.start_console().await?;
+      AuthMode::Optional
+    } else {
+      AuthMode::Required
+    };
+
+    let greeting =
+      Greeting::new(Protocol::Ssh, "SSH SESSION READY", auth_mode);
+    Ok((client, greeting))
+  }
+
+  /// Drive an already running console, without an SSH connection.
+  #[cfg(test)]
+  fn with_console(console: Console, timeout: Duration) -> Self {
+    Self {
+      session: None,
+      console: Some(console),
+      timeout,
+      messages: VecDeque::new(),
+      closed: false,
+      traffic: Traffic::default(),
+    }
+  }
+
+  pub(super) fn is_closed(&self) -> bool {
+    self.closed
+  }
+
+  pub(super) fn set_timeout(&mut self, timeout: Duration) {
+    self.timeout = timeout;
+  }
+
+  pub(super) fn traffic(&self) -> &Traffic {
+    &self.traffic
+  }
+
+  pub(super) fn take_messages(&mut self) -> Vec<ServerMessage> {
+    self.messages.drain(..).collect()
+  }
+
+  pub(super) async fn authenticate(
+    &mut self,
+    password: &str,
+  ) -> Result<AuthOutcome> {
+    if self.console.is_some() {
+      tracing::debug!("already authenticated with a key");
+      return Ok(AuthOutcome::Success);
+    }
+    let session = self
+      .session
+      .as_mut()
+      .ok_or_else(|| anyhow!("connection already closed"))?;
+
+    let accepted = await_timeout(
+      self.timeout,
+      authenticate_with_password(
+        &mut session.handle,
+        &session.user,
+        password,
+      ),
+    )
+    .await
+    .map_err(|_| {
+      TimedOut::new("SSH password authentication", self.timeout)
+    })??;
+    if !accepted {
+      return Ok(AuthOutcome::Failure);
+    }
+
+    self.start_console().await?;
+    Ok(AuthOutcome::Success)
+  }
+
+  pub(super) async fn send_command(
+    &mut self,
+    command: &str,
+  ) -> Result<CommandOutcome> {
+    if self.closed {
+      bail!("connection already closed");
+    }
+
+    if command.trim().is_empty() {
+      bail!("command must not be empty");
+    }
+
+    if command.contains(['\r', '\n']) {
+      bail!("command must not contain newline characters");
+    }
+
+    let console = self
+      .console
+      .as_mut()
+      .ok_or_else(|| anyhow!("not authenticated; supply a password"))?;
+
+    // Anything already buffered predates the command.
+    while let Ok(output) = console.output.try_recv() {
+      self.messages.push_back(output.into_message());
+    }
+
+    tracing::debug!("--> {}", command);
+    with_timeout(
+      self.timeout,
+      console.stdin.write_all(format!("{command}\n").as_bytes()),
+      format!("writing `{command}` to the remote command"),
+    )
+    .await?;
+    with_timeout(
+      self.timeout,
+      console.stdin.flush(),
+      "flushing ssh stdin",
+    )
+    .await?;
+    self.traffic.message_sent();
+
+    let mut payload = Vec::new();
+    let mut deadline =
+      Instant::now() + self.timeout.min(FIRST_OUTPUT_WINDOW);
+
+    loop {
+      let Ok(output) = timeout_at(deadline, console.output.recv()).await
+      else {
+        break;
+      };
+      let Some(output) = output else {
+        self.closed = true;
+        if payload.is_empty() {
+          return Ok(CommandOutcome::Bye);
+        }
+        break;
+      };
+
+      let (Output::Stdout(line) | Output::Stderr(line)) = output;
+      payload.push(line);
+      deadline = Instant::now() + RESPONSE_IDLE_WINDOW;
+    }
+
+    Ok(CommandOutcome::Response(RconResponse {
+      status: ResponseStatus::Ok,
+      raw: join_lines(&payload),
+      payload,
+      error: None,
+    }))
+  }
+
+  /// Wait for output printed while no command is in flight, returning
+  /// `None` once the remote command exits.
+  pub(super) async fn recv_message(
+    &mut self,
+  ) -> Result<Option<ServerMessage>> {
+    if let Some(message) = self.messages.pop_front() {
+      return Ok(Some(message));
+    }
+    let Some(console) = self.console.as_mut() else {
+      // Nothing is printed before the console starts.
+      return std::future::pending().await;
+    };
+
+    match console.output.recv().await {
+      Some(output) => Ok(Some(output.into_message())),
+      None => {
+        self.closed = true;
+        Ok(None)
+      }
+    }
+  }
+
+  pub(super) async fn quit(&mut self) -> Result<()> {
+    self.closed = true;
+
+    if let Some(mut console) = self.console.take() {
+      // EOF on stdin lets well-behaved console helpers exit on their own.
+      if let Some(channel) = &console.channel {
+        channel.eof().await.ok();
+      }
+      drop(console.stdin);
+      let exited = await_timeout(self.timeout, async {
+        while console.output.recv().await.is_some() {}
+      })
+      .await;
+      if exited.is_err() {
+        tracing::debug!("remote command did not exit; disconnecting");
+      }
+    }
+
+    if let Some(session) = self.session.take() {
+      session
+        .handle
+        .disconnect(Disconnect::ByApplication, "", "en")
+        .await
+        .ok();
+    }
+
+    Ok(())
+  }
+
+  /// Run the console command on a new exec channel and wait until it
+  /// starts.
+  async fn start_console(&mut self) -> Result<()> {
+    let session = self
+      .session
+      .as_ref()
+      .ok_or_else(|| anyhow!("connection already closed"))?;
+
+    let channel = await_timeout(self.timeout, async {
+      let channel = session.handle.channel_open_session().await?;
+      channel.exec(true, session.remote.as_str()).await?;
+      Ok::<_, russh::Error>(channel)
+    })
+    .await
+    .map_err(|_| {
+      TimedOut::new("starting the remote command", self.timeout)
+    })?
+    .context("failed to start the remote command")?;
+    let (read, write) = channel.split();
+    let (sender, output) = mpsc::channel(256);
+    tokio::spawn(pump(read, sender, self.traffic.clone()));
+
+    self.console = Some(Console {
+      stdin: Box::pin(write.make_writer()),
+      output,
+      channel: Some(write),
+    });
+    self.await_ready().await
+  }
+
+  /// Wait for the ready marker, surfacing what the remote shell printed
+  /// to stderr when the console cannot be started.
+  async fn await_ready(&mut self) -> Result<()> {
+    let console = self
+      .console
+      .as_mut()
+      .ok_or_else(|| anyhow!("connection already closed"))?;
+    let deadline = Instant::now() + self.timeout;
+    let mut diagnostics = Vec::new();
+
+    loop {
+      let output = timeout_at(deadline, console.output.recv())
+        .await
+        .map_err(|_| {
+          anyhow!(
+            "ssh session not ready after {} ms",
+            self.timeout.as_millis()
+          )
+        })?;
+
+      match output {
+        Some(Output::Stdout(line)) if line == READY_MARKER => {
+          return Ok(());
+        }
+        Some(Output::Stdout(line)) => tracing::debug!("<-- {line}"),
+        Some(Output::Stderr(line)) => {
+          tracing::debug!("<-- {line}");
+          diagnostics.push(line);
+        }
+        None => {
+          self.closed = true;
+          let detail = if diagnostics.is_empty() {
+            "the remote command exited".to_string()
+          } else {
+            diagnostics.join("; ")
+          };
+          bail!("ssh session failed: {detail}");
+        }
+      }
+    }
+  }
+}
+
+impl fmt::Debug for SshClient {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("SshClient")
+      .field("user", &self.session.as_ref().map(|session| &session.user))
+      .field("started", &self.console.is_some())
+      .field("timeout", &self.timeout)
+      .field("closed", &self.closed)
+      .finish_non_exhaustive()
+  }
+}
+
+impl Output {
+  fn into_message(self) -> ServerMessage {
+    let (kind, text) = match self {
+      Self::Stdout(text) => ("Output", text),
+      Self::Stderr(text) => ("Stderr", text),
+    };
+    ServerMessage {
+      kind: Some(kind.to_string()),
+      text,
+    }
+  }
+}
+
+/// Accepts servers whose host key is recorded in `~/.ssh/known_hosts`,
+/// like OpenSSH with `StrictHostKeyChecking=yes`.
+pub(super) struct KnownHosts {
+  host: String,
+  port: u16,
+}
+
+impl client::Handler for KnownHosts {
+  type Error = anyhow::Error;
+
+  async fn check_server_key(&mut self, key: &PublicKey) -> Result<bool> {
+    match keys::check_known_hosts(&self.host, self.port, key) {
+      Ok(true) => Ok(true),
+      Ok(false) => bail!(
+        "the host key of {} is not in ~/.ssh/known_hosts; add it with \
+         `ssh-keyscan -p {} {}`",
+        self.host,
+        self.port,
+        self.host
+      ),
+      Err(keys::Error::KeyChanged { line }) => bail!(
+        "the host key of {} does not match line {line} of \
+         ~/.ssh/known_hosts",
+        self.host
+      ),
+      Err(err) => Err(err).context("failed to read ~/.ssh/known_hosts"),
+    }
+  }
+}
+
+/// Run the SSH handshake with `host:port` over `stream`, checking the
+/// server's host key with [`KnownHosts`], by `give_up` at the latest.
+pub(super) async fn handshake(
+  stream: BoxedStream,
+  host: &str,
+  port: u16,
+  give_up: Instant,
+  deadline: Duration,
+) -> Result<Handle<KnownHosts>> {
+  let handler = KnownHosts {
+    host: host.to_string(),
+    port,
+  };
+  let config = Arc::new(client::Config::default());
+  let handle =
+    timeout_at(give_up, client::connect_stream(config, stream, handler))
+      .await
+      .map_err(|_| TimedOut::new("SSH handshake", deadline))??;
+  Ok(handle)
+}
+
+/// Offer the agent's keys, then the unencrypted default identities,
+/// until one is accepted.
+pub(super) async fn authenticate_with_keys(
+  handle: &mut Handle<KnownHosts>,
+  user: &str,
+) -> Result<bool> {
+  #[cfg(unix)]
+  if let Ok(mut agent) =
+    keys::agent::client::AgentClient::connect_env().await
+  {
+    let identities = agent.request_identities().await.unwrap_or_default();
+    for key in identities {
+      let hash = if key.algorithm().is_rsa() {
+        handle.best_supported_rsa_hash().await?.flatten()
+      } else {
+        None
+      };
+      match handle
+        .authenticate_publickey_with(user, key, hash, &mut agent)
+        .await
+      {
+        Ok(result) if result.success() => return Ok(true),
+        Ok(_) => {}
+        Err(err) => tracing::debug!("ssh-agent could not sign: {err}"),
+      }
+    }
+  }
+
+  let Some(home) = env::home_dir() else {
+    return Ok(false);
+  };
+  for name in DEFAULT_IDENTITIES {
+    let path = home.join(".ssh").join(name);
+    if !path.exists() {
+      continue;
+    }
+    let key = match keys::load_secret_key(&path, None) {
+      Ok(key) => key,
+      Err(err) => {
+        tracing::debug!("skipping {}: {err}", path.display());
+        continue;
+      }
+    };
+    if offer_key(handle, user, key).await? {
+      return Ok(true);
+    }
+  }
+  Ok(false)
+}
+
+/// Offer only the unencrypted private key in `path`.
+pub(super) async fn authenticate_with_key_file(
+  handle: &mut Handle<KnownHosts>,
+  user: &str,
+  path: &Path,
+) -> Result<bool> {
+  let key = keys::load_secret_key(path, None).with_context(|| {
+    format!("failed to load the SSH key {}", path.display())
+  })?;
+  offer_key(handle, user, key).await
+}
+
+async fn offer_key(
+  handle: &mut Handle<KnownHosts>,
+  user: &str,
+  key: PrivateKey,
+) -> Result<bool> {
+  let hash = if key.algorithm().is_rsa() {
+    handle.best_supported_rsa_hash().await?.flatten()
+  } else {
+    None
+  };
+  let key = PrivateKeyWithHashAlg::new(Arc::new(key), hash);
+  Ok(handle.authenticate_publickey(user, key).await?.success())
+}
+
+/// Log in with `password`, falling back to answering keyboard-interactive
+/// prompts with it, as PAM-backed servers often require.
+async fn authenticate_with_password(
+  handle: &mut Handle<KnownHosts>,
+  user: &str,
+  password: &str,
+) -> Result<bool> {
+  if handle
+    .authenticate_password(user, password)
+    .await?
+    .success()
+  {
+    return Ok(true);
+  }
+
+  let mut reply = handle
+    .authenticate_keyboard_interactive_start(user, None::<String>)
+    .await?;
+  for _ in 0..MAX_PROMPT_ROUNDS {
+    let prompts = match reply {
+      KeyboardInteractiveAuthResponse::Success => return Ok(true),
+      KeyboardInteractiveAuthResponse::Failure { .. } => return Ok(false),
+      KeyboardInteractiveAuthResponse::InfoRequest { prompts, .. } => {
+        prompts
+      }
+    };
+    // Prompts that echo ask for something other than a secret.
+    let answers = prompts
+      .iter()
+      .map(|prompt| {
+        if prompt.echo {
+          String::new()
+        } else {
+          password.to_string()
+        }
+      })
+      .collect();
+    reply = handle
+      .authenticate_keyboard_interactive_respond(answers)
+      .await?;
+  }
+  Ok(false)
+}
+
+/// Forward the console's output as lines until its channel closes.
+async fn pump(
+  mut read: ChannelReadHalf,
+  sender: mpsc::Sender<Output>,
+  traffic: Traffic,
+) {
+  let mut framer = Framer::default();
+  while let Some(message) = read.wait().await {
+    let lines = match message {
+      ChannelMsg::Data { data } => framer.push(Pipe::Stdout, &data),
+      ChannelMsg::ExtendedData { data, ext: STDERR } => {
+        framer.push(Pipe::Stderr, &data)
+      }
+      ChannelMsg::Failure => {
+        vec![Output::Stderr(
+          "the server refused to run the remote command".to_string(),
+        )]
+      }
+      ChannelMsg::ExitStatus { exit_status } => {
+        tracing::debug!("remote command exited with status {exit_status}");
+        continue;
+      }
+      ChannelMsg::Eof | ChannelMsg::Close => break,
+      _ => continue,
+    };
+    for line in lines {
+      traffic.message_received();
+      if sender.send(line).await.is_err() {
+        return;
+      }
+    }
+  }
+  for line in framer.finish() {
+    traffic.message_received();
+    if sender.send(line).await.is_err() {
+      return;
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Pipe {
+  Stdout,
+  Stderr,
+}
+
+/// Splits the console's stdout and stderr into lines, each stream
+/// keeping its own partial line between chunks.
+#[derive(Debug, Default)]
+struct Framer {
+  stdout: Vec<u8>,
+  stderr: Vec<u8>,
+}
+
+impl Framer {
+  /// The lines completed by `bytes` arriving on `pipe`.
+  fn push(&mut self, pipe: Pipe, bytes: &[u8]) -> Vec<Output> {
+    let buffer = match pipe {
+      Pipe::Stdout => &mut self.stdout,
+      Pipe::Stderr => &mut self.stderr,
+    };
+    buffer.extend_from_slice(bytes);
+
+    let mut lines = Vec::new();
+    while let Some(end) = buffer.iter().position(|&byte| byte == b'\n') {
+      let line: Vec<u8> = buffer.drain(..=end).collect();
+      lines.push(pipe.wrap(&line[..end]));
+    }
+    lines
+  }
+
+  /// Unterminated lines left when the channel closes.
+  fn finish(&mut self) -> Vec<Output> {
+    let stdout = std::mem::take(&mut self.stdout);
+    let stderr = std::mem::take(&mut self.stderr);
+    [(Pipe::Stdout, stdout), (Pipe::Stderr, stderr)]
+      .into_iter()
+      .filter(|(_, rest)| !rest.is_empty())
+      .map(|(pipe, rest)| pipe.wrap(&rest))
+      .collect()
+  }
+}
+
+impl Pipe {
+  fn wrap(self, line: &[u8]) -> Output {
+    let line = String::from_utf8_lossy(line)
+      .trim_end_matches('\r')
+      .to_string();
+    match self {
+      Self::Stdout => Output::Stdout(line),
+      Self::Stderr => Output::Stderr(line),
+    }
+  }
+}
+
+/// Login name and hostname of `host`, given as `[user@]host`; the login
+/// name defaults to the local user's, as with OpenSSH.
+fn split_user(host: &str) -> Result<(String, &str)> {
+  if let Some((user, host)) = host.rsplit_once('@') {
+    return Ok((user.to_string(), host));
+  }
+  let user =
+    local_user().context("no SSH login name; pass --host user@host")?;
+  Ok((user, host))
+}
+
+/// Name of the local user, the default SSH login name.
+pub(super) fn local_user() -> Option<String> {
+  env::var("USER").or_else(|_| env::var("USERNAME")).ok()
+}
+
+/// Shell snippet run on the exec channel: print the ready marker, then
+/// replace the shell with `command` (run by `sh -c`, so pipelines and
+/// `&&` work) or with the login shell.
+fn remote_command(command: Option<&str>) -> String {
+  let console = match command {
+    Some(command) => format!("/bin/sh -c {}", shell_quote(command)),
+    None => "\"${SHELL:-/bin/sh}\"".to_string(),
+  };
+  format!("printf '%s\\n' {READY_MARKER} && exec {console}")
+}
+
+/// Quote `text` as a single POSIX shell word.
+fn shell_quote(text: &str) -> String {
+  format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+  use tokio::io::{AsyncBufReadExt, BufReader, DuplexStream};
+
+  use super::*;
+
+  /// A console whose output the test writes and whose stdin it reads.
+  fn fake_console() -> (Console, mpsc::Sender<Output>, DuplexStream) {
+    let (stdin, remote) = tokio::io::duplex(1024);
+    let (sender, output) = mpsc::channel(16);
+    let console = Console {
+      stdin: Box::pin(stdin),
+      output,
+      channel: None,
+    };
+    (console, sender, remote)
+  }
+
+  fn stdout(line: &str) -> Output {
+    Output::Stdout(line.to_string())
+  }
+
+  fn stderr(line: &str) -> Output {
+    Output::Stderr(line.to_string())
+  }
+
+  #[test]
+  fn console_commands_are_quoted_for_sh() {
+    assert_eq!(shell_quote("it's"), r"'it'\''s'");
+    assert_eq!(
+      remote_command(Some("cd /srv/mc && rcon-cli --name 'a b'")),
+      "printf '%s\\n' __HYRCON_SSH_READY__ && exec /bin/sh -c \
+       'cd /srv/mc && rcon-cli --name '\\''a b'\\'''"
+    );
+    assert_eq!(
+      remote_command(None),
+      "printf '%s\\n' __HYRCON_SSH_READY__ && exec \"${SHELL:-/bin/sh}\""
+    );
+  }
+
+  #[test]
+  fn stdout_and_stderr_are_framed_separately() {
+    let mut framer = Framer::default();
+    assert_eq!(framer.push(Pipe::Stdout, b"There are "), []);
+    assert_eq!(
+      framer.push(Pipe::Stderr, b"warn: slow\r\nerr"),
+      [stderr("warn: slow")]
+    );
+    assert_eq!(
+      framer.push(Pipe::Stdout, b"2 players\nSteve\n\nAl"),
+      [stdout("There are 2 players"), stdout("Steve"), stdout("")]
+    );
+    assert_eq!(framer.finish(), [stdout("Al"), stderr("err")]);
+    assert_eq!(framer.finish(), []);
+  }
+
+  #[test]
+  fn user_names_come_from_the_host() {
+    let (user, host) = split_user("mc@play.example.com").expect("user");
+    assert_eq!((user.as_str(), host), ("mc", "play.example.com"));
+  }
+
+  #[tokio::test]
+  async fn commands_run_once_the_marker_arrives() {
+    let (console, output, remote) = fake_console();
+    for line in [
+      stdout("Last login: Mon Oct 12"),
+      stderr("stty: not a tty"),
+      stdout(READY_MARKER),
+      stdout("[Server] Done (3.2s)!"),
+    ] {
+      output.send(line).await.expect("send");
+    }
+    let mut client =
+      SshClient::with_console(console, Duration::from_secs(2));
+    client.await_ready().await.expect("ready");
+
+    let server = tokio::spawn(async move {
+      let mut lines = BufReader::new(remote).lines();
+      let command = lines.next_line().await.expect("read").expect("line");
+      output
+        .send(stdout("There are 0 players"))
+        .await
+        .expect("send");
+      output.send(stderr("Can't keep up!")).await.expect("send");
+      (command, output, lines)
+    });
+    let outcome = client.send_command("list").await.expect("list");
+    let (command, output, _lines) = server.await.expect("server");
+    assert_eq!(command, "list");
+    let CommandOutcome::Response(response) = outcome else {
+      panic!("unexpected outcome: {outcome:?}");
+    };
+    assert_eq!(
+      response.payload,
+      ["There are 0 players", "Can't keep up!"]
+    );
+    let messages: Vec<_> = client
+      .take_messages()
+      .into_iter()
+      .map(|message| message.text)
+      .collect();
+    assert_eq!(messages, ["[Server] Done (3.2s)!"]);
+
+    drop(output);
+    let outcome = client.send_command("stop").await.expect("stop");
+    assert!(matches!(outcome, CommandOutcome::Bye));
+    assert!(client.is_closed());
+  }
+
+  #[tokio::test]
+  async fn failed_starts_report_stderr() {
+    let (console, output, _remote) = fake_console();
+    output
+      .send(stderr("/bin/sh: rcon-cli: not found"))
+      .await
+      .expect("send");
+    drop(output);
+
+    let mut client =
+      SshClient::with_console(console, Duration::from_secs(2));
+    let err = client.await_ready().await.expect_err("no marker");
+    assert_eq!(
+      err.to_string(),
+      "ssh session failed: /bin/sh: rcon-cli: not found"
+    );
+    assert!(client.is_closed());
+  }
+}
@@ -0,0 +1,177 @@
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Traffic exchanged during a session, as returned by
+/// [`RconClient::stats`](super::RconClient::stats).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SessionStats {
+  /// Bytes written to the connection. Stream protocols count what went
+  /// over the socket (including TLS, WebSocket and SSH framing); `rest`
+  /// counts request bodies.
+  pub bytes_sent: u64,
+  /// Bytes read from the connection, counted like `bytes_sent`.
+  pub bytes_received: u64,
+  /// Protocol units written: packets, datagrams, lines, WebSocket frames
+  /// or HTTP requests, depending on the protocol.
+  pub messages_sent: u64,
+  /// Protocol units read, counted like `messages_sent`.
+  pub messages_received: u64,
+  /// Commands sent through
+  /// [`RconClient::send_command`](super::RconClient::send_command).
+  pub commands: u64,
+}
+
+/// Counters shared between a backend and the streams it reads and writes
+/// through.
+#[derive(Debug, Clone, Default)]
+pub(super) struct Traffic(Arc<Counters>);
+
+#[derive(Debug, Default)]
+struct Counters {
+  bytes_sent: AtomicU64,
+  bytes_received: AtomicU64,
+  messages_sent: AtomicU64,
+  messages_received: AtomicU64,
+}
+
+impl Traffic {
+  pub(super) fn add_bytes_sent(&self, bytes: usize) {
+    self.0.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+  }
+
+  pub(super) fn add_bytes_received(&self, bytes: usize) {
+    self
+      .0
+      .bytes_received
+      .fetch_add(bytes as u64, Ordering::Relaxed);
+  }
+
+  pub(super) fn message_sent(&self) {
+    self.0.messages_sent.fetch_add(1, Ordering::Relaxed);
+  }
+
+  pub(super) fn message_received(&self) {
+    self.0.messages_received.fetch_add(1, Ordering::Relaxed);
+  }
+
+  /// Record a datagram or other message whose size is known up front.
+  pub(super) fn sent(&self, bytes: usize) {
+    self.add_bytes_sent(bytes);
+    self.message_sent();
+  }
+
+  /// Counterpart of [`Traffic::sent`] for incoming messages.
+  pub(super) fn received(&self, bytes: usize) {
+    self.add_bytes_received(bytes);
+    self.message_received();
+  }
+
+  /// Current totals; `commands` is left for the caller to fill in.
+  pub(super) fn snapshot(&self) -> SessionStats {
+    let counters = &self.0;
+    SessionStats {
+      bytes_sent: counters.bytes_sent.load(Ordering::Relaxed),
+      bytes_received: counters.bytes_received.load(Ordering::Relaxed),
+      messages_sent: counters.messages_sent.load(Ordering::Relaxed),
+      messages_received: counters
+        .messages_received
+        .load(Ordering::Relaxed),
+      commands: 0,
+    }
+  }
+}
+
+/// Stream wrapper counting the bytes read and written through it.
+#[derive(Debug)]
+pub(super) struct Metered<S> {
+  inner: S,
+  traffic: Traffic,
+}
+
+impl<S> Metered<S> {
+  pub(super) fn new(inner: S, traffic: &Traffic) -> Self {
+    Self {
+      inner,
+      traffic: traffic.clone(),
+    }
+  }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Metered<S> {
+  fn poll_read(
+    mut self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+  ) -> Poll<io::Result<()>> {
+    let before = buf.filled().len();
+    let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+    if let Poll::Ready(Ok(())) = poll {
+      self.traffic.add_bytes_received(buf.filled().len() - before);
+    }
+    poll
+  }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Metered<S> {
+  fn poll_write(
+    mut self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &[u8],
+  ) -> Poll<io::Result<usize>> {
+    let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+    if let Poll::Ready(Ok(written)) = poll {
+      self.traffic.add_bytes_sent(written);
+    }
+    poll
+  }
+
+  fn poll_flush(
+    mut self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+  ) -> Poll<io::Result<()>> {
+    Pin::new(&mut self.inner).poll_flush(cx)
+  }
+
+  fn poll_shutdown(
+    mut self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+  ) -> Poll<io::Result<()>> {
+    Pin::new(&mut self.inner).poll_shutdown(cx)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+  #[tokio::test]
+  async fn metered_streams_count_bytes() {
+    let (client, mut server) = tokio::io::duplex(64);
+    let traffic = Traffic::default();
+    let mut client = Metered::new(client, &traffic);
+
+    client.write_all(b"status\n").await.expect("write");
+    server.write_all(b"OK\n.\n").await.expect("reply");
+    let mut reply = [0; 5];
+    client.read_exact(&mut reply).await.expect("read");
+    traffic.message_sent();
+    traffic.message_received();
+
+    assert_eq!(
+      traffic.snapshot(),
+      SessionStats {
+        bytes_sent: 7,
+        bytes_received: 5,
+        messages_sent: 1,
+        messages_received: 1,
+        commands: 0,
+      }
+    );
+  }
+}
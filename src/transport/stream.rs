@@ -0,0 +1,461 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+#[cfg(feature = "tls")]
+use std::pin::Pin;
+#[cfg(feature = "tls")]
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use anyhow::{Context as _, Result};
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use futures_util::stream::FuturesUnordered;
+use socket2::{SockRef, TcpKeepalive};
+#[cfg(feature = "tls")]
+use tokio::io::ReadBuf;
+use tokio::io::{AsyncRead, AsyncWrite, ReadHalf, WriteHalf};
+use tokio::net::{TcpSocket, TcpStream};
+#[cfg(feature = "tls")]
+use tokio::time::timeout as await_timeout;
+use tokio::time::{Instant, sleep, timeout_at};
+
+use super::stats::{Metered, Traffic};
+use super::{ConnectOptions, SshTunnel, TlsOptions, resolve};
+
+/// How long an attempt may stay pending before the next address is raced
+/// against it (RFC 8305 "Connection Attempt Delay").
+const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Byte stream carrying a line or packet protocol: plain TCP, TCP
+/// wrapped in TLS, or whatever a [`Connector`] opens.
+pub trait Stream:
+  AsyncRead + AsyncWrite + Send + Sync + Unpin + fmt::Debug
+{
+}
+
+impl<T> Stream for T where
+  T: AsyncRead + AsyncWrite + Send + Sync + Unpin + fmt::Debug
+{
+}
+
+pub(super) type BoxedStream = Box<dyn Stream>;
+
+/// Opens the byte streams sessions run over, in place of the built-in
+/// TCP dialing, e.g. to reach servers through unix sockets, a custom
+/// tunnel, or in-memory pipes in tests. Set through
+/// [`ConnectOptions::connector`].
+///
+/// TLS from [`ConnectOptions::tls`] is still layered on top of the
+/// returned stream, and the protocol codecs are unchanged.
+#[async_trait]
+pub trait Connector: Send + Sync + fmt::Debug {
+  /// Open a stream to the server known as `host:port`.
+  async fn connect(
+    &self,
+    host: &str,
+    port: u16,
+  ) -> io::Result<Box<dyn Stream>>;
+}
+pub(super) type StreamReader = ReadHalf<BoxedStream>;
+pub(super) type StreamWriter = WriteHalf<BoxedStream>;
+
+/// Open a connection to `host:port` as configured by `options` (through
+/// a proxy or SSH tunnel, wrapped in TLS) and split it into independently
+/// usable halves.
+pub(super) async fn connect(
+  host: &str,
+  port: u16,
+  deadline: Duration,
+  options: &ConnectOptions,
+  traffic: &Traffic,
+) -> Result<(StreamReader, StreamWriter)> {
+  let stream = open(host, port, deadline, options, traffic).await?;
+
+  let stream = match &options.tls {
+    Some(options) => wrap_tls(stream, host, deadline, options).await?,
+    None => Box::new(stream),
+  };
+
+  Ok(tokio::io::split(stream))
+}
+
+/// Split a stream the caller connected themselves, counting the bytes
+/// read and written through it in `traffic`.
+pub(super) fn adopt(
+  stream: impl Stream + 'static,
+  traffic: &Traffic,
+) -> (StreamReader, StreamWriter) {
+  let stream: BoxedStream = Box::new(Metered::new(stream, traffic));
+  tokio::io::split(stream)
+}
+
+/// Open an unencrypted byte stream to `host:port`: forwarded through the
+/// SSH tunnel or proxy in `options`, or a direct TCP connection. Bytes
+/// read and written are counted in `traffic`.
+pub(super) async fn open(
+  host: &str,
+  port: u16,
+  deadline: Duration,
+  options: &ConnectOptions,
+  traffic: &Traffic,
+) -> Result<BoxedStream> {
+  if let Some(connector) = &options.connector {
+    let stream =
+      timeout_at(Instant::now() + deadline, connector.connect(host, port))
+        .await
+        .context("connect timed out")?
+        .context("connect failed")?;
+    return Ok(Box::new(Metered::new(stream, traffic)));
+  }
+
+  if let Some(tunnel) = &options.ssh_tunnel {
+    let stream = ssh_tunnel(tunnel, host, port, deadline, options).await?;
+    return Ok(Box::new(Metered::new(stream, traffic)));
+  }
+
+  let stream = match &options.proxy {
+    Some(proxy) => proxy.connect(host, port, deadline, options).await?,
+    None => connect_tcp(host, port, deadline, options).await?,
+  };
+  Ok(Box::new(Metered::new(stream, traffic)))
+}
+
+/// Connect to `host:port` over the configured family, from `options.bind`
+/// when set.
+///
+/// Dual-stack hosts are raced happy-eyeballs style: addresses alternate
+/// between families, and the next one is tried as soon as the previous
+/// attempt fails or has been pending for [`CONNECTION_ATTEMPT_DELAY`]. The
+/// first established connection wins; `deadline` bounds the whole race.
+pub(super) async fn connect_tcp(
+  host: &str,
+  port: u16,
+  deadline: Duration,
+  options: &ConnectOptions,
+) -> Result<TcpStream> {
+  let give_up = Instant::now() + deadline;
+  let addresses = resolve(host, port, deadline, options).await?;
+
+  let stream =
+    timeout_at(give_up, race(interleave(addresses), options.bind))
+      .await
+      .context("connect timed out")?
+      .context("connect failed")?;
+  stream.set_nodelay(true)?;
+  if let Some(idle) = options.tcp_keepalive {
+    SockRef::from(&stream)
+      .set_tcp_keepalive(&TcpKeepalive::new().with_time(idle))
+      .context("failed to enable TCP keepalive")?;
+  }
+  Ok(stream)
+}
+
+async fn race(
+  addresses: Vec<SocketAddr>,
+  bind: Option<IpAddr>,
+) -> io::Result<TcpStream> {
+  async fn attempt(
+    address: SocketAddr,
+    bind: Option<IpAddr>,
+  ) -> (SocketAddr, io::Result<TcpStream>) {
+    let result = async {
+      let socket = if address.is_ipv4() {
+        TcpSocket::new_v4()?
+      } else {
+        TcpSocket::new_v6()?
+      };
+      if let Some(bind) = bind {
+        socket.bind(SocketAddr::new(bind, 0)).map_err(|err| {
+          io::Error::new(err.kind(), format!("cannot bind {bind}: {err}"))
+        })?;
+      }
+      socket.connect(address).await
+    };
+    (address, result.await)
+  }
+
+  let mut queue = VecDeque::from(addresses);
+  let mut attempts = FuturesUnordered::new();
+  let mut last_error = None;
+
+  loop {
+    if attempts.is_empty() {
+      let Some(address) = queue.pop_front() else {
+        return Err(last_error.unwrap_or_else(|| {
+          io::Error::new(io::ErrorKind::NotFound, "no addresses to try")
+        }));
+      };
+      tracing::debug!("--> connect {address}");
+      attempts.push(attempt(address, bind));
+    }
+
+    tokio::select! {
+      Some((address, result)) = attempts.next() => match result {
+        Ok(stream) => return Ok(stream),
+        Err(err) => {
+          tracing::debug!("connect to {address} failed: {err}");
+          last_error = Some(err);
+          if let Some(address) = queue.pop_front() {
+            tracing::debug!("--> connect {address}");
+            attempts.push(attempt(address, bind));
+          }
+        }
+      },
+      () = sleep(CONNECTION_ATTEMPT_DELAY), if !queue.is_empty() => {
+        if let Some(address) = queue.pop_front() {
+          tracing::debug!("--> connect {address} (racing)");
+          attempts.push(attempt(address, bind));
+        }
+      }
+    }
+  }
+}
+
+/// Alternate address families, starting with the one the resolver listed
+/// first (RFC 8305, section 4).
+fn interleave(addresses: Vec<SocketAddr>) -> Vec<SocketAddr> {
+  let Some(first) = addresses.first() else {
+    return addresses;
+  };
+  let first_is_v6 = first.is_ipv6();
+  let (mut preferred, mut other): (VecDeque<_>, VecDeque<_>) = addresses
+    .into_iter()
+    .partition(|address| address.is_ipv6() == first_is_v6);
+
+  let mut ordered = Vec::with_capacity(preferred.len() + other.len());
+  while !preferred.is_empty() || !other.is_empty() {
+    ordered.extend(preferred.pop_front());
+    ordered.extend(other.pop_front());
+  }
+  ordered
+}
+
+#[cfg(feature = "ssh")]
+async fn ssh_tunnel(
+  tunnel: &SshTunnel,
+  host: &str,
+  port: u16,
+  deadline: Duration,
+  options: &ConnectOptions,
+) -> Result<BoxedStream> {
+  tunnel.connect(host, port, deadline, options).await
+}
+
+#[cfg(not(feature = "ssh"))]
+async fn ssh_tunnel(
+  _tunnel: &SshTunnel,
+  _host: &str,
+  _port: u16,
+  _deadline: Duration,
+  _options: &ConnectOptions,
+) -> Result<BoxedStream> {
+  anyhow::bail!("SSH tunnels require building with the `ssh` feature")
+}
+
+#[cfg(feature = "tls")]
+async fn wrap_tls(
+  stream: BoxedStream,
+  host: &str,
+  deadline: Duration,
+  options: &TlsOptions,
+) -> Result<BoxedStream> {
+  use tokio_rustls::TlsConnector;
+
+  let config = super::tls::client_config(options)?;
+  let server_name = super::tls::server_name(host)?;
+
+  let stream = await_timeout(
+    deadline,
+    TlsConnector::from(config).connect(server_name, stream),
+  )
+  .await
+  .context("TLS handshake timed out")?
+  .map_err(|err| super::tls::explain_rejection(err, options))
+  .context("TLS handshake failed")?;
+
+  Ok(Box::new(TlsStream {
+    inner: stream,
+    options: options.clone(),
+  }))
+}
+
+/// TLS stream that rewords client certificate rejections reported after
+/// the handshake (TLS 1.3).
+#[cfg(feature = "tls")]
+#[derive(Debug)]
+struct TlsStream {
+  inner: tokio_rustls::client::TlsStream<BoxedStream>,
+  options: TlsOptions,
+}
+
+#[cfg(feature = "tls")]
+impl AsyncRead for TlsStream {
+  fn poll_read(
+    mut self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+  ) -> Poll<io::Result<()>> {
+    let this = &mut *self;
+    Pin::new(&mut this.inner)
+      .poll_read(cx, buf)
+      .map_err(|err| super::tls::explain_rejection(err, &this.options))
+  }
+}
+
+#[cfg(feature = "tls")]
+impl AsyncWrite for TlsStream {
+  fn poll_write(
+    mut self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &[u8],
+  ) -> Poll<io::Result<usize>> {
+    let this = &mut *self;
+    Pin::new(&mut this.inner)
+      .poll_write(cx, buf)
+      .map_err(|err| super::tls::explain_rejection(err, &this.options))
+  }
+
+  fn poll_flush(
+    mut self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+  ) -> Poll<io::Result<()>> {
+    Pin::new(&mut self.inner).poll_flush(cx)
+  }
+
+  fn poll_shutdown(
+    mut self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+  ) -> Poll<io::Result<()>> {
+    Pin::new(&mut self.inner).poll_shutdown(cx)
+  }
+}
+
+#[cfg(not(feature = "tls"))]
+async fn wrap_tls(
+  _stream: BoxedStream,
+  _host: &str,
+  _deadline: Duration,
+  _options: &TlsOptions,
+) -> Result<BoxedStream> {
+  anyhow::bail!("TLS support requires building with the `tls` feature")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tokio::net::TcpListener;
+
+  #[test]
+  fn interleave_alternates_families() {
+    let addresses: Vec<SocketAddr> =
+      ["[::1]:1", "[::2]:1", "[::3]:1", "10.0.0.1:1", "10.0.0.2:1"]
+        .iter()
+        .map(|address| address.parse().expect("address"))
+        .collect();
+    let ordered: Vec<_> = interleave(addresses)
+      .iter()
+      .map(ToString::to_string)
+      .collect();
+    assert_eq!(
+      ordered,
+      ["[::1]:1", "10.0.0.1:1", "[::2]:1", "10.0.0.2:1", "[::3]:1"]
+    );
+  }
+
+  #[tokio::test]
+  async fn race_skips_unreachable_addresses() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let reachable = listener.local_addr().expect("addr");
+    let refused = {
+      let closed = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+      closed.local_addr().expect("addr")
+    };
+    // TEST-NET-1 never answers, so this attempt stays pending or fails.
+    let blackhole: SocketAddr = "192.0.2.1:9".parse().expect("address");
+
+    let stream = timeout_at(
+      Instant::now() + Duration::from_secs(2),
+      race(vec![blackhole, refused, reachable], None),
+    )
+    .await
+    .expect("race finished in time")
+    .expect("connected");
+    assert_eq!(stream.peer_addr().expect("peer"), reachable);
+  }
+
+  #[tokio::test]
+  async fn connect_tcp_binds_the_local_address() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let port = listener.local_addr().expect("addr").port();
+    let options = ConnectOptions {
+      bind: Some("127.0.0.2".parse().expect("address")),
+      ..ConnectOptions::default()
+    };
+
+    let stream =
+      connect_tcp("127.0.0.1", port, Duration::from_secs(2), &options)
+        .await
+        .expect("connected");
+    let local = stream.local_addr().expect("local").ip();
+    assert_eq!(local.to_string(), "127.0.0.2");
+  }
+
+  #[tokio::test]
+  async fn connect_tcp_uses_resolve_overrides() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let port = listener.local_addr().expect("addr").port();
+    let options = ConnectOptions {
+      resolve: vec![
+        format!("standby.invalid:{port}:127.0.0.1")
+          .parse()
+          .expect("override"),
+      ],
+      ..ConnectOptions::default()
+    };
+
+    let stream = connect_tcp(
+      "standby.invalid",
+      port,
+      Duration::from_secs(2),
+      &options,
+    )
+    .await
+    .expect("connected without DNS");
+    assert_eq!(stream.peer_addr().expect("peer").port(), port);
+    // Other ports still go through the resolver.
+    connect_tcp("standby.invalid", 1, Duration::from_secs(2), &options)
+      .await
+      .expect_err("not pinned");
+  }
+
+  #[tokio::test]
+  async fn connect_tcp_enables_keepalive() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let port = listener.local_addr().expect("addr").port();
+    let connect = |tcp_keepalive| {
+      let options = ConnectOptions {
+        tcp_keepalive,
+        ..ConnectOptions::default()
+      };
+      async move {
+        connect_tcp("127.0.0.1", port, Duration::from_secs(2), &options)
+          .await
+          .expect("connected")
+      }
+    };
+
+    let stream = connect(None).await;
+    assert!(!SockRef::from(&stream).keepalive().expect("keepalive"));
+
+    let stream = connect(Some(Duration::from_secs(45))).await;
+    let socket = SockRef::from(&stream);
+    assert!(socket.keepalive().expect("keepalive"));
+    #[cfg(target_os = "linux")]
+    assert_eq!(
+      socket.tcp_keepalive_time().expect("idle"),
+      Duration::from_secs(45)
+    );
+  }
+}
@@ -0,0 +1,710 @@
+//! HYRCON and Source clients over [`std::net::TcpStream`], for small
+//! tools that want no async runtime at all. Enabled by the
+//! `std-transport` feature.
+//!
+//! They share the async backends' parsing, so replies, greetings and
+//! errors come out the same, but only cover connecting, authenticating
+//! and sending commands:
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use hyrcon_client::transport::sync::SourceClient;
+//!
+//! # fn example() -> Result<(), hyrcon_client::Error> {
+//! let mut client =
+//!   SourceClient::connect("mc.example.com", 25_575, Duration::from_secs(5))?;
+//! client.authenticate("hunter2")?;
+//! client.send_command("list")?;
+//! client.quit()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Every read and write gives up once the client's timeout has passed,
+//! using the socket's read and write timeouts.
+
+use std::collections::VecDeque;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, anyhow, bail};
+
+use super::hyrcon::check_command;
+use super::source::{
+  Packet, RESPONSE_IDLE_WINDOW, SENTINEL_GRACE_WINDOW, SERVERDATA_AUTH,
+  SERVERDATA_AUTH_RESPONSE, SERVERDATA_EXECCOMMAND,
+  SERVERDATA_RESPONSE_VALUE, SPLIT_PAYLOAD_THRESHOLD, SentinelSupport,
+  command_chunks, encode_packet, is_out_of_sync, response, take_packet,
+};
+use super::{
+  AuthOutcome, CommandOutcome, Greeting, ResponseStatus, ServerMessage,
+  TimedOut,
+};
+use crate::codec::hyrcon::{
+  BlockDecoder, event_messages, is_event, parse_capabilities, parse_reply,
+};
+use crate::error::Error;
+use crate::protocol::Protocol;
+
+/// A connected socket and the bytes read from it but not parsed yet.
+#[derive(Debug)]
+struct Connection {
+  stream: TcpStream,
+  buffer: Vec<u8>,
+  timeout: Duration,
+  closed: bool,
+}
+
+impl Connection {
+  fn open(host: &str, port: u16, timeout: Duration) -> Result<Self> {
+    if timeout.is_zero() {
+      bail!("timeout must be greater than zero");
+    }
+    let addrs = (host, port)
+      .to_socket_addrs()
+      .with_context(|| format!("resolving {host}"))?;
+    let mut last_err = None;
+    for addr in addrs {
+      match TcpStream::connect_timeout(&addr, timeout) {
+        Ok(stream) => {
+          stream.set_nodelay(true)?;
+          stream.set_write_timeout(Some(timeout))?;
+          return Ok(Self {
+            stream,
+            buffer: Vec::new(),
+            timeout,
+            closed: false,
+          });
+        }
+        Err(err) => last_err = Some(err),
+      }
+    }
+    Err(match last_err {
+      Some(err) if is_timeout(&err) => {
+        TimedOut::new(format!("connecting to {host}:{port}"), timeout)
+          .into()
+      }
+      Some(err) => anyhow::Error::new(err)
+        .context(format!("connecting to {host}:{port}")),
+      None => anyhow!("{host} did not resolve to any address"),
+    })
+  }
+
+  fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+    if timeout.is_zero() {
+      bail!("timeout must be greater than zero");
+    }
+    self.stream.set_write_timeout(Some(timeout))?;
+    self.timeout = timeout;
+    Ok(())
+  }
+
+  fn write(&mut self, bytes: &[u8], label: &str) -> Result<()> {
+    let written = self
+      .stream
+      .write_all(bytes)
+      .and_then(|()| self.stream.flush());
+    match written {
+      Ok(()) => Ok(()),
+      Err(err) if is_timeout(&err) => Err(
+        TimedOut::new(
+          format!("writing `{label}` to socket"),
+          self.timeout,
+        )
+        .into(),
+      ),
+      Err(err) => Err(err).context(format!("writing `{label}` to socket")),
+    }
+  }
+
+  /// Wait until `deadline` for more bytes and add them to the buffer.
+  fn read_until(
+    &mut self,
+    deadline: Instant,
+    operation: &str,
+  ) -> Result<()> {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    if remaining.is_zero() || !self.read_within(remaining)? {
+      return Err(TimedOut::new(operation, self.timeout).into());
+    }
+    Ok(())
+  }
+
+  /// Read whatever arrives within `window`, returning `false` if the
+  /// socket stayed quiet.
+  fn read_within(&mut self, window: Duration) -> Result<bool> {
+    self.stream.set_read_timeout(Some(window))?;
+    let mut chunk = [0_u8; 4096];
+    match self.stream.read(&mut chunk) {
+      Ok(0) => {
+        self.closed = true;
+        Err(Error::Closed.into())
+      }
+      Ok(read) => {
+        self.buffer.extend_from_slice(&chunk[..read]);
+        Ok(true)
+      }
+      Err(err) if is_timeout(&err) => Ok(false),
+      Err(err) => Err(err).context("reading from server"),
+    }
+  }
+
+  fn shutdown(&mut self) -> Result<()> {
+    self.closed = true;
+    match self.stream.shutdown(Shutdown::Both) {
+      Err(err) if err.kind() != ErrorKind::NotConnected => {
+        Err(err).context("shutting down the connection")
+      }
+      _ => Ok(()),
+    }
+  }
+}
+
+/// Blocking sockets report an expired timeout as either kind, depending
+/// on the platform.
+fn is_timeout(err: &std::io::Error) -> bool {
+  matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+}
+
+/// HYRCON session on a blocking socket.
+#[derive(Debug)]
+pub struct HyrconClient {
+  connection: Connection,
+  /// Lines and blocks received so far, kept across timed out reads.
+  decoder: BlockDecoder,
+  events: VecDeque<ServerMessage>,
+  /// Set while a reply has not been fully read, e.g. after a timeout.
+  awaiting_response: bool,
+}
+
+impl HyrconClient {
+  /// Connect, read the greeting and ask for capabilities, each step
+  /// within `timeout`.
+  pub fn connect(
+    host: &str,
+    port: u16,
+    timeout: Duration,
+  ) -> Result<(Self, Greeting), Error> {
+    Self::open(host, port, timeout).map_err(|err| {
+      match Error::from_internal(err) {
+        err @ Error::Unsupported(_) => err,
+        err => Error::Connect {
+          host: host.to_string(),
+          port,
+          protocol: Protocol::Hyrcon,
+          source: Box::new(err),
+        },
+      }
+    })
+  }
+
+  fn open(
+    host: &str,
+    port: u16,
+    timeout: Duration,
+  ) -> Result<(Self, Greeting)> {
+    let mut client = Self {
+      connection: Connection::open(host, port, timeout)?,
+      decoder: BlockDecoder::new(),
+      events: VecDeque::new(),
+      awaiting_response: false,
+    };
+    let lines = client.read_reply().context("failed to read greeting")?;
+    let mut greeting = Greeting::hyrcon_from_lines(lines)?;
+    if greeting.capabilities().is_empty() {
+      match client.exchange("HELLO")? {
+        CommandOutcome::Response(response)
+          if response.status == ResponseStatus::Ok =>
+        {
+          if let Some(capabilities) = parse_capabilities(&response.payload)
+          {
+            greeting = greeting.with_capabilities(capabilities);
+          }
+        }
+        CommandOutcome::Response(_) => {}
+        CommandOutcome::Bye => {
+          bail!("server closed the connection on HELLO")
+        }
+      }
+    }
+    Ok((client, greeting))
+  }
+
+  pub fn is_closed(&self) -> bool {
+    self.connection.closed
+  }
+
+  pub fn timeout(&self) -> Duration {
+    self.connection.timeout
+  }
+
+  pub fn set_timeout(&mut self, timeout: Duration) -> Result<(), Error> {
+    self
+      .connection
+      .set_timeout(timeout)
+      .map_err(Error::from_internal)
+  }
+
+  /// `EVENT` messages that arrived while waiting for replies.
+  pub fn take_messages(&mut self) -> Vec<ServerMessage> {
+    self.events.drain(..).collect()
+  }
+
+  pub fn authenticate(
+    &mut self,
+    password: &str,
+  ) -> Result<AuthOutcome, Error> {
+    self.auth(password).map_err(Error::from_internal)
+  }
+
+  fn auth(&mut self, password: &str) -> Result<AuthOutcome> {
+    if password.contains(['\r', '\n']) {
+      bail!("password must not contain newline characters");
+    }
+    self.skip_abandoned_reply()?;
+    self.write_line(&format!("AUTH {password}"), "AUTH <redacted>")?;
+    self.awaiting_response = true;
+    let block = self
+      .read_reply()
+      .context("failed to read authentication response")?;
+    self.awaiting_response = false;
+
+    match block.first().map(String::as_str) {
+      Some("AUTH OK") => Ok(AuthOutcome::Success),
+      Some("AUTH FAIL") => Ok(AuthOutcome::Failure),
+      Some(other) => bail!("unexpected auth response: {other}"),
+      None => bail!("server returned an empty block for AUTH response"),
+    }
+  }
+
+  pub fn send_command(
+    &mut self,
+    command: &str,
+  ) -> Result<CommandOutcome, Error> {
+    self.exchange(command).map_err(Error::from_internal)
+  }
+
+  fn exchange(&mut self, command: &str) -> Result<CommandOutcome> {
+    if self.connection.closed {
+      bail!("connection already closed");
+    }
+    check_command(command)?;
+    self.skip_abandoned_reply()?;
+    self.write_line(command, command)?;
+    self.awaiting_response = true;
+    let block = self
+      .read_reply()
+      .context("failed to read command response")?;
+    self.awaiting_response = false;
+
+    let outcome = parse_reply(block)?;
+    if matches!(outcome, CommandOutcome::Bye) {
+      self.connection.closed = true;
+    }
+    Ok(outcome)
+  }
+
+  /// Send `QUIT` and wait for the server's `BYE`.
+  pub fn quit(&mut self) -> Result<(), Error> {
+    if self.connection.closed {
+      return Ok(());
+    }
+    let quit = if self.awaiting_response {
+      // A QUIT would only read the stale reply.
+      self.connection.shutdown()
+    } else {
+      match self.exchange("QUIT") {
+        Ok(CommandOutcome::Bye) => Ok(()),
+        Ok(CommandOutcome::Response(response)) => {
+          self.connection.closed = true;
+          Err(anyhow!("unexpected payload in QUIT response: {response:?}"))
+        }
+        Err(err) => Err(err),
+      }
+    };
+    quit.map_err(Error::from_internal)
+  }
+
+  /// A reply that timed out would otherwise be taken for the next
+  /// command's, so read and drop it first.
+  fn skip_abandoned_reply(&mut self) -> Result<()> {
+    if self.awaiting_response {
+      let block = self
+        .read_reply()
+        .context("failed to skip the reply to a timed out command")?;
+      tracing::debug!(?block, "discarding reply to abandoned command");
+      self.awaiting_response = false;
+    }
+    Ok(())
+  }
+
+  /// Read the next block that is not an `EVENT`, queueing any events
+  /// that arrive first.
+  fn read_reply(&mut self) -> Result<Vec<String>> {
+    let deadline = Instant::now() + self.connection.timeout;
+    loop {
+      let block = self.next_block(deadline)?;
+      if !is_event(&block) {
+        return Ok(block);
+      }
+      for message in event_messages(block) {
+        tracing::debug!("<-- event {}", message.text);
+        self.events.push_back(message);
+      }
+    }
+  }
+
+  fn next_block(&mut self, deadline: Instant) -> Result<Vec<String>> {
+    loop {
+      let block = self
+        .decoder
+        .next_block()
+        .context("server sent a line that is not valid UTF-8")?;
+      if let Some(block) = block {
+        return Ok(block);
+      }
+      self
+        .connection
+        .read_until(deadline, "reading block from server")?;
+      self.decoder.feed(&self.connection.buffer);
+      self.connection.buffer.clear();
+    }
+  }
+
+  fn write_line(&mut self, line: &str, label: &str) -> Result<()> {
+    tracing::debug!("--> {}", label);
+    let mut bytes = Vec::with_capacity(line.len() + 1);
+    bytes.extend_from_slice(line.as_bytes());
+    bytes.push(b'\n');
+    self.connection.write(&bytes, label)
+  }
+}
+
+/// Source RCON session on a blocking socket.
+#[derive(Debug)]
+pub struct SourceClient {
+  connection: Connection,
+  authed: bool,
+  next_request_id: i32,
+  sentinel: SentinelSupport,
+}
+
+impl SourceClient {
+  /// Open the connection within `timeout`. Source RCON has no greeting,
+  /// so nothing is exchanged until [`authenticate`](Self::authenticate).
+  pub fn connect(
+    host: &str,
+    port: u16,
+    timeout: Duration,
+  ) -> Result<Self, Error> {
+    let connection =
+      Connection::open(host, port, timeout).map_err(|err| {
+        match Error::from_internal(err) {
+          err @ Error::Unsupported(_) => err,
+          err => Error::Connect {
+            host: host.to_string(),
+            port,
+            protocol: Protocol::Source,
+            source: Box::new(err),
+          },
+        }
+      })?;
+    Ok(Self {
+      connection,
+      authed: false,
+      next_request_id: 1,
+      sentinel: SentinelSupport::Unknown,
+    })
+  }
+
+  pub fn is_closed(&self) -> bool {
+    self.connection.closed
+  }
+
+  pub fn timeout(&self) -> Duration {
+    self.connection.timeout
+  }
+
+  pub fn set_timeout(&mut self, timeout: Duration) -> Result<(), Error> {
+    self
+      .connection
+      .set_timeout(timeout)
+      .map_err(Error::from_internal)
+  }
+
+  pub fn authenticate(
+    &mut self,
+    password: &str,
+  ) -> Result<AuthOutcome, Error> {
+    self.auth(password).map_err(Error::from_internal)
+  }
+
+  fn auth(&mut self, password: &str) -> Result<AuthOutcome> {
+    if password.contains(['\r', '\n']) {
+      bail!("password must not contain newline characters");
+    }
+    if password.contains('\0') {
+      bail!("password must not contain NUL characters");
+    }
+
+    let auth_id = self.next_request_id();
+    self.write_packet(
+      auth_id,
+      SERVERDATA_AUTH,
+      password,
+      "AUTH <redacted>",
+    )?;
+    loop {
+      let packet = self.read_packet()?;
+      match packet.kind {
+        // Some servers send an empty response value first.
+        SERVERDATA_RESPONSE_VALUE => continue,
+        SERVERDATA_AUTH_RESPONSE if packet.id == auth_id => {
+          self.authed = true;
+          return Ok(AuthOutcome::Success);
+        }
+        SERVERDATA_AUTH_RESPONSE => {
+          self.authed = false;
+          return Ok(AuthOutcome::Failure);
+        }
+        other => {
+          tracing::debug!(
+            packet_id = packet.id,
+            packet_kind = other,
+            "ignoring unexpected packet while authenticating"
+          );
+        }
+      }
+    }
+  }
+
+  /// Run `command`, split into several packets at `;` when it is too
+  /// long for one, and collect the reply.
+  pub fn send_command(
+    &mut self,
+    command: &str,
+  ) -> Result<CommandOutcome, Error> {
+    self.run(command).map_err(Error::from_internal)
+  }
+
+  fn run(&mut self, command: &str) -> Result<CommandOutcome> {
+    if self.connection.closed {
+      bail!("connection already closed");
+    }
+    if !self.authed {
+      bail!("server requires authentication before sending commands");
+    }
+    let mut body = String::new();
+    for chunk in command_chunks(command, None)? {
+      body.push_str(&self.exchange(chunk)?);
+    }
+    Ok(response(body))
+  }
+
+  /// Close the connection; Source RCON has no goodbye.
+  pub fn quit(&mut self) -> Result<(), Error> {
+    if self.connection.closed {
+      return Ok(());
+    }
+    self.connection.shutdown().map_err(Error::from_internal)
+  }
+
+  fn next_request_id(&mut self) -> i32 {
+    let id = self.next_request_id;
+    self.next_request_id = self.next_request_id.wrapping_add(1);
+    id
+  }
+
+  /// Send one command packet, followed by the empty sentinel command
+  /// whose echo ends the reply, and collect the reply's text. Servers
+  /// that never echo the sentinel get a quiet period instead.
+  fn exchange(&mut self, command: &str) -> Result<String> {
+    let command_id = self.next_request_id();
+    self.write_packet(
+      command_id,
+      SERVERDATA_EXECCOMMAND,
+      command,
+      command,
+    )?;
+    let sentinel_id = if self.sentinel == SentinelSupport::Missing {
+      None
+    } else {
+      let id = self.next_request_id();
+      self.write_packet(id, SERVERDATA_EXECCOMMAND, "", "<sentinel>")?;
+      Some(id)
+    };
+
+    let mut body = String::new();
+    let mut received_data = false;
+    loop {
+      if received_data && self.connection.buffer.is_empty() {
+        let window = match self.sentinel {
+          SentinelSupport::Echoed => None,
+          SentinelSupport::Unknown => Some(SENTINEL_GRACE_WINDOW),
+          SentinelSupport::Missing => Some(RESPONSE_IDLE_WINDOW),
+        };
+        if let Some(window) = window
+          && !self.connection.read_within(window)?
+        {
+          if self.sentinel == SentinelSupport::Unknown {
+            tracing::debug!(
+              "server did not answer the sentinel packet; \
+               falling back to idle-window reassembly"
+            );
+            self.sentinel = SentinelSupport::Missing;
+          }
+          break;
+        }
+      }
+
+      let packet = self.read_packet()?;
+      if packet.kind == SERVERDATA_AUTH_RESPONSE && packet.id == -1 {
+        self.authed = false;
+        bail!("server reported that authentication is no longer valid");
+      }
+      if Some(packet.id) == sentinel_id {
+        if !packet.payload.is_empty() {
+          bail!("server returned data alongside sentinel response");
+        }
+        self.sentinel = SentinelSupport::Echoed;
+        break;
+      }
+      if packet.kind == SERVERDATA_RESPONSE_VALUE
+        && packet.id == command_id
+      {
+        body.push_str(&packet.payload);
+        received_data = true;
+        if self.sentinel == SentinelSupport::Missing
+          && packet.payload_len < SPLIT_PAYLOAD_THRESHOLD
+        {
+          break;
+        }
+        continue;
+      }
+      tracing::debug!(
+        packet_id = packet.id,
+        packet_kind = packet.kind,
+        "ignoring non-matching packet while collecting response"
+      );
+    }
+    Ok(body)
+  }
+
+  fn write_packet(
+    &mut self,
+    id: i32,
+    kind: i32,
+    payload: &str,
+    label: &str,
+  ) -> Result<()> {
+    tracing::trace!(
+      request_id = id,
+      packet_kind = kind,
+      "writing packet {label}"
+    );
+    let packet = encode_packet(id, kind, payload)?;
+    self.connection.write(&packet, label)
+  }
+
+  fn read_packet(&mut self) -> Result<Packet> {
+    let deadline = Instant::now() + self.connection.timeout;
+    loop {
+      match take_packet(&mut self.connection.buffer, None) {
+        Ok(Some(packet)) => return Ok(packet),
+        Ok(None) => {}
+        Err(err) => {
+          self.connection.closed = is_out_of_sync(&err);
+          return Err(err);
+        }
+      }
+      self
+        .connection
+        .read_until(deadline, "reading packet from Source RCON server")?;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use tokio::runtime::Runtime;
+
+  use super::*;
+  use crate::testing::{MockHyrconServer, MockSourceServer, Reply};
+
+  #[test]
+  fn hyrcon_commands_run_without_a_runtime() {
+    let server_runtime = Runtime::new().expect("runtime");
+    let server = server_runtime
+      .block_on(
+        MockHyrconServer::builder()
+          .password("secret")
+          .expect("list", Reply::ok(["Steve"]))
+          .expect("kick Steve", Reply::err("no such player"))
+          .start(),
+      )
+      .expect("start");
+
+    let (mut client, greeting) = HyrconClient::connect(
+      "127.0.0.1",
+      server.port(),
+      Duration::from_secs(2),
+    )
+    .expect("connect");
+    assert_eq!(greeting.protocol(), Protocol::Hyrcon);
+    let outcome = client.authenticate("secret").expect("auth");
+    assert!(matches!(outcome, AuthOutcome::Success));
+    let CommandOutcome::Response(list) =
+      client.send_command("list").expect("list")
+    else {
+      panic!("unexpected bye");
+    };
+    assert_eq!(list.payload, ["Steve"]);
+    let CommandOutcome::Response(kick) =
+      client.send_command("kick Steve").expect("kick")
+    else {
+      panic!("unexpected bye");
+    };
+    assert_eq!(kick.status, ResponseStatus::Err);
+    client.quit().expect("quit");
+    assert!(client.is_closed());
+    server_runtime
+      .block_on(server.finish())
+      .expect("script followed");
+  }
+
+  #[test]
+  fn source_replies_end_at_the_sentinel() {
+    let server_runtime = Runtime::new().expect("runtime");
+    let server = server_runtime
+      .block_on(
+        MockSourceServer::builder()
+          .password("secret")
+          .expect("status", Reply::ok(["hostname: Dust", "map: de_dust2"]))
+          .start(),
+      )
+      .expect("start");
+
+    let mut client = SourceClient::connect(
+      "127.0.0.1",
+      server.port(),
+      Duration::from_secs(2),
+    )
+    .expect("connect");
+    let err = client.send_command("status").expect_err("not authed");
+    assert!(matches!(err, Error::Protocol(_)));
+    let outcome = client.authenticate("secret").expect("auth");
+    assert!(matches!(outcome, AuthOutcome::Success));
+    let CommandOutcome::Response(status) =
+      client.send_command("status").expect("status")
+    else {
+      panic!("unexpected bye");
+    };
+    assert_eq!(status.payload, ["hostname: Dust", "map: de_dust2"]);
+    client.quit().expect("quit");
+    server_runtime
+      .block_on(server.finish())
+      .expect("script followed");
+  }
+}
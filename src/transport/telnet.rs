@@ -0,0 +1,473 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::time::{Instant, timeout_at};
+
+use super::stats::Traffic;
+use super::stream::{self, StreamReader, StreamWriter};
+use super::{
+  AuthMode, AuthOutcome, CommandOutcome, ConnectOptions, Greeting,
+  RconResponse, ResponseStatus, ServerMessage, join_lines, with_timeout,
+};
+use crate::protocol::Protocol;
+
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+const SB: u8 = 250;
+const SE: u8 = 240;
+
+/// How long the server may stay quiet before a command reply is considered
+/// complete. Line consoles have no end-of-response marker.
+const RESPONSE_IDLE_WINDOW: Duration = Duration::from_millis(300);
+
+/// How long to wait for a password prompt after connecting.
+const PROMPT_WINDOW: Duration = Duration::from_millis(750);
+
+/// Password-protected telnet console client (7 Days to Die and similar).
+///
+/// The console streams log output continuously; lines that look like log
+/// entries are routed to the console queue while everything else received
+/// after a command is treated as its reply.
+#[derive(Debug)]
+pub(super) struct TelnetClient {
+  reader: StreamReader,
+  writer: StreamWriter,
+  timeout: Duration,
+  decoder: Decoder,
+  console: VecDeque<ServerMessage>,
+  closed: bool,
+  /// Bytes and lines exchanged.
+  traffic: Traffic,
+}
+
+impl TelnetClient {
+  pub(super) async fn connect(
+    host: &str,
+    port: u16,
+    deadline: Duration,
+    options: &ConnectOptions,
+  ) -> Result<(Self, Greeting)> {
+    let traffic = Traffic::default();
+    let (reader, writer) =
+      stream::connect(host, port, deadline, options, &traffic).await?;
+    Self::handshake(reader, writer, deadline, traffic).await
+  }
+
+  /// Watch a connected stream for a password prompt.
+  pub(super) async fn handshake(
+    reader: StreamReader,
+    writer: StreamWriter,
+    deadline: Duration,
+    traffic: Traffic,
+  ) -> Result<(Self, Greeting)> {
+    let mut client = Self {
+      reader,
+      writer,
+      timeout: deadline,
+      decoder: Decoder::default(),
+      console: VecDeque::new(),
+      closed: false,
+      traffic,
+    };
+
+    let window = deadline.min(PROMPT_WINDOW);
+    let prompt_deadline = Instant::now() + window;
+    let mut auth_mode = AuthMode::Optional;
+    while client.fill(Some(prompt_deadline)).await? && !client.closed {
+      for line in client.decoder.take_lines() {
+        tracing::debug!("<-- {line}");
+      }
+      if client.decoder.partial_contains("password") {
+        auth_mode = AuthMode::Required;
+        break;
+      }
+    }
+
+    let greeting =
+      Greeting::new(Protocol::Telnet, "TELNET CONSOLE READY", auth_mode);
+    Ok((client, greeting))
+  }
+
+  pub(super) fn is_closed(&self) -> bool {
+    self.closed
+  }
+
+  pub(super) fn set_timeout(&mut self, timeout: Duration) {
+    self.timeout = timeout;
+  }
+
+  pub(super) fn traffic(&self) -> &Traffic {
+    &self.traffic
+  }
+
+  pub(super) fn take_messages(&mut self) -> Vec<ServerMessage> {
+    self.console.drain(..).collect()
+  }
+
+  pub(super) async fn authenticate(
+    &mut self,
+    password: &str,
+  ) -> Result<AuthOutcome> {
+    if password.contains(['\r', '\n']) {
+      bail!("password must not contain newline characters");
+    }
+
+    self.decoder.clear_partial();
+    self.write_line(password, Some("<redacted>")).await?;
+
+    let deadline = Instant::now() + self.timeout;
+    loop {
+      if !self.fill(Some(deadline)).await? {
+        bail!(
+          "no authentication reply within {} ms",
+          self.timeout.as_millis()
+        );
+      }
+      if self.closed {
+        bail!("server closed the connection during authentication");
+      }
+
+      let mut outcome = None;
+      for line in self.decoder.take_lines() {
+        if outcome.is_some() {
+          // Banner lines sent right after the login reply.
+          if !line.trim().is_empty() {
+            self.console.push_back(log_message(line));
+          }
+          continue;
+        }
+
+        let lower = line.to_ascii_lowercase();
+        if lower.contains("logon successful") {
+          outcome = Some(AuthOutcome::Success);
+        } else if lower.contains("incorrect") || lower.contains("denied") {
+          outcome = Some(AuthOutcome::Failure);
+        } else {
+          tracing::debug!("<-- {line}");
+        }
+      }
+      if let Some(outcome) = outcome {
+        return Ok(outcome);
+      }
+
+      if self.decoder.partial_contains("incorrect") {
+        return Ok(AuthOutcome::Failure);
+      }
+    }
+  }
+
+  pub(super) async fn send_command(
+    &mut self,
+    command: &str,
+  ) -> Result<CommandOutcome> {
+    if self.closed {
+      bail!("connection already closed");
+    }
+
+    if command.trim().is_empty() {
+      bail!("command must not be empty");
+    }
+
+    if command.contains(['\r', '\n']) {
+      bail!("command must not contain newline characters");
+    }
+
+    // Anything already buffered predates the command.
+    self.route_console_lines();
+    self.write_line(command, Some(command)).await?;
+
+    let mut payload = Vec::new();
+    let mut deadline = Instant::now() + self.timeout;
+    let mut seen_reply = false;
+
+    loop {
+      if !self.fill(Some(deadline)).await? {
+        if seen_reply {
+          break;
+        }
+        bail!(
+          "no reply to `{command}` within {} ms",
+          self.timeout.as_millis()
+        );
+      }
+
+      for line in self.decoder.take_lines() {
+        if line.trim() == command.trim() {
+          // Local echo of what we just typed.
+          continue;
+        }
+        if is_log_line(&line) {
+          self.console.push_back(log_message(line));
+          continue;
+        }
+        payload.push(line);
+        seen_reply = true;
+      }
+
+      if seen_reply {
+        deadline = Instant::now() + RESPONSE_IDLE_WINDOW;
+      }
+
+      if self.closed {
+        return Ok(CommandOutcome::Bye);
+      }
+    }
+
+    Ok(CommandOutcome::Response(RconResponse {
+      status: ResponseStatus::Ok,
+      raw: join_lines(&payload),
+      payload,
+      error: None,
+    }))
+  }
+
+  /// Wait for the next console line while no command is in flight.
+  pub(super) async fn recv_message(
+    &mut self,
+  ) -> Result<Option<ServerMessage>> {
+    loop {
+      if let Some(message) = self.console.pop_front() {
+        return Ok(Some(message));
+      }
+
+      self.fill(None).await?;
+      if self.closed {
+        return Ok(None);
+      }
+      self.route_console_lines();
+    }
+  }
+
+  pub(super) async fn quit(&mut self) -> Result<()> {
+    if self.closed {
+      return Ok(());
+    }
+
+    self.closed = true;
+    self.write_line("exit", None).await?;
+
+    with_timeout(
+      self.timeout,
+      self.writer.shutdown(),
+      "shutting down telnet writer".to_string(),
+    )
+    .await
+  }
+
+  fn route_console_lines(&mut self) {
+    for line in self.decoder.take_lines() {
+      if !line.trim().is_empty() {
+        self.console.push_back(log_message(line));
+      }
+    }
+  }
+
+  /// Read one chunk from the socket into the decoder, answering option
+  /// negotiation on the way. Returns `false` when `deadline` passes first.
+  async fn fill(&mut self, deadline: Option<Instant>) -> Result<bool> {
+    let mut chunk = [0_u8; 4096];
+    let read = match deadline {
+      Some(deadline) => {
+        match timeout_at(deadline, self.reader.read(&mut chunk)).await {
+          Ok(read) => read,
+          Err(_) => return Ok(false),
+        }
+      }
+      None => self.reader.read(&mut chunk).await,
+    };
+    let read = read.context("reading from telnet console")?;
+
+    if read == 0 {
+      self.closed = true;
+      return Ok(true);
+    }
+
+    let complete = self.decoder.lines.len();
+    let replies = self.decoder.feed(&chunk[..read]);
+    for _ in complete..self.decoder.lines.len() {
+      self.traffic.message_received();
+    }
+    if !replies.is_empty() {
+      with_timeout(
+        self.timeout,
+        self.writer.write_all(&replies),
+        "answering telnet option negotiation",
+      )
+      .await?;
+    }
+
+    Ok(true)
+  }
+
+  async fn write_line(
+    &mut self,
+    line: &str,
+    log_repr: Option<&str>,
+  ) -> Result<()> {
+    let label = log_repr.unwrap_or(line);
+    tracing::debug!("--> {}", label);
+
+    let mut bytes = Vec::with_capacity(line.len() + 2);
+    bytes.extend_from_slice(line.as_bytes());
+    bytes.extend_from_slice(b"\r\n");
+
+    with_timeout(
+      self.timeout,
+      self.writer.write_all(&bytes),
+      format!("writing `{label}` to socket"),
+    )
+    .await?;
+    self.traffic.message_sent();
+    Ok(())
+  }
+}
+
+/// Incremental telnet stream decoder: strips IAC sequences, splits text into
+/// lines and keeps any trailing partial line (such as a password prompt).
+#[derive(Debug, Default)]
+struct Decoder {
+  state: DecodeState,
+  pending: Vec<u8>,
+  lines: VecDeque<String>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum DecodeState {
+  #[default]
+  Data,
+  Iac,
+  Option(u8),
+  Subnegotiation,
+  SubnegotiationIac,
+}
+
+impl Decoder {
+  /// Consume raw bytes, returning the negotiation replies to send back.
+  fn feed(&mut self, bytes: &[u8]) -> Vec<u8> {
+    let mut replies = Vec::new();
+
+    for &byte in bytes {
+      self.state = match (self.state, byte) {
+        (DecodeState::Data, IAC) => DecodeState::Iac,
+        (DecodeState::Data, b'\n') => {
+          self.finish_line();
+          DecodeState::Data
+        }
+        (DecodeState::Data, b'\r' | 0) => DecodeState::Data,
+        (DecodeState::Data, byte) => {
+          self.pending.push(byte);
+          DecodeState::Data
+        }
+        (DecodeState::Iac, IAC) => {
+          self.pending.push(IAC);
+          DecodeState::Data
+        }
+        (DecodeState::Iac, verb @ (DO | DONT | WILL | WONT)) => {
+          DecodeState::Option(verb)
+        }
+        (DecodeState::Iac, SB) => DecodeState::Subnegotiation,
+        (DecodeState::Iac, _) => DecodeState::Data,
+        (DecodeState::Option(verb), option) => {
+          // Refuse every option: we are a dumb line-mode client.
+          let reply = match verb {
+            DO => Some(WONT),
+            WILL => Some(DONT),
+            _ => None,
+          };
+          if let Some(reply) = reply {
+            replies.extend_from_slice(&[IAC, reply, option]);
+          }
+          DecodeState::Data
+        }
+        (DecodeState::Subnegotiation, IAC) => {
+          DecodeState::SubnegotiationIac
+        }
+        (DecodeState::Subnegotiation, _) => DecodeState::Subnegotiation,
+        (DecodeState::SubnegotiationIac, SE) => DecodeState::Data,
+        (DecodeState::SubnegotiationIac, _) => DecodeState::Subnegotiation,
+      };
+    }
+
+    replies
+  }
+
+  fn finish_line(&mut self) {
+    let line = String::from_utf8_lossy(&self.pending).into_owned();
+    self.pending.clear();
+    self.lines.push_back(line);
+  }
+
+  fn take_lines(&mut self) -> Vec<String> {
+    self.lines.drain(..).collect()
+  }
+
+  fn partial_contains(&self, needle: &str) -> bool {
+    String::from_utf8_lossy(&self.pending)
+      .to_ascii_lowercase()
+      .contains(needle)
+  }
+
+  fn clear_partial(&mut self) {
+    self.pending.clear();
+  }
+}
+
+fn log_message(line: String) -> ServerMessage {
+  ServerMessage {
+    kind: Some("Log".to_string()),
+    text: line,
+  }
+}
+
+/// Recognise `2024-05-01T12:34:56 ...` style log lines.
+fn is_log_line(line: &str) -> bool {
+  let bytes = line.as_bytes();
+  if bytes.len() < 20 {
+    return false;
+  }
+
+  bytes[..19]
+    .iter()
+    .enumerate()
+    .all(|(index, byte)| match index {
+      4 | 7 => *byte == b'-',
+      10 => *byte == b'T',
+      13 | 16 => *byte == b':',
+      _ => byte.is_ascii_digit(),
+    })
+    && bytes[19] == b' '
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn decoder_strips_negotiation_and_keeps_prompt() {
+    let mut decoder = Decoder::default();
+    let replies = decoder.feed(
+      b"\xff\xfb\x01*** Connected\r\n\xff\xfa\x18\x01\xff\xf0Please enter password:",
+    );
+
+    assert_eq!(replies, vec![IAC, DONT, 1]);
+    assert_eq!(decoder.take_lines(), vec!["*** Connected"]);
+    assert!(decoder.partial_contains("password"));
+
+    decoder.feed(b"\r\n");
+    assert_eq!(decoder.take_lines(), vec!["Please enter password:"]);
+  }
+
+  #[test]
+  fn log_lines_are_detected_by_timestamp() {
+    assert!(is_log_line(
+      "2024-05-01T12:34:56 1234.567 INF Player connected"
+    ));
+    assert!(!is_log_line("Total of 3 in the game"));
+    assert!(!is_log_line("2024-05-01"));
+  }
+}
@@ -0,0 +1,355 @@
+use std::error::Error as StdError;
+use std::path::Path;
+use std::sync::Arc;
+use std::{fmt, io};
+
+use anyhow::{Context, Result, bail};
+use rustls::client::danger::{
+  HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+};
+use rustls::crypto::{CryptoProvider, WebPkiSupportedAlgorithms};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{
+  CertificateDer, PrivateKeyDer, ServerName, UnixTime,
+};
+use rustls::{
+  AlertDescription, ClientConfig, DigitallySignedStruct, RootCertStore,
+};
+
+use super::TlsOptions;
+
+/// Build a client TLS configuration for `options`.
+///
+/// Servers are verified against the bundled Mozilla root store, or only
+/// against the certificates in `ca_file` when one is given. The ring
+/// provider is passed explicitly so no process-wide default provider has
+/// to be installed. With `insecure` set, any certificate is accepted, so
+/// a `ca_file` would be ignored and is refused instead. A client
+/// certificate is presented when both `client_cert` and `client_key` are
+/// set.
+pub(super) fn client_config(
+  options: &TlsOptions,
+) -> Result<Arc<ClientConfig>> {
+  if options.insecure && options.ca_file.is_some() {
+    bail!("a CA file cannot be used when certificates are not verified");
+  }
+
+  let mut roots = RootCertStore::empty();
+  match &options.ca_file {
+    Some(path) => {
+      let certs = CertificateDer::pem_file_iter(path)
+        .with_context(|| {
+          format!("failed to read CA file {}", path.display())
+        })?
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| {
+          format!("failed to parse CA file {}", path.display())
+        })?;
+      let (added, _ignored) = roots.add_parsable_certificates(certs);
+      if added == 0 {
+        bail!(
+          "CA file {} contains no usable certificates",
+          path.display()
+        );
+      }
+    }
+    None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+  }
+
+  let provider = Arc::new(rustls::crypto::ring::default_provider());
+  let builder = ClientConfig::builder_with_provider(provider.clone())
+    .with_safe_default_protocol_versions()
+    .expect("ring supports the default TLS protocol versions")
+    .with_root_certificates(roots);
+
+  let mut config = match (&options.client_cert, &options.client_key) {
+    (Some(cert), Some(key)) => {
+      let chain = load_cert_chain(cert)?;
+      let key = PrivateKeyDer::from_pem_file(key).with_context(|| {
+        format!("failed to read client key {}", key.display())
+      })?;
+      builder.with_client_auth_cert(chain, key).with_context(|| {
+        format!(
+          "client certificate {} does not match its key",
+          cert.display()
+        )
+      })?
+    }
+    (None, None) => builder.with_no_client_auth(),
+    _ => bail!("a client certificate needs both a certificate and a key"),
+  };
+
+  if options.insecure {
+    tracing::warn!("TLS certificate verification is disabled");
+    config
+      .dangerous()
+      .set_certificate_verifier(Arc::new(NoVerification(provider)));
+  }
+
+  Ok(Arc::new(config))
+}
+
+/// Name the server certificate must match, also sent as SNI: the host
+/// as given, or its address when it is an IP literal.
+pub(super) fn server_name(host: &str) -> Result<ServerName<'static>> {
+  ServerName::try_from(host.to_string())
+    .with_context(|| format!("`{host}` is not a valid TLS server name"))
+}
+
+fn load_cert_chain(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+  let chain = CertificateDer::pem_file_iter(path)
+    .with_context(|| {
+      format!("failed to read client certificate {}", path.display())
+    })?
+    .collect::<Result<Vec<_>, _>>()
+    .with_context(|| {
+      format!("failed to parse client certificate {}", path.display())
+    })?;
+  if chain.is_empty() {
+    bail!(
+      "client certificate {} contains no certificates",
+      path.display()
+    );
+  }
+  Ok(chain)
+}
+
+/// Reword I/O errors caused by the server refusing our client
+/// certificate (or its absence), keeping the TLS alert as the source.
+///
+/// With TLS 1.3 the server only checks the certificate after the client
+/// considers the handshake complete, so this also applies to reads.
+pub(super) fn explain_rejection(
+  err: io::Error,
+  options: &TlsOptions,
+) -> io::Error {
+  let rejected = err
+    .get_ref()
+    .and_then(|inner| inner.downcast_ref::<rustls::Error>())
+    .is_some_and(|tls_err| {
+      matches!(
+        tls_err,
+        rustls::Error::AlertReceived(
+          AlertDescription::CertificateRequired
+            | AlertDescription::BadCertificate
+            | AlertDescription::UnsupportedCertificate
+            | AlertDescription::CertificateRevoked
+            | AlertDescription::CertificateExpired
+            | AlertDescription::CertificateUnknown
+            | AlertDescription::UnknownCA
+            | AlertDescription::AccessDenied
+        )
+      )
+    });
+  if !rejected {
+    return err;
+  }
+
+  let message = match &options.client_cert {
+    Some(cert) => {
+      format!("server rejected the client certificate {}", cert.display())
+    }
+    None => "server requires a client certificate; pass --tls-cert and \
+             --tls-key"
+      .to_string(),
+  };
+  io::Error::new(
+    err.kind(),
+    Rejection {
+      message,
+      source: err,
+    },
+  )
+}
+
+#[derive(Debug)]
+struct Rejection {
+  message: String,
+  source: io::Error,
+}
+
+impl fmt::Display for Rejection {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(&self.message)
+  }
+}
+
+impl StdError for Rejection {
+  fn source(&self) -> Option<&(dyn StdError + 'static)> {
+    Some(&self.source)
+  }
+}
+
+/// Accepts every server certificate while still checking that handshake
+/// signatures are well-formed.
+#[derive(Debug)]
+struct NoVerification(Arc<CryptoProvider>);
+
+impl NoVerification {
+  fn algorithms(&self) -> &WebPkiSupportedAlgorithms {
+    &self.0.signature_verification_algorithms
+  }
+}
+
+impl ServerCertVerifier for NoVerification {
+  fn verify_server_cert(
+    &self,
+    _end_entity: &CertificateDer<'_>,
+    _intermediates: &[CertificateDer<'_>],
+    _server_name: &ServerName<'_>,
+    _ocsp_response: &[u8],
+    _now: UnixTime,
+  ) -> Result<ServerCertVerified, rustls::Error> {
+    Ok(ServerCertVerified::assertion())
+  }
+
+  fn verify_tls12_signature(
+    &self,
+    message: &[u8],
+    cert: &CertificateDer<'_>,
+    dss: &DigitallySignedStruct,
+  ) -> Result<HandshakeSignatureValid, rustls::Error> {
+    rustls::crypto::verify_tls12_signature(
+      message,
+      cert,
+      dss,
+      self.algorithms(),
+    )
+  }
+
+  fn verify_tls13_signature(
+    &self,
+    message: &[u8],
+    cert: &CertificateDer<'_>,
+    dss: &DigitallySignedStruct,
+  ) -> Result<HandshakeSignatureValid, rustls::Error> {
+    rustls::crypto::verify_tls13_signature(
+      message,
+      cert,
+      dss,
+      self.algorithms(),
+    )
+  }
+
+  fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+    self.algorithms().supported_schemes()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::PathBuf;
+
+  use super::*;
+
+  fn testdata(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+      .join("src/transport/testdata")
+      .join(name)
+  }
+
+  fn with_ca(name: &str) -> TlsOptions {
+    TlsOptions {
+      ca_file: Some(testdata(name)),
+      ..TlsOptions::default()
+    }
+  }
+
+  fn with_client(cert: &str, key: &str) -> TlsOptions {
+    TlsOptions {
+      client_cert: Some(testdata(cert)),
+      client_key: Some(testdata(key)),
+      ..TlsOptions::default()
+    }
+  }
+
+  fn message(options: &TlsOptions) -> String {
+    let err = client_config(options).expect_err("config rejected");
+    format!("{err:#}")
+  }
+
+  #[test]
+  fn ca_files_are_loaded_with_context() {
+    client_config(&with_ca("ca.pem")).expect("CA file");
+
+    let missing = message(&with_ca("missing.pem"));
+    assert!(
+      missing.starts_with("failed to read CA file ")
+        && missing.contains("missing.pem: "),
+      "{missing}"
+    );
+    let corrupt = message(&with_ca("corrupt.pem"));
+    assert!(
+      corrupt.starts_with("failed to parse CA file ")
+        && corrupt.contains("corrupt.pem: "),
+      "{corrupt}"
+    );
+    assert!(
+      message(&with_ca("ca.key")).contains("no usable certificates")
+    );
+  }
+
+  #[test]
+  fn client_certificates_need_their_key() {
+    client_config(&with_client("ca.pem", "ca.key")).expect("client auth");
+
+    let corrupt = message(&with_client("corrupt.pem", "ca.key"));
+    assert!(
+      corrupt.starts_with("failed to parse client certificate ")
+        && corrupt.contains("corrupt.pem: "),
+      "{corrupt}"
+    );
+    let missing = message(&with_client("ca.pem", "missing.key"));
+    assert!(
+      missing.starts_with("failed to read client key ")
+        && missing.contains("missing.key: "),
+      "{missing}"
+    );
+    let mismatched = message(&with_client("ca.pem", "other.key"));
+    assert!(
+      mismatched.contains("does not match its key"),
+      "{mismatched}"
+    );
+
+    let cert_only = TlsOptions {
+      client_cert: Some(testdata("ca.pem")),
+      ..TlsOptions::default()
+    };
+    assert!(message(&cert_only).contains("needs both"));
+  }
+
+  #[test]
+  fn insecure_refuses_a_ca_file() {
+    let insecure = TlsOptions {
+      insecure: true,
+      ..TlsOptions::default()
+    };
+    client_config(&insecure).expect("insecure");
+    let both = TlsOptions {
+      insecure: true,
+      ..with_ca("ca.pem")
+    };
+    assert!(message(&both).contains("CA file cannot be used"));
+  }
+
+  #[test]
+  fn server_names_come_from_the_host() {
+    assert!(matches!(
+      server_name("play.example.com").expect("DNS name"),
+      ServerName::DnsName(name) if name.as_ref() == "play.example.com"
+    ));
+    assert!(matches!(
+      server_name("10.0.0.5").expect("IPv4"),
+      ServerName::IpAddress(_)
+    ));
+    assert!(matches!(
+      server_name("::1").expect("IPv6"),
+      ServerName::IpAddress(_)
+    ));
+    let err = server_name("not a host").expect_err("invalid");
+    assert_eq!(
+      err.to_string(),
+      "`not a host` is not a valid TLS server name"
+    );
+  }
+}
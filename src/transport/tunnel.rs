@@ -0,0 +1,312 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+#[cfg(feature = "ssh")]
+use std::{
+  io,
+  pin::Pin,
+  task::{Context as TaskContext, Poll},
+  time::Duration,
+};
+
+#[cfg(feature = "ssh")]
+use anyhow::{Context, Result, bail};
+#[cfg(feature = "ssh")]
+use russh::ChannelStream;
+#[cfg(feature = "ssh")]
+use russh::client::{Handle, Msg};
+#[cfg(feature = "ssh")]
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+#[cfg(feature = "ssh")]
+use tokio::time::{Instant, timeout_at};
+
+#[cfg(feature = "ssh")]
+use super::ssh::{self, KnownHosts};
+#[cfg(feature = "ssh")]
+use super::stream::{self, BoxedStream};
+#[cfg(feature = "ssh")]
+use super::{ConnectOptions, TimedOut};
+
+/// Port SSH bastions listen on unless told otherwise.
+#[cfg(feature = "ssh")]
+const DEFAULT_PORT: u16 = 22;
+
+/// SSH bastion that connections are forwarded through, like `ssh -L`.
+///
+/// Parsed from `[user@]host[:port]`. The forwarding runs over a
+/// `direct-tcpip` channel of the same built-in client as the `ssh`
+/// protocol: the bastion's host key must be in `~/.ssh/known_hosts`, and
+/// the login uses the agent's keys and the default identities in
+/// `~/.ssh`, or only the [identity file](Self::identity_file).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SshTunnel {
+  user: Option<String>,
+  host: String,
+  port: Option<u16>,
+  identity_file: Option<PathBuf>,
+}
+
+impl SshTunnel {
+  /// Login name, when not the local user's.
+  pub fn user(&self) -> Option<&str> {
+    self.user.as_deref()
+  }
+
+  /// Bastion hostname or address.
+  pub fn host(&self) -> &str {
+    &self.host
+  }
+
+  /// SSH port, when not the default 22.
+  pub fn port(&self) -> Option<u16> {
+    self.port
+  }
+
+  /// Private key used instead of the agent and default identities.
+  pub fn identity_file(&self) -> Option<&Path> {
+    self.identity_file.as_deref()
+  }
+
+  /// Authenticate with the key in `path` only.
+  #[must_use]
+  pub fn with_identity_file(mut self, path: impl Into<PathBuf>) -> Self {
+    self.identity_file = Some(path.into());
+    self
+  }
+
+  /// Forward a connection to `host:port` through the bastion.
+  #[cfg(feature = "ssh")]
+  pub(super) async fn connect(
+    &self,
+    host: &str,
+    port: u16,
+    deadline: Duration,
+    options: &ConnectOptions,
+  ) -> Result<BoxedStream> {
+    let give_up = Instant::now() + deadline;
+    let user = match &self.user {
+      Some(user) => user.clone(),
+      None => ssh::local_user()
+        .context("no SSH login name; pass --ssh user@host")?,
+    };
+    let ssh_port = self.port.unwrap_or(DEFAULT_PORT);
+
+    tracing::debug!("--> ssh {user}@{} -p {ssh_port}", self.host);
+    let stream =
+      stream::connect_tcp(&self.host, ssh_port, deadline, options)
+        .await
+        .with_context(|| format!("failed to reach SSH bastion {self}"))?;
+    let mut handle = ssh::handshake(
+      Box::new(stream),
+      &self.host,
+      ssh_port,
+      give_up,
+      deadline,
+    )
+    .await?;
+
+    let login = async {
+      match &self.identity_file {
+        Some(path) => {
+          ssh::authenticate_with_key_file(&mut handle, &user, path).await
+        }
+        None => ssh::authenticate_with_keys(&mut handle, &user).await,
+      }
+    };
+    let authenticated = timeout_at(give_up, login)
+      .await
+      .map_err(|_| TimedOut::new("SSH key authentication", deadline))??;
+    if !authenticated {
+      bail!("SSH bastion {self} accepted none of the offered keys");
+    }
+
+    let target = options.forwarded_host(host, port);
+    tracing::debug!("--> direct-tcpip {target}:{port}");
+    let channel = timeout_at(
+      give_up,
+      handle.channel_open_direct_tcpip(
+        target,
+        port.into(),
+        "127.0.0.1",
+        0,
+      ),
+    )
+    .await
+    .map_err(|_| TimedOut::new("opening the SSH tunnel", deadline))?
+    .with_context(|| {
+      format!("SSH bastion {self} refused to forward to {host}:{port}")
+    })?;
+
+    Ok(Box::new(TunnelStream {
+      channel: channel.into_stream(),
+      _session: handle,
+    }))
+  }
+}
+
+impl fmt::Display for SshTunnel {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if let Some(user) = &self.user {
+      write!(f, "{user}@")?;
+    }
+    match self.port {
+      Some(port) if self.host.contains(':') => {
+        write!(f, "[{}]:{port}", self.host)
+      }
+      Some(port) => write!(f, "{}:{port}", self.host),
+      None => f.write_str(&self.host),
+    }
+  }
+}
+
+/// Error returned when parsing an [`SshTunnel`] destination fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseSshTunnelError {
+  reason: &'static str,
+}
+
+impl fmt::Display for ParseSshTunnelError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "invalid SSH destination: {}", self.reason)
+  }
+}
+
+impl std::error::Error for ParseSshTunnelError {}
+
+impl FromStr for SshTunnel {
+  type Err = ParseSshTunnelError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let error = |reason| ParseSshTunnelError { reason };
+
+    let s = s.trim();
+    let (user, address) = match s.rsplit_once('@') {
+      Some(("", _)) => {
+        return Err(error("missing user before `@`"));
+      }
+      Some((user, address)) => (Some(user), address),
+      None => (None, s),
+    };
+    let (host, port) = super::proxy::split_host_port(address)
+      .ok_or(error("expected `[user@]host[:port]`"))?;
+    if host.is_empty() || host.starts_with('-') {
+      return Err(error("missing host"));
+    }
+    let port = port
+      .map(str::parse)
+      .transpose()
+      .map_err(|_| error("invalid port"))?;
+
+    Ok(Self {
+      user: user.map(str::to_string),
+      host: host.to_string(),
+      port,
+      identity_file: None,
+    })
+  }
+}
+
+/// The `direct-tcpip` channel, with the SSH session it runs over.
+#[cfg(feature = "ssh")]
+struct TunnelStream {
+  channel: ChannelStream<Msg>,
+  // Disconnected when the stream is dropped.
+  _session: Handle<KnownHosts>,
+}
+
+#[cfg(feature = "ssh")]
+impl fmt::Debug for TunnelStream {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("TunnelStream").finish_non_exhaustive()
+  }
+}
+
+#[cfg(feature = "ssh")]
+impl AsyncRead for TunnelStream {
+  fn poll_read(
+    mut self: Pin<&mut Self>,
+    cx: &mut TaskContext<'_>,
+    buf: &mut ReadBuf<'_>,
+  ) -> Poll<io::Result<()>> {
+    Pin::new(&mut self.channel).poll_read(cx, buf)
+  }
+}
+
+#[cfg(feature = "ssh")]
+impl AsyncWrite for TunnelStream {
+  fn poll_write(
+    mut self: Pin<&mut Self>,
+    cx: &mut TaskContext<'_>,
+    buf: &[u8],
+  ) -> Poll<io::Result<usize>> {
+    Pin::new(&mut self.channel).poll_write(cx, buf)
+  }
+
+  fn poll_flush(
+    mut self: Pin<&mut Self>,
+    cx: &mut TaskContext<'_>,
+  ) -> Poll<io::Result<()>> {
+    Pin::new(&mut self.channel).poll_flush(cx)
+  }
+
+  fn poll_shutdown(
+    mut self: Pin<&mut Self>,
+    cx: &mut TaskContext<'_>,
+  ) -> Poll<io::Result<()>> {
+    Pin::new(&mut self.channel).poll_shutdown(cx)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_destinations() {
+    let tunnel: SshTunnel =
+      "ops@bastion.example.com:2222".parse().expect("destination");
+    assert_eq!(tunnel.user(), Some("ops"));
+    assert_eq!(tunnel.host(), "bastion.example.com");
+    assert_eq!(tunnel.port(), Some(2222));
+    assert_eq!(tunnel.to_string(), "ops@bastion.example.com:2222");
+
+    let tunnel: SshTunnel = "jump".parse().expect("alias");
+    assert_eq!((tunnel.user(), tunnel.host()), (None, "jump"));
+    assert_eq!(tunnel.port(), None);
+
+    let tunnel: SshTunnel = "root@[fd00::1]:22".parse().expect("ipv6");
+    assert_eq!(tunnel.host(), "fd00::1");
+    assert_eq!(tunnel.to_string(), "root@[fd00::1]:22");
+
+    assert!("ops@".parse::<SshTunnel>().is_err());
+    assert!("@bastion".parse::<SshTunnel>().is_err());
+    assert!("bastion:ssh".parse::<SshTunnel>().is_err());
+    assert!("-oProxyCommand=x".parse::<SshTunnel>().is_err());
+  }
+
+  #[cfg(feature = "ssh")]
+  #[tokio::test]
+  async fn unreachable_bastions_are_reported() {
+    let listener =
+      std::net::TcpListener::bind("127.0.0.1:0").expect("bind");
+    let port = listener.local_addr().expect("addr").port();
+    drop(listener);
+
+    let tunnel: SshTunnel = format!("ops@127.0.0.1:{port}")
+      .parse()
+      .expect("destination");
+    let err = tunnel
+      .connect(
+        "10.0.0.5",
+        27015,
+        Duration::from_secs(2),
+        &ConnectOptions::default(),
+      )
+      .await
+      .expect_err("nothing listens");
+    assert_eq!(
+      err.to_string(),
+      format!("failed to reach SSH bastion ops@127.0.0.1:{port}")
+    );
+  }
+}
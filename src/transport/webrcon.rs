@@ -0,0 +1,541 @@
+use std::collections::VecDeque;
+use std::fmt::{self, Write as _};
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::time::timeout as await_timeout;
+#[cfg(feature = "tls")]
+use tokio_tungstenite::client_async_tls_with_config;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::handshake::client::{
+  Request, Response,
+};
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::{self, Message};
+use tokio_tungstenite::{
+  Connector, MaybeTlsStream, WebSocketStream, client_async,
+};
+
+use super::stats::Traffic;
+use super::stream::{self, BoxedStream};
+use super::{
+  AuthOutcome, CommandOutcome, ConnectOptions, RconResponse,
+  ResponseStatus, ServerMessage, TimedOut, TlsOptions, split_lines,
+};
+use crate::error::Error;
+
+/// Name reported to the server for every outgoing command.
+const CLIENT_NAME: &str = "WebRcon";
+
+/// User agent sent with the handshake so reverse proxies can identify us.
+const USER_AGENT: &str =
+  concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+type Socket = WebSocketStream<MaybeTlsStream<BoxedStream>>;
+
+/// JSON-over-WebSocket RCON client (as used by Rust and similar games).
+///
+/// The password is part of the WebSocket URL, so `connect` only dials the
+/// TCP socket and the WebSocket handshake itself happens in `authenticate`.
+/// With TLS enabled the handshake runs over `wss://`, verifying the
+/// certificate against `host` (also sent as SNI).
+#[derive(Debug)]
+pub(super) struct WebRconClient {
+  host: String,
+  port: u16,
+  tls: Option<TlsConnector>,
+  timeout: Duration,
+  connection: Connection,
+  next_identifier: i32,
+  console: VecDeque<ServerMessage>,
+  /// Bytes and WebSocket frames exchanged.
+  traffic: Traffic,
+}
+
+#[derive(Debug)]
+enum Connection {
+  Pending(BoxedStream),
+  Open(Box<Socket>),
+  Closed,
+}
+
+/// TLS connector prepared up front so configuration errors (such as an
+/// unreadable CA file) surface when connecting.
+struct TlsConnector(Connector);
+
+impl fmt::Debug for TlsConnector {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("TlsConnector")
+  }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct OutgoingCommand<'a> {
+  identifier: i32,
+  message: &'a str,
+  name: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct IncomingMessage {
+  #[serde(default)]
+  message: String,
+  #[serde(default)]
+  identifier: i32,
+  #[serde(rename = "Type", default)]
+  kind: Option<String>,
+}
+
+impl WebRconClient {
+  pub(super) async fn connect(
+    host: &str,
+    port: u16,
+    deadline: Duration,
+    options: &ConnectOptions,
+  ) -> Result<Self> {
+    let tls = options.tls.as_ref().map(tls_connector).transpose()?;
+
+    let traffic = Traffic::default();
+    let stream =
+      stream::open(host, port, deadline, options, &traffic).await?;
+
+    Ok(Self {
+      host: host.to_string(),
+      port,
+      tls,
+      timeout: deadline,
+      connection: Connection::Pending(stream),
+      next_identifier: 1,
+      console: VecDeque::new(),
+      traffic,
+    })
+  }
+
+  pub(super) fn is_closed(&self) -> bool {
+    matches!(self.connection, Connection::Closed)
+  }
+
+  pub(super) fn set_timeout(&mut self, timeout: Duration) {
+    self.timeout = timeout;
+  }
+
+  pub(super) fn traffic(&self) -> &Traffic {
+    &self.traffic
+  }
+
+  pub(super) fn take_messages(&mut self) -> Vec<ServerMessage> {
+    self.console.drain(..).collect()
+  }
+
+  fn next_identifier(&mut self) -> i32 {
+    // Identifiers <= 0 are reserved for unsolicited console output.
+    let id = self.next_identifier;
+    self.next_identifier =
+      self.next_identifier.checked_add(1).unwrap_or(1);
+    id
+  }
+
+  pub(super) async fn authenticate(
+    &mut self,
+    password: &str,
+  ) -> Result<AuthOutcome> {
+    let stream =
+      match std::mem::replace(&mut self.connection, Connection::Closed) {
+        Connection::Pending(stream) => stream,
+        Connection::Open(socket) => {
+          self.connection = Connection::Open(socket);
+          bail!("WebRCON session is already authenticated");
+        }
+        Connection::Closed => bail!("connection already closed"),
+      };
+
+    let authority = self.authority();
+    let mut request = format!("{authority}/{}", percent_encode(password))
+      .into_client_request()
+      .context("failed to build WebRCON handshake request")?;
+    request
+      .headers_mut()
+      .insert("User-Agent", HeaderValue::from_static(USER_AGENT));
+    tracing::debug!("--> WebSocket handshake {authority}/<redacted>");
+
+    let handshake =
+      await_timeout(self.timeout, self.handshake(request, stream))
+        .await
+        .map_err(|_| TimedOut::new("WebRCON handshake", self.timeout))?;
+
+    match handshake {
+      Ok((socket, _response)) => {
+        self.connection = Connection::Open(Box::new(socket));
+        Ok(AuthOutcome::Success)
+      }
+      Err(err) if is_rejected_handshake(&err) => {
+        tracing::debug!(error = %err, "WebRCON handshake rejected");
+        Ok(AuthOutcome::Failure)
+      }
+      Err(err) => Err(err).context("WebRCON handshake failed"),
+    }
+  }
+
+  pub(super) async fn send_command(
+    &mut self,
+    command: &str,
+  ) -> Result<CommandOutcome> {
+    if command.trim().is_empty() {
+      bail!("command must not be empty");
+    }
+
+    let identifier = self.next_identifier();
+    let frame = serde_json::to_string(&OutgoingCommand {
+      identifier,
+      message: command,
+      name: CLIENT_NAME,
+    })
+    .context("failed to encode WebRCON command")?;
+
+    let timeout = self.timeout;
+    let socket = self.socket()?;
+    tracing::debug!(request_id = identifier, "--> {}", command);
+
+    await_timeout(timeout, socket.send(Message::Text(frame.into())))
+      .await
+      .map_err(|_| {
+        TimedOut::new(format!("writing `{command}` frame"), timeout)
+      })?
+      .with_context(|| format!("writing `{command}` frame to socket"))?;
+    self.traffic.message_sent();
+
+    loop {
+      let Some(message) = self.read_message(Some(self.timeout)).await?
+      else {
+        return Ok(CommandOutcome::Bye);
+      };
+
+      if message.identifier == identifier {
+        let status = match message.kind.as_deref() {
+          Some("Error") => ResponseStatus::Err,
+          _ => ResponseStatus::Ok,
+        };
+        return Ok(CommandOutcome::Response(RconResponse {
+          status,
+          payload: split_lines(&message.message),
+          error: None,
+          raw: message.message,
+        }));
+      }
+
+      tracing::trace!(
+        response_id = message.identifier,
+        expected_id = identifier,
+        "queueing unsolicited WebRCON message"
+      );
+      self.console.push_back(ServerMessage {
+        kind: message.kind,
+        text: message.message,
+      });
+    }
+  }
+
+  pub(super) async fn quit(&mut self) -> Result<()> {
+    let connection =
+      std::mem::replace(&mut self.connection, Connection::Closed);
+    let Connection::Open(mut socket) = connection else {
+      return Ok(());
+    };
+
+    await_timeout(self.timeout, WebSocketStream::close(&mut socket, None))
+      .await
+      .map_err(|_| TimedOut::new("closing WebRCON socket", self.timeout))?
+      .context("closing WebRCON socket")?;
+
+    Ok(())
+  }
+
+  async fn handshake(
+    &self,
+    request: Request,
+    stream: BoxedStream,
+  ) -> Result<(Socket, Response), tungstenite::Error> {
+    #[cfg(feature = "tls")]
+    if let Some(TlsConnector(connector)) = &self.tls {
+      return client_async_tls_with_config(
+        request,
+        stream,
+        None,
+        Some(connector.clone()),
+      )
+      .await;
+    }
+
+    client_async(request, MaybeTlsStream::Plain(stream)).await
+  }
+
+  /// `ws[s]://host[:port]`, omitting the port when it is the scheme
+  /// default so the `Host` header matches what reverse proxies expect.
+  fn authority(&self) -> String {
+    let (scheme, default_port) = if self.tls.is_some() {
+      ("wss", 443)
+    } else {
+      ("ws", 80)
+    };
+    let host = if self.host.contains(':') {
+      format!("[{}]", self.host)
+    } else {
+      self.host.clone()
+    };
+
+    if self.port == default_port {
+      format!("{scheme}://{host}")
+    } else {
+      format!("{scheme}://{host}:{}", self.port)
+    }
+  }
+
+  fn socket(&mut self) -> Result<&mut Socket> {
+    match &mut self.connection {
+      Connection::Open(socket) => Ok(socket),
+      Connection::Pending(_) => {
+        bail!("server requires authentication before sending commands")
+      }
+      Connection::Closed => bail!("connection already closed"),
+    }
+  }
+
+  /// Wait for the next unsolicited console message while no command is in
+  /// flight, returning `None` once the server closes the socket.
+  pub(super) async fn recv_message(
+    &mut self,
+  ) -> Result<Option<ServerMessage>> {
+    if let Some(message) = self.console.pop_front() {
+      return Ok(Some(message));
+    }
+
+    Ok(self.read_message(None).await?.map(|message| ServerMessage {
+      kind: message.kind,
+      text: message.message,
+    }))
+  }
+
+  /// Read the next JSON message, returning `None` once the server closes
+  /// the socket. Without a `timeout` the read waits indefinitely.
+  async fn read_message(
+    &mut self,
+    timeout: Option<Duration>,
+  ) -> Result<Option<IncomingMessage>> {
+    loop {
+      let socket = self.socket()?;
+      let frame = match timeout {
+        Some(timeout) => {
+          await_timeout(timeout, socket.next()).await.map_err(|_| {
+            TimedOut::new("reading WebRCON message", timeout)
+          })?
+        }
+        None => socket.next().await,
+      };
+
+      if let Some(Ok(_)) = &frame {
+        self.traffic.message_received();
+      }
+      let text = match frame {
+        Some(Ok(Message::Text(text))) => text,
+        Some(Ok(Message::Close(frame))) => {
+          tracing::debug!(?frame, "WebRCON server closed the socket");
+          self.connection = Connection::Closed;
+          return Ok(None);
+        }
+        Some(Ok(other)) => {
+          tracing::trace!(?other, "ignoring non-text WebRCON frame");
+          continue;
+        }
+        Some(Err(err)) => {
+          self.connection = Connection::Closed;
+          return Err(err).context("reading WebRCON message");
+        }
+        None => {
+          self.connection = Connection::Closed;
+          return Err(Error::Closed.into());
+        }
+      };
+
+      let message: IncomingMessage = serde_json::from_str(&text)
+        .with_context(|| {
+          format!("received malformed WebRCON message: {text}")
+        })?;
+
+      tracing::trace!(
+        packet_id = message.identifier,
+        message_type = message.kind.as_deref().unwrap_or("-"),
+        payload_len = message.message.len(),
+        "received WebRCON message"
+      );
+
+      return Ok(Some(message));
+    }
+  }
+}
+
+#[cfg(feature = "tls")]
+fn tls_connector(options: &TlsOptions) -> Result<TlsConnector> {
+  Ok(TlsConnector(Connector::Rustls(super::tls::client_config(
+    options,
+  )?)))
+}
+
+#[cfg(not(feature = "tls"))]
+fn tls_connector(_options: &TlsOptions) -> Result<TlsConnector> {
+  bail!("TLS support requires building with the `tls` feature")
+}
+
+/// Servers reject a bad password either with an HTTP error status or by
+/// dropping the connection before the upgrade completes.
+fn is_rejected_handshake(err: &tungstenite::Error) -> bool {
+  match err {
+    tungstenite::Error::Http(response) => {
+      response.status().is_client_error()
+    }
+    tungstenite::Error::ConnectionClosed
+    | tungstenite::Error::Protocol(
+      tungstenite::error::ProtocolError::HandshakeIncomplete,
+    ) => true,
+    tungstenite::Error::Io(io_err) => {
+      io_err.kind() == std::io::ErrorKind::UnexpectedEof
+    }
+    _ => false,
+  }
+}
+
+/// Percent-encode a value for use as a single URL path segment.
+fn percent_encode(raw: &str) -> String {
+  let mut encoded = String::with_capacity(raw.len());
+  for byte in raw.bytes() {
+    if byte.is_ascii_alphanumeric()
+      || matches!(byte, b'-' | b'.' | b'_' | b'~')
+    {
+      encoded.push(byte as char);
+    } else {
+      let _ = write!(encoded, "%{byte:02X}");
+    }
+  }
+  encoded
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn percent_encode_escapes_reserved_characters() {
+    assert_eq!(percent_encode("hunter2"), "hunter2");
+    assert_eq!(percent_encode("a b/c?d"), "a%20b%2Fc%3Fd");
+    assert_eq!(percent_encode("pä"), "p%C3%A4");
+  }
+
+  #[tokio::test]
+  async fn authority_omits_default_ports() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+      .await
+      .expect("bind");
+    let port = listener.local_addr().expect("local addr").port();
+    let mut client = WebRconClient::connect(
+      "127.0.0.1",
+      port,
+      Duration::from_secs(1),
+      &ConnectOptions::default(),
+    )
+    .await
+    .expect("connect");
+    assert_eq!(client.authority(), format!("ws://127.0.0.1:{port}"));
+
+    client.host = "::1".to_string();
+    client.port = 80;
+    assert_eq!(client.authority(), "ws://[::1]");
+  }
+
+  #[test]
+  fn incoming_message_tolerates_missing_fields() {
+    let message: IncomingMessage = serde_json::from_str(
+      r#"{"Message":"hello","Identifier":7,"Type":"Generic","Stacktrace":""}"#,
+    )
+    .expect("decode message");
+    assert_eq!(message.identifier, 7);
+    assert_eq!(message.kind.as_deref(), Some("Generic"));
+
+    let message: IncomingMessage =
+      serde_json::from_str(r#"{"Message":"[CHAT] hi"}"#)
+        .expect("decode console message");
+    assert_eq!(message.identifier, 0);
+    assert!(message.kind.is_none());
+  }
+
+  #[tokio::test]
+  #[allow(clippy::result_large_err)]
+  async fn correlates_responses_and_queues_console_output() {
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::accept_hdr_async;
+    use tokio_tungstenite::tungstenite::handshake::server::{
+      Request, Response,
+    };
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let port = listener.local_addr().expect("local addr").port();
+
+    let server = tokio::spawn(async move {
+      let (stream, _) = listener.accept().await.expect("accept");
+      let mut socket = accept_hdr_async(
+        stream,
+        |request: &Request, response: Response| {
+          assert_eq!(request.uri().path(), "/s3cret%21");
+          Ok(response)
+        },
+      )
+      .await
+      .expect("handshake");
+
+      let frame = socket.next().await.expect("frame").expect("text");
+      let command: serde_json::Value =
+        serde_json::from_str(frame.to_text().expect("text"))
+          .expect("json");
+      assert_eq!(command["Message"], "status");
+      assert_eq!(command["Name"], CLIENT_NAME);
+      let id = command["Identifier"].as_i64().expect("identifier");
+
+      let push = r#"{"Message":"[CHAT] hi","Identifier":0,"Type":"Chat"}"#;
+      socket.send(Message::Text(push.into())).await.expect("push");
+      let reply = format!(
+        r#"{{"Message":"players: 1\nmap: Procedural","Identifier":{id},"Type":"Generic"}}"#
+      );
+      socket
+        .send(Message::Text(reply.into()))
+        .await
+        .expect("reply");
+    });
+
+    let mut client = WebRconClient::connect(
+      "127.0.0.1",
+      port,
+      Duration::from_secs(5),
+      &ConnectOptions::default(),
+    )
+    .await
+    .expect("connect");
+    let outcome = client.authenticate("s3cret!").await.expect("auth");
+    assert!(matches!(outcome, AuthOutcome::Success));
+
+    let CommandOutcome::Response(response) =
+      client.send_command("status").await.expect("send")
+    else {
+      panic!("expected a response");
+    };
+    assert_eq!(response.payload, vec!["players: 1", "map: Procedural"]);
+
+    let messages = client.take_messages();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].kind.as_deref(), Some("Chat"));
+    assert_eq!(messages[0].text, "[CHAT] hi");
+
+    server.await.expect("server task");
+  }
+}
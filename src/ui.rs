@@ -1,27 +1,220 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use chrono::{Local, Utc};
 use owo_colors::OwoColorize;
-use tokio::io::{self, AsyncWriteExt, Stdout};
+use rustyline::ExternalPrinter;
+use serde::Serialize;
+use serde_json::json;
+use tokio::io::{self, AsyncBufReadExt, AsyncWriteExt, BufReader, Stdout};
+
+use crate::cli::{TableStyle, TimestampZone};
+use crate::config::{Config, Profile};
+use crate::format::{SECTION_SIGN, translate_section_codes};
+use crate::meta::MetaCommand;
+use crate::transport::{
+  CommandOutcome, Greeting, RconClient, RconResponse, ResponseStatus,
+  ServerMessage, SessionStats,
+};
+
+/// How `--timestamps` stamps rendered lines.
+#[derive(Debug, Clone, Copy)]
+pub struct Timestamps<'a> {
+  zone: TimestampZone,
+  format: &'a str,
+}
+
+impl<'a> Timestamps<'a> {
+  /// `format` is a strftime-style format string.
+  pub fn new(zone: TimestampZone, format: &'a str) -> Self {
+    Self { zone, format }
+  }
+
+  fn now(&self) -> String {
+    match self.zone {
+      TimestampZone::Local => Local::now().format(self.format).to_string(),
+      TimestampZone::Utc => Utc::now().format(self.format).to_string(),
+    }
+  }
+}
+
+/// Glyphs that decorate output, as Unicode or as plain ASCII for
+/// consoles that garble anything else.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Symbols {
+  /// Marks the banner and session notices.
+  pub arrow: &'static str,
+  pub ok: &'static str,
+  pub err: &'static str,
+  pub warning: &'static str,
+  /// Leads each cause of a reported error.
+  pub cause: &'static str,
+  /// Ends truncated text.
+  pub ellipsis: &'static str,
+  pub horizontal: &'static str,
+  pub vertical: &'static str,
+  /// Left, middle and right joints of the top, heading and bottom rules
+  /// of a boxed table.
+  pub top: [&'static str; 3],
+  pub middle: [&'static str; 3],
+  pub bottom: [&'static str; 3],
+}
+
+impl Symbols {
+  pub const UNICODE: Self = Self {
+    arrow: "⇢",
+    ok: "✔",
+    err: "✖",
+    warning: "⚠",
+    cause: "↳",
+    ellipsis: "…",
+    horizontal: "─",
+    vertical: "│",
+    top: ["┌", "┬", "┐"],
+    middle: ["├", "┼", "┤"],
+    bottom: ["└", "┴", "┘"],
+  };
+
+  pub const ASCII: Self = Self {
+    arrow: "=>",
+    ok: "+",
+    err: "x",
+    warning: "!",
+    cause: "->",
+    ellipsis: "...",
+    horizontal: "-",
+    vertical: "|",
+    top: ["+", "+", "+"],
+    middle: ["+", "+", "+"],
+    bottom: ["+", "+", "+"],
+  };
+}
+
+/// Whether output is decorated with [`Symbols::ASCII`].
+static ASCII: AtomicBool = AtomicBool::new(false);
 
-use crate::transport::{Greeting, RconResponse, ResponseStatus};
+/// Decorate all further output with plain ASCII instead of Unicode
+/// glyphs, for `--ascii`.
+pub fn set_ascii(ascii: bool) {
+  ASCII.store(ascii, Ordering::Relaxed);
+}
+
+/// The glyphs output is decorated with.
+#[must_use]
+pub fn symbols() -> &'static Symbols {
+  if ASCII.load(Ordering::Relaxed) {
+    &Symbols::ASCII
+  } else {
+    &Symbols::UNICODE
+  }
+}
+
+/// Where pushed output goes while the line editor has a prompt open.
+static PRINTER: Mutex<Option<Box<dyn ExternalPrinter + Send>>> =
+  Mutex::new(None);
+
+/// Print pushed messages and connection notices through `printer`, which
+/// keeps them above an open prompt; `None` goes back to plain stdout.
+pub fn set_printer(printer: Option<Box<dyn ExternalPrinter + Send>>) {
+  *PRINTER.lock().unwrap_or_else(|err| err.into_inner()) = printer;
+}
+
+/// Print a line that may arrive while the user is typing.
+fn emit(line: String) {
+  let mut printer = PRINTER.lock().unwrap_or_else(|err| err.into_inner());
+  let printed = printer
+    .as_mut()
+    .is_some_and(|printer| printer.print(format!("{line}\n")).is_ok());
+  if !printed {
+    println!("{line}");
+  }
+}
+
+/// Line prefix for `timestamps`, empty when they are off.
+fn stamp(timestamps: Option<Timestamps<'_>>, use_color: bool) -> String {
+  match timestamps {
+    Some(timestamps) if use_color => {
+      format!("{} ", timestamps.now().bright_black())
+    }
+    Some(timestamps) => format!("{} ", timestamps.now()),
+    None => String::new(),
+  }
+}
+
+/// Round trips shown in yellow in the prompt from this long on.
+const SLOW_REPLY: Duration = Duration::from_millis(250);
+
+/// Round trips shown in red in the prompt from this long on.
+const VERY_SLOW_REPLY: Duration = Duration::from_secs(1);
+
+/// The interactive prompt prefix, naming the active `session` when the
+/// shell holds several and showing the `latency` of the last command
+/// when given.
+#[must_use]
+pub fn prompt(
+  session: Option<&str>,
+  latency: Option<Duration>,
+  use_color: bool,
+) -> String {
+  let name = match session {
+    Some(session) => format!("rcon@{session}"),
+    None => "rcon".to_owned(),
+  };
+  let Some(latency) = latency else {
+    return if use_color {
+      format!("{} ", format!("{name}>").bright_magenta().bold())
+    } else {
+      format!("{name}> ")
+    };
+  };
+
+  let time = if latency < Duration::from_secs(1) {
+    format!("({}ms)", latency.as_millis())
+  } else {
+    format!("({:.1}s)", latency.as_secs_f64())
+  };
+  if use_color {
+    let time = if latency >= VERY_SLOW_REPLY {
+      time.red().bold().to_string()
+    } else if latency >= SLOW_REPLY {
+      time.yellow().to_string()
+    } else {
+      time.dimmed().to_string()
+    };
+    format!(
+      "{} {time}{} ",
+      name.bright_magenta().bold(),
+      ">".bright_magenta().bold()
+    )
+  } else {
+    format!("{name} {time}> ")
+  }
+}
 
 /// Render the interactive prompt prefix to the provided stdout handle.
 pub async fn render_prompt(
   stdout: &mut Stdout,
+  session: Option<&str>,
+  latency: Option<Duration>,
   use_color: bool,
 ) -> io::Result<()> {
-  let prompt = if use_color {
-    format!("{} ", "rcon>".bright_magenta().bold())
-  } else {
-    "rcon> ".to_owned()
-  };
-
-  stdout.write_all(prompt.as_bytes()).await?;
+  stdout
+    .write_all(prompt(session, latency, use_color).as_bytes())
+    .await?;
   stdout.flush().await
 }
 
 /// Pretty-print the server greeting block.
 pub fn render_greeting(greeting: &Greeting, use_color: bool) {
   if use_color {
-    println!("{} {}", "⇢".bright_cyan(), greeting.banner().bold());
+    println!(
+      "{} {}",
+      symbols().arrow.bright_cyan(),
+      greeting.banner().bold()
+    );
   } else {
     println!("{}", greeting.banner());
   }
@@ -44,58 +237,926 @@ pub fn render_greeting(greeting: &Greeting, use_color: bool) {
     println!("{}", auth_message);
   }
 
+  if !greeting.capabilities().is_empty() {
+    let capabilities =
+      format!("Capabilities: {}", greeting.capabilities().join(", "));
+    if use_color {
+      println!("{}", capabilities.dimmed());
+    } else {
+      println!("{}", capabilities);
+    }
+  }
+
+  for (key, value) in greeting.extensions() {
+    let line = format!("{key}: {value}");
+    if use_color {
+      println!("{}", line.dimmed());
+    } else {
+      println!("{line}");
+    }
+  }
+
   println!();
 }
 
-/// Render a command response in a human-friendly format.
+/// Ask a yes/no question on stdin; anything but `y` or `yes` (including
+/// end of input) means no.
+pub async fn confirm(question: &str, use_color: bool) -> io::Result<bool> {
+  let mut stdout = io::stdout();
+  stdout
+    .write_all(yes_no_question(question, use_color).as_bytes())
+    .await?;
+  stdout.flush().await?;
+
+  let mut answer = String::new();
+  BufReader::new(io::stdin()).read_line(&mut answer).await?;
+  Ok(is_yes(&answer))
+}
+
+/// `question` followed by `[y/N]`, ready to print as a prompt.
+#[must_use]
+pub fn yes_no_question(question: &str, use_color: bool) -> String {
+  let question = format!("{question} [y/N] ");
+  if use_color {
+    question.yellow().bold().to_string()
+  } else {
+    question
+  }
+}
+
+/// Whether `answer` to a [`yes_no_question`] is `y` or `yes`.
+#[must_use]
+pub fn is_yes(answer: &str) -> bool {
+  let answer = answer.trim();
+  answer.eq_ignore_ascii_case("y") || answer.eq_ignore_ascii_case("yes")
+}
+
+/// Ask for a value on stdin; an empty answer (or end of input) picks
+/// `default`.
+pub async fn ask(
+  question: &str,
+  default: &str,
+  use_color: bool,
+) -> io::Result<String> {
+  let mut stdout = io::stdout();
+  let question = if default.is_empty() {
+    format!("{question}: ")
+  } else {
+    format!("{question} [{default}]: ")
+  };
+  if use_color {
+    stdout
+      .write_all(question.bold().to_string().as_bytes())
+      .await?;
+  } else {
+    stdout.write_all(question.as_bytes()).await?;
+  }
+  stdout.flush().await?;
+
+  let mut answer = String::new();
+  BufReader::new(io::stdin()).read_line(&mut answer).await?;
+  match answer.trim() {
+    "" => Ok(default.to_string()),
+    answer => Ok(answer.to_string()),
+  }
+}
+
+/// Render a command response in a human-friendly format, laying out
+/// tabular payloads as `tables` says.
 pub fn render_response(
   command: &str,
   response: &RconResponse,
+  timestamps: Option<Timestamps<'_>>,
+  tables: TableStyle,
   use_color: bool,
 ) {
+  let indent = stamp(timestamps, false).chars().count() + 2;
+  let stamp = stamp(timestamps, use_color);
   let status_label = match response.status {
     ResponseStatus::Ok => {
       if use_color {
-        format!("{}", "✔ OK".green().bold())
+        format!("{} OK", symbols().ok).green().bold().to_string()
       } else {
         "OK".to_owned()
       }
     }
     ResponseStatus::Err => {
       if use_color {
-        format!("{}", "✖ ERR".red().bold())
+        format!("{} ERR", symbols().err).red().bold().to_string()
       } else {
         "ERR".to_owned()
       }
     }
   };
 
-  println!("{status_label} {command}");
+  println!("{stamp}{status_label} {command}");
 
-  for line in &response.payload {
-    if use_color {
-      println!("  {}", line.cyan());
+  let plain: Vec<_> = response
+    .payload
+    .iter()
+    .map(|line| translate_section_codes(line, false))
+    .collect();
+  let plain: Vec<&str> = plain.iter().map(AsRef::as_ref).collect();
+  let mut found = match tables {
+    TableStyle::Off => Vec::new(),
+    TableStyle::Aligned | TableStyle::Boxed => find_tables(&plain),
+  }
+  .into_iter()
+  .peekable();
+  let max_width = terminal_size::terminal_size()
+    .map(|(width, _)| usize::from(width.0).saturating_sub(indent));
+
+  let mut index = 0;
+  while index < plain.len() {
+    if let Some(table) = found.next_if(|table| table.start == index) {
+      let rows: Vec<_> = plain[table.clone()]
+        .iter()
+        .map(|line| cells(line))
+        .collect();
+      for row in layout_table(&rows, tables, max_width, symbols()) {
+        if use_color {
+          println!("{stamp}  {}", row.cyan());
+        } else {
+          println!("{stamp}  {row}");
+        }
+      }
+      index = table.end;
+      continue;
+    }
+
+    let line = &response.payload[index];
+    let text = translate_section_codes(line, use_color);
+    if use_color && !line.contains(SECTION_SIGN) {
+      println!("{stamp}  {}", text.cyan());
     } else {
-      println!("  {line}");
+      println!("{stamp}  {text}");
     }
+    index += 1;
   }
 
   if let Some(error) = &response.error {
     if use_color {
-      println!("  {} {}", "⚠ ERROR".yellow().bold(), error.red().bold());
+      println!(
+        "{stamp}  {} {}",
+        format!("{} ERROR", symbols().warning).yellow().bold(),
+        error.red().bold()
+      );
     } else {
-      println!("  ERROR {error}");
+      println!("{stamp}  ERROR {error}");
     }
   }
 
   println!();
 }
 
+/// Columns of a truncated table are not narrowed below this width.
+const MIN_COLUMN_WIDTH: usize = 4;
+
+/// The cells of a whitespace-aligned row: its text split at tabs and at
+/// runs of two or more spaces.
+fn cells(line: &str) -> Vec<&str> {
+  let line = line.trim();
+  let mut cells = Vec::new();
+  let mut start = 0;
+  let mut chars = line.char_indices().peekable();
+  while let Some((at, ch)) = chars.next() {
+    let gap = ch == '\t'
+      || (ch == ' ' && chars.peek().is_some_and(|&(_, next)| next == ' '));
+    if !gap {
+      continue;
+    }
+    let mut end = at + ch.len_utf8();
+    while let Some(&(next_at, next)) = chars.peek() {
+      if next != ' ' && next != '\t' {
+        break;
+      }
+      end = next_at + next.len_utf8();
+      chars.next();
+    }
+    cells.push(&line[start..at]);
+    start = end;
+  }
+  cells.push(&line[start..]);
+  cells
+}
+
+/// The runs of `lines` that form tables: two or more consecutive lines
+/// of several [`cells`] each, at least two of which have as many cells
+/// as the widest, so a stray double space in prose is left alone.
+fn find_tables(lines: &[&str]) -> Vec<Range<usize>> {
+  let mut tables = Vec::new();
+  let mut start = 0;
+  while start < lines.len() {
+    let counts: Vec<_> = lines[start..]
+      .iter()
+      .map(|line| cells(line).len())
+      .take_while(|&count| count >= 2)
+      .collect();
+    if counts.is_empty() {
+      start += 1;
+      continue;
+    }
+    let widest = counts.iter().copied().max().unwrap_or(0);
+    if counts.iter().filter(|&&count| count == widest).count() >= 2 {
+      tables.push(start..start + counts.len());
+    }
+    start += counts.len();
+  }
+  tables
+}
+
+/// Lay out `rows` in `style`, drawn with `symbols`, narrowing the widest
+/// columns with an ellipsis until each line fits in `max_width`
+/// characters.
+fn layout_table(
+  rows: &[Vec<&str>],
+  style: TableStyle,
+  max_width: Option<usize>,
+  symbols: &Symbols,
+) -> Vec<String> {
+  let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+  let mut widths = vec![0; columns];
+  for row in rows {
+    for (width, cell) in widths.iter_mut().zip(row) {
+      *width = (*width).max(cell.chars().count());
+    }
+  }
+
+  let boxed = style == TableStyle::Boxed;
+  let overhead = if boxed {
+    3 * columns + 1
+  } else {
+    2 * columns.saturating_sub(1)
+  };
+  if let Some(max_width) = max_width {
+    while overhead + widths.iter().sum::<usize>() > max_width {
+      let Some(widest) = widths
+        .iter_mut()
+        .filter(|width| **width > MIN_COLUMN_WIDTH)
+        .max_by_key(|width| **width)
+      else {
+        break;
+      };
+      *widest -= 1;
+    }
+  }
+
+  let cell = |row: &[&str], column: usize| {
+    let text = row.get(column).copied().unwrap_or_default();
+    let width = widths[column];
+    let text = if text.chars().count() > width {
+      let ellipsis = symbols.ellipsis;
+      let kept: String = text
+        .chars()
+        .take(width.saturating_sub(ellipsis.chars().count()))
+        .collect();
+      format!("{kept}{ellipsis}")
+    } else {
+      text.to_string()
+    };
+    format!("{text:width$}")
+  };
+
+  if !boxed {
+    return rows
+      .iter()
+      .map(|row| {
+        let line: Vec<_> =
+          (0..columns).map(|column| cell(row, column)).collect();
+        line.join("  ").trim_end().to_string()
+      })
+      .collect();
+  }
+
+  let rule = |[left, middle, right]: [&str; 3]| {
+    let segments: Vec<_> = widths
+      .iter()
+      .map(|width| symbols.horizontal.repeat(width + 2))
+      .collect();
+    format!("{left}{}{right}", segments.join(middle))
+  };
+  let bar = symbols.vertical;
+  let mut lines = vec![rule(symbols.top)];
+  for (index, row) in rows.iter().enumerate() {
+    let line: Vec<_> =
+      (0..columns).map(|column| cell(row, column)).collect();
+    lines.push(format!("{bar} {} {bar}", line.join(&format!(" {bar} "))));
+    if index == 0 && rows.len() > 1 {
+      lines.push(rule(symbols.middle));
+    }
+  }
+  lines.push(rule(symbols.bottom));
+  lines
+}
+
+/// Print only the payload lines of a response, for `--quiet`. The error
+/// message of a rejected command goes to stderr.
+pub fn render_payload(
+  response: &RconResponse,
+  timestamps: Option<Timestamps<'_>>,
+  use_color: bool,
+) {
+  let stamp = stamp(timestamps, use_color);
+  for line in &response.payload {
+    println!("{stamp}{}", translate_section_codes(line, use_color));
+  }
+  if let Some(error) = &response.error {
+    eprintln!("{error}");
+  }
+}
+
+/// Print the body of a response exactly as the server sent it, for
+/// `--raw`, so tables keep their tabs, padding and blank lines. The error
+/// message of a rejected command goes to stderr.
+pub fn render_raw(response: &RconResponse) {
+  print!("{}", response.raw);
+  if let Some(error) = &response.error {
+    eprintln!("{error}");
+  }
+}
+
+/// Outcome of one command as printed by `--output json`.
+#[derive(Debug, Serialize)]
+pub struct CommandRecord<'a> {
+  pub command: &'a str,
+  /// `ok`, `err` (rejected by the server), `bye` (session closed) or
+  /// `error` (not completed, see `error`).
+  pub status: &'static str,
+  pub payload: Vec<String>,
+  pub error: Option<String>,
+  pub duration_ms: f64,
+  pub host: &'a str,
+  pub port: u16,
+}
+
+impl<'a> CommandRecord<'a> {
+  /// Record for `outcome`, with formatting codes stripped from the
+  /// payload.
+  pub fn new(
+    command: &'a str,
+    outcome: &CommandOutcome,
+    elapsed: Duration,
+    client: &'a RconClient,
+  ) -> Self {
+    let (status, payload, error) = match outcome {
+      CommandOutcome::Response(response) => (
+        match response.status {
+          ResponseStatus::Ok => "ok",
+          ResponseStatus::Err => "err",
+        },
+        response
+          .payload
+          .iter()
+          .map(|line| translate_section_codes(line, false).into_owned())
+          .collect(),
+        response.error.clone(),
+      ),
+      CommandOutcome::Bye => ("bye", Vec::new(), None),
+    };
+    Self {
+      command,
+      status,
+      payload,
+      error,
+      duration_ms: millis(elapsed),
+      host: client.host(),
+      port: client.port(),
+    }
+  }
+
+  /// Record for a command that failed before the server answered.
+  pub fn failed(
+    command: &'a str,
+    err: &anyhow::Error,
+    elapsed: Duration,
+    client: &'a RconClient,
+  ) -> Self {
+    Self {
+      command,
+      status: "error",
+      payload: Vec::new(),
+      error: Some(format!("{err:#}")),
+      duration_ms: millis(elapsed),
+      host: client.host(),
+      port: client.port(),
+    }
+  }
+}
+
+/// Print `record` as a single line of JSON.
+pub fn render_json_record(record: &CommandRecord<'_>) {
+  println!("{}", json!(record));
+}
+
+/// Print a pushed message as a single line of JSON.
+pub fn render_json_message(message: &ServerMessage) {
+  println!(
+    "{}",
+    json!({ "kind": message.kind, "message": message.text })
+  );
+}
+
 /// Show a farewell message when the server closes the session.
 pub fn render_bye(use_color: bool) {
   if use_color {
-    println!("{}", "⇢ Session closed by server".bright_magenta().bold());
+    emit(
+      format!("{} Session closed by server", symbols().arrow)
+        .bright_magenta()
+        .bold()
+        .to_string(),
+    );
+  } else {
+    emit("Session closed by server".to_string());
+  }
+}
+
+/// Clear the terminal and title the next `--watch` cycle.
+pub fn render_watch_header(
+  interval: Duration,
+  command: &str,
+  use_color: bool,
+) {
+  print!("\x1b[2J\x1b[H");
+  let title = format!("Every {}s: {command}", interval.as_secs());
+  if use_color {
+    println!("{}", title.dimmed());
+  } else {
+    println!("{title}");
+  }
+  println!();
+}
+
+/// Print a short note from the client itself, such as the outcome of a
+/// shell command that never reaches the server.
+pub fn render_notice(message: &str, use_color: bool) {
+  if use_color {
+    emit(message.dimmed().to_string());
+  } else {
+    emit(message.to_string());
+  }
+}
+
+/// Report that the session dropped and a reconnect is about to start.
+pub fn render_connection_lost(reason: &str, use_color: bool) {
+  if use_color {
+    emit(format!(
+      "{} {}",
+      format!("{} Connection lost:", symbols().warning)
+        .yellow()
+        .bold(),
+      reason.yellow()
+    ));
+  } else {
+    emit(format!("Connection lost: {reason}"));
+  }
+}
+
+/// Announce the next reconnect attempt.
+pub fn render_reconnecting(
+  attempt: u32,
+  max_attempts: u32,
+  delay: Duration,
+  use_color: bool,
+) {
+  let message = format!(
+    "Reconnecting in {:.1}s (attempt {attempt}/{max_attempts})...",
+    delay.as_secs_f64()
+  );
+  if use_color {
+    emit(message.dimmed().to_string());
+  } else {
+    emit(message);
+  }
+}
+
+/// Tell the user that a dropped session was re-established.
+pub fn render_session_restored(use_color: bool) {
+  if use_color {
+    emit(
+      format!("{} Session restored", symbols().arrow)
+        .green()
+        .bold()
+        .to_string(),
+    );
+  } else {
+    emit("Session restored".to_string());
+  }
+  emit(String::new());
+}
+
+/// Print the round-trip time of one `--ping` sample.
+pub fn render_ping(sequence: u32, rtt: Duration, use_color: bool) {
+  let time = format!("{:.2} ms", millis(rtt));
+  if use_color {
+    println!("seq={sequence} time={}", time.cyan());
+  } else {
+    println!("seq={sequence} time={time}");
+  }
+}
+
+/// Summarise `--ping` samples as min/avg/max latency.
+pub fn render_ping_summary(samples: &[Duration], use_color: bool) {
+  let (Some(min), Some(max)) =
+    (samples.iter().min(), samples.iter().max())
+  else {
+    return;
+  };
+  let total: Duration = samples.iter().sum();
+  let avg = total / samples.len() as u32;
+
+  let noun = if samples.len() == 1 {
+    "sample"
+  } else {
+    "samples"
+  };
+  let summary = format!(
+    "{} {noun}, min/avg/max = {:.2}/{:.2}/{:.2} ms",
+    samples.len(),
+    millis(*min),
+    millis(avg),
+    millis(*max)
+  );
+  if use_color {
+    println!("{}", summary.bold());
+  } else {
+    println!("{summary}");
+  }
+}
+
+/// List the profiles in `config`, marking the default with `*`.
+pub fn render_profile_list(config: &Config, use_color: bool) {
+  if config.profiles.is_empty() {
+    println!("No profiles configured");
+    return;
+  }
+
+  let width = config.profiles.keys().map(String::len).max().unwrap_or(0);
+  for (name, profile) in &config.profiles {
+    let marker = if config.default_profile.as_ref() == Some(name) {
+      '*'
+    } else {
+      ' '
+    };
+    let target = match (&profile.host, profile.port) {
+      (Some(host), Some(port)) => format!("{host}:{port}"),
+      (Some(host), None) => host.clone(),
+      (None, Some(port)) => format!(":{port}"),
+      (None, None) => "-".to_string(),
+    };
+    let protocol = profile.protocol.as_deref().unwrap_or("-");
+    if use_color {
+      println!(
+        "{marker} {:width$}  {:11}  {}",
+        name.bold(),
+        protocol.cyan(),
+        target
+      );
+    } else {
+      println!("{marker} {name:width$}  {protocol:11}  {target}");
+    }
+  }
+}
+
+/// Print a profile as the TOML table it is stored as.
+pub fn render_profile(
+  name: &str,
+  profile: &Profile,
+) -> anyhow::Result<()> {
+  let table = Config {
+    default_profile: None,
+    profiles: [(name.to_string(), profile.clone())].into(),
+    ..Config::default()
+  };
+  print!("{}", toml::to_string(&table)?);
+  Ok(())
+}
+
+/// List the shell's own commands, for `:help`.
+pub fn render_meta_help(use_color: bool) {
+  let width = MetaCommand::USAGE
+    .iter()
+    .map(|(usage, _)| usage.len())
+    .max()
+    .unwrap_or(0);
+  for (usage, summary) in MetaCommand::USAGE {
+    if use_color {
+      println!("{:width$}  {summary}", usage.bold());
+    } else {
+      println!("{usage:width$}  {summary}");
+    }
+  }
+  println!();
+}
+
+/// List the shell's sessions for `:switch`, marking the active one with
+/// `*`.
+pub fn render_sessions<'a>(
+  active: &str,
+  sessions: impl IntoIterator<Item = (&'a str, &'a RconClient)>,
+  use_color: bool,
+) {
+  for (name, client) in sessions {
+    let marker = if name == active { '*' } else { ' ' };
+    let target = format!("{}:{}", client.host(), client.port());
+    if use_color {
+      println!("{marker} {}  {}", name.bold(), target.cyan());
+    } else {
+      println!("{marker} {name}  {target}");
+    }
+  }
+  println!();
+}
+
+/// Print a command's help text for `:describe`.
+pub fn render_description(help: &[String], use_color: bool) {
+  for line in help {
+    let text = translate_section_codes(line, use_color);
+    if use_color && !line.contains(SECTION_SIGN) {
+      println!("  {}", text.cyan());
+    } else {
+      println!("  {text}");
+    }
+  }
+  println!();
+}
+
+/// List variables and their values, for `:vars`.
+pub fn render_vars(vars: &BTreeMap<&str, &str>, use_color: bool) {
+  if vars.is_empty() {
+    render_notice(
+      "No variables; set one with `:set NAME=VALUE`",
+      use_color,
+    );
+    return;
+  }
+  for (name, value) in vars {
+    if use_color {
+      println!("{}={value}", name.bold());
+    } else {
+      println!("{name}={value}");
+    }
+  }
+  println!();
+}
+
+/// Describe the session and the shell's settings, for `:status`.
+pub fn render_status(
+  client: &RconClient,
+  timeout: Duration,
+  use_color: bool,
+) {
+  let stats = client.stats();
+  let rows = [
+    (
+      "Server",
+      format!(
+        "{}:{} ({})",
+        client.host(),
+        client.port(),
+        client.protocol().as_str()
+      ),
+    ),
+    ("Banner", client.greeting().banner().to_string()),
+    ("Timeout", format!("{:.1}s", timeout.as_secs_f64())),
+    ("Colors", if use_color { "on" } else { "off" }.to_string()),
+    (
+      "Traffic",
+      format!(
+        "{}; sent {}; received {}",
+        counted(stats.commands, "command"),
+        counted(stats.bytes_sent, "byte"),
+        counted(stats.bytes_received, "byte")
+      ),
+    ),
+  ];
+  for (label, value) in rows {
+    let label = format!("{label}:");
+    if use_color {
+      println!("{:8} {value}", label.bold());
+    } else {
+      println!("{label:8} {value}");
+    }
+  }
+  println!();
+}
+
+/// Summarise the traffic of a session on stderr, so it stays out of
+/// piped command output.
+pub fn render_session_stats(stats: &SessionStats, use_color: bool) {
+  let summary = format!(
+    "{}; sent {} / {}; received {} / {}",
+    counted(stats.commands, "command"),
+    counted(stats.messages_sent, "message"),
+    counted(stats.bytes_sent, "byte"),
+    counted(stats.messages_received, "message"),
+    counted(stats.bytes_received, "byte")
+  );
+  if use_color {
+    eprintln!("{}", summary.dimmed());
+  } else {
+    eprintln!("{summary}");
+  }
+}
+
+fn counted(count: u64, noun: &str) -> String {
+  if count == 1 {
+    format!("{count} {noun}")
   } else {
-    println!("Session closed by server");
+    format!("{count} {noun}s")
+  }
+}
+
+fn millis(duration: Duration) -> f64 {
+  duration.as_secs_f64() * 1_000.0
+}
+
+/// Move the cursor back over an already rendered prompt so that
+/// asynchronous output does not end up appended to it.
+pub fn clear_prompt_line(use_color: bool) {
+  if use_color {
+    print!("\r\x1b[2K");
+  } else {
+    println!();
+  }
+}
+
+/// Print an unsolicited server message (console output, chat, ...).
+pub fn render_server_message(
+  message: &ServerMessage,
+  timestamps: Option<Timestamps<'_>>,
+  use_color: bool,
+) {
+  let label = message.kind.as_deref().unwrap_or("Console");
+  let stamp = stamp(timestamps, use_color);
+
+  for line in message.text.lines() {
+    if use_color {
+      emit(format!(
+        "{stamp}{} {}",
+        format!("[{label}]").bright_black(),
+        line.dimmed()
+      ));
+    } else {
+      emit(format!("{stamp}[{label}] {line}"));
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn cells_split_at_tabs_and_wide_gaps() {
+    assert_eq!(
+      cells("  Steve\t\t12 ms  world nether"),
+      ["Steve", "12 ms", "world nether"]
+    );
+    assert_eq!(cells("one cell only"), ["one cell only"]);
+  }
+
+  #[test]
+  fn tables_need_rows_of_matching_width() {
+    let lines = [
+      "Players online:",
+      "name   ping  world",
+      "Steve  12    overworld",
+      "Alex   140   nether",
+      "",
+      "Note:  a stray gap",
+      "and more prose",
+      "tps\t20.0",
+      "mspt\t12.4",
+    ];
+    assert_eq!(find_tables(&lines), [1..4, 7..9]);
+  }
+
+  #[test]
+  fn aligned_tables_pad_and_truncate() {
+    let rows = [
+      vec!["name", "ping", "world"],
+      vec!["Steve", "12", "overworld"],
+      vec!["Alexandria", "140"],
+    ];
+    assert_eq!(
+      layout_table(&rows, TableStyle::Aligned, None, &Symbols::UNICODE),
+      [
+        "name        ping  world",
+        "Steve       12    overworld",
+        "Alexandria  140",
+      ]
+    );
+    assert_eq!(
+      layout_table(
+        &rows,
+        TableStyle::Aligned,
+        Some(20),
+        &Symbols::UNICODE
+      ),
+      ["name    ping  world", "Steve   12    overw…", "Alexa…  140"]
+    );
+  }
+
+  #[test]
+  fn boxed_tables_rule_off_the_heading() {
+    let rows = [vec!["name", "ping"], vec!["Steve", "12"]];
+    assert_eq!(
+      layout_table(&rows, TableStyle::Boxed, None, &Symbols::UNICODE),
+      [
+        "┌───────┬──────┐",
+        "│ name  │ ping │",
+        "├───────┼──────┤",
+        "│ Steve │ 12   │",
+        "└───────┴──────┘",
+      ]
+    );
+  }
+
+  #[test]
+  fn ascii_tables_use_no_other_characters() {
+    let rows = [vec!["name", "world"], vec!["Steve", "overworld"]];
+    let lines =
+      layout_table(&rows, TableStyle::Boxed, Some(18), &Symbols::ASCII);
+    assert_eq!(
+      lines,
+      [
+        "+-------+--------+",
+        "| name  | world  |",
+        "+-------+--------+",
+        "| Steve | ove... |",
+        "+-------+--------+",
+      ]
+    );
+    assert!(lines.iter().all(|line| line.is_ascii()));
+  }
+
+  #[tokio::test]
+  async fn json_records_keep_their_shape() {
+    use crate::protocol::Protocol;
+    use crate::testing::{MockHyrconServer, Reply};
+    use crate::transport::TimedOut;
+
+    let server = MockHyrconServer::builder()
+      .expect("LIST", Reply::ok(["\u{a7}aSteve", "Alex"]))
+      .expect("KICK Notch", Reply::err("no such player"))
+      .start()
+      .await
+      .expect("start");
+    let mut client = RconClient::connect(
+      Protocol::Hyrcon,
+      "127.0.0.1",
+      server.port(),
+      Duration::from_secs(2),
+    )
+    .await
+    .expect("connect");
+    let elapsed = Duration::from_micros(12_500);
+
+    let outcome = client.send_command("LIST").await.expect("list");
+    assert_eq!(
+      json!(CommandRecord::new("LIST", &outcome, elapsed, &client)),
+      json!({
+        "command": "LIST",
+        "status": "ok",
+        "payload": ["Steve", "Alex"],
+        "error": null,
+        "duration_ms": 12.5,
+        "host": "127.0.0.1",
+        "port": server.port(),
+      })
+    );
+
+    let outcome = client.send_command("KICK Notch").await.expect("kick");
+    assert_eq!(
+      json!(CommandRecord::new("KICK Notch", &outcome, elapsed, &client)),
+      json!({
+        "command": "KICK Notch",
+        "status": "err",
+        "payload": [],
+        "error": "no such player",
+        "duration_ms": 12.5,
+        "host": "127.0.0.1",
+        "port": server.port(),
+      })
+    );
+
+    let timed_out = anyhow::Error::from(crate::Error::from(
+      TimedOut::new("reading block from server", Duration::from_secs(5)),
+    ));
+    assert_eq!(
+      json!(CommandRecord::failed("LIST", &timed_out, elapsed, &client)),
+      json!({
+        "command": "LIST",
+        "status": "error",
+        "payload": [],
+        "error": "reading block from server timed out after 5000 ms",
+        "duration_ms": 12.5,
+        "host": "127.0.0.1",
+        "port": server.port(),
+      })
+    );
+
+    client.quit().await.expect("quit");
+    server.finish().await.expect("script followed");
   }
 }
@@ -2,8 +2,9 @@
 
 /// Utilities shared across the HYRCON client.
 ///
-/// Currently this module provides helpers for normalising user input so that it
-/// can be safely transmitted to the RCON server.
+/// This module provides helpers for normalising user input so that it can be
+/// safely transmitted to the RCON server, for reading dotenv files, and for
+/// pacing reconnect attempts.
 pub mod command {
   /// Sanitise raw user input before it is sent to the HYRCON server.
   ///
@@ -30,6 +31,160 @@ pub mod command {
     }
   }
 
+  /// Commands of a script file with their 1-based line numbers: one per
+  /// line, skipping blank lines and lines whose first non-blank character
+  /// is `#`.
+  ///
+  /// ```
+  /// use hyrcon_client::util::command::script_lines;
+  ///
+  /// let script = "# nightly\nsave-all\n\nsay done\n";
+  /// let commands: Vec<_> = script_lines(script).collect();
+  /// assert_eq!(commands, [(2, "save-all".to_string()), (4, "say done".to_string())]);
+  /// ```
+  pub fn script_lines(
+    contents: &str,
+  ) -> impl Iterator<Item = (usize, String)> + '_ {
+    contents
+      .lines()
+      .enumerate()
+      .filter(|(_, line)| !line.trim_start().starts_with('#'))
+      .filter_map(|(index, line)| Some((index + 1, sanitize(line)?)))
+  }
+
+  /// Fill in the placeholders of a command template.
+  ///
+  /// `{name}` is replaced by the last value given for `name` in `vars`, and
+  /// `{env:NAME}` by the environment variable `NAME`. `{{name}}` stands for
+  /// a literal `{name}`. Placeholders without a value and braces around
+  /// anything other than a name are left alone, so Lua tables, JSON
+  /// arguments and selectors such as `@a[scores={kills=1}]` pass through
+  /// untouched. `$name` is short for `{name}` when `name` (letters, digits
+  /// and `_`) has a value; any other `$` is kept.
+  ///
+  /// ```
+  /// use hyrcon_client::util::command::expand;
+  ///
+  /// let vars = [("player".to_string(), "Steve".to_string())];
+  /// assert_eq!(
+  ///   expand("whitelist add {player}", &vars).unwrap(),
+  ///   "whitelist add Steve"
+  /// );
+  /// assert_eq!(expand("say {{player}}", &vars).unwrap(), "say {player}");
+  /// assert_eq!(expand("kick $player", &vars).unwrap(), "kick Steve");
+  /// assert_eq!(expand("kick {target}", &vars).unwrap(), "kick {target}");
+  /// ```
+  pub fn expand(
+    raw: &str,
+    vars: &[(String, String)],
+  ) -> Result<String, ExpandError> {
+    let mut expanded = String::with_capacity(raw.len());
+    let mut rest = raw;
+
+    while let Some(start) = rest.find(['{', '$']) {
+      expanded.push_str(&rest[..start]);
+      rest = &rest[start..];
+
+      if let Some(after) = rest.strip_prefix('$') {
+        let end = after
+          .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+          .unwrap_or(after.len());
+        match lookup(&after[..end], vars) {
+          Ok(Some(value)) if is_variable_name(&after[..end]) => {
+            expanded.push_str(&value);
+            rest = &after[end..];
+          }
+          _ => {
+            expanded.push('$');
+            rest = after;
+          }
+        }
+      } else if let Some((placeholder, after)) = rest
+        .strip_prefix("{{")
+        .and_then(placeholder)
+        .and_then(|(name, after)| Some((name, after.strip_prefix('}')?)))
+      {
+        expanded.push('{');
+        expanded.push_str(placeholder);
+        expanded.push('}');
+        rest = after;
+      } else if let Some((value, after)) = match placeholder(&rest[1..]) {
+        Some((name, after)) => {
+          lookup(name, vars)?.map(|value| (value, after))
+        }
+        None => None,
+      } {
+        expanded.push_str(&value);
+        rest = after;
+      } else {
+        expanded.push('{');
+        rest = &rest[1..];
+      }
+    }
+
+    expanded.push_str(rest);
+    Ok(expanded)
+  }
+
+  /// Error raised when a command template names an unset environment
+  /// variable.
+  #[derive(Debug, Clone, PartialEq, Eq)]
+  pub enum ExpandError {
+    /// `{env:NAME}` while `NAME` is unset or not valid UTF-8.
+    MissingEnv(String),
+  }
+
+  impl std::fmt::Display for ExpandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+      match self {
+        Self::MissingEnv(name) => {
+          write!(f, "environment variable ${name} is not set")
+        }
+      }
+    }
+  }
+
+  impl std::error::Error for ExpandError {}
+
+  /// Split `name}...` or `env:NAME}...` off the start of `text`, returning
+  /// the placeholder without its closing brace.
+  fn placeholder(text: &str) -> Option<(&str, &str)> {
+    let end = text.find('}')?;
+    let name = &text[..end];
+    let ident = name.strip_prefix("env:").unwrap_or(name);
+    is_variable_name(ident).then(|| (name, &text[end + 1..]))
+  }
+
+  /// Value of `placeholder`, or `None` for a variable without one.
+  fn lookup(
+    placeholder: &str,
+    vars: &[(String, String)],
+  ) -> Result<Option<String>, ExpandError> {
+    if let Some(name) = placeholder.strip_prefix("env:") {
+      return std::env::var(name)
+        .map(Some)
+        .map_err(|_| ExpandError::MissingEnv(name.to_string()));
+    }
+    Ok(
+      vars
+        .iter()
+        .rev()
+        .find(|(name, _)| name == placeholder)
+        .map(|(_, value)| value.clone()),
+    )
+  }
+
+  /// Whether `name` can be used as `{name}`: ASCII letters, digits, `_`
+  /// and `-`, not starting with a digit or `-`.
+  #[must_use]
+  pub fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+      .next()
+      .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+      && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+  }
+
   /// Determine whether the supplied command corresponds to a graceful exit.
   ///
   /// This helper recognises the built-in `quit` and `exit` verbs, ignoring
@@ -39,16 +194,240 @@ pub mod command {
     matches!(
       sanitize(raw)
         .as_deref()
+        .map(str::trim)
         .map(|cmd| cmd.eq_ignore_ascii_case("quit")
           || cmd.eq_ignore_ascii_case("exit")),
       Some(true)
     )
   }
+
+  /// Whether `raw` matches `pattern`, in which `*` stands for any run of
+  /// characters. Both are compared whole, ignoring ASCII case, and a
+  /// leading `/` on the command is ignored.
+  ///
+  /// ```
+  /// use hyrcon_client::util::command::matches_pattern;
+  ///
+  /// assert!(matches_pattern("ban *", "/ban Steve griefing"));
+  /// assert!(!matches_pattern("stop", "stopsound Steve"));
+  /// ```
+  #[must_use]
+  pub fn matches_pattern(pattern: &str, raw: &str) -> bool {
+    let pattern = pattern.trim().to_ascii_lowercase();
+    let raw = raw.trim();
+    let raw = raw.strip_prefix('/').unwrap_or(raw).to_ascii_lowercase();
+    let mut parts = pattern.split('*');
+    let Some(mut rest) =
+      raw.strip_prefix(parts.next().unwrap_or_default())
+    else {
+      return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+      return rest.is_empty();
+    };
+    for part in parts {
+      match rest.find(part) {
+        Some(index) => rest = &rest[index + part.len()..],
+        None => return false,
+      }
+    }
+    rest.ends_with(last)
+  }
+
+  /// Words that mark a command as carrying credentials, such as
+  /// `auth`, `login` or `setpassword`.
+  const SECRET_WORDS: [&str; 6] =
+    ["pass", "secret", "token", "login", "register", "auth"];
+
+  /// Whether `raw` looks like it carries a password or token, so it
+  /// should be kept out of saved history. Matches are case-insensitive
+  /// and may fall anywhere in the command.
+  ///
+  /// ```
+  /// use hyrcon_client::util::command::contains_secret;
+  ///
+  /// assert!(contains_secret("setPassword hunter2"));
+  /// assert!(!contains_secret("say hello"));
+  /// ```
+  #[must_use]
+  pub fn contains_secret(raw: &str) -> bool {
+    let raw = raw.to_ascii_lowercase();
+    SECRET_WORDS.iter().any(|word| raw.contains(word))
+  }
+}
+
+/// Minimal dotenv (`.env`) parsing used to seed connection variables.
+pub mod dotenv {
+  use std::fmt;
+
+  /// Error raised when a dotenv line cannot be parsed.
+  #[derive(Debug, Clone, PartialEq, Eq)]
+  pub struct ParseError {
+    line: usize,
+    message: &'static str,
+  }
+
+  impl ParseError {
+    /// Returns the 1-based line number that failed to parse.
+    pub fn line(&self) -> usize {
+      self.line
+    }
+  }
+
+  impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      write!(f, "line {}: {}", self.line, self.message)
+    }
+  }
+
+  impl std::error::Error for ParseError {}
+
+  /// Parse the contents of a dotenv file into ordered key/value pairs.
+  ///
+  /// Blank lines and `#` comments are skipped, an optional leading `export`
+  /// keyword is accepted, and values may be wrapped in single or double
+  /// quotes. Double-quoted values understand `\n`, `\t`, `\"` and `\\`
+  /// escapes; unquoted values end at the first ` #` inline comment.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use hyrcon_client::util::dotenv::parse;
+  ///
+  /// let vars = parse("RCON_HOST=10.0.0.5\nexport RCON_PORT=\"27015\"\n")
+  ///   .expect("valid dotenv");
+  /// assert_eq!(vars[0], ("RCON_HOST".to_string(), "10.0.0.5".to_string()));
+  /// assert_eq!(vars[1], ("RCON_PORT".to_string(), "27015".to_string()));
+  /// ```
+  pub fn parse(
+    contents: &str,
+  ) -> Result<Vec<(String, String)>, ParseError> {
+    let mut vars = Vec::new();
+
+    for (index, raw_line) in contents.lines().enumerate() {
+      let line_no = index + 1;
+      let line = raw_line.trim();
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+
+      let line = line
+        .strip_prefix("export ")
+        .map(str::trim_start)
+        .unwrap_or(line);
+
+      let Some((key, value)) = line.split_once('=') else {
+        return Err(ParseError {
+          line: line_no,
+          message: "expected KEY=VALUE",
+        });
+      };
+
+      let key = key.trim();
+      if key.is_empty()
+        || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+      {
+        return Err(ParseError {
+          line: line_no,
+          message: "invalid variable name",
+        });
+      }
+
+      let value = parse_value(value.trim(), line_no)?;
+      vars.push((key.to_string(), value));
+    }
+
+    Ok(vars)
+  }
+
+  fn parse_value(raw: &str, line: usize) -> Result<String, ParseError> {
+    if let Some(rest) = raw.strip_prefix('\'') {
+      return rest.find('\'').map(|end| rest[..end].to_string()).ok_or(
+        ParseError {
+          line,
+          message: "unterminated single-quoted value",
+        },
+      );
+    }
+
+    if let Some(rest) = raw.strip_prefix('"') {
+      let mut value = String::new();
+      let mut chars = rest.chars();
+      while let Some(c) = chars.next() {
+        match c {
+          '"' => return Ok(value),
+          '\\' => match chars.next() {
+            Some('n') => value.push('\n'),
+            Some('t') => value.push('\t'),
+            Some(other) => value.push(other),
+            None => break,
+          },
+          other => value.push(other),
+        }
+      }
+      return Err(ParseError {
+        line,
+        message: "unterminated double-quoted value",
+      });
+    }
+
+    let value = raw.find(" #").map_or(raw, |idx| &raw[..idx]);
+    Ok(value.trim_end().to_string())
+  }
+}
+
+pub mod backoff {
+  use std::time::Duration;
+
+  /// Exponential backoff between connection attempts.
+  ///
+  /// The first delay is `initial`; every following one doubles, up to
+  /// `max`.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use std::time::Duration;
+  /// use hyrcon_client::util::backoff::Backoff;
+  ///
+  /// let mut backoff =
+  ///   Backoff::new(Duration::from_secs(1), Duration::from_secs(3));
+  /// assert_eq!(backoff.next_delay(), Duration::from_secs(1));
+  /// assert_eq!(backoff.next_delay(), Duration::from_secs(2));
+  /// assert_eq!(backoff.next_delay(), Duration::from_secs(3));
+  /// ```
+  #[derive(Debug, Clone)]
+  pub struct Backoff {
+    next: Duration,
+    max: Duration,
+  }
+
+  impl Backoff {
+    #[must_use]
+    pub fn new(initial: Duration, max: Duration) -> Self {
+      Self { next: initial, max }
+    }
+
+    /// Delay before the next attempt.
+    pub fn next_delay(&mut self) -> Duration {
+      let delay = self.next.min(self.max);
+      self.next = delay.saturating_mul(2);
+      delay
+    }
+  }
 }
 
 #[cfg(test)]
 mod tests {
-  use super::command::{is_exit_command, sanitize};
+  use std::time::Duration;
+
+  use super::backoff::Backoff;
+  use super::command::{
+    ExpandError, contains_secret, expand, is_exit_command,
+    matches_pattern, sanitize, script_lines,
+  };
+  use super::dotenv;
 
   #[test]
   fn sanitize_removes_trailing_newlines() {
@@ -57,12 +436,96 @@ mod tests {
     assert_eq!(sanitize("say hello\r\n\n"), Some("say hello".to_string()));
   }
 
+  #[test]
+  fn secrets_are_spotted_anywhere_in_a_command() {
+    assert!(contains_secret("AUTH hunter2"));
+    assert!(contains_secret("/login s3cret"));
+    assert!(contains_secret("rcon_password new"));
+    assert!(contains_secret("whitelist token abc"));
+    assert!(!contains_secret("list"));
+    assert!(!contains_secret("say hello world"));
+  }
+
+  #[test]
+  fn patterns_match_whole_commands() {
+    assert!(matches_pattern("stop", " STOP "));
+    assert!(!matches_pattern("stop", "stop now"));
+    assert!(matches_pattern("wipe*", "wipeworld"));
+    assert!(matches_pattern("wipe*", "wipe"));
+    assert!(matches_pattern("ban *", "ban Steve"));
+    assert!(!matches_pattern("ban *", "banlist"));
+    assert!(matches_pattern("*op*", "deop Alex"));
+    assert!(matches_pattern("kick * now", "kick Alex now"));
+    assert!(!matches_pattern("kick * now", "kick Alex later"));
+    assert!(!matches_pattern("a*a", "a"));
+  }
+
   #[test]
   fn sanitize_rejects_blank_input() {
     assert_eq!(sanitize("   \n"), None);
     assert_eq!(sanitize("\n\n"), None);
   }
 
+  #[test]
+  fn script_lines_skip_comments_and_blanks() {
+    let script =
+      "# restart\r\n  # indented\nsay §cbye # now\r\n\n\t\nstop\n";
+    let commands: Vec<_> = script_lines(script).collect();
+    assert_eq!(
+      commands,
+      [(3, "say §cbye # now".to_string()), (6, "stop".to_string())]
+    );
+  }
+
+  #[test]
+  fn expand_fills_placeholders_and_leaves_other_braces() {
+    let vars = [
+      ("time".to_string(), "5 minutes".to_string()),
+      ("player".to_string(), "Alex".to_string()),
+      ("player".to_string(), "Steve".to_string()),
+    ];
+    assert_eq!(
+      expand("say Restart in {time}, {player}", &vars).unwrap(),
+      "say Restart in 5 minutes, Steve"
+    );
+    assert_eq!(
+      expand(r#"tellraw @a {"text":"{player}"}"#, &vars).unwrap(),
+      r#"tellraw @a {"text":"Steve"}"#
+    );
+    assert_eq!(
+      expand("kill @a[scores={kills=1}] {{time}} {{x", &vars).unwrap(),
+      "kill @a[scores={kills=1}] {time} {{x"
+    );
+    assert_eq!(
+      expand("say {env:HYRCON_TEST_UNSET_VARIABLE}", &vars),
+      Err(ExpandError::MissingEnv(
+        "HYRCON_TEST_UNSET_VARIABLE".to_string()
+      ))
+    );
+    assert_eq!(
+      expand("give $player diamond 64 for $5 $", &vars).unwrap(),
+      "give Steve diamond 64 for $5 $"
+    );
+    assert_eq!(
+      expand("say $players $env:HOME", &vars).unwrap(),
+      "say $players $env:HOME"
+    );
+    assert_eq!(expand("ban {target}", &vars).unwrap(), "ban {target}");
+  }
+
+  #[test]
+  fn expand_leaves_unknown_placeholders_literal() {
+    assert_eq!(
+      expand("/c game.print({x})", &[]).unwrap(),
+      "/c game.print({x})"
+    );
+    let vars = [("x".to_string(), "42".to_string())];
+    assert_eq!(
+      expand("/c game.print({x}, {y})", &vars).unwrap(),
+      "/c game.print(42, {y})"
+    );
+  }
+
   #[test]
   fn exit_detection_is_case_insensitive() {
     assert!(is_exit_command("quit"));
@@ -70,4 +533,46 @@ mod tests {
     assert!(is_exit_command(" Exit \n"));
     assert!(!is_exit_command("quiet"));
   }
+
+  #[test]
+  fn backoff_doubles_up_to_the_cap() {
+    let mut backoff =
+      Backoff::new(Duration::from_millis(500), Duration::from_secs(30));
+    let delays: Vec<_> = (0..8).map(|_| backoff.next_delay()).collect();
+    assert_eq!(delays[0], Duration::from_millis(500));
+    assert_eq!(delays[3], Duration::from_secs(4));
+    assert_eq!(delays[6], Duration::from_secs(30));
+    assert_eq!(delays[7], Duration::from_secs(30));
+  }
+
+  #[test]
+  fn dotenv_parses_quotes_comments_and_export() {
+    let vars = dotenv::parse(
+      "# deployment\n\nexport RCON_HOST=play.example.com\nRCON_PASSWORD='s3cr#t'\nRCON_PORT=27015 # rcon\nHYRCON_BANNER=\"a\\tb\"\n",
+    )
+    .expect("parse dotenv");
+
+    assert_eq!(
+      vars,
+      vec![
+        ("RCON_HOST".to_string(), "play.example.com".to_string()),
+        ("RCON_PASSWORD".to_string(), "s3cr#t".to_string()),
+        ("RCON_PORT".to_string(), "27015".to_string()),
+        ("HYRCON_BANNER".to_string(), "a\tb".to_string()),
+      ]
+    );
+  }
+
+  #[test]
+  fn dotenv_reports_malformed_lines() {
+    let err = dotenv::parse("RCON_HOST=ok\nnot a pair\n").unwrap_err();
+    assert_eq!(err.line(), 2);
+    assert_eq!(err.to_string(), "line 2: expected KEY=VALUE");
+
+    let err = dotenv::parse("RCON_PASSWORD=\"open\n").unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "line 1: unterminated double-quoted value"
+    );
+  }
 }